@@ -0,0 +1,163 @@
+//! Benchmarks of the work done for every sample: classifying it, keeping
+//! histories, drawing graphs, encoding it for peers and the whole update
+//! loop of the monitor.
+//!
+//! ```text
+//! cargo bench --bench engine                            # all benchmarks
+//! cargo bench --bench engine -- history                 # those whose name matches
+//! cargo bench --bench engine -- --save-baseline before  # record a baseline
+//! cargo bench --bench engine -- --baseline before       # compare against it
+//! ```
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use latencee::{
+    ServerStatus, classify_latency,
+    config::Config,
+    history::{History, HistoryStore},
+    monitor::Monitor,
+    wire,
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const INTERVAL: Duration = Duration::from_secs(2);
+const WINDOW: Duration = Duration::from_secs(600);
+
+/// A result of a target answering in `rtt`, or not at all.
+fn status(name: &str, at: Instant, rtt: Option<Duration>) -> ServerStatus {
+    let mut status = ServerStatus::pending(name, WINDOW);
+    status.last_update = at;
+    status.latency = rtt;
+    status.status = classify_latency(rtt);
+    status
+}
+
+/// A full history of a target that now and then answers slowly or not at
+/// all.
+fn full_history(now: Instant) -> History {
+    let mut history = History::new(WINDOW);
+    let samples = (WINDOW.as_secs() / INTERVAL.as_secs()) as u32;
+    for i in 0..samples {
+        let rtt = match i % 50 {
+            0 => None,
+            7 => Some(Duration::from_millis(180)),
+            _ => Some(Duration::from_millis(20 + u64::from(i % 40))),
+        };
+        history.push(now - INTERVAL * (samples - i), classify_latency(rtt), rtt);
+    }
+    history
+}
+
+fn classify(c: &mut Criterion) {
+    let latencies: Vec<Option<Duration>> = (0..64)
+        .map(|i| (i % 9 != 0).then(|| Duration::from_micros(i * 9_000)))
+        .collect();
+    c.bench_function("classify", |b| {
+        b.iter(|| {
+            for latency in &latencies {
+                black_box(classify_latency(black_box(*latency)));
+            }
+        })
+    });
+}
+
+fn histories(c: &mut Criterion) {
+    let start = Instant::now();
+    let mut history = full_history(start);
+    let mut pushed = 0;
+    c.bench_function("history/push", |b| {
+        b.iter(|| {
+            // A full window, so every sample prunes the oldest.
+            pushed += 1;
+            let rtt = Some(Duration::from_millis(20));
+            history.push(start + INTERVAL * pushed, classify_latency(rtt), rtt);
+            black_box(&history);
+        })
+    });
+
+    let mut store = HistoryStore::new(WINDOW, INTERVAL, 64 << 20);
+    let mut updates: Vec<ServerStatus> = (0..500)
+        .map(|i| {
+            status(
+                &format!("target-{}", i),
+                start,
+                Some(Duration::from_millis(20)),
+            )
+        })
+        .collect();
+    let mut tracked = 0;
+    c.bench_function("history/track 500 targets", |b| {
+        b.iter(|| {
+            let update = &mut updates[tracked % 500];
+            update.last_update += INTERVAL;
+            black_box(store.track(update));
+            tracked += 1;
+        })
+    });
+}
+
+fn graph(c: &mut Criterion) {
+    let graph = full_history(Instant::now());
+    let mut columns = [None; 60];
+    c.bench_function("graph/columns", |b| {
+        b.iter(|| {
+            graph.columns(Instant::now(), WINDOW, &mut columns);
+            black_box(&columns);
+        })
+    });
+}
+
+fn encoding(c: &mut Criterion) {
+    let sample = status(
+        "Cloudflare DNS",
+        Instant::now(),
+        Some(Duration::from_millis(12)),
+    );
+    c.bench_function("wire/encode", |b| {
+        b.iter(|| black_box(wire::encode_status(black_box(&sample))))
+    });
+
+    let mut decoder = wire::Decoder::new(WINDOW);
+    let line = wire::encode_status(&sample);
+    c.bench_function("wire/decode", |b| {
+        b.iter(|| black_box(decoder.decode(black_box(&line))))
+    });
+}
+
+fn monitor(c: &mut Criterion) {
+    let mut monitor = Monitor::new(
+        &Config::default(),
+        HistoryStore::new(WINDOW, INTERVAL, 64 << 20),
+    )
+    .expect("default configuration");
+    let start = Instant::now();
+    let mut observed: Vec<ServerStatus> = (0..500)
+        .map(|i| {
+            status(
+                &format!("target-{}", i),
+                start,
+                Some(Duration::from_millis(20)),
+            )
+        })
+        .collect();
+    let mut round = 0;
+    c.bench_function("monitor/observe 500 targets", |b| {
+        b.iter(|| {
+            let status = &mut observed[round % 500];
+            status.last_update += INTERVAL;
+            status.latency = if round % 37 == 0 {
+                None
+            } else {
+                Some(Duration::from_millis(20 + (round % 200) as u64))
+            };
+            status.status = classify_latency(status.latency);
+            monitor.observe(status);
+            round += 1;
+        })
+    });
+}
+
+criterion_group!(benches, classify, histories, graph, encoding, monitor);
+criterion_main!(benches);
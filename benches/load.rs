@@ -0,0 +1,117 @@
+//! Synthetic load: simulated targets probed as fast as their probers go,
+//! with every result going through the monitor the way the dashboard and
+//! headless mode feed it. The time per result is that of the whole path,
+//! from the probers through the channel to the monitor, so it tells how many
+//! results per second an instance keeps up with.
+//!
+//! ```text
+//! cargo bench --bench load
+//! cargo bench --bench load -- targets/1000
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use latencee::{
+    Prober, Target,
+    config::Config,
+    history::{self, HistoryStore},
+    monitor::Monitor,
+    rt,
+    simulate::{Pattern, Simulate},
+    sinks,
+};
+use smol::channel;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const WINDOW: Duration = Duration::from_secs(600);
+
+/// The shortest interval probers take. A thousand targets then produce a
+/// million results per second, more than the monitor handles, so the
+/// benchmark measures it rather than the probers' schedule.
+const INTERVAL: Duration = Duration::from_millis(1);
+
+/// Every fourth target spikes, drifts or drops out now and then; the rest
+/// answer steadily.
+fn pattern(i: usize) -> Pattern {
+    let base = Duration::from_millis(10 + (i % 40) as u64);
+    match i % 4 {
+        1 => Pattern::Spike {
+            base,
+            peak: Duration::from_millis(300),
+            every: 10,
+        },
+        2 => Pattern::Drift {
+            base,
+            step: Duration::from_micros(100),
+        },
+        3 => Pattern::Outage {
+            base,
+            at: 20,
+            length: 5,
+        },
+        _ => Pattern::Steady(base),
+    }
+}
+
+fn load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    group.throughput(Throughput::Elements(1));
+    group.sample_size(20);
+    for count in [1000, 5000] {
+        let targets: Vec<Target> = (0..count)
+            .map(|i| {
+                Target::new(
+                    format!("target-{}", i),
+                    format!("10.0.{}.{}", i / 250, i % 250),
+                )
+            })
+            .collect();
+        let probe = Arc::new(Simulate::new(
+            targets
+                .iter()
+                .enumerate()
+                .map(|(i, target)| (target.name.clone(), pattern(i))),
+        ));
+        let mut monitor = Monitor::new(
+            &Config::default(),
+            HistoryStore::new(WINDOW, INTERVAL, history::DEFAULT_MEMORY),
+        )
+        .expect("default configuration");
+
+        // Bounded like the queues of the application, so that probers wait
+        // for the monitor instead of piling results up.
+        let (sender, receiver) = channel::bounded(sinks::QUEUE);
+        let tasks: Vec<_> = targets
+            .into_iter()
+            .enumerate()
+            .map(|(i, target)| {
+                // Spread the targets over the interval, as the dashboard does.
+                let delay = INTERVAL.mul_f64(i as f64 / count as f64);
+                let prober =
+                    Prober::with_probe(target, probe.clone(), INTERVAL, WINDOW).with_delay(delay);
+                rt::spawn(prober.run(sender.clone()))
+            })
+            .collect();
+        drop(sender);
+
+        group.bench_function(BenchmarkId::new("targets", count), |b| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                rt::block_on(async {
+                    for _ in 0..iters {
+                        let mut status = receiver.recv().await.expect("probers run");
+                        monitor.observe(&mut status);
+                    }
+                });
+                start.elapsed()
+            })
+        });
+        drop(tasks);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
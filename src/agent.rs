@@ -0,0 +1,138 @@
+//! Agent mode: probing on a remote machine and streaming the results to a
+//! central instance.
+//!
+//! The connection is made by `openssl s_client` with this agent's client
+//! certificate, so the central instance only accepts agents signed by its CA
+//! and the agent only talks to a central instance signed by the same CA
+//! whose certificate is issued for the host it connects to.
+
+use crate::{ServerStatus, config::AgentConfig, wire};
+use std::{
+    io::{self, Write},
+    net::IpAddr,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// Wait between connection attempts.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+pub struct Uplink {
+    config: AgentConfig,
+    child: Option<Child>,
+    retry_at: Instant,
+    error: Option<String>,
+}
+
+impl Uplink {
+    pub fn new(config: AgentConfig) -> Self {
+        Uplink {
+            config,
+            child: None,
+            retry_at: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// The host part of the central address, without port or brackets.
+    fn central_host(&self) -> &str {
+        let central = &self.config.central;
+        let host = match central.rsplit_once(':') {
+            Some((host, _)) if !host.contains(':') || host.ends_with(']') => host,
+            _ => central,
+        };
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    fn connect(&self) -> io::Result<Child> {
+        let host = self.central_host();
+        let check = if host.parse::<IpAddr>().is_ok() {
+            "-verify_ip"
+        } else {
+            "-verify_hostname"
+        };
+        Command::new(&self.config.openssl)
+            .args(["s_client", "-quiet", "-verify_return_error"])
+            .args(["-verify_name", "ssl_server", check, host])
+            .arg("-connect")
+            .arg(&self.config.central)
+            .arg("-cert")
+            .arg(&self.config.cert)
+            .arg("-key")
+            .arg(&self.config.key)
+            .arg("-CAfile")
+            .arg(&self.config.ca)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot run {}: {}", self.config.openssl, e),
+                )
+            })
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.error = Some(format!("{}: {}", self.config.central, reason));
+        self.retry_at = Instant::now() + RETRY_DELAY;
+    }
+
+    fn send(&mut self, data: &str) {
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        let written = child
+            .stdin
+            .as_mut()
+            .expect("stdin is piped")
+            .write_all(data.as_bytes());
+        if let Err(e) = written {
+            self.disconnect(format!("connection lost: {}", e));
+        }
+    }
+
+    /// Notices a dropped connection and reconnects when due, sending the
+    /// history in `statuses` first. Call regularly.
+    pub fn tick(&mut self, statuses: &[ServerStatus]) {
+        if let Some(child) = &mut self.child
+            && let Ok(Some(exit)) = child.try_wait()
+        {
+            self.disconnect(format!("connection closed ({})", exit));
+        }
+        if self.child.is_some() || Instant::now() < self.retry_at {
+            return;
+        }
+        match self.connect() {
+            Ok(child) => {
+                self.child = Some(child);
+                let backlog: String = statuses.iter().map(wire::encode_history).collect();
+                self.send(&backlog);
+            }
+            Err(e) => self.disconnect(e.to_string()),
+        }
+    }
+
+    /// Streams a new result while connected.
+    pub fn publish(&mut self, status: &ServerStatus) {
+        self.send(&wire::encode_status(status));
+    }
+
+    /// The most recent connection failure, cleared once read.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+}
+
+impl Drop for Uplink {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+        }
+    }
+}
@@ -0,0 +1,718 @@
+//! Alert rules: conditions over each target's recent probe results, e.g.
+//! `p95 > 120ms for 2m` or `loss > 5% over 50 samples`.
+//!
+//! A rule fires once its condition has held for the requested time and
+//! resolves once its clear condition has, so a separate, stricter clear
+//! condition keeps a target hovering around a threshold from flapping.
+
+use crate::{ConnectionStatus, ServerStatus, config::parse_duration, schedule::Schedule, stats};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Number of probe results carried in [`Alert::recent`].
+const RECENT: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub rule: String,
+    pub target: String,
+    pub at: SystemTime,
+    pub state: AlertState,
+    /// The condition that was met, e.g. `p95 > 120ms for 2m`.
+    pub condition: String,
+    /// The measured value that met it, e.g. `134ms`.
+    pub value: String,
+    /// Status of the target's latest probe.
+    pub status: ConnectionStatus,
+    pub latency: Option<Duration>,
+    /// How long the alert was firing, for resolved alerts.
+    pub duration: Option<Duration>,
+    /// The worst values seen while firing, for resolved alerts.
+    pub worst: Option<Worst>,
+    /// The target's most recent probe results, oldest first.
+    pub recent: Vec<Option<Duration>>,
+}
+
+/// The worst values observed while an alert was firing.
+#[derive(Clone, Debug)]
+pub struct Worst {
+    /// Worst value of the metric of the rule's fire condition.
+    pub value: String,
+    /// Highest round-trip time of an answered probe.
+    pub max_latency: Option<Duration>,
+    pub lost: usize,
+    pub samples: usize,
+    /// `value` as a number, to compare against.
+    score: f64,
+}
+
+impl Worst {
+    fn new(score: f64, value: String) -> Self {
+        Worst {
+            value,
+            max_latency: None,
+            lost: 0,
+            samples: 0,
+            score,
+        }
+    }
+
+    fn record(&mut self, latency: Option<Duration>) {
+        self.samples += 1;
+        match latency {
+            Some(l) => self.max_latency = self.max_latency.max(Some(l)),
+            None => self.lost += 1,
+        }
+    }
+
+    /// One-line description, e.g. `worst 180ms, max rtt 240ms, 3/60 lost`.
+    pub fn describe(&self) -> String {
+        format!(
+            "worst {}, max rtt {}, {}/{} lost",
+            self.value,
+            stats::format_ms(self.max_latency),
+            self.lost,
+            self.samples
+        )
+    }
+}
+
+impl Alert {
+    /// One-line human readable description.
+    pub fn summary(&self) -> String {
+        format!("{}: {} {}", self.target, self.rule, self.state.label())
+    }
+}
+
+/// One-line description of a group of alerts sent together.
+pub fn group_summary(alerts: &[Alert]) -> String {
+    match alerts {
+        [] => String::new(),
+        [alert] => alert.summary(),
+        [first, rest @ ..]
+            if rest
+                .iter()
+                .all(|a| a.rule == first.rule && a.state == first.state) =>
+        {
+            let targets: Vec<&str> = alerts.iter().map(|a| a.target.as_str()).collect();
+            format!(
+                "{}: {} {}",
+                targets.join(", "),
+                first.rule,
+                first.state.label()
+            )
+        }
+        _ => {
+            let firing = alerts
+                .iter()
+                .filter(|a| a.state == AlertState::Firing)
+                .count();
+            format!(
+                "{} alerts ({} firing, {} resolved)",
+                alerts.len(),
+                firing,
+                alerts.len() - firing
+            )
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Metric {
+    Status,
+    /// Latest round-trip time.
+    Rtt,
+    Min,
+    Avg,
+    Max,
+    Jitter,
+    Percentile(f64),
+    /// Lost probes in percent.
+    Loss,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Window {
+    Samples(usize),
+    Duration(Duration),
+}
+
+/// A parsed condition: `<metric> <op> <value> [over <window>] [for <duration>]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    metric: Metric,
+    op: Op,
+    /// Threshold in the metric's unit: milliseconds, percent or status rank.
+    threshold: f64,
+    window: Window,
+    hold: Duration,
+    text: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut words = text.split_whitespace();
+        let mut next = |what: &str| {
+            words
+                .next()
+                .ok_or_else(|| format!("missing {} in '{}'", what, text))
+        };
+
+        let metric = match next("metric")? {
+            "status" => Metric::Status,
+            "rtt" | "latency" => Metric::Rtt,
+            "min" => Metric::Min,
+            "avg" => Metric::Avg,
+            "max" => Metric::Max,
+            "jitter" => Metric::Jitter,
+            "loss" => Metric::Loss,
+            p if p.starts_with('p')
+                && p[1..].parse::<f64>().is_ok_and(|n| n > 0.0 && n <= 100.0) =>
+            {
+                Metric::Percentile(p[1..].parse().unwrap())
+            }
+            other => return Err(format!("unknown metric '{}'", other)),
+        };
+        let op = match next("operator")? {
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "==" | "=" => Op::Eq,
+            "!=" => Op::Ne,
+            other => return Err(format!("unknown operator '{}'", other)),
+        };
+        let raw = next("value")?;
+        let threshold = match metric {
+            Metric::Status => ConnectionStatus::from_label(raw)
+                .map(|s| s as u8 as f64)
+                .ok_or_else(|| format!("unknown status '{}'", raw))?,
+            Metric::Loss => raw
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("invalid percentage '{}'", raw))?,
+            _ => match raw.parse::<f64>() {
+                Ok(ms) => ms,
+                Err(_) => parse_duration(raw)
+                    .map(stats::millis)
+                    .ok_or_else(|| format!("invalid latency '{}'", raw))?,
+            },
+        };
+
+        let mut window = match metric {
+            Metric::Status | Metric::Rtt => Window::Samples(1),
+            _ => Window::Duration(Duration::from_secs(60)),
+        };
+        let mut hold = Duration::ZERO;
+        while let Some(keyword) = words.next() {
+            let value = words
+                .next()
+                .ok_or_else(|| format!("'{}' needs a value in '{}'", keyword, text))?;
+            match keyword {
+                "over" => {
+                    window = match value.parse::<usize>() {
+                        Ok(0) => return Err("window must not be empty".to_string()),
+                        Ok(n) => {
+                            // "over 50 samples"
+                            if let Some(unit) = words.clone().next()
+                                && (unit == "samples" || unit == "sample")
+                            {
+                                words.next();
+                            }
+                            Window::Samples(n)
+                        }
+                        Err(_) => Window::Duration(
+                            parse_duration(value)
+                                .ok_or_else(|| format!("invalid window '{}'", value))?,
+                        ),
+                    }
+                }
+                "for" => {
+                    hold = parse_duration(value)
+                        .ok_or_else(|| format!("invalid duration '{}'", value))?
+                }
+                other => return Err(format!("unexpected '{}' in '{}'", other, text)),
+            }
+        }
+
+        Ok(Condition {
+            metric,
+            op,
+            threshold,
+            window,
+            hold,
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Condition {
+    /// The logical opposite over the same window, used as the default clear
+    /// condition.
+    pub fn negate(&self) -> Condition {
+        let (op, symbol) = match self.op {
+            Op::Lt => (Op::Ge, ">="),
+            Op::Le => (Op::Gt, ">"),
+            Op::Gt => (Op::Le, "<="),
+            Op::Ge => (Op::Lt, "<"),
+            Op::Eq => (Op::Ne, "!="),
+            Op::Ne => (Op::Eq, "=="),
+        };
+        let mut words: Vec<&str> = self.text.split_whitespace().collect();
+        words[1] = symbol;
+        // The clear condition holds as soon as the fire condition stops.
+        if let Some(i) = words.iter().position(|w| *w == "for") {
+            words.drain(i..(i + 2).min(words.len()));
+        }
+        Condition {
+            op,
+            hold: Duration::ZERO,
+            text: words.join(" "),
+            ..self.clone()
+        }
+    }
+
+    /// Evaluates the condition over `samples` (oldest first, latest last),
+    /// and `status`, the classification of the latest one, returning whether
+    /// it holds and the measured value, as a number and for display. `None`
+    /// until the samples fill the window: `n` of them, or reaching back its
+    /// full length.
+    fn evaluate(
+        &self,
+        samples: &VecDeque<(SystemTime, Option<Duration>)>,
+        status: ConnectionStatus,
+    ) -> Option<(bool, f64, String)> {
+        let (latest_at, latest) = *samples.back()?;
+        let (oldest_at, _) = *samples.front()?;
+        let full = match self.window {
+            Window::Samples(n) => samples.len() >= n,
+            Window::Duration(span) => {
+                latest_at.duration_since(oldest_at).unwrap_or_default() >= span
+            }
+        };
+        if !full {
+            return None;
+        }
+        let window: Vec<Option<Duration>> = match self.window {
+            Window::Samples(n) => samples
+                .iter()
+                .skip(samples.len().saturating_sub(n))
+                .map(|&(_, l)| l)
+                .collect(),
+            Window::Duration(span) => samples
+                .iter()
+                .filter(|(at, _)| latest_at.duration_since(*at).unwrap_or_default() <= span)
+                .map(|&(_, l)| l)
+                .collect(),
+        };
+
+        // Lost probes count as infinitely slow.
+        let latency = |l: Option<Duration>| l.map_or(f64::INFINITY, stats::millis);
+        let (value, display) = match self.metric {
+            Metric::Status => (status as u8 as f64, status.label().to_string()),
+            Metric::Rtt => (latency(latest), stats::format_ms(latest)),
+            Metric::Loss => {
+                let summary = stats::summarize(window);
+                let loss = summary.loss() * 100.0;
+                (loss, format!("{:.1}%", loss))
+            }
+            Metric::Percentile(pct) => {
+                let mut answered: Vec<Duration> = window.iter().flatten().copied().collect();
+                answered.sort_unstable();
+                let value = (!answered.is_empty()).then(|| stats::percentile(&answered, pct));
+                (latency(value), stats::format_ms(value))
+            }
+            Metric::Min | Metric::Avg | Metric::Max | Metric::Jitter => {
+                let summary = stats::summarize(window);
+                let value = match self.metric {
+                    Metric::Min => summary.min,
+                    Metric::Avg => summary.avg,
+                    Metric::Max => summary.max,
+                    _ => summary.jitter,
+                };
+                (latency(value), stats::format_ms(value))
+            }
+        };
+
+        let holds = match self.op {
+            Op::Lt => value < self.threshold,
+            Op::Le => value <= self.threshold,
+            Op::Gt => value > self.threshold,
+            Op::Ge => value >= self.threshold,
+            Op::Eq => value == self.threshold,
+            Op::Ne => value != self.threshold,
+        };
+        Some((holds, value, display))
+    }
+
+    /// Whether measured `a` is further past the threshold than `b`.
+    fn worse(&self, a: f64, b: f64) -> bool {
+        match self.op {
+            Op::Gt | Op::Ge => a > b,
+            Op::Lt | Op::Le => a < b,
+            Op::Eq | Op::Ne => false,
+        }
+    }
+
+    /// How much history the condition needs.
+    fn extent(&self) -> (usize, Duration) {
+        match self.window {
+            Window::Samples(n) => (n, Duration::ZERO),
+            Window::Duration(d) => (1, d),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    /// Targets the rule applies to; all when empty.
+    pub targets: Vec<String>,
+    pub fire: Condition,
+    pub clear: Condition,
+    /// Shell commands run when the rule fires or resolves.
+    pub on_fire: Option<String>,
+    pub on_resolve: Option<String>,
+}
+
+impl Rule {
+    /// The rule used when none are configured: alert once a target's status
+    /// drops to `level` or worse.
+    pub fn degraded(level: ConnectionStatus) -> Rule {
+        let fire: Condition = format!("status >= {}", level.label())
+            .parse()
+            .expect("valid built-in condition");
+        Rule {
+            name: "degraded".to_string(),
+            targets: Vec::new(),
+            clear: fire.negate(),
+            fire,
+            on_fire: None,
+            on_resolve: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RuleState {
+    firing: Option<SystemTime>,
+    worst: Option<Worst>,
+    /// Since when the condition that would change the state has held.
+    pending: Option<SystemTime>,
+}
+
+/// Evaluates every rule against every probe result.
+pub struct Engine {
+    rules: Vec<Rule>,
+    samples: HashMap<Arc<str>, VecDeque<(SystemTime, Option<Duration>)>>,
+    states: HashMap<(usize, Arc<str>), RuleState>,
+    keep_samples: usize,
+    keep_for: Duration,
+}
+
+impl Engine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let (keep_samples, keep_for) = rules
+            .iter()
+            .flat_map(|r| [r.fire.extent(), r.clear.extent()])
+            .fold((RECENT, Duration::ZERO), |(n, d), (rn, rd)| {
+                (n.max(rn), d.max(rd))
+            });
+        Engine {
+            rules,
+            samples: HashMap::new(),
+            states: HashMap::new(),
+            keep_samples,
+            keep_for,
+        }
+    }
+
+    pub fn observe(&mut self, status: &ServerStatus) -> Vec<Alert> {
+        let at = status.sampled_at;
+        let samples = self.samples.entry(Arc::clone(&status.name)).or_default();
+        samples.push_back((at, status.latency));
+        // The oldest sample kept reaches back at least `keep_for`, so that
+        // the longest window can tell it is full.
+        while samples.len() > self.keep_samples
+            && let Some(&(second, _)) = samples.get(1)
+            && at.duration_since(second).unwrap_or_default() >= self.keep_for
+        {
+            samples.pop_front();
+        }
+
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.targets.is_empty() && !rule.targets.iter().any(|t| **t == *status.name) {
+                continue;
+            }
+            let state = self
+                .states
+                .entry((index, Arc::clone(&status.name)))
+                .or_default();
+            if let Some(worst) = &mut state.worst
+                && let Some((_, value, display)) = rule.fire.evaluate(samples, status.status)
+            {
+                if rule.fire.worse(value, worst.score) {
+                    worst.score = value;
+                    worst.value = display;
+                }
+                worst.record(status.latency);
+            }
+            let condition = if state.firing.is_some() {
+                &rule.clear
+            } else {
+                &rule.fire
+            };
+            let Some((holds, score, value)) = condition.evaluate(samples, status.status) else {
+                continue;
+            };
+            if !holds {
+                state.pending = None;
+                continue;
+            }
+            let since = *state.pending.get_or_insert(at);
+            if at.duration_since(since).unwrap_or_default() < condition.hold {
+                continue;
+            }
+
+            state.pending = None;
+            let (alert_state, duration, worst) = match state.firing.take() {
+                Some(fired) => (
+                    AlertState::Resolved,
+                    at.duration_since(fired).ok(),
+                    state.worst.take(),
+                ),
+                None => {
+                    state.firing = Some(at);
+                    let mut worst = Worst::new(score, value.clone());
+                    worst.record(status.latency);
+                    state.worst = Some(worst);
+                    (AlertState::Firing, None, None)
+                }
+            };
+            alerts.push(Alert {
+                rule: rule.name.clone(),
+                target: status.name.to_string(),
+                at,
+                state: alert_state,
+                condition: condition.to_string(),
+                value,
+                status: status.status,
+                latency: status.latency,
+                duration,
+                worst,
+                recent: samples
+                    .iter()
+                    .skip(samples.len().saturating_sub(RECENT))
+                    .map(|&(_, l)| l)
+                    .collect(),
+            });
+        }
+        alerts
+    }
+
+    /// Forgets the samples and rule states of a target no longer probed, so
+    /// that it starts afresh if it comes back.
+    pub fn forget(&mut self, target: &str) {
+        self.samples.remove(target);
+        self.states.retain(|(_, name), _| **name != *target);
+    }
+}
+
+/// Sits between the rules and the sinks: drops alerts that re-fire within
+/// the cooldown and groups alerts raised close together into one
+/// notification.
+pub struct Dispatcher {
+    cooldown: Duration,
+    group_wait: Duration,
+    /// When each rule/target pair last fired a notification.
+    last_fired: HashMap<(String, String), SystemTime>,
+    /// Pairs whose firing was suppressed, so their resolve is dropped too.
+    suppressed: HashSet<(String, String)>,
+    pending: Vec<Alert>,
+    pending_since: Option<Instant>,
+}
+
+impl Dispatcher {
+    pub fn new(cooldown: Duration, group_wait: Duration) -> Self {
+        Dispatcher {
+            cooldown,
+            group_wait,
+            last_fired: HashMap::new(),
+            suppressed: HashSet::new(),
+            pending: Vec::new(),
+            pending_since: None,
+        }
+    }
+
+    pub fn push(&mut self, alert: Alert) {
+        let key = (alert.rule.clone(), alert.target.clone());
+        match alert.state {
+            AlertState::Firing => {
+                if let Some(last) = self.last_fired.get(&key)
+                    && alert.at.duration_since(*last).unwrap_or_default() < self.cooldown
+                {
+                    self.suppressed.insert(key);
+                    return;
+                }
+                self.last_fired.insert(key, alert.at);
+            }
+            AlertState::Resolved => {
+                if self.suppressed.remove(&key) {
+                    return;
+                }
+            }
+        }
+        self.pending.push(alert);
+        self.pending_since.get_or_insert_with(Instant::now);
+    }
+
+    /// The alerts to send once the group wait has passed.
+    pub fn take_ready(&mut self) -> Option<Vec<Alert>> {
+        let since = self.pending_since?;
+        if since.elapsed() < self.group_wait {
+            return None;
+        }
+        self.pending_since = None;
+        Some(std::mem::take(&mut self.pending))
+    }
+
+    /// Every pending alert without waiting for the group to fill, for a
+    /// last notification before exiting.
+    pub fn take_all(&mut self) -> Option<Vec<Alert>> {
+        self.pending_since.take()?;
+        Some(std::mem::take(&mut self.pending))
+    }
+}
+
+/// The most recent alerts, newest last, for the history pane.
+pub struct AlertLog {
+    entries: VecDeque<Alert>,
+    capacity: usize,
+}
+
+impl AlertLog {
+    pub fn new(capacity: usize) -> Self {
+        AlertLog {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, alert: Alert) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(alert);
+    }
+
+    /// Entries newest first, only those of `target` when given.
+    pub fn newest<'a>(&'a self, target: Option<&'a str>) -> impl Iterator<Item = &'a Alert> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(move |a| target.is_none_or(|t| a.target == t))
+    }
+}
+
+/// A recurring maintenance window during which alerts are silenced.
+#[derive(Clone, Debug)]
+pub struct Maintenance {
+    pub schedule: Schedule,
+    pub duration: Duration,
+    /// Targets silenced by the window; all when empty.
+    pub targets: Vec<String>,
+}
+
+/// Suppresses alerts of silenced targets: during maintenance windows and
+/// while a target is silenced from the UI. Probing and recording go on.
+pub struct Silencer {
+    windows: Vec<Maintenance>,
+    until: HashMap<String, SystemTime>,
+    /// Rule/target pairs whose firing was suppressed, so their resolve is
+    /// dropped too.
+    suppressed: HashSet<(String, String)>,
+}
+
+impl Silencer {
+    pub fn new(windows: Vec<Maintenance>) -> Self {
+        Silencer {
+            windows,
+            until: HashMap::new(),
+            suppressed: HashSet::new(),
+        }
+    }
+
+    /// Silences `target` until `until`.
+    pub fn silence(&mut self, target: &str, until: SystemTime) {
+        self.until.insert(target.to_string(), until);
+    }
+
+    pub fn unsilence(&mut self, target: &str) {
+        self.until.remove(target);
+    }
+
+    /// When the silence of `target` in effect at `at` ends, if any.
+    pub fn silenced_until(&self, target: &str, at: SystemTime) -> Option<SystemTime> {
+        let manual = self.until.get(target).copied().filter(|until| *until > at);
+        let windows = self
+            .windows
+            .iter()
+            .filter(|w| w.targets.is_empty() || w.targets.iter().any(|t| t == target))
+            .filter_map(|w| Some(w.schedule.last_start(at, w.duration)? + w.duration));
+        manual.into_iter().chain(windows).max()
+    }
+
+    /// Whether `alert` should be passed on.
+    pub fn allows(&mut self, alert: &Alert) -> bool {
+        let key = (alert.rule.clone(), alert.target.clone());
+        match alert.state {
+            AlertState::Firing => {
+                if self.silenced_until(&alert.target, alert.at).is_some() {
+                    self.suppressed.insert(key);
+                    return false;
+                }
+                true
+            }
+            AlertState::Resolved => !self.suppressed.remove(&key),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Streaming probe results from a running daemon to dashboards attached to
+//! it over a Unix socket.
+//!
+//! A client first receives the recent history of every target and then each
+//! new result, in the [`crate::wire`] format.
+
+use crate::rt::channel;
+use crate::{
+    ServerStatus,
+    wire::{self, Decoder},
+};
+use std::{
+    fs,
+    io::{self, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How long a stalled dashboard may block the daemon before it is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Results read from the daemon but not yet taken by the dashboard. Once
+/// full, reading stops and the daemon eventually drops the dashboard.
+const QUEUE: usize = 1024;
+
+/// The daemon's end: accepts dashboards and sends them every result.
+pub struct Server {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<UnixStream>,
+}
+
+impl Server {
+    /// Listens on `path`, replacing a socket left behind by a previous run.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            path: path.to_path_buf(),
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accepts waiting dashboards and sends each the history in `statuses`.
+    pub fn accept(&mut self, statuses: &[ServerStatus]) {
+        while let Ok((mut stream, _)) = self.listener.accept() {
+            let backlog: String = statuses.iter().map(wire::encode_history).collect();
+            let accepted = stream.set_nonblocking(false).is_ok()
+                && stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_ok()
+                && stream.write_all(backlog.as_bytes()).is_ok();
+            if accepted {
+                self.clients.push(stream);
+            }
+        }
+    }
+
+    /// Sends a new result to every dashboard, dropping those that are gone.
+    pub fn publish(&mut self, status: &ServerStatus) {
+        let line = wire::encode_status(status);
+        self.clients
+            .retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Connects to the daemon at `path` and returns its results, keeping
+/// `window` of history per target. The channel closes when the daemon goes
+/// away.
+pub fn subscribe(path: &Path, window: Duration) -> io::Result<channel::Receiver<ServerStatus>> {
+    let stream = UnixStream::connect(path)?;
+    let (sender, receiver) = channel::bounded(QUEUE);
+    thread::spawn(move || {
+        let mut decoder = Decoder::new(window);
+        for line in wire::lines(BufReader::new(stream)) {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(status) = decoder.decode(&line)
+                && sender.send_blocking(status).is_err()
+            {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
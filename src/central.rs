@@ -0,0 +1,124 @@
+//! Central mode: receiving the results of remote agents.
+//!
+//! Every agent connects to its own port, served by an `openssl s_server`
+//! that requires a client certificate signed by the configured CA and
+//! issued for the agent's name, so that one agent cannot pose as another.
+//! Received targets are named `<agent>/<target>` so that alerts and storage
+//! keep them apart from local targets of the same name.
+
+use crate::rt::channel;
+use crate::{
+    ServerStatus,
+    config::{AgentListener, CentralConfig},
+    wire::{self, Decoder},
+};
+use std::{
+    io::{self, BufReader},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Wait before restarting a listener that exited.
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// The listeners for all configured agents.
+pub struct Central {
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Central {
+    /// Starts listening for every agent in `config` and sends their results
+    /// to `sender`, keeping `window` of history per target.
+    pub fn start(
+        config: &CentralConfig,
+        window: Duration,
+        sender: channel::Sender<ServerStatus>,
+    ) -> Self {
+        let last_error = Arc::new(Mutex::new(None));
+        for agent in &config.agents {
+            let config = config.clone();
+            let agent = agent.clone();
+            let sender = sender.clone();
+            let last_error = Arc::clone(&last_error);
+            thread::spawn(move || {
+                loop {
+                    let result = listen(&config, &agent, window, &sender);
+                    if sender.is_closed() {
+                        break;
+                    }
+                    let reason = match result {
+                        Ok(()) => "listener exited".to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    *last_error.lock().unwrap() = Some(format!(
+                        "agent {} (port {}): {}",
+                        agent.name, agent.port, reason
+                    ));
+                    thread::sleep(RESTART_DELAY);
+                }
+            });
+        }
+        Central { last_error }
+    }
+
+    /// The most recent listener failure, cleared once read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+/// Serves one agent until the listener exits.
+fn listen(
+    config: &CentralConfig,
+    agent: &AgentListener,
+    window: Duration,
+    sender: &channel::Sender<ServerStatus>,
+) -> io::Result<()> {
+    let mut child = Command::new(&config.openssl)
+        .args(["s_server", "-quiet", "-Verify", "1", "-verify_return_error"])
+        .args([
+            "-verify_name",
+            "ssl_client",
+            "-verify_hostname",
+            &agent.name,
+        ])
+        .arg("-accept")
+        .arg(agent.port.to_string())
+        .arg("-cert")
+        .arg(&config.cert)
+        .arg("-key")
+        .arg(&config.key)
+        .arg("-CAfile")
+        .arg(&config.ca)
+        // s_server stops when its stdin closes, so keep the pipe open.
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", config.openssl, e)))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut decoder = Decoder::new(window);
+    for line in wire::lines(BufReader::new(stdout)) {
+        let Some(mut status) = decoder.decode(&line?) else {
+            continue;
+        };
+        status.name = format!("{}/{}", agent.name, status.name).into();
+        status.source = Some(agent.name.clone());
+        if sender.send_blocking(status).is_err() {
+            let _ = child.kill();
+            break;
+        }
+    }
+    let exit = child.wait()?;
+    if exit.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} s_server {}",
+            config.openssl, exit
+        )))
+    }
+}
@@ -0,0 +1,204 @@
+//! One-shot health check with Nagios-style exit codes.
+
+use crate::cli::{CheckFormat, CheckOptions};
+use latencee::{
+    ProbeError, Target, default_targets, echo,
+    stats::{self, Summary},
+};
+use std::{thread, time::Duration};
+
+/// Result of a check, ordered from best to worst. The discriminant is the
+/// process exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+}
+
+impl Outcome {
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Ok => "OK",
+            Outcome::Warning => "WARNING",
+            Outcome::Critical => "CRITICAL",
+        }
+    }
+}
+
+/// Resolves a configured target name to its host; anything else is probed
+/// as given.
+fn resolve(target: &str) -> Target {
+    default_targets()
+        .into_iter()
+        .find(|t| t.name.eq_ignore_ascii_case(target))
+        .unwrap_or_else(|| Target::new(target, target))
+}
+
+fn grade(summary: &Summary, options: &CheckOptions) -> Outcome {
+    // Every probe lost leaves no latency to compare, which is critical too.
+    let rtt = summary.avg.unwrap_or(Duration::MAX);
+    let loss = summary.loss() * 100.0;
+    let exceeds = |max_rtt: Option<Duration>, max_loss: Option<f64>| {
+        max_rtt.is_some_and(|max| rtt > max) || max_loss.is_some_and(|max| loss > max)
+    };
+    if exceeds(options.max_rtt, options.max_loss) {
+        Outcome::Critical
+    } else if exceeds(options.warn_rtt, options.warn_loss) {
+        Outcome::Warning
+    } else {
+        Outcome::Ok
+    }
+}
+
+/// What the probes of one target came to.
+struct Checked {
+    target: Target,
+    summary: Summary,
+    outcome: Outcome,
+    error: Option<ProbeError>,
+}
+
+impl Checked {
+    /// `Google DNS: rtt 12ms, loss 0% (10 samples)`
+    fn describe(&self) -> String {
+        format!(
+            "{}: rtt {}, loss {:.0}% ({} samples{})",
+            self.target.name,
+            stats::format_ms(self.summary.avg),
+            self.summary.loss() * 100.0,
+            self.summary.samples,
+            self.error
+                .map_or(String::new(), |e| format!(", last error: {}", e)),
+        )
+    }
+
+    /// Performance data for round-trip time and loss, with labels prefixed
+    /// by `prefix`.
+    fn perfdata(&self, prefix: &str, options: &CheckOptions) -> String {
+        format!(
+            "{}={};{};{};0 {}={:.0}%;{};{};0;100",
+            label(prefix, "rtt"),
+            // "U" marks an unknown value in performance data.
+            self.summary
+                .avg
+                .map_or("U".to_string(), |avg| format!("{}ms", stats::millis(avg))),
+            threshold_ms(options.warn_rtt),
+            threshold_ms(options.max_rtt),
+            label(prefix, "loss"),
+            self.summary.loss() * 100.0,
+            threshold(options.warn_loss),
+            threshold(options.max_loss),
+        )
+    }
+}
+
+/// A performance data label, quoted when it has to be.
+fn label(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("'{} {}'", prefix.replace('\'', "''"), name)
+    }
+}
+
+/// Probes every target `options.samples` times, prints the results in the
+/// requested format and returns the worst outcome.
+pub fn run(options: &CheckOptions) -> Outcome {
+    let targets: Vec<_> = options.targets.iter().map(|t| resolve(t)).collect();
+    let mut latencies = vec![Vec::with_capacity(options.samples); targets.len()];
+    let mut errors = vec![None; targets.len()];
+
+    for i in 0..options.samples {
+        if i > 0 {
+            thread::sleep(options.interval);
+        }
+        for ((target, samples), error) in targets.iter().zip(&mut latencies).zip(&mut errors) {
+            let result = echo(&target.host);
+            if let Err(e) = result {
+                *error = Some(e);
+            }
+            samples.push(result.ok());
+        }
+    }
+
+    let checked: Vec<Checked> = targets
+        .into_iter()
+        .zip(latencies)
+        .zip(errors)
+        .map(|((target, samples), error)| {
+            let summary = stats::summarize(samples);
+            Checked {
+                outcome: grade(&summary, options),
+                target,
+                summary,
+                error,
+            }
+        })
+        .collect();
+    let worst = checked
+        .iter()
+        .map(|c| c.outcome)
+        .max()
+        .unwrap_or(Outcome::Ok);
+    match options.format {
+        CheckFormat::Text => {
+            for c in &checked {
+                println!(
+                    "{} - {}|{}",
+                    c.outcome.label(),
+                    c.describe(),
+                    c.perfdata("", options)
+                );
+            }
+        }
+        CheckFormat::Nagios => print!("{}", nagios(&checked, worst, options)),
+    }
+    worst
+}
+
+/// The plugin output: a status line naming the targets that decided the
+/// outcome, with the performance data of all targets, then one line per
+/// target.
+fn nagios(checked: &[Checked], worst: Outcome, options: &CheckOptions) -> String {
+    let summary = match checked {
+        [single] => single.describe(),
+        _ if worst == Outcome::Ok => format!("{} targets ok", checked.len()),
+        _ => {
+            let decisive: Vec<String> = checked
+                .iter()
+                .filter(|c| c.outcome == worst)
+                .map(Checked::describe)
+                .collect();
+            format!(
+                "{} of {} targets {}: {}",
+                decisive.len(),
+                checked.len(),
+                worst.label().to_lowercase(),
+                decisive.join("; ")
+            )
+        }
+    };
+    let perfdata: Vec<String> = match checked {
+        [single] => vec![single.perfdata("", options)],
+        _ => checked
+            .iter()
+            .map(|c| c.perfdata(&c.target.name, options))
+            .collect(),
+    };
+    let mut out = format!("{} - {}|{}\n", worst.label(), summary, perfdata.join(" "));
+    if checked.len() > 1 {
+        for c in checked {
+            out.push_str(&format!("{} - {}\n", c.outcome.label(), c.describe()));
+        }
+    }
+    out
+}
+
+fn threshold_ms(value: Option<Duration>) -> String {
+    threshold(value.map(stats::millis))
+}
+
+fn threshold(value: Option<f64>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
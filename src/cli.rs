@@ -0,0 +1,404 @@
+//! Command line parsing.
+
+use latencee::{
+    config::parse_duration,
+    presets::{self, Preset},
+};
+use std::{fmt, path::PathBuf, time::Duration};
+
+pub const USAGE: &str = "\
+Usage: latencee [OPTIONS]
+       latencee export --parquet <FILE> [--step <DURATION>] [OPTIONS]
+       latencee check --target <NAME|HOST>... [CHECK OPTIONS]
+       latencee daemon start|stop|status|run [OPTIONS]
+       latencee attach
+       latencee agent --config <PATH>
+       latencee service install|uninstall [OPTIONS]
+       latencee replay <FILE> [--speed <N>x]
+       latencee setup-caps [--sysctl | --setcap]
+
+Commands:
+  export                Write persisted history to a file
+  check                 Probe targets once and exit 0 (ok), 1 (warning) or 2 (critical)
+  daemon start          Monitor in the background
+  daemon stop           Stop the background monitor
+  daemon status         Show whether the background monitor runs (exit 3 if not)
+  daemon run            Monitor in the foreground as the daemon would
+  attach                Show the dashboard of the background monitor
+  agent                 Probe headless and stream results to a central instance
+  service install       Monitor headless from boot on Windows (as administrator)
+  service uninstall     Remove the Windows boot-time monitor
+  replay                Play back a session recorded with --record
+  setup-caps            Show how targets are pinged and what would let
+                        latencee ping without root (Linux)
+
+Setup options (as root):
+  --sysctl              Allow unprivileged ICMP sockets for every group
+  --setcap              Grant this binary CAP_NET_RAW instead
+
+Replay options:
+  --speed <N>x          Playback speed (default: 1x)
+
+Export options:
+  --parquet <FILE>      Write a Parquet file with one row per stored step
+  --step <DURATION>     Archive resolution to export (default: finest)
+
+Check options:
+  --target <NAME|HOST>  Target to probe; repeat for several
+  --samples <N>         Probes per target (default: 10)
+  --interval <DURATION> Time between probes (default: 1s)
+  --max-rtt <DURATION>  Critical above this average round-trip time
+  --max-loss <PERCENT>  Critical above this packet loss
+  --warn-rtt <DURATION> Warning above this average round-trip time
+  --warn-loss <PERCENT> Warning above this packet loss
+  --format <FORMAT>     text: a line per target (default); nagios: the
+                        plugin format, one status line, perfdata of all
+                        targets, exit 3 (unknown) on invalid arguments
+
+Options:
+  -c, --config <PATH>   Load configuration from PATH
+      --headless        Monitor and feed the outputs without a terminal UI
+      --record <FILE>   Append every probe result to FILE for replay
+      --preset <NAME>   Monitor a built-in group of targets instead of the
+                        defaults; repeat for several. aws-regions,
+                        azure-regions, cloudflare, gcp-regions,
+                        valorant-eu, valve or discord
+      --netdata-plugin [SECONDS]
+                        Run headless as a Netdata external plugin, writing
+                        charts to stdout every SECONDS (default: 1)
+  -h, --help            Print this help
+  -V, --version         Print version";
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub config: Option<PathBuf>,
+    pub headless: bool,
+    pub record: Option<PathBuf>,
+    /// Built-in target groups monitored instead of the default targets.
+    pub presets: Vec<&'static Preset>,
+    /// How often charts are updated when running as a Netdata plugin.
+    pub netdata_plugin: Option<Duration>,
+    pub command: Command,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Monitor,
+    Export(ExportOptions),
+    Check(CheckOptions),
+    Daemon(DaemonAction),
+    Attach,
+    Agent,
+    Service(ServiceAction),
+    Replay(ReplayOptions),
+    SetupCaps(SetupOptions),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ExportOptions {
+    pub parquet: PathBuf,
+    pub step: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DaemonAction {
+    Start,
+    Stop,
+    Status,
+    Run,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SetupOptions {
+    /// Opens `net.ipv4.ping_group_range` to every group.
+    pub sysctl: bool,
+    /// Raw sockets for this binary rather than datagram sockets for all.
+    pub setcap: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayOptions {
+    pub session: PathBuf,
+    /// Recorded seconds played back per second.
+    pub speed: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ServiceAction {
+    Install,
+    Uninstall,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CheckOptions {
+    pub targets: Vec<String>,
+    pub samples: usize,
+    pub interval: Duration,
+    pub max_rtt: Option<Duration>,
+    pub max_loss: Option<f64>,
+    pub warn_rtt: Option<Duration>,
+    pub warn_loss: Option<f64>,
+    pub format: CheckFormat,
+}
+
+/// How `check` reports its results.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CheckFormat {
+    /// One Nagios-style line per target.
+    #[default]
+    Text,
+    /// The Nagios plugin output format: a status line with the performance
+    /// data of every target, followed by a line per target.
+    Nagios,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            targets: Vec::new(),
+            samples: 10,
+            interval: Duration::from_secs(1),
+            max_rtt: None,
+            max_loss: None,
+            warn_rtt: None,
+            warn_loss: None,
+            format: CheckFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.0, USAGE)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, UsageError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+
+    if let Some(name) = args.next_if(|a| !a.starts_with('-')) {
+        options.command = match name.as_str() {
+            "export" => Command::Export(ExportOptions::default()),
+            "check" => Command::Check(CheckOptions::default()),
+            "daemon" => Command::Daemon(match args.next().as_deref() {
+                Some("start") => DaemonAction::Start,
+                Some("stop") => DaemonAction::Stop,
+                Some("status") => DaemonAction::Status,
+                Some("run") => DaemonAction::Run,
+                _ => {
+                    return Err(UsageError(
+                        "daemon requires start, stop, status or run".to_string(),
+                    ));
+                }
+            }),
+            "attach" => Command::Attach,
+            "agent" => Command::Agent,
+            "service" => Command::Service(match args.next().as_deref() {
+                Some("install") => ServiceAction::Install,
+                Some("uninstall") => ServiceAction::Uninstall,
+                _ => {
+                    return Err(UsageError(
+                        "service requires install or uninstall".to_string(),
+                    ));
+                }
+            }),
+            "replay" => match args.next_if(|a| !a.starts_with('-')) {
+                Some(session) => Command::Replay(ReplayOptions {
+                    session: PathBuf::from(session),
+                    speed: 1.0,
+                }),
+                None => return Err(UsageError("replay requires a session file".to_string())),
+            },
+            "setup-caps" => Command::SetupCaps(SetupOptions::default()),
+            other => return Err(UsageError(format!("unknown command '{}'", other))),
+        };
+    }
+
+    let mut help = false;
+    let mut version = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--config" => options.config = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--headless" => options.headless = true,
+            "--record" => options.record = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--preset" => {
+                let name = value(&arg, &mut args)?;
+                let preset = presets::find(&name).ok_or_else(|| {
+                    let names: Vec<String> = presets::PRESETS
+                        .iter()
+                        .map(|p| format!("\n  {:<16}{}", p.name, p.description))
+                        .collect();
+                    UsageError(format!(
+                        "{}: unknown preset '{}', expected one of:{}",
+                        arg,
+                        name,
+                        names.concat()
+                    ))
+                })?;
+                options.presets.push(preset);
+            }
+            "--netdata-plugin" => {
+                // Netdata passes the update frequency in seconds.
+                let every = args.next_if(|a| !a.starts_with('-'));
+                let seconds = match every {
+                    Some(raw) => raw.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                        UsageError(format!("{}: invalid update frequency '{}'", arg, raw))
+                    })?,
+                    None => 1,
+                };
+                options.netdata_plugin = Some(Duration::from_secs(seconds));
+            }
+            "-h" | "--help" => help = true,
+            "-V" | "--version" => version = true,
+            _ => {
+                let known = match &mut options.command {
+                    Command::Export(export) => export.parse_flag(&arg, &mut args)?,
+                    Command::Check(check) => check.parse_flag(&arg, &mut args)?,
+                    Command::Replay(replay) => replay.parse_flag(&arg, &mut args)?,
+                    Command::SetupCaps(setup) if arg == "--sysctl" => {
+                        setup.sysctl = true;
+                        true
+                    }
+                    Command::SetupCaps(setup) if arg == "--setcap" => {
+                        setup.setcap = true;
+                        true
+                    }
+                    _ => false,
+                };
+                if !known {
+                    return Err(UsageError(format!("unexpected argument '{}'", arg)));
+                }
+            }
+        }
+    }
+
+    if help {
+        options.command = Command::Help;
+    } else if version {
+        options.command = Command::Version;
+    } else if let Command::Export(export) = &options.command
+        && export.parquet.as_os_str().is_empty()
+    {
+        return Err(UsageError("export requires --parquet <FILE>".to_string()));
+    } else if let Command::Check(check) = &options.command
+        && check.targets.is_empty()
+    {
+        return Err(UsageError(
+            "check requires --target <NAME|HOST>".to_string(),
+        ));
+    } else if let Command::SetupCaps(setup) = &options.command
+        && setup.sysctl
+        && setup.setcap
+    {
+        return Err(UsageError(
+            "setup-caps takes either --sysctl or --setcap".to_string(),
+        ));
+    }
+
+    Ok(options)
+}
+
+impl ExportOptions {
+    fn parse_flag(
+        &mut self,
+        flag: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Result<bool, UsageError> {
+        match flag {
+            "--parquet" => self.parquet = PathBuf::from(value(flag, args)?),
+            "--step" => self.step = Some(duration(flag, args)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl ReplayOptions {
+    fn parse_flag(
+        &mut self,
+        flag: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Result<bool, UsageError> {
+        match flag {
+            "--speed" => {
+                let raw = value(flag, args)?;
+                self.speed = raw
+                    .trim_end_matches('x')
+                    .parse()
+                    .ok()
+                    .filter(|speed: &f64| *speed > 0.0)
+                    .ok_or_else(|| UsageError(format!("{}: invalid speed '{}'", flag, raw)))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl CheckOptions {
+    fn parse_flag(
+        &mut self,
+        flag: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Result<bool, UsageError> {
+        match flag {
+            "--target" => self.targets.push(value(flag, args)?),
+            "--samples" => {
+                let raw = value(flag, args)?;
+                self.samples = raw
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| UsageError(format!("{}: invalid count '{}'", flag, raw)))?;
+            }
+            "--interval" => self.interval = duration(flag, args)?,
+            "--max-rtt" => self.max_rtt = Some(duration(flag, args)?),
+            "--max-loss" => self.max_loss = Some(percent(flag, args)?),
+            "--warn-rtt" => self.warn_rtt = Some(duration(flag, args)?),
+            "--warn-loss" => self.warn_loss = Some(percent(flag, args)?),
+            "--format" => {
+                self.format = match value(flag, args)?.as_str() {
+                    "text" => CheckFormat::Text,
+                    "nagios" | "icinga" => CheckFormat::Nagios,
+                    other => {
+                        return Err(UsageError(format!(
+                            "{}: unknown format '{}', expected text or nagios",
+                            flag, other
+                        )));
+                    }
+                };
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, UsageError> {
+    args.next()
+        .ok_or_else(|| UsageError(format!("{} requires a value", flag)))
+}
+
+fn duration(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<Duration, UsageError> {
+    let raw = value(flag, args)?;
+    parse_duration(&raw).ok_or_else(|| UsageError(format!("{}: invalid duration '{}'", flag, raw)))
+}
+
+/// Parses `5%` or `5` as a percentage.
+fn percent(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<f64, UsageError> {
+    let raw = value(flag, args)?;
+    raw.trim_end_matches('%')
+        .parse()
+        .ok()
+        .filter(|p: &f64| (0.0..=100.0).contains(p))
+        .ok_or_else(|| UsageError(format!("{}: invalid percentage '{}'", flag, raw)))
+}
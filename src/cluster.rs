@@ -0,0 +1,261 @@
+//! Cluster mode: showing the targets of other instances next to our own.
+//!
+//! Every instance with an `[http]` section streams the results it measures
+//! itself at `/samples`. A cluster member follows that stream of each peer,
+//! so one dashboard shows how the same target looks from the office and
+//! from home. Targets of a peer are named `<peer>/<target>`, like those of
+//! agents.
+//!
+//! Peers are listed in the configuration or found with mDNS, which relies
+//! on Avahi: members announce themselves with `avahi-publish` and browse for
+//! each other with `avahi-browse`.
+
+use crate::{
+    ServerStatus,
+    config::{ClusterConfig, HttpConfig, Peer},
+    rt::channel,
+    wire::{self, Decoder},
+};
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// The mDNS service type members announce.
+const SERVICE_TYPE: &str = "_latencee._tcp";
+
+/// Wait before reconnecting to a peer that went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long a peer may stay silent before it is considered gone; peers send
+/// a sample per target every few seconds.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often mDNS is browsed for new peers.
+const BROWSE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct Cluster {
+    last_error: Arc<Mutex<Option<String>>>,
+    /// The `avahi-publish` process announcing this instance.
+    announcement: Option<Child>,
+}
+
+impl Cluster {
+    /// Follows every peer in `config`, and those found over mDNS, sending
+    /// their results to `sender` with `window` of history per target.
+    pub fn start(
+        config: &ClusterConfig,
+        http: Option<&HttpConfig>,
+        window: Duration,
+        sender: channel::Sender<ServerStatus>,
+    ) -> Self {
+        let last_error = Arc::new(Mutex::new(None));
+        for peer in &config.peers {
+            follow(
+                peer.clone(),
+                window,
+                sender.clone(),
+                Arc::clone(&last_error),
+            );
+        }
+
+        let mut announcement = None;
+        if config.mdns {
+            match http.map(|http| announce(&config.name, http)) {
+                Some(Ok(child)) => announcement = Some(child),
+                Some(Err(e)) => {
+                    *last_error.lock().unwrap() = Some(format!("cluster: announcing: {}", e));
+                }
+                None => {
+                    *last_error.lock().unwrap() = Some(
+                        "cluster: not announced over mDNS, peers need [http] to follow us"
+                            .to_string(),
+                    );
+                }
+            }
+            let own_name = config.name.clone();
+            let mut known: HashSet<String> = config.peers.iter().map(|p| p.name.clone()).collect();
+            let last_error = Arc::clone(&last_error);
+            thread::spawn(move || {
+                while !sender.is_closed() {
+                    match browse() {
+                        Ok(peers) => {
+                            for peer in peers {
+                                if peer.name != own_name && known.insert(peer.name.clone()) {
+                                    follow(peer, window, sender.clone(), Arc::clone(&last_error));
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            *last_error.lock().unwrap() = Some(format!("cluster: mDNS: {}", e));
+                        }
+                    }
+                    thread::sleep(BROWSE_INTERVAL);
+                }
+            });
+        }
+        Cluster {
+            last_error,
+            announcement,
+        }
+    }
+
+    /// The most recent peer or mDNS failure, cleared once read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.announcement {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Follows `peer` on a background thread, reconnecting whenever it goes
+/// away, until the receiver is gone.
+fn follow(
+    peer: Peer,
+    window: Duration,
+    sender: channel::Sender<ServerStatus>,
+    last_error: Arc<Mutex<Option<String>>>,
+) {
+    thread::spawn(move || {
+        loop {
+            let result = receive(&peer, window, &sender);
+            if sender.is_closed() {
+                break;
+            }
+            let reason = match result {
+                Ok(()) => "connection closed".to_string(),
+                Err(e) => e.to_string(),
+            };
+            *last_error.lock().unwrap() =
+                Some(format!("peer {} ({}): {}", peer.name, peer.address, reason));
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+}
+
+/// Reads the `/samples` stream of `peer` until it ends.
+fn receive(
+    peer: &Peer,
+    window: Duration,
+    sender: &channel::Sender<ServerStatus>,
+) -> io::Result<()> {
+    let address = peer
+        .address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+    let mut stream = TcpStream::connect_timeout(&address, READ_TIMEOUT)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    write!(
+        stream,
+        "GET /samples HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        peer.address
+    )?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::other(format!(
+            "unexpected answer {:?}",
+            line.trim_end()
+        )));
+    }
+    while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+        line.clear();
+    }
+
+    let mut decoder = Decoder::new(window);
+    for line in wire::lines(reader) {
+        let Some(mut status) = decoder.decode(&line?) else {
+            continue;
+        };
+        status.name = format!("{}/{}", peer.name, status.name).into();
+        status.source = Some(peer.name.clone());
+        if sender.send_blocking(status).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Announces this instance's HTTP API as `name`.
+fn announce(name: &str, http: &HttpConfig) -> io::Result<Child> {
+    let port = http
+        .listen
+        .rsplit_once(':')
+        .map_or("", |(_, port)| port)
+        .to_string();
+    Command::new("avahi-publish")
+        .args(["-s", name, SERVICE_TYPE, &port])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run avahi-publish: {}", e)))
+}
+
+/// The members currently announced on the local network.
+fn browse() -> io::Result<Vec<Peer>> {
+    let output = Command::new("avahi-browse")
+        .args(["--parsable", "--resolve", "--terminate", SERVICE_TYPE])
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run avahi-browse: {}", e)))?;
+    // Resolved entries look like
+    // =;eth0;IPv4;office;_latencee._tcp;local;office.local;192.168.1.5;8080;
+    let mut peers: Vec<Peer> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.split(';').collect();
+        if fields.len() < 9 || fields[0] != "=" {
+            continue;
+        }
+        let address = if fields[2] == "IPv6" {
+            format!("[{}]:{}", fields[7], fields[8])
+        } else {
+            format!("{}:{}", fields[7], fields[8])
+        };
+        let name = unescape(fields[3]);
+        // Announced on several interfaces or address families: keep one,
+        // preferring IPv4 as link-local IPv6 addresses need a scope.
+        match peers.iter_mut().find(|p| p.name == name) {
+            Some(known) if known.address.starts_with('[') => known.address = address,
+            Some(_) => {}
+            None => peers.push(Peer { name, address }),
+        }
+    }
+    Ok(peers)
+}
+
+/// Decodes the `\DDD` escapes avahi-browse writes for special characters in
+/// names, e.g. `\032` for a space.
+fn unescape(name: &str) -> String {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let digits = tail.get(..3).and_then(|d| std::str::from_utf8(d).ok());
+        match digits.and_then(|d| d.parse::<u8>().ok()) {
+            Some(code) if b == b'\\' => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
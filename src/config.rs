@@ -0,0 +1,3122 @@
+//! Configuration file loading.
+//!
+//! The configuration format is a small subset of TOML: tables, arrays of
+//! tables, strings, integers, floats, booleans, arrays and inline tables.
+//! That covers everything latencee needs without pulling in a parser crate.
+
+use crate::{
+    ConnectionStatus, Target,
+    alert::{Condition, Maintenance, Rule},
+    discovery::{
+        Consul, Discord, Discovery, Docker, DockerAddresses, Kubernetes, KubernetesAddresses,
+        Source, Srv, Steam, Tailscale, WireGuard,
+    },
+    host,
+    keys::{self, ACTIONS, Action, Keymap},
+    network::{Network, NetworkRoute},
+    ping::Jump,
+    pipeline::Stage,
+    proxy::{Proxy, ProxyRoute, ProxyRules},
+    router::{Router, RouterApi, RouterOs, Snmp},
+    simulate::Pattern,
+};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    path::PathBuf,
+    time::Duration,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        ConfigError {
+            line,
+            message: message.into(),
+        }
+    }
+
+    /// An error that is not tied to a particular line of the file.
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ConfigError::new(0, message)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}: {}", self.line, self.message)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Parses a TOML document into its root table.
+pub fn parse(input: &str) -> Result<Table, ConfigError> {
+    Parser::new(input).parse_document()
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser {
+            chars: input.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ConfigError {
+        ConfigError::new(self.line, message)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ConfigError> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    /// Skips spaces and tabs on the current line.
+    fn skip_inline_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.next();
+        }
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') | Some('\n') => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn expect_line_end(&mut self) -> Result<(), ConfigError> {
+        self.skip_inline_whitespace();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{}' after value", c))),
+        }
+    }
+
+    fn parse_document(mut self) -> Result<Table, ConfigError> {
+        let mut root = Table::new();
+        // Path of the table currently receiving key/value pairs.
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    self.next();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.next();
+                    }
+                    self.skip_inline_whitespace();
+                    let path = self.parse_key_path()?;
+                    self.skip_inline_whitespace();
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    let line = self.line;
+                    self.expect_line_end()?;
+
+                    if array {
+                        let (last, parents) = path.split_last().expect("key path is never empty");
+                        let parent = table_at(&mut root, parents, line)?;
+                        let entry = parent
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(Vec::new()));
+                        match entry {
+                            Value::Array(items) => items.push(Value::Table(Table::new())),
+                            _ => {
+                                return Err(ConfigError::new(
+                                    line,
+                                    format!("'{}' is not an array of tables", last),
+                                ));
+                            }
+                        }
+                    } else {
+                        table_at(&mut root, &path, line)?;
+                    }
+                    current = path;
+                }
+                Some(_) => {
+                    let path = self.parse_key_path()?;
+                    self.skip_inline_whitespace();
+                    self.expect('=')?;
+                    self.skip_inline_whitespace();
+                    let value = self.parse_value()?;
+                    let line = self.line;
+                    self.expect_line_end()?;
+
+                    let table = table_at(&mut root, &current, line)?;
+                    insert(table, &path, value, line)?;
+                }
+            }
+        }
+
+        Ok(root)
+    }
+
+    fn parse_key_path(&mut self) -> Result<Vec<String>, ConfigError> {
+        let mut path = vec![self.parse_key()?];
+        loop {
+            self.skip_inline_whitespace();
+            if self.peek() != Some('.') {
+                break;
+            }
+            self.next();
+            self.skip_inline_whitespace();
+            path.push(self.parse_key()?);
+        }
+        Ok(path)
+    }
+
+    fn parse_key(&mut self) -> Result<String, ConfigError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        key.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                if key.is_empty() {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ConfigError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some('t') | Some('f') => {
+                let word = self.take_while(|c| c.is_ascii_alphabetic());
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(self.error(format!("unknown value '{}'", word))),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => self.parse_number(),
+            Some(c) => Err(self.error(format!("unexpected '{}' at start of value", c))),
+            None => Err(self.error("expected a value, found end of file")),
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            if !predicate(c) {
+                break;
+            }
+            out.push(c);
+            self.next();
+        }
+        out
+    }
+
+    fn parse_number(&mut self) -> Result<Value, ConfigError> {
+        let raw = self.take_while(|c| {
+            c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' || c == '+'
+        });
+        let cleaned: String = raw.chars().filter(|c| *c != '_').collect();
+        if let Ok(i) = cleaned.parse::<i64>() {
+            return Ok(Value::Integer(i));
+        }
+        cleaned
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| self.error(format!("invalid number '{}'", raw)))
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            // A line end is left unread so that the error names the
+            // string's line.
+            let Some(c) = self.peek().filter(|&c| c != '\n') else {
+                return Err(self.error("unterminated string"));
+            };
+            self.next();
+            match c {
+                '"' => return Ok(out),
+                '\\' => match self.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error(format!("invalid escape '\\u{}'", hex)))?;
+                        out.push(c);
+                    }
+                    Some(c) => return Err(self.error(format!("invalid escape '\\{}'", c))),
+                    None => return Err(self.error("unterminated string")),
+                },
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('\'')?;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek().filter(|&c| c != '\n') else {
+                return Err(self.error("unterminated string"));
+            };
+            self.next();
+            match c {
+                '\'' => return Ok(out),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ConfigError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_trivia();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, ConfigError> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_inline_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_inline_whitespace();
+            let path = self.parse_key_path()?;
+            self.skip_inline_whitespace();
+            self.expect('=')?;
+            self.skip_inline_whitespace();
+            let value = self.parse_value()?;
+            let line = self.line;
+            insert(&mut table, &path, value, line)?;
+            self.skip_inline_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Table(table)),
+                _ => return Err(self.error("expected ',' or '}' in inline table")),
+            }
+        }
+    }
+}
+
+/// Walks (and creates) nested tables along `path`. When a path segment is an
+/// array of tables, the most recently added table is used.
+fn table_at<'t>(
+    root: &'t mut Table,
+    path: &[String],
+    line: usize,
+) -> Result<&'t mut Table, ConfigError> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(t) => t,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => {
+                    return Err(ConfigError::new(line, format!("'{}' is not a table", key)));
+                }
+            },
+            _ => {
+                return Err(ConfigError::new(line, format!("'{}' is not a table", key)));
+            }
+        };
+    }
+    Ok(table)
+}
+
+fn insert(
+    table: &mut Table,
+    path: &[String],
+    value: Value,
+    line: usize,
+) -> Result<(), ConfigError> {
+    let (last, parents) = path.split_last().expect("key path is never empty");
+    let table = table_at(table, parents, line)?;
+    if table.contains_key(last) {
+        return Err(ConfigError::new(line, format!("duplicate key '{}'", last)));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Parses durations such as `250ms`, `10s`, `5m`, `1h` or `7d`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        "d" => number * 86400.0,
+        _ => return None,
+    };
+    (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+/// Parses sizes such as `512KB`, `64MB` or `1GB`, in multiples of 1024
+/// bytes; a plain number is bytes.
+pub fn parse_size(input: &str) -> Option<usize> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: usize = number.parse().ok()?;
+    let shift = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
+
+/// Typed accessors used when turning the parsed document into [`Config`].
+struct Section<'a> {
+    name: String,
+    table: &'a Table,
+}
+
+impl<'a> Section<'a> {
+    fn root(table: &'a Table) -> Self {
+        Section {
+            name: String::new(),
+            table,
+        }
+    }
+
+    fn child(&self, key: &str, table: &'a Table) -> Self {
+        let name = if self.name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.name, key)
+        };
+        Section { name, table }
+    }
+
+    fn error(&self, key: &str, message: impl fmt::Display) -> ConfigError {
+        if self.name.is_empty() {
+            ConfigError::invalid(format!("{}: {}", key, message))
+        } else {
+            ConfigError::invalid(format!("{}.{}: {}", self.name, key, message))
+        }
+    }
+
+    /// Errors if the section contains keys other than `known`, catching
+    /// typos that would otherwise be silently ignored.
+    fn deny_unknown(&self, known: &[&str]) -> Result<(), ConfigError> {
+        match self.table.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(self.error(key, "unknown key")),
+            None => Ok(()),
+        }
+    }
+
+    fn wrong_type(&self, key: &str, expected: &str, found: &Value) -> ConfigError {
+        self.error(
+            key,
+            format!("expected {}, found {}", expected, found.type_name()),
+        )
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(other) => Err(self.wrong_type(key, "string", other)),
+        }
+    }
+
+    fn required_string(&self, key: &str) -> Result<String, ConfigError> {
+        self.string(key)?
+            .ok_or_else(|| self.error(key, "is required"))
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Boolean(b)) => Ok(Some(*b)),
+            Some(other) => Err(self.wrong_type(key, "boolean", other)),
+        }
+    }
+
+    fn integer(&self, key: &str) -> Result<Option<i64>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(i)) => Ok(Some(*i)),
+            Some(other) => Err(self.wrong_type(key, "integer", other)),
+        }
+    }
+
+    fn duration(&self, key: &str) -> Result<Option<Duration>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(secs)) if *secs >= 0 => Ok(Some(Duration::from_secs(*secs as u64))),
+            Some(Value::String(s)) => parse_duration(s)
+                .map(Some)
+                .ok_or_else(|| self.error(key, format!("invalid duration '{}'", s))),
+            Some(other) => Err(self.wrong_type(key, "duration", other)),
+        }
+    }
+
+    /// A single string or an array of strings.
+    fn strings(&self, key: &str) -> Result<Vec<String>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(Vec::new()),
+            Some(Value::String(s)) => Ok(vec![s.clone()]),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s.clone()),
+                    other => Err(self.wrong_type(key, "string", other)),
+                })
+                .collect(),
+            Some(other) => Err(self.wrong_type(key, "string or array", other)),
+        }
+    }
+
+    fn table(&self, key: &str) -> Result<Option<Section<'a>>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(None),
+            Some(Value::Table(table)) => Ok(Some(self.child(key, table))),
+            Some(other) => Err(self.wrong_type(key, "table", other)),
+        }
+    }
+
+    fn tables(&self, key: &str) -> Result<Vec<Section<'a>>, ConfigError> {
+        match self.table.get(key) {
+            None => Ok(Vec::new()),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Table(table) => Ok(self.child(key, table)),
+                    other => Err(self.wrong_type(key, "table", other)),
+                })
+                .collect(),
+            Some(other) => Err(self.wrong_type(key, "array of tables", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The `[[target]]`s, monitored instead of the default targets.
+    pub targets: Vec<Target>,
+    pub storage: Option<StorageConfig>,
+    pub report: Option<ReportConfig>,
+    pub syslog: Option<SyslogConfig>,
+    pub journald: Option<JournaldConfig>,
+    pub mqtt: Option<MqttConfig>,
+    pub kafka: Option<KafkaConfig>,
+    pub socket: Option<SocketConfig>,
+    pub pushgateway: Option<PushgatewayConfig>,
+    pub zabbix: Option<ZabbixConfig>,
+    pub healthchecks: Option<HealthchecksConfig>,
+    pub uptime_kuma: Option<UptimeKumaConfig>,
+    pub speedtest: Option<SpeedtestConfig>,
+    pub alerts: AlertsConfig,
+    pub rules: Vec<Rule>,
+    pub maintenance: Vec<Maintenance>,
+    pub desktop: Option<DesktopConfig>,
+    pub sound: Option<SoundConfig>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub emails: Vec<EmailConfig>,
+    pub pagerduty: Option<PagerDutyConfig>,
+    pub opsgenie: Option<OpsgenieConfig>,
+    pub telegram: Option<TelegramConfig>,
+    pub agent: Option<AgentConfig>,
+    pub central: Option<CentralConfig>,
+    pub cluster: Option<ClusterConfig>,
+    pub http: Option<HttpConfig>,
+    pub grpc: Option<GrpcConfig>,
+    pub script: Option<ScriptConfig>,
+    /// Where incident snapshot bundles are written (default: current directory).
+    pub snapshot_dir: Option<PathBuf>,
+    /// Memory the histories of all targets may take, in bytes; `None` for
+    /// [`crate::history::DEFAULT_MEMORY`].
+    pub history_memory: Option<usize>,
+    /// Kind of probe measuring the targets, see [`crate::probe::Registry`];
+    /// `None` for ICMP.
+    pub probe: Option<String>,
+    /// Latency patterns of the `simulate` probe, by target name.
+    pub simulations: Vec<(String, Pattern)>,
+    /// Port of the `tcp`, game and mail probes; the `tcp` probe's is also
+    /// used when ping is unusable.
+    pub tcp_port: Option<u16>,
+    /// Proxies the `tcp` probe connects through.
+    pub proxies: ProxyRules,
+    /// Network namespaces and VRFs targets are probed in.
+    pub networks: Vec<NetworkRoute>,
+    /// Machines targets are also pinged from, through ssh.
+    pub jumps: Vec<Jump>,
+    /// Routers and switches targets are also pinged from, through their
+    /// management APIs.
+    pub routers: Vec<Router>,
+    /// How often host names are looked up again; `None` for
+    /// [`crate::resolve::DEFAULT_REFRESH`].
+    pub dns_refresh: Option<Duration>,
+    /// Module of the `wasm` probe.
+    pub wasm_probe: Option<WasmConfig>,
+    /// WebAssembly modules receiving events.
+    pub wasm_sinks: Vec<WasmConfig>,
+    /// Processors applied to every result, in order.
+    pub processors: Vec<Stage>,
+    /// Where targets are discovered at runtime.
+    pub discovery: Vec<Discovery>,
+    /// The keys of the dashboard.
+    pub keys: Keymap,
+    /// Whether quitting the dashboard asks first.
+    pub confirm_quit: bool,
+    /// The view the dashboard starts in: `graph`, `table` or `summary`.
+    pub view: Option<String>,
+}
+
+/// Fixed-size round-robin storage, see [`crate::rrd`].
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub path: PathBuf,
+    pub archives: Vec<ArchiveSpec>,
+}
+
+/// One consolidation level: `rows` slots of `step` width each.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArchiveSpec {
+    pub step: Duration,
+    pub rows: u32,
+}
+
+impl StorageConfig {
+    /// One day at 10s resolution, one week at 5m and one year at 1h.
+    pub fn default_archives() -> Vec<ArchiveSpec> {
+        vec![
+            ArchiveSpec {
+                step: Duration::from_secs(10),
+                rows: 8640,
+            },
+            ArchiveSpec {
+                step: Duration::from_secs(300),
+                rows: 2016,
+            },
+            ArchiveSpec {
+                step: Duration::from_secs(3600),
+                rows: 8760,
+            },
+        ]
+    }
+
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["path", "archive"])?;
+        let path = section.required_string("path")?;
+        let mut archives = Vec::new();
+        for archive in section.tables("archive")? {
+            archive.deny_unknown(&["step", "rows"])?;
+            let step = archive
+                .duration("step")?
+                .ok_or_else(|| archive.error("step", "is required"))?;
+            let rows = archive
+                .integer("rows")?
+                .ok_or_else(|| archive.error("rows", "is required"))?;
+            // Files keep the step in whole seconds.
+            if step.as_secs() == 0 || step.subsec_nanos() != 0 || step.as_secs() > u32::MAX as u64 {
+                return Err(archive.error("step", "must be a positive number of whole seconds"));
+            }
+            if rows <= 0 || rows > u32::MAX as i64 {
+                return Err(archive.error("rows", "must be positive"));
+            }
+            archives.push(ArchiveSpec {
+                step,
+                rows: rows as u32,
+            });
+        }
+        if archives.is_empty() {
+            archives = StorageConfig::default_archives();
+        }
+        Ok(StorageConfig {
+            path: PathBuf::from(path),
+            archives,
+        })
+    }
+}
+
+/// Periodic summary reports, see [`crate::report`].
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    pub dir: PathBuf,
+    pub period: Duration,
+    pub formats: Vec<ReportFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["dir", "interval", "format"])?;
+        let dir = section.required_string("dir")?;
+        let period = match section.string("interval")?.as_deref() {
+            None | Some("daily") => Duration::from_secs(86400),
+            Some("hourly") => Duration::from_secs(3600),
+            Some(other) => parse_duration(other)
+                .filter(|d| d.as_secs() > 0)
+                .ok_or_else(|| {
+                    section.error(
+                        "interval",
+                        format!("expected hourly, daily or a duration, found '{}'", other),
+                    )
+                })?,
+        };
+        let mut formats = Vec::new();
+        for format in section.strings("format")? {
+            formats.push(match format.as_str() {
+                "markdown" | "md" => ReportFormat::Markdown,
+                "html" => ReportFormat::Html,
+                other => {
+                    return Err(section.error("format", format!("unknown format '{}'", other)));
+                }
+            });
+        }
+        if formats.is_empty() {
+            formats.push(ReportFormat::Markdown);
+        }
+        Ok(ReportConfig {
+            dir: PathBuf::from(dir),
+            period,
+            formats,
+        })
+    }
+}
+
+/// Syslog output, see [`crate::sinks::syslog`].
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    pub transport: SyslogTransport,
+    pub facility: u8,
+    pub app_name: String,
+    pub severity: SeverityMap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyslogTransport {
+    Udp(String),
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+/// Syslog severity per event: status changes use the severity of the status
+/// being entered.
+#[derive(Debug, Clone)]
+pub struct SeverityMap {
+    pub good: u8,
+    pub fair: u8,
+    pub poor: u8,
+    pub timeout: u8,
+    pub outage_start: u8,
+    pub outage_end: u8,
+}
+
+impl Default for SeverityMap {
+    fn default() -> Self {
+        SeverityMap {
+            good: 6,         // info
+            fair: 5,         // notice
+            poor: 4,         // warning
+            timeout: 3,      // err
+            outage_start: 3, // err
+            outage_end: 5,   // notice
+        }
+    }
+}
+
+impl SyslogConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        use crate::sinks::syslog::{facility_code, severity_code};
+
+        section.deny_unknown(&["server", "facility", "app_name", "severity"])?;
+        let transport = match section.string("server")? {
+            None => SyslogTransport::Unix(PathBuf::from(if cfg!(target_os = "macos") {
+                "/var/run/syslog"
+            } else if cfg!(any(target_os = "freebsd", target_os = "dragonfly")) {
+                "/var/run/log"
+            } else {
+                "/dev/log"
+            })),
+            Some(server) => {
+                if let Some(addr) = server.strip_prefix("udp://") {
+                    SyslogTransport::Udp(addr.to_string())
+                } else if let Some(addr) = server.strip_prefix("tcp://") {
+                    SyslogTransport::Tcp(addr.to_string())
+                } else if let Some(path) = server.strip_prefix("unix://") {
+                    SyslogTransport::Unix(PathBuf::from(path))
+                } else {
+                    return Err(section.error(
+                        "server",
+                        "expected udp://host:port, tcp://host:port or unix:///path",
+                    ));
+                }
+            }
+        };
+
+        let facility = match section.string("facility")? {
+            None => 3,
+            Some(name) => facility_code(&name)
+                .ok_or_else(|| section.error("facility", format!("unknown facility '{}'", name)))?,
+        };
+
+        let mut severity = SeverityMap::default();
+        if let Some(map) = section.table("severity")? {
+            map.deny_unknown(&[
+                "good",
+                "fair",
+                "poor",
+                "timeout",
+                "outage_start",
+                "outage_end",
+            ])?;
+            for (key, slot) in [
+                ("good", &mut severity.good),
+                ("fair", &mut severity.fair),
+                ("poor", &mut severity.poor),
+                ("timeout", &mut severity.timeout),
+                ("outage_start", &mut severity.outage_start),
+                ("outage_end", &mut severity.outage_end),
+            ] {
+                if let Some(name) = map.string(key)? {
+                    *slot = severity_code(&name)
+                        .ok_or_else(|| map.error(key, format!("unknown severity '{}'", name)))?;
+                }
+            }
+        }
+
+        Ok(SyslogConfig {
+            transport,
+            facility,
+            app_name: section
+                .string("app_name")?
+                .unwrap_or_else(|| "latencee".to_string()),
+            severity,
+        })
+    }
+}
+
+/// systemd journal output, see [`crate::sinks::journald`].
+#[derive(Debug, Clone)]
+pub struct JournaldConfig {
+    pub identifier: String,
+    /// Log every probe result, not only status changes and outages.
+    pub samples: bool,
+}
+
+impl JournaldConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["identifier", "samples"])?;
+        if !cfg!(target_os = "linux") {
+            return Err(ConfigError::invalid(
+                "journald: the systemd journal is only available on Linux",
+            ));
+        }
+        Ok(JournaldConfig {
+            identifier: section
+                .string("identifier")?
+                .unwrap_or_else(|| "latencee".to_string()),
+            samples: section.boolean("samples")?.unwrap_or(true),
+        })
+    }
+}
+
+/// MQTT publishing, see [`crate::sinks::mqtt`].
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    /// `host:port` of the broker.
+    pub broker: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+    /// Retain latency messages (status and state are always retained).
+    pub retain: bool,
+    /// Home Assistant discovery prefix, when discovery is enabled.
+    pub discovery_prefix: Option<String>,
+}
+
+impl MqttConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "broker",
+            "client_id",
+            "username",
+            "password",
+            "topic_prefix",
+            "retain",
+            "homeassistant",
+            "discovery_prefix",
+        ])?;
+        let broker = section.required_string("broker")?;
+        let broker = broker
+            .strip_prefix("mqtt://")
+            .or_else(|| broker.strip_prefix("tcp://"))
+            .unwrap_or(&broker);
+        let broker = if broker.contains(':') {
+            broker.to_string()
+        } else {
+            format!("{}:1883", broker)
+        };
+        let homeassistant = section.boolean("homeassistant")?.unwrap_or(false);
+        let discovery_prefix = section
+            .string("discovery_prefix")?
+            .unwrap_or_else(|| "homeassistant".to_string());
+        Ok(MqttConfig {
+            broker,
+            client_id: section
+                .string("client_id")?
+                .unwrap_or_else(|| format!("latencee-{}", crate::host::hostname())),
+            username: section.string("username")?,
+            password: section.string("password")?,
+            topic_prefix: section
+                .string("topic_prefix")?
+                .unwrap_or_else(|| "latencee".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            retain: section.boolean("retain")?.unwrap_or(false),
+            discovery_prefix: homeassistant.then_some(discovery_prefix),
+        })
+    }
+}
+
+/// Kafka producer, see [`crate::sinks::kafka`].
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// librdkafka properties passed to kcat as `-X key=value`.
+    pub properties: Vec<(String, String)>,
+    /// Path or name of the kcat binary.
+    pub kcat: String,
+}
+
+impl KafkaConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["brokers", "topic", "properties", "kcat"])?;
+        let brokers: Vec<String> = section
+            .strings("brokers")?
+            .iter()
+            .flat_map(|b| b.split(','))
+            .map(|b| b.trim().to_string())
+            .filter(|b| !b.is_empty())
+            .collect();
+        if brokers.is_empty() {
+            return Err(section.error("brokers", "is required"));
+        }
+        let mut properties = Vec::new();
+        if let Some(table) = section.table("properties")? {
+            for key in table.table.keys() {
+                let value = table.required_string(key)?;
+                properties.push((key.clone(), value));
+            }
+        }
+        Ok(KafkaConfig {
+            brokers,
+            topic: section.required_string("topic")?,
+            properties,
+            kcat: section
+                .string("kcat")?
+                .unwrap_or_else(|| "kcat".to_string()),
+        })
+    }
+}
+
+/// Newline-delimited JSON events over a stream socket, see
+/// [`crate::sinks::socket`].
+#[derive(Debug, Clone)]
+pub struct SocketConfig {
+    pub address: SocketAddress,
+    /// Types of the events sent; all when empty.
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl SocketConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["address", "events"])?;
+        let address = section.required_string("address")?;
+        let address = if let Some(addr) = address.strip_prefix("tcp://") {
+            SocketAddress::Tcp(addr.to_string())
+        } else if let Some(path) = address.strip_prefix("unix://") {
+            SocketAddress::Unix(PathBuf::from(path))
+        } else {
+            return Err(section.error("address", "expected tcp://host:port or unix:///path"));
+        };
+        let events = section.strings("events")?;
+        const TYPES: [&str; 5] = [
+            "sample",
+            "status_change",
+            "outage_start",
+            "outage_end",
+            "alert",
+        ];
+        if let Some(unknown) = events.iter().find(|e| !TYPES.contains(&e.as_str())) {
+            return Err(section.error(
+                "events",
+                format!(
+                    "unknown event type '{}', expected {}",
+                    unknown,
+                    TYPES.join(", ")
+                ),
+            ));
+        }
+        Ok(SocketConfig { address, events })
+    }
+}
+
+/// Zabbix trapper items, see [`crate::sinks::zabbix`].
+#[derive(Debug, Clone)]
+pub struct ZabbixConfig {
+    /// `host:port` of the Zabbix server or proxy.
+    pub server: String,
+    /// Host the items belong to; may contain `{target}` and `{name}`.
+    pub host: String,
+    pub keys: ZabbixKeys,
+    /// How often collected values are sent, at most.
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Item key templates, with `{target}` standing for the target name as a
+/// key parameter and `{name}` for the plain name. Empty ones are not sent.
+#[derive(Debug, Clone)]
+pub struct ZabbixKeys {
+    /// Round-trip time in milliseconds, for answered probes.
+    pub rtt: String,
+    /// 1 when a probe got an answer, else 0.
+    pub up: String,
+    /// `good`, `fair`, `poor` or `timeout`.
+    pub status: String,
+}
+
+impl ZabbixConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "server",
+            "host",
+            "rtt_key",
+            "up_key",
+            "status_key",
+            "interval",
+            "timeout",
+        ])?;
+        let server = section.required_string("server")?;
+        let server = if server.contains(':') {
+            server
+        } else {
+            format!("{}:10051", server)
+        };
+        let key = |name: &str, default: &str| -> Result<String, ConfigError> {
+            Ok(section.string(name)?.unwrap_or_else(|| default.to_string()))
+        };
+        let interval = section
+            .duration("interval")?
+            .unwrap_or(Duration::from_secs(30));
+        if interval.is_zero() {
+            return Err(section.error("interval", "must be positive"));
+        }
+        Ok(ZabbixConfig {
+            server,
+            host: section
+                .string("host")?
+                .unwrap_or_else(crate::host::hostname),
+            keys: ZabbixKeys {
+                rtt: key("rtt_key", "latencee.rtt[{target}]")?,
+                up: key("up_key", "latencee.up[{target}]")?,
+                status: key("status_key", "latencee.status[{target}]")?,
+            },
+            interval,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(10)),
+        })
+    }
+}
+
+/// Prometheus Pushgateway, see [`crate::sinks::pushgateway`].
+#[derive(Debug, Clone)]
+pub struct PushgatewayConfig {
+    /// Base URL of the Pushgateway, e.g. `http://pushgateway:9091`.
+    pub url: String,
+    pub job: String,
+    pub instance: String,
+    /// Further grouping labels.
+    pub labels: Vec<(String, String)>,
+    /// How often metrics are pushed, at most.
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl PushgatewayConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "url", "job", "instance", "labels", "interval", "timeout", "curl",
+        ])?;
+        let mut labels = Vec::new();
+        if let Some(table) = section.table("labels")? {
+            for key in table.table.keys() {
+                if matches!(key.as_str(), "job" | "instance") {
+                    return Err(table.error(key, "is set by its own key"));
+                }
+                let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(table.error(key, "is not a valid Prometheus label name"));
+                }
+                labels.push((key.clone(), table.required_string(key)?));
+            }
+        }
+        let interval = section
+            .duration("interval")?
+            .unwrap_or(Duration::from_secs(15));
+        if interval.is_zero() {
+            return Err(section.error("interval", "must be positive"));
+        }
+        Ok(PushgatewayConfig {
+            url: section.required_string("url")?,
+            job: section
+                .string("job")?
+                .unwrap_or_else(|| "latencee".to_string()),
+            instance: section
+                .string("instance")?
+                .unwrap_or_else(crate::host::hostname),
+            labels,
+            interval,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(10)),
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// Dead man's switch pings, see [`crate::sinks::healthchecks`].
+#[derive(Debug, Clone)]
+pub struct HealthchecksConfig {
+    /// Ping URL of the check, e.g. `https://hc-ping.com/<uuid>`.
+    pub url: String,
+    /// How often the check is pinged while nothing changes.
+    pub interval: Duration,
+    /// Names of the targets whose outages fail the check; all when empty.
+    pub targets: Vec<String>,
+    pub timeout: Duration,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl HealthchecksConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["url", "interval", "targets", "timeout", "curl"])?;
+        let interval = section
+            .duration("interval")?
+            .unwrap_or(Duration::from_secs(60));
+        if interval.is_zero() {
+            return Err(section.error("interval", "must be positive"));
+        }
+        Ok(HealthchecksConfig {
+            url: section.required_string("url")?,
+            interval,
+            targets: section.strings("targets")?,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(10)),
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// Uptime Kuma push monitors, see [`crate::sinks::uptime_kuma`].
+#[derive(Debug, Clone)]
+pub struct UptimeKumaConfig {
+    /// Push URL of each target's monitor, by target name.
+    pub targets: Vec<(String, String)>,
+    /// The best classification reported as down.
+    pub down: ConnectionStatus,
+    /// How often each target is pushed while its state holds.
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl UptimeKumaConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["targets", "down", "interval", "timeout", "curl"])?;
+        let mut targets = Vec::new();
+        if let Some(table) = section.table("targets")? {
+            for name in table.table.keys() {
+                let url = table.required_string(name)?;
+                // Kuma shows push URLs with example parameters; ours replace
+                // them.
+                let url = url.split_once('?').map_or(&*url, |(url, _)| url);
+                targets.push((name.clone(), url.to_string()));
+            }
+        }
+        if targets.is_empty() {
+            return Err(section.error("targets", "needs a push URL for at least one target"));
+        }
+        let down = match section.string("down")? {
+            Some(level) => ConnectionStatus::from_label(&level)
+                .filter(|&l| l != ConnectionStatus::Good)
+                .ok_or_else(|| {
+                    section.error("down", "expected \"fair\", \"poor\" or \"timeout\"")
+                })?,
+            None => ConnectionStatus::Timeout,
+        };
+        let interval = section
+            .duration("interval")?
+            .unwrap_or(Duration::from_secs(30));
+        if interval.is_zero() {
+            return Err(section.error("interval", "must be positive"));
+        }
+        Ok(UptimeKumaConfig {
+            targets,
+            down,
+            interval,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(10)),
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// On-demand throughput tests, see [`crate::speedtest`].
+#[derive(Debug, Clone)]
+pub struct SpeedtestConfig {
+    pub tool: SpeedtestTool,
+    /// Path or name of the client binary; `None` for the tool's usual one.
+    pub command: Option<String>,
+    /// The iperf3 server, or the ID of the Speedtest server to use.
+    pub server: Option<String>,
+    /// Port of the iperf3 server.
+    pub port: u16,
+    /// How long iperf3 sends in each direction.
+    pub duration: Duration,
+    /// How long a test may take before it is given up.
+    pub timeout: Duration,
+    /// Where results are appended; `None` to not keep them.
+    pub log: Option<PathBuf>,
+}
+
+/// The client a throughput test runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedtestTool {
+    Iperf3,
+    /// Ookla's Speedtest CLI.
+    Ookla,
+    /// The Python `speedtest-cli`.
+    SpeedtestCli,
+}
+
+impl SpeedtestTool {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeedtestTool::Iperf3 => "iperf3",
+            SpeedtestTool::Ookla => "speedtest",
+            SpeedtestTool::SpeedtestCli => "speedtest-cli",
+        }
+    }
+}
+
+impl SpeedtestConfig {
+    /// The client binary to run.
+    pub fn command(&self) -> &str {
+        self.command.as_deref().unwrap_or(self.tool.name())
+    }
+
+    fn parse(section: &Section, storage: Option<&StorageConfig>) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "tool", "command", "server", "port", "duration", "timeout", "log",
+        ])?;
+        let tool = match section.string("tool")?.as_deref() {
+            None | Some("iperf3") => SpeedtestTool::Iperf3,
+            Some("speedtest") => SpeedtestTool::Ookla,
+            Some("speedtest-cli") => SpeedtestTool::SpeedtestCli,
+            Some(_) => {
+                return Err(section.error(
+                    "tool",
+                    "expected \"iperf3\", \"speedtest\" or \"speedtest-cli\"",
+                ));
+            }
+        };
+        let server = section.string("server")?;
+        if tool == SpeedtestTool::Iperf3 && server.is_none() {
+            return Err(section.error("server", "is required for iperf3"));
+        }
+        let port = match section.integer("port")? {
+            Some(port) => u16::try_from(port)
+                .ok()
+                .filter(|&port| port != 0)
+                .ok_or_else(|| section.error("port", format!("invalid port {}", port)))?,
+            None => 5201,
+        };
+        // Kept next to the latency history unless placed elsewhere.
+        let log = match section.string("log")? {
+            Some(log) => Some(PathBuf::from(log)),
+            None => storage.map(|storage| storage.path.join("speedtest.jsonl")),
+        };
+        Ok(SpeedtestConfig {
+            tool,
+            command: section.string("command")?,
+            server,
+            port,
+            duration: section
+                .duration("duration")?
+                .unwrap_or(Duration::from_secs(10)),
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(120)),
+            log,
+        })
+    }
+}
+
+/// Settings of the built-in alert rule, see [`crate::alert`].
+#[derive(Debug, Clone)]
+pub struct AlertsConfig {
+    /// Alert when a target drops to this status or worse; only used when no
+    /// `[[rule]]`s are configured.
+    pub level: ConnectionStatus,
+    /// Minimum time between two notifications for the same rule and target.
+    pub cooldown: Duration,
+    /// How long to wait for more alerts to send along in one notification.
+    pub group_wait: Duration,
+    /// How long pressing `z` silences the selected target.
+    pub silence: Duration,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        AlertsConfig {
+            level: ConnectionStatus::Poor,
+            cooldown: Duration::from_secs(300),
+            group_wait: Duration::from_secs(2),
+            silence: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+impl AlertsConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["level", "cooldown", "group_wait", "silence"])?;
+        let defaults = AlertsConfig::default();
+        let level = match section.string("level")? {
+            Some(level) => ConnectionStatus::from_label(&level)
+                .filter(|&l| l != ConnectionStatus::Good)
+                .ok_or_else(|| {
+                    section.error("level", "expected \"fair\", \"poor\" or \"timeout\"")
+                })?,
+            None => defaults.level,
+        };
+        Ok(AlertsConfig {
+            level,
+            cooldown: section.duration("cooldown")?.unwrap_or(defaults.cooldown),
+            group_wait: section
+                .duration("group_wait")?
+                .unwrap_or(defaults.group_wait),
+            silence: section.duration("silence")?.unwrap_or(defaults.silence),
+        })
+    }
+}
+
+/// An alert rule, see [`crate::alert`].
+fn parse_rule(section: &Section) -> Result<Rule, ConfigError> {
+    section.deny_unknown(&["name", "targets", "fire", "clear", "on_fire", "on_resolve"])?;
+    let condition = |key: &str| -> Result<Option<Condition>, ConfigError> {
+        section
+            .string(key)?
+            .map(|text| text.parse().map_err(|e| section.error(key, e)))
+            .transpose()
+    };
+    let fire = condition("fire")?.ok_or_else(|| section.error("fire", "missing"))?;
+    Ok(Rule {
+        name: section.required_string("name")?,
+        targets: section.strings("targets")?,
+        clear: condition("clear")?.unwrap_or_else(|| fire.negate()),
+        fire,
+        on_fire: section.string("on_fire")?,
+        on_resolve: section.string("on_resolve")?,
+    })
+}
+
+/// A `[[target]]`: a host, the name it is shown under (the host itself by
+/// default), and optionally the kind of probe measuring it and the port
+/// that probe connects to.
+fn parse_target(section: &Section) -> Result<Target, ConfigError> {
+    section.deny_unknown(&["name", "host", "probe", "port"])?;
+    let host = section.required_string("host")?;
+    let name = section.string("name")?.unwrap_or_else(|| host.clone());
+    if name.is_empty() {
+        return Err(section.error("name", "must not be empty"));
+    }
+    let mut target = Target::new(name, host);
+    target.probe = section.string("probe")?;
+    if let Some(port) = section.integer("port")? {
+        // Pinging falls back to TCP connects.
+        if !matches!(
+            target.probe.as_deref(),
+            None | Some("icmp" | "ping" | "tcp" | "a2s" | "minecraft" | "smtp" | "imap")
+        ) {
+            return Err(section.error(
+                "port",
+                "only applies to probes tcp, a2s, minecraft, smtp and imap",
+            ));
+        }
+        target.port = Some(
+            u16::try_from(port)
+                .ok()
+                .filter(|&port| port != 0)
+                .ok_or_else(|| section.error("port", format!("invalid port {}", port)))?,
+        );
+    }
+    Ok(target)
+}
+
+/// A processor, see [`crate::pipeline`].
+fn parse_processor(section: &Section) -> Result<Stage, ConfigError> {
+    let targets = section.strings("targets")?;
+    match section.required_string("kind")?.as_str() {
+        "smooth" => {
+            section.deny_unknown(&["kind", "targets", "samples"])?;
+            let samples = section.integer("samples")?.unwrap_or(5);
+            if samples < 1 {
+                return Err(section.error("samples", "must be at least 1"));
+            }
+            Ok(Stage::Smooth {
+                samples: samples as usize,
+                targets,
+            })
+        }
+        "classify" => {
+            section.deny_unknown(&["kind", "targets", "good", "fair", "poor"])?;
+            let bound = |key: &str| {
+                section
+                    .duration(key)?
+                    .ok_or_else(|| section.error(key, "is required"))
+            };
+            let (good, fair, poor) = (bound("good")?, bound("fair")?, bound("poor")?);
+            if !(good <= fair && fair <= poor) {
+                return Err(section.error("fair", "thresholds must increase from good to poor"));
+            }
+            Ok(Stage::Classify {
+                good,
+                fair,
+                poor,
+                targets,
+            })
+        }
+        other => Err(section.error(
+            "kind",
+            format!("unknown processor '{}', expected smooth or classify", other),
+        )),
+    }
+}
+
+fn parse_discovery(section: &Section) -> Result<Discovery, ConfigError> {
+    let refresh = section
+        .duration("refresh")?
+        .unwrap_or(Duration::from_secs(30));
+    if refresh.is_zero() {
+        return Err(section.error("refresh", "must be positive"));
+    }
+    let source = match section.required_string("kind")?.as_str() {
+        "kubernetes" => {
+            section.deny_unknown(&[
+                "kind",
+                "refresh",
+                "selector",
+                "namespace",
+                "addresses",
+                "kubeconfig",
+                "context",
+                "kubectl",
+            ])?;
+            let addresses = match section.string("addresses")?.as_deref() {
+                None | Some("pods") => KubernetesAddresses::Pods,
+                Some("services") => KubernetesAddresses::Services,
+                Some(_) => {
+                    return Err(section.error("addresses", "expected \"pods\" or \"services\""));
+                }
+            };
+            Source::Kubernetes(Kubernetes {
+                selector: section.required_string("selector")?,
+                namespace: section.string("namespace")?,
+                addresses,
+                kubeconfig: section.string("kubeconfig")?.map(PathBuf::from),
+                context: section.string("context")?,
+                kubectl: section
+                    .string("kubectl")?
+                    .unwrap_or_else(|| "kubectl".to_string()),
+            })
+        }
+        "consul" => {
+            section.deny_unknown(&[
+                "kind",
+                "refresh",
+                "address",
+                "services",
+                "filter",
+                "passing",
+                "datacenter",
+                "token",
+                "curl",
+            ])?;
+            Source::Consul(Consul {
+                address: section
+                    .string("address")?
+                    .unwrap_or_else(|| "http://127.0.0.1:8500".to_string()),
+                services: section.strings("services")?,
+                filter: section.string("filter")?,
+                passing: section.boolean("passing")?.unwrap_or(true),
+                datacenter: section.string("datacenter")?,
+                token: section.string("token")?,
+                curl: section
+                    .string("curl")?
+                    .unwrap_or_else(|| "curl".to_string()),
+            })
+        }
+        "srv" => {
+            section.deny_unknown(&["kind", "refresh", "name", "nameserver"])?;
+            let name = section.required_string("name")?;
+            let nameserver = match section.string("nameserver")? {
+                None => None,
+                Some(address) => Some(
+                    address
+                        .parse::<SocketAddr>()
+                        .or_else(|_| address.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                        .map_err(|_| {
+                            section.error(
+                                "nameserver",
+                                "expected an IP address, optionally with a port",
+                            )
+                        })?,
+                ),
+            };
+            Source::Srv(Srv {
+                name: name.strip_prefix("srv:").unwrap_or(&name).to_string(),
+                nameserver,
+            })
+        }
+        "tailscale" => {
+            section.deny_unknown(&["kind", "refresh", "offline", "socket", "tailscale"])?;
+            Source::Tailscale(Tailscale {
+                offline: section.boolean("offline")?.unwrap_or(false),
+                socket: section.string("socket")?.map(PathBuf::from),
+                tailscale: section
+                    .string("tailscale")?
+                    .unwrap_or_else(|| "tailscale".to_string()),
+            })
+        }
+        "docker" => {
+            section.deny_unknown(&[
+                "kind",
+                "refresh",
+                "labels",
+                "addresses",
+                "host",
+                "network",
+                "socket",
+                "curl",
+            ])?;
+            let addresses = match section.string("addresses")?.as_deref() {
+                None | Some("published") => DockerAddresses::Published,
+                Some("container") => DockerAddresses::Container,
+                Some(_) => {
+                    return Err(
+                        section.error("addresses", "expected \"published\" or \"container\"")
+                    );
+                }
+            };
+            // DOCKER_HOST names the socket as unix:///path.
+            let socket = section
+                .string("socket")?
+                .map(PathBuf::from)
+                .unwrap_or_else(|| {
+                    std::env::var("DOCKER_HOST")
+                        .ok()
+                        .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+                        .unwrap_or_else(|| PathBuf::from("/var/run/docker.sock"))
+                });
+            Source::Docker(Docker {
+                labels: section.strings("labels")?,
+                addresses,
+                host: section
+                    .string("host")?
+                    .unwrap_or_else(|| "127.0.0.1".to_string()),
+                network: section.string("network")?,
+                socket,
+                curl: section
+                    .string("curl")?
+                    .unwrap_or_else(|| "curl".to_string()),
+            })
+        }
+        "wireguard" => {
+            section.deny_unknown(&["kind", "refresh", "interface", "peers", "stale", "wg"])?;
+            let mut names = Vec::new();
+            if let Some(table) = section.table("peers")? {
+                for key in table.table.keys() {
+                    names.push((key.clone(), table.required_string(key)?));
+                }
+            }
+            Source::WireGuard(WireGuard {
+                interface: section.string("interface")?,
+                names,
+                // WireGuard rejects sessions older than three minutes.
+                stale: section
+                    .duration("stale")?
+                    .unwrap_or(Duration::from_secs(180)),
+                wg: section.string("wg")?.unwrap_or_else(|| "wg".to_string()),
+            })
+        }
+        "steam" => {
+            section.deny_unknown(&["kind", "refresh", "app", "curl"])?;
+            let app = section.integer("app")?.unwrap_or(730);
+            Source::Steam(Steam {
+                app: u32::try_from(app)
+                    .map_err(|_| section.error("app", format!("invalid app id {}", app)))?,
+                curl: section
+                    .string("curl")?
+                    .unwrap_or_else(|| "curl".to_string()),
+            })
+        }
+        "discord" => {
+            section.deny_unknown(&["kind", "refresh", "curl"])?;
+            Source::Discord(Discord {
+                curl: section
+                    .string("curl")?
+                    .unwrap_or_else(|| "curl".to_string()),
+            })
+        }
+        other => {
+            return Err(section.error(
+                "kind",
+                format!(
+                    "unknown discovery '{}', expected kubernetes, consul, srv, tailscale, docker, \
+                     wireguard, steam or discord",
+                    other
+                ),
+            ));
+        }
+    };
+    Ok(Discovery { source, refresh })
+}
+
+/// The `proxy` of `section`; `"direct"` and no proxy at all mean none.
+fn parse_proxy(section: &Section) -> Result<Option<Proxy>, ConfigError> {
+    match section.string("proxy")?.as_deref() {
+        None | Some("direct") => Ok(None),
+        Some(url) => url.parse().map(Some).map_err(|e| section.error("proxy", e)),
+    }
+}
+
+/// A `[[probe.network]]`: targets probed in the network namespace `netns`
+/// or bound to the VRF device `vrf`.
+fn parse_network(section: &Section) -> Result<NetworkRoute, ConfigError> {
+    section.deny_unknown(&["targets", "netns", "vrf"])?;
+    if !cfg!(target_os = "linux") {
+        return Err(ConfigError::invalid(
+            "probe.network: namespaces and VRFs are only available on Linux",
+        ));
+    }
+    let network = match (section.string("netns")?, section.string("vrf")?) {
+        (Some(namespace), None) => Network::Namespace(namespace),
+        (None, Some(device)) => Network::Vrf(device),
+        (Some(_), Some(_)) => return Err(section.error("vrf", "conflicts with netns")),
+        (None, None) => return Err(section.error("netns", "either netns or vrf is required")),
+    };
+    Ok(NetworkRoute {
+        targets: section.strings("targets")?,
+        network,
+    })
+}
+
+/// A `[[probe.ssh]]`: a machine the targets are also pinged from.
+fn parse_jump(section: &Section) -> Result<Jump, ConfigError> {
+    section.deny_unknown(&["host", "name", "targets", "ssh"])?;
+    let host = section.required_string("host")?;
+    let name = section.string("name")?.unwrap_or_else(|| {
+        // Targets are named after the machine, not the login.
+        host.rsplit('@').next().unwrap_or(&host).to_string()
+    });
+    if name.is_empty() || name.contains('/') {
+        return Err(section.error("name", "must be non-empty and without '/'"));
+    }
+    Ok(Jump {
+        name,
+        host,
+        targets: section.strings("targets")?,
+        ssh: section.string("ssh")?.unwrap_or_else(|| "ssh".to_string()),
+    })
+}
+
+/// A `[[probe.router]]`: a router or switch the targets are also pinged
+/// from.
+fn parse_router(section: &Section) -> Result<Router, ConfigError> {
+    let api = section.required_string("api")?;
+    let address = section.required_string("address")?;
+    let api = match api.as_str() {
+        "routeros" => {
+            section.deny_unknown(&[
+                "api", "address", "name", "targets", "user", "password", "insecure", "curl",
+            ])?;
+            let url = if address.contains("://") {
+                address.clone()
+            } else {
+                format!("https://{}", address)
+            };
+            RouterApi::RouterOs(RouterOs {
+                url,
+                user: section
+                    .string("user")?
+                    .unwrap_or_else(|| "admin".to_string()),
+                password: section.string("password")?.unwrap_or_default(),
+                insecure: section.boolean("insecure")?.unwrap_or(false),
+                curl: section
+                    .string("curl")?
+                    .unwrap_or_else(|| "curl".to_string()),
+            })
+        }
+        "snmp" => {
+            section.deny_unknown(&["api", "address", "name", "targets", "community", "tools"])?;
+            RouterApi::Snmp(Snmp {
+                agent: address.clone(),
+                community: section
+                    .string("community")?
+                    .unwrap_or_else(|| "private".to_string()),
+                tools: section.string("tools")?,
+            })
+        }
+        _ => return Err(section.error("api", "expected routeros or snmp")),
+    };
+    let name = match section.string("name")? {
+        Some(name) => name,
+        // The host, without scheme, port or path.
+        None => {
+            let host = address
+                .split_once("://")
+                .map_or(address.as_str(), |(_, rest)| rest);
+            let host = host.split('/').next().unwrap_or(host);
+            match host.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => {
+                    host.to_string()
+                }
+                _ => host.to_string(),
+            }
+        }
+    };
+    if name.is_empty() || name.contains('/') {
+        return Err(section.error("name", "must be non-empty and without '/'"));
+    }
+    Ok(Router {
+        name,
+        api,
+        targets: section.strings("targets")?,
+    })
+}
+
+/// A maintenance window, see [`crate::alert::Silencer`].
+fn parse_maintenance(section: &Section) -> Result<Maintenance, ConfigError> {
+    section.deny_unknown(&["schedule", "duration", "targets"])?;
+    let schedule = section
+        .required_string("schedule")?
+        .parse()
+        .map_err(|e| section.error("schedule", e))?;
+    let duration = section
+        .duration("duration")?
+        .ok_or_else(|| section.error("duration", "missing"))?;
+    Ok(Maintenance {
+        schedule,
+        duration,
+        targets: section.strings("targets")?,
+    })
+}
+
+/// Desktop notifications, see [`crate::sinks::desktop`].
+#[derive(Debug, Clone)]
+pub struct DesktopConfig {
+    /// Names of the targets that notify.
+    pub targets: Vec<String>,
+}
+
+impl DesktopConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["targets"])?;
+        Ok(DesktopConfig {
+            targets: section.strings("targets")?,
+        })
+    }
+}
+
+/// Audible alerts, see [`crate::sinks::sound`].
+#[derive(Debug, Clone)]
+pub struct SoundConfig {
+    /// Names of the targets that make a sound.
+    pub targets: Vec<String>,
+    /// Shell command playing a sound instead of the terminal bell.
+    pub command: Option<String>,
+}
+
+impl SoundConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["targets", "command"])?;
+        Ok(SoundConfig {
+            targets: section.strings("targets")?,
+            command: section.string("command")?,
+        })
+    }
+}
+
+/// Body layout of a webhook request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    Json,
+    /// Slack incoming webhook message with an attachment.
+    Slack,
+    /// Discord webhook message with an embed.
+    Discord,
+}
+
+/// An alert webhook, see [`crate::sinks::webhook`].
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Only alert for these targets; all targets when empty.
+    pub targets: Vec<String>,
+    /// Only alert for these rules; all rules when empty.
+    pub rules: Vec<String>,
+    /// Extra request headers, as `Name: value`.
+    pub headers: Vec<String>,
+    /// Request body with `{{placeholder}}`s; the default is a JSON document.
+    pub template: Option<String>,
+    /// Additional attempts after a failed delivery.
+    pub retries: u32,
+    pub timeout: Duration,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl WebhookConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "url", "format", "targets", "rules", "headers", "template", "retries", "timeout",
+            "curl",
+        ])?;
+        let format = match section.string("format")?.as_deref() {
+            None | Some("json") => WebhookFormat::Json,
+            Some("slack") => WebhookFormat::Slack,
+            Some("discord") => WebhookFormat::Discord,
+            Some(other) => {
+                return Err(section.error(
+                    "format",
+                    format!(
+                        "unknown format '{}', expected json, slack or discord",
+                        other
+                    ),
+                ));
+            }
+        };
+        let template = section.string("template")?;
+        if template.is_some() && format != WebhookFormat::Json {
+            return Err(section.error("template", "only supported with format = \"json\""));
+        }
+        Ok(WebhookConfig {
+            url: section.required_string("url")?,
+            format,
+            targets: section.strings("targets")?,
+            rules: section.strings("rules")?,
+            headers: section.strings("headers")?,
+            template,
+            retries: retries(section)?,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(10)),
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    StartTls,
+    /// TLS from the first byte, usually port 465.
+    Implicit,
+    None,
+}
+
+/// Email alerts, see [`crate::sinks::email`].
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    /// `host:port` of the SMTP server.
+    pub server: String,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Only alert for these targets; all targets when empty.
+    pub targets: Vec<String>,
+    /// Only alert for these rules; all rules when empty.
+    pub rules: Vec<String>,
+    /// Also send a mail when an alert resolves.
+    pub recovery: bool,
+    /// Collect alerts and send them in one mail per period.
+    pub digest: Option<Duration>,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl EmailConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "server", "tls", "username", "password", "from", "to", "targets", "rules", "recovery",
+            "digest", "curl",
+        ])?;
+        let tls = match section.string("tls")?.as_deref() {
+            None | Some("starttls") => SmtpTls::StartTls,
+            Some("implicit") => SmtpTls::Implicit,
+            Some("none") => SmtpTls::None,
+            Some(other) => {
+                return Err(section.error(
+                    "tls",
+                    format!(
+                        "unknown mode '{}', expected starttls, implicit or none",
+                        other
+                    ),
+                ));
+            }
+        };
+        let server = section.required_string("server")?;
+        let server = if server.contains(':') {
+            server
+        } else {
+            let port = match tls {
+                SmtpTls::StartTls => 587,
+                SmtpTls::Implicit => 465,
+                SmtpTls::None => 25,
+            };
+            format!("{}:{}", server, port)
+        };
+        let to = section.strings("to")?;
+        if to.is_empty() {
+            return Err(section.error("to", "at least one recipient is required"));
+        }
+        Ok(EmailConfig {
+            server,
+            tls,
+            username: section.string("username")?,
+            password: section.string("password")?,
+            from: section.required_string("from")?,
+            to,
+            targets: section.strings("targets")?,
+            rules: section.strings("rules")?,
+            recovery: section.boolean("recovery")?.unwrap_or(true),
+            digest: section.duration("digest")?,
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+fn retries(section: &Section) -> Result<u32, ConfigError> {
+    match section.integer("retries")? {
+        Some(n) => u32::try_from(n).map_err(|_| section.error("retries", "must not be negative")),
+        None => Ok(3),
+    }
+}
+
+/// PagerDuty incidents, see [`crate::sinks::pagerduty`].
+#[derive(Debug, Clone)]
+pub struct PagerDutyConfig {
+    /// Integration key of an Events API v2 integration.
+    pub routing_key: String,
+    /// `critical`, `error`, `warning` or `info`.
+    pub severity: String,
+    /// Only alert for these targets; all targets when empty.
+    pub targets: Vec<String>,
+    /// Only alert for these rules; all rules when empty.
+    pub rules: Vec<String>,
+    pub retries: u32,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl PagerDutyConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "routing_key",
+            "severity",
+            "targets",
+            "rules",
+            "retries",
+            "curl",
+        ])?;
+        let severity = section
+            .string("severity")?
+            .unwrap_or_else(|| "critical".to_string());
+        if !["critical", "error", "warning", "info"].contains(&severity.as_str()) {
+            return Err(section.error("severity", "expected critical, error, warning or info"));
+        }
+        Ok(PagerDutyConfig {
+            routing_key: section.required_string("routing_key")?,
+            severity,
+            targets: section.strings("targets")?,
+            rules: section.strings("rules")?,
+            retries: retries(section)?,
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// Opsgenie alerts, see [`crate::sinks::opsgenie`].
+#[derive(Debug, Clone)]
+pub struct OpsgenieConfig {
+    pub api_key: String,
+    /// `https://api.opsgenie.com`, or the EU instance.
+    pub api_url: String,
+    /// `P1` (critical) to `P5` (informational).
+    pub priority: String,
+    /// Only alert for these targets; all targets when empty.
+    pub targets: Vec<String>,
+    /// Only alert for these rules; all rules when empty.
+    pub rules: Vec<String>,
+    pub retries: u32,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl OpsgenieConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&[
+            "api_key", "region", "priority", "targets", "rules", "retries", "curl",
+        ])?;
+        let api_url = match section.string("region")?.as_deref() {
+            None | Some("us") => "https://api.opsgenie.com",
+            Some("eu") => "https://api.eu.opsgenie.com",
+            Some(other) => {
+                return Err(section.error(
+                    "region",
+                    format!("unknown region '{}', expected us or eu", other),
+                ));
+            }
+        };
+        let priority = section
+            .string("priority")?
+            .unwrap_or_else(|| "P2".to_string());
+        if !["P1", "P2", "P3", "P4", "P5"].contains(&priority.as_str()) {
+            return Err(section.error("priority", "expected P1 to P5"));
+        }
+        Ok(OpsgenieConfig {
+            api_key: section.required_string("api_key")?,
+            api_url: api_url.to_string(),
+            priority,
+            targets: section.strings("targets")?,
+            rules: section.strings("rules")?,
+            retries: retries(section)?,
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// Telegram bot messages, see [`crate::sinks::telegram`].
+#[derive(Debug, Clone)]
+pub struct TelegramConfig {
+    pub token: String,
+    /// Numeric chat id or `@channelname`.
+    pub chat_id: String,
+    /// Only alert for these targets; all targets when empty.
+    pub targets: Vec<String>,
+    /// Only alert for these rules; all rules when empty.
+    pub rules: Vec<String>,
+    pub retries: u32,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+impl TelegramConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["token", "chat_id", "targets", "rules", "retries", "curl"])?;
+        // Chat ids are numbers, but channels can also be addressed by name.
+        let chat_id = match section.integer("chat_id") {
+            Ok(Some(id)) => id.to_string(),
+            _ => section.required_string("chat_id")?,
+        };
+        Ok(TelegramConfig {
+            token: section.required_string("token")?,
+            chat_id,
+            targets: section.strings("targets")?,
+            rules: section.strings("rules")?,
+            retries: retries(section)?,
+            curl: section
+                .string("curl")?
+                .unwrap_or_else(|| "curl".to_string()),
+        })
+    }
+}
+
+/// Embedded HTTP server, see [`crate::http`].
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Address to listen on, `host:port`.
+    pub listen: String,
+}
+
+impl HttpConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["listen"])?;
+        Ok(HttpConfig {
+            listen: section
+                .string("listen")?
+                .unwrap_or_else(|| "127.0.0.1:8080".to_string()),
+        })
+    }
+}
+
+/// gRPC service, see [`crate::grpc`].
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    /// Address to listen on, `host:port`.
+    pub listen: String,
+}
+
+impl GrpcConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["listen"])?;
+        Ok(GrpcConfig {
+            listen: section
+                .string("listen")?
+                .unwrap_or_else(|| "127.0.0.1:50051".to_string()),
+        })
+    }
+}
+
+/// Runtime of the `wasm` probe: WASI with sockets and name lookups, as
+/// probing needs the network.
+const WASM_PROBE_RUNTIME: &str = "wasmtime run -S inherit-network -S allow-ip-name-lookup";
+
+/// Runtime of WebAssembly sinks: WASI without network or file access.
+const WASM_SINK_RUNTIME: &str = "wasmtime run";
+
+/// A WebAssembly plugin, see [`crate::wasm`].
+#[derive(Debug, Clone)]
+pub struct WasmConfig {
+    pub module: PathBuf,
+    /// Command running a WASI module, followed by the module path.
+    pub runtime: Vec<String>,
+    /// How long a probe module may take to answer.
+    pub timeout: Duration,
+}
+
+impl WasmConfig {
+    fn parse(section: &Section, runtime: &str) -> Result<Self, ConfigError> {
+        let runtime = section
+            .string("runtime")?
+            .unwrap_or_else(|| runtime.to_string());
+        let runtime: Vec<String> = runtime.split_whitespace().map(String::from).collect();
+        if runtime.is_empty() {
+            return Err(section.error("runtime", "is empty"));
+        }
+        Ok(WasmConfig {
+            module: section.required_string("module")?.into(),
+            runtime,
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_secs(2)),
+        })
+    }
+}
+
+/// A user script post-processing samples, see [`crate::script`].
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    /// The Rhai script, see [`crate::script`].
+    pub path: PathBuf,
+    /// How long each call of the script may take.
+    pub timeout: Duration,
+}
+
+impl ScriptConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["path", "timeout"])?;
+        Ok(ScriptConfig {
+            path: PathBuf::from(section.required_string("path")?),
+            timeout: section
+                .duration("timeout")?
+                .unwrap_or(Duration::from_millis(200)),
+        })
+    }
+}
+
+/// Streaming results to a central instance, see [`crate::agent`].
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// `host:port` of the central instance.
+    pub central: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA that signed the central instance's certificate.
+    pub ca: PathBuf,
+    /// Path or name of the openssl binary.
+    pub openssl: String,
+}
+
+impl AgentConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["central", "cert", "key", "ca", "openssl"])?;
+        Ok(AgentConfig {
+            central: section.required_string("central")?,
+            cert: section.required_string("cert")?.into(),
+            key: section.required_string("key")?.into(),
+            ca: section.required_string("ca")?.into(),
+            openssl: section
+                .string("openssl")?
+                .unwrap_or_else(|| "openssl".to_string()),
+        })
+    }
+}
+
+/// Sharing targets with other instances, see [`crate::cluster`].
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// How this instance is announced over mDNS.
+    pub name: String,
+    pub peers: Vec<Peer>,
+    /// Find peers, and announce this instance, with mDNS.
+    pub mdns: bool,
+}
+
+/// Another instance whose targets are shown here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    /// Shown as the source of the peer's targets.
+    pub name: String,
+    /// `host:port` of the peer's HTTP API.
+    pub address: String,
+}
+
+impl ClusterConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["name", "peer", "mdns"])?;
+        let peers = section
+            .tables("peer")?
+            .iter()
+            .map(|peer| {
+                peer.deny_unknown(&["name", "address"])?;
+                Ok(Peer {
+                    name: peer.required_string("name")?,
+                    address: peer.required_string("address")?,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        let mdns = section.boolean("mdns")?.unwrap_or(false);
+        if peers.is_empty() && !mdns {
+            return Err(section.error("peer", "at least one peer or mdns = true is required"));
+        }
+        Ok(ClusterConfig {
+            name: section.string("name")?.unwrap_or_else(host::hostname),
+            peers,
+            mdns,
+        })
+    }
+}
+
+/// Receiving results from agents, see [`crate::central`].
+#[derive(Debug, Clone)]
+pub struct CentralConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA that signed the agents' certificates.
+    pub ca: PathBuf,
+    pub agents: Vec<AgentListener>,
+    /// Path or name of the openssl binary.
+    pub openssl: String,
+}
+
+/// The port a single agent connects to.
+#[derive(Debug, Clone)]
+pub struct AgentListener {
+    /// Shown as the source of the agent's targets.
+    pub name: String,
+    pub port: u16,
+}
+
+impl CentralConfig {
+    fn parse(section: &Section) -> Result<Self, ConfigError> {
+        section.deny_unknown(&["cert", "key", "ca", "agent", "openssl"])?;
+        let agents = section
+            .tables("agent")?
+            .iter()
+            .map(|agent| {
+                agent.deny_unknown(&["name", "port"])?;
+                let port = agent
+                    .integer("port")?
+                    .ok_or_else(|| agent.error("port", "is required"))?;
+                Ok(AgentListener {
+                    name: agent.required_string("name")?,
+                    port: u16::try_from(port)
+                        .map_err(|_| agent.error("port", format!("invalid port {}", port)))?,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        if agents.is_empty() {
+            return Err(section.error("agent", "at least one agent is required"));
+        }
+        Ok(CentralConfig {
+            cert: section.required_string("cert")?.into(),
+            key: section.required_string("key")?.into(),
+            ca: section.required_string("ca")?.into(),
+            agents,
+            openssl: section
+                .string("openssl")?
+                .unwrap_or_else(|| "openssl".to_string()),
+        })
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError::invalid(format!("cannot read {}: {}", path.display(), e)))?;
+        text.parse()
+    }
+}
+
+/// Sets `key` of `[section]` in the configuration file at `path` to the
+/// string `value`, which needs no escaping, leaving the rest of the file
+/// as it is. A line setting the key is replaced, else the key goes first
+/// in the section, which is added at the end of the file if missing.
+/// Written through a temporary file next to the real one, so that a crash
+/// does not leave half of it.
+pub fn set(path: &Path, section: &str, key: &str, value: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let text = fs::read_to_string(&path)?;
+    let line = format!("{} = \"{}\"", key, value);
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let header = lines.iter().position(|l| {
+        l.trim()
+            .strip_prefix('[')
+            .and_then(|l| l.split_once(']'))
+            .is_some_and(|(name, _)| name.trim() == section)
+    });
+    match header {
+        Some(header) => {
+            let end = lines[header + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with('['))
+                .map_or(lines.len(), |i| header + 1 + i);
+            let existing = (header + 1..end).find(|&i| {
+                lines[i]
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.trim() == key)
+            });
+            match existing {
+                Some(i) => lines[i] = line,
+                None => lines.insert(header + 1, line),
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.push(line);
+        }
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, text)?;
+    fs::rename(&temp, &path)
+}
+
+impl std::str::FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(text: &str) -> Result<Config, ConfigError> {
+        let table = parse(text)?;
+        let root = Section::root(&table);
+        root.deny_unknown(&[
+            "storage",
+            "report",
+            "syslog",
+            "journald",
+            "mqtt",
+            "kafka",
+            "socket",
+            "pushgateway",
+            "zabbix",
+            "healthchecks",
+            "uptime_kuma",
+            "speedtest",
+            "alerts",
+            "rule",
+            "maintenance",
+            "desktop",
+            "webhook",
+            "email",
+            "pagerduty",
+            "opsgenie",
+            "telegram",
+            "sound",
+            "snapshot",
+            "history",
+            "agent",
+            "central",
+            "cluster",
+            "http",
+            "grpc",
+            "probe",
+            "script",
+            "processor",
+            "wasm_sink",
+            "discovery",
+            "keys",
+            "dashboard",
+            "target",
+        ])?;
+        let targets = root
+            .tables("target")?
+            .iter()
+            .map(parse_target)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, target) in targets.iter().enumerate() {
+            if targets[..i].iter().any(|t| t.name == target.name) {
+                return Err(ConfigError::invalid(format!(
+                    "target: two targets are named '{}'",
+                    target.name
+                )));
+            }
+        }
+        let snapshot_dir = match root.table("snapshot")? {
+            Some(snapshot) => {
+                snapshot.deny_unknown(&["dir"])?;
+                snapshot.string("dir")?.map(PathBuf::from)
+            }
+            None => None,
+        };
+        let (confirm_quit, view) = match root.table("dashboard")? {
+            Some(dashboard) => {
+                dashboard.deny_unknown(&["confirm_quit", "view"])?;
+                let view = dashboard.string("view")?;
+                if let Some(other) = view
+                    .as_deref()
+                    .filter(|view| !["graph", "table", "summary"].contains(view))
+                {
+                    return Err(dashboard.error(
+                        "view",
+                        format!("unknown view '{}', expected graph, table or summary", other),
+                    ));
+                }
+                (dashboard.boolean("confirm_quit")?.unwrap_or(false), view)
+            }
+            None => (false, None),
+        };
+        let history_memory = match root.table("history")? {
+            Some(history) => {
+                history.deny_unknown(&["memory"])?;
+                match history.string("memory")? {
+                    Some(memory) => Some(parse_size(&memory).ok_or_else(|| {
+                        history.error("memory", format!("invalid size '{}'", memory))
+                    })?),
+                    None => None,
+                }
+            }
+            None => None,
+        };
+        let mut simulations = Vec::new();
+        let mut wasm_probe = None;
+        let mut tcp_port = None;
+        let mut proxies = ProxyRules::default();
+        let mut networks = Vec::new();
+        let mut jumps = Vec::new();
+        let mut routers = Vec::new();
+        let mut dns_refresh = None;
+        let probe = match root.table("probe")? {
+            Some(probe) => {
+                probe.deny_unknown(&[
+                    "kind",
+                    "simulate",
+                    "port",
+                    "proxy",
+                    "route",
+                    "network",
+                    "module",
+                    "runtime",
+                    "timeout",
+                    "dns_refresh",
+                    "ssh",
+                    "router",
+                ])?;
+                dns_refresh = probe.duration("dns_refresh")?;
+                for simulation in probe.tables("simulate")? {
+                    simulation.deny_unknown(&["target", "pattern"])?;
+                    let pattern = simulation
+                        .required_string("pattern")?
+                        .parse()
+                        .map_err(|e| simulation.error("pattern", e))?;
+                    simulations.push((simulation.required_string("target")?, pattern));
+                }
+                let kind = probe.string("kind")?;
+                if kind.as_deref() == Some("wasm") {
+                    wasm_probe = Some(WasmConfig::parse(&probe, WASM_PROBE_RUNTIME)?);
+                } else if let Some(key) = ["module", "runtime", "timeout"]
+                    .into_iter()
+                    .find(|key| probe.table.contains_key(*key))
+                {
+                    return Err(probe.error(key, "only applies to kind = \"wasm\""));
+                }
+                // Pinging falls back to TCP connects.
+                let tcp = matches!(kind.as_deref(), None | Some("icmp" | "ping" | "tcp"));
+                // Game and mail probes connect to a port too.
+                let service =
+                    matches!(kind.as_deref(), Some("a2s" | "minecraft" | "smtp" | "imap"));
+                if !tcp && !service && probe.table.contains_key("port") {
+                    return Err(probe.error(
+                        "port",
+                        "only applies to kinds tcp, a2s, minecraft, smtp and imap",
+                    ));
+                }
+                if let Some(key) = ["proxy", "route"]
+                    .into_iter()
+                    .find(|key| !tcp && probe.table.contains_key(*key))
+                {
+                    return Err(probe.error(key, "only applies to kind = \"tcp\""));
+                }
+                if !tcp && !service && probe.table.contains_key("network") {
+                    return Err(probe.error(
+                        "network",
+                        "only applies to kinds icmp, ping, tcp, a2s, minecraft, smtp and imap",
+                    ));
+                }
+                networks = probe
+                    .tables("network")?
+                    .iter()
+                    .map(parse_network)
+                    .collect::<Result<_, _>>()?;
+                jumps = probe
+                    .tables("ssh")?
+                    .iter()
+                    .map(parse_jump)
+                    .collect::<Result<Vec<_>, _>>()?;
+                routers = probe
+                    .tables("router")?
+                    .iter()
+                    .map(parse_router)
+                    .collect::<Result<Vec<_>, _>>()?;
+                // Both prefix the names of the targets pinged there.
+                let names: Vec<&str> = jumps
+                    .iter()
+                    .map(|jump| jump.name.as_str())
+                    .chain(routers.iter().map(|router| router.name.as_str()))
+                    .collect();
+                for (i, name) in names.iter().enumerate() {
+                    if names[..i].contains(name) {
+                        return Err(ConfigError::invalid(format!(
+                            "probe: two machines or routers are named '{}'",
+                            name
+                        )));
+                    }
+                }
+                proxies.default = parse_proxy(&probe)?;
+                for route in probe.tables("route")? {
+                    route.deny_unknown(&["targets", "proxy"])?;
+                    proxies.routes.push(ProxyRoute {
+                        targets: route.strings("targets")?,
+                        proxy: parse_proxy(&route)?,
+                    });
+                }
+                if let Some(port) = probe.integer("port")? {
+                    tcp_port = Some(
+                        u16::try_from(port)
+                            .ok()
+                            .filter(|&port| port != 0)
+                            .ok_or_else(|| probe.error("port", format!("invalid port {}", port)))?,
+                    );
+                }
+                kind
+            }
+            None => None,
+        };
+
+        let storage = root
+            .table("storage")?
+            .map(|s| StorageConfig::parse(&s))
+            .transpose()?;
+        let speedtest = root
+            .table("speedtest")?
+            .map(|s| SpeedtestConfig::parse(&s, storage.as_ref()))
+            .transpose()?;
+
+        Ok(Config {
+            targets,
+            storage,
+            speedtest,
+            report: root
+                .table("report")?
+                .map(|s| ReportConfig::parse(&s))
+                .transpose()?,
+            syslog: root
+                .table("syslog")?
+                .map(|s| SyslogConfig::parse(&s))
+                .transpose()?,
+            journald: root
+                .table("journald")?
+                .map(|s| JournaldConfig::parse(&s))
+                .transpose()?,
+            mqtt: root
+                .table("mqtt")?
+                .map(|s| MqttConfig::parse(&s))
+                .transpose()?,
+            kafka: root
+                .table("kafka")?
+                .map(|s| KafkaConfig::parse(&s))
+                .transpose()?,
+            socket: root
+                .table("socket")?
+                .map(|s| SocketConfig::parse(&s))
+                .transpose()?,
+            pushgateway: root
+                .table("pushgateway")?
+                .map(|s| PushgatewayConfig::parse(&s))
+                .transpose()?,
+            zabbix: root
+                .table("zabbix")?
+                .map(|s| ZabbixConfig::parse(&s))
+                .transpose()?,
+            healthchecks: root
+                .table("healthchecks")?
+                .map(|s| HealthchecksConfig::parse(&s))
+                .transpose()?,
+            uptime_kuma: root
+                .table("uptime_kuma")?
+                .map(|s| UptimeKumaConfig::parse(&s))
+                .transpose()?,
+            alerts: root
+                .table("alerts")?
+                .map(|s| AlertsConfig::parse(&s))
+                .transpose()?
+                .unwrap_or_default(),
+            rules: root
+                .tables("rule")?
+                .iter()
+                .map(parse_rule)
+                .collect::<Result<_, _>>()?,
+            maintenance: root
+                .tables("maintenance")?
+                .iter()
+                .map(parse_maintenance)
+                .collect::<Result<_, _>>()?,
+            desktop: root
+                .table("desktop")?
+                .map(|s| DesktopConfig::parse(&s))
+                .transpose()?,
+            sound: root
+                .table("sound")?
+                .map(|s| SoundConfig::parse(&s))
+                .transpose()?,
+            webhooks: root
+                .tables("webhook")?
+                .iter()
+                .map(WebhookConfig::parse)
+                .collect::<Result<_, _>>()?,
+            emails: root
+                .tables("email")?
+                .iter()
+                .map(EmailConfig::parse)
+                .collect::<Result<_, _>>()?,
+            pagerduty: root
+                .table("pagerduty")?
+                .map(|s| PagerDutyConfig::parse(&s))
+                .transpose()?,
+            opsgenie: root
+                .table("opsgenie")?
+                .map(|s| OpsgenieConfig::parse(&s))
+                .transpose()?,
+            telegram: root
+                .table("telegram")?
+                .map(|s| TelegramConfig::parse(&s))
+                .transpose()?,
+            agent: root
+                .table("agent")?
+                .map(|s| AgentConfig::parse(&s))
+                .transpose()?,
+            central: root
+                .table("central")?
+                .map(|s| CentralConfig::parse(&s))
+                .transpose()?,
+            cluster: root
+                .table("cluster")?
+                .map(|s| ClusterConfig::parse(&s))
+                .transpose()?,
+            http: root
+                .table("http")?
+                .map(|s| HttpConfig::parse(&s))
+                .transpose()?,
+            grpc: root
+                .table("grpc")?
+                .map(|s| GrpcConfig::parse(&s))
+                .transpose()?,
+            script: root
+                .table("script")?
+                .map(|s| ScriptConfig::parse(&s))
+                .transpose()?,
+            processors: root
+                .tables("processor")?
+                .iter()
+                .map(parse_processor)
+                .collect::<Result<_, _>>()?,
+            discovery: root
+                .tables("discovery")?
+                .iter()
+                .map(parse_discovery)
+                .collect::<Result<_, _>>()?,
+            wasm_sinks: root
+                .tables("wasm_sink")?
+                .iter()
+                .map(|s| {
+                    s.deny_unknown(&["module", "runtime"])?;
+                    WasmConfig::parse(s, WASM_SINK_RUNTIME)
+                })
+                .collect::<Result<_, _>>()?,
+            keys: match root.table("keys")? {
+                Some(keys) => parse_keys(&keys)?,
+                None => Keymap::default(),
+            },
+            confirm_quit,
+            view,
+            snapshot_dir,
+            history_memory,
+            probe,
+            simulations,
+            tcp_port,
+            proxies,
+            networks,
+            jumps,
+            routers,
+            dns_refresh,
+            wasm_probe,
+        })
+    }
+}
+
+/// The `[keys]` section: action names and the keys bound to them, see
+/// [`crate::keys`].
+fn parse_keys(section: &Section) -> Result<Keymap, ConfigError> {
+    let names: Vec<&str> = ACTIONS.iter().map(|(_, name, _)| *name).collect();
+    section.deny_unknown(&names)?;
+    let bindings = section
+        .table
+        .keys()
+        .map(|name| {
+            let keys = section
+                .strings(name)?
+                .iter()
+                .map(|keys| keys::parse_sequence(keys).map_err(|e| section.error(name, e)))
+                .collect::<Result<_, _>>()?;
+            let action = Action::named(name).expect("checked by deny_unknown");
+            Ok((action, keys))
+        })
+        .collect::<Result<Vec<_>, ConfigError>>()?;
+    Keymap::with_bindings(&bindings).map_err(|e| ConfigError::invalid(format!("keys: {}", e)))
+}
+
+/// A preset file, see [`crate::presets`]: its description and its
+/// `[[target]]`s, in order, as in the configuration.
+pub fn parse_preset(text: &str) -> Result<(String, Vec<Target>, Vec<Discovery>), ConfigError> {
+    let table = parse(text)?;
+    let root = Section::root(&table);
+    root.deny_unknown(&["description", "target", "discovery"])?;
+    let targets = root
+        .tables("target")?
+        .iter()
+        .map(parse_target)
+        .collect::<Result<Vec<_>, _>>()?;
+    let discovery = root
+        .tables("discovery")?
+        .iter()
+        .map(parse_discovery)
+        .collect::<Result<Vec<_>, _>>()?;
+    if targets.is_empty() && discovery.is_empty() {
+        return Err(root.error("target", "missing"));
+    }
+    Ok((
+        root.string("description")?.unwrap_or_default(),
+        targets,
+        discovery,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        text.parse::<Config>().unwrap_err().to_string()
+    }
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn reads_strings_and_their_escapes() {
+        let table = parse(concat!(
+            r#"basic = "tab\t quote\" backslash\\ newline\n \u00e9""#,
+            "\n",
+            r#"literal = 'C:\no\escapes "here"'"#,
+            "\n",
+            r#""quoted key" = """#,
+        ))
+        .unwrap();
+        assert_eq!(
+            table["basic"],
+            string("tab\t quote\" backslash\\ newline\n \u{e9}")
+        );
+        assert_eq!(table["literal"], string(r#"C:\no\escapes "here""#));
+        assert_eq!(table["quoted key"], string(""));
+
+        for (text, message) in [
+            ("a = \"open", "line 1: unterminated string"),
+            ("a = 'open\nb = 1", "line 1: unterminated string"),
+            (r#"a = "\q""#, r"line 1: invalid escape '\q'"),
+            (r#"a = "\u12""#, r#"line 1: invalid escape '\u12"'"#),
+            (r#"a = "\ud800""#, r"line 1: invalid escape '\ud800'"),
+        ] {
+            assert_eq!(parse(text).unwrap_err().to_string(), message, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_numbers_booleans_and_inline_tables() {
+        let table = parse("a = 1_000\nb = -2.5\nc = true\nd = { e = false, f.g = 'h' }").unwrap();
+        assert_eq!(table["a"], Value::Integer(1000));
+        assert_eq!(table["b"], Value::Float(-2.5));
+        assert_eq!(table["c"], Value::Boolean(true));
+        let Value::Table(d) = &table["d"] else {
+            panic!("d is {:?}", table["d"]);
+        };
+        assert_eq!(d["e"], Value::Boolean(false));
+        assert_eq!(
+            d["f"],
+            Value::Table(Table::from([("g".to_string(), string("h"))]))
+        );
+        assert_eq!(
+            parse("a = 1x").unwrap_err().to_string(),
+            "line 1: invalid number '1x'"
+        );
+        assert_eq!(
+            parse("a = yes").unwrap_err().to_string(),
+            "line 1: unexpected 'y' at start of value"
+        );
+    }
+
+    #[test]
+    fn reads_arrays() {
+        let table = parse(concat!(
+            "empty = []\n",
+            "mixed = [1, 'two', [3.0]]\n",
+            "long = [\n",
+            "    'a', # the first\n",
+            "    'b',\n",
+            "]\n",
+        ))
+        .unwrap();
+        assert_eq!(table["empty"], Value::Array(Vec::new()));
+        assert_eq!(
+            table["mixed"],
+            Value::Array(vec![
+                Value::Integer(1),
+                string("two"),
+                Value::Array(vec![Value::Float(3.0)]),
+            ])
+        );
+        assert_eq!(table["long"], Value::Array(vec![string("a"), string("b")]));
+        assert_eq!(
+            parse("a = [1 2]").unwrap_err().to_string(),
+            "line 1: expected ',' or ']' in array"
+        );
+    }
+
+    #[test]
+    fn reads_tables_and_arrays_of_tables() {
+        let table = parse(concat!(
+            "[storage]\n",
+            "path = 'a'\n",
+            "\n",
+            "[[storage.archive]]\n",
+            "step = 10\n",
+            "\n",
+            "[[storage.archive]]\n",
+            "step = 60\n",
+            "[storage.archive.extra]\n",
+            "x = 1\n",
+        ))
+        .unwrap();
+        let Value::Table(storage) = &table["storage"] else {
+            panic!("storage is {:?}", table["storage"]);
+        };
+        assert_eq!(storage["path"], string("a"));
+        let Value::Array(archives) = &storage["archive"] else {
+            panic!("archive is {:?}", storage["archive"]);
+        };
+        let first = Table::from([("step".to_string(), Value::Integer(10))]);
+        let extra = Table::from([("x".to_string(), Value::Integer(1))]);
+        let second = Table::from([
+            ("step".to_string(), Value::Integer(60)),
+            ("extra".to_string(), Value::Table(extra)),
+        ]);
+        assert_eq!(archives, &[Value::Table(first), Value::Table(second)]);
+
+        for (text, message) in [
+            ("a = 1\n[a]", "line 2: 'a' is not a table"),
+            ("[a]\n[[a]]", "line 2: 'a' is not an array of tables"),
+            ("[a]\nb = 1\nb = 2", "line 3: duplicate key 'b'"),
+            ("[a] b = 1", "line 1: unexpected 'b' after value"),
+        ] {
+            assert_eq!(parse(text).unwrap_err().to_string(), message, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_durations() {
+        for (text, expected) in [
+            ("250ms", Duration::from_millis(250)),
+            ("10", Duration::from_secs(10)),
+            ("1.5s", Duration::from_millis(1500)),
+            ("5 m", Duration::from_secs(300)),
+            ("2h", Duration::from_secs(7200)),
+            ("7d", Duration::from_secs(7 * 86400)),
+        ] {
+            assert_eq!(parse_duration(text), Some(expected), "{}", text);
+        }
+        for text in ["", "s", "-1s", "10 parsecs", "1.2.3s"] {
+            assert_eq!(parse_duration(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_storage() {
+        let config: Config = concat!(
+            "[storage]\n",
+            "path = 'latencee.rrd'\n",
+            "[[storage.archive]]\n",
+            "step = 10\n",
+            "rows = 360\n",
+            "[[storage.archive]]\n",
+            "step = '5m'\n",
+            "rows = 288\n",
+        )
+        .parse()
+        .unwrap();
+        let storage = config.storage.unwrap();
+        assert_eq!(storage.path, PathBuf::from("latencee.rrd"));
+        assert_eq!(
+            storage.archives,
+            [
+                ArchiveSpec {
+                    step: Duration::from_secs(10),
+                    rows: 360,
+                },
+                ArchiveSpec {
+                    step: Duration::from_secs(300),
+                    rows: 288,
+                },
+            ]
+        );
+        let config: Config = "[storage]\npath = 'a'".parse().unwrap();
+        assert_eq!(
+            config.storage.unwrap().archives,
+            StorageConfig::default_archives()
+        );
+    }
+
+    #[test]
+    fn names_the_key_of_wrong_values() {
+        let archive = |step: &str, rows: &str| {
+            error(&format!(
+                "[storage]\npath = 'a'\n[[storage.archive]]\nstep = {}\nrows = {}",
+                step, rows
+            ))
+        };
+        assert_eq!(
+            archive("'10s'", "'many'"),
+            "storage.archive.rows: expected integer, found string"
+        );
+        assert_eq!(
+            archive("true", "1"),
+            "storage.archive.step: expected duration, found boolean"
+        );
+        assert_eq!(
+            archive("'10 parsecs'", "1"),
+            "storage.archive.step: invalid duration '10 parsecs'"
+        );
+        assert_eq!(
+            archive("'1.5s'", "1"),
+            "storage.archive.step: must be a positive number of whole seconds"
+        );
+        assert_eq!(archive("10", "0"), "storage.archive.rows: must be positive");
+        assert_eq!(
+            error("storage = 1"),
+            "storage: expected table, found integer"
+        );
+        assert_eq!(error("[storage]"), "storage.path: is required");
+    }
+
+    #[test]
+    fn names_unknown_keys() {
+        assert_eq!(error("[storage]\npaht = 'a'"), "storage.paht: unknown key");
+        assert_eq!(
+            error("[storage]\npath = 'a'\n[[storage.archive]]\nsteps = 10"),
+            "storage.archive.steps: unknown key"
+        );
+        assert_eq!(error("[storgae]\npath = 'a'"), "storgae: unknown key");
+    }
+}
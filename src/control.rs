@@ -0,0 +1,117 @@
+//! Driving a running daemon over a Unix socket.
+//!
+//! A client connects, writes one request line and reads the answer until the
+//! daemon closes the connection. The answer starts with `ok` or
+//! `error: <reason>`, followed by any output:
+//!
+//! ```text
+//! add <host> <name>    start probing <host>, displayed as <name>
+//! remove <name>        stop probing a target and forget it
+//! pause <name>         stop probing a target, keeping its history
+//! resume <name>        probe a paused target again
+//! state                one JSON object per target
+//! ```
+//!
+//! Names are the rest of the line and may contain spaces.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    Add { host: String, name: String },
+    Remove(String),
+    Pause(String),
+    Resume(String),
+    State,
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let name = |command: &str| {
+            if rest.is_empty() {
+                Err(format!("{} needs a target name", command))
+            } else {
+                Ok(rest.to_string())
+            }
+        };
+        match command {
+            "add" => match rest.split_once(' ') {
+                Some((host, name)) if !name.trim().is_empty() => Ok(Request::Add {
+                    host: host.to_string(),
+                    name: name.trim().to_string(),
+                }),
+                _ => Err("add needs a host and a name".to_string()),
+            },
+            "remove" => name(command).map(Request::Remove),
+            "pause" => name(command).map(Request::Pause),
+            "resume" => name(command).map(Request::Resume),
+            "state" => Ok(Request::State),
+            "" => Err("empty request".to_string()),
+            other => Err(format!("unknown command '{}'", other)),
+        }
+    }
+}
+
+/// The daemon's end of the control socket.
+pub struct Server {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl Server {
+    /// Listens on `path`, replacing a socket left behind by a previous run.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            path: path.to_path_buf(),
+            listener,
+        })
+    }
+
+    /// Answers every waiting client with `handle`, which returns the output
+    /// lines of a request or why it failed.
+    pub fn poll(&self, mut handle: impl FnMut(Request) -> Result<Vec<String>, String>) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            let mut line = String::new();
+            if BufReader::new(&stream).read_line(&mut line).is_err() {
+                continue;
+            }
+            let answer = match line.parse().and_then(&mut handle) {
+                Ok(output) => std::iter::once("ok".to_string())
+                    .chain(output)
+                    .map(|line| line + "\n")
+                    .collect(),
+                Err(reason) => format!("error: {}\n", reason),
+            };
+            let _ = (&stream).write_all(answer.as_bytes());
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
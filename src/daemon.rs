@@ -0,0 +1,144 @@
+//! Running the monitor in the background and controlling it.
+//!
+//! `daemon start` re-runs this executable as `daemon run` in its own process
+//! group with output going to a log file. The pidfile, sockets and log live
+//! in `$XDG_RUNTIME_DIR` or the temporary directory.
+
+use latencee::{attach, control};
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+fn dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
+
+pub fn pid_path() -> PathBuf {
+    dir().join("latencee.pid")
+}
+
+pub fn socket_path() -> PathBuf {
+    dir().join("latencee.sock")
+}
+
+pub fn control_path() -> PathBuf {
+    dir().join("latencee.ctl")
+}
+
+pub fn log_path() -> PathBuf {
+    dir().join("latencee.log")
+}
+
+/// The sockets a running daemon serves: results for attached dashboards and
+/// requests from scripts.
+pub struct Sockets {
+    pub attach: attach::Server,
+    pub control: control::Server,
+}
+
+impl Sockets {
+    pub fn bind() -> io::Result<Self> {
+        Ok(Sockets {
+            attach: attach::Server::bind(&socket_path())?,
+            control: control::Server::bind(&control_path())?,
+        })
+    }
+}
+
+/// Sends `signal` to `pid` with kill(1); signal 0 only checks that it exists.
+fn kill(pid: u32, signal: &str) -> bool {
+    Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg(pid.to_string())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The pid of the running daemon, if any.
+pub fn running() -> Option<u32> {
+    let pid = fs::read_to_string(pid_path()).ok()?.trim().parse().ok()?;
+    kill(pid, "0").then_some(pid)
+}
+
+/// Records the current process as the daemon.
+pub fn write_pidfile() -> io::Result<()> {
+    fs::write(pid_path(), format!("{}\n", process::id()))
+}
+
+pub fn start(config: Option<&Path>) -> io::Result<()> {
+    if let Some(pid) = running() {
+        println!("latencee is already running (pid {})", pid);
+        return Ok(());
+    }
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path())?;
+    let mut command = Command::new(env::current_exe()?);
+    command.args(["daemon", "run"]);
+    if let Some(config) = config {
+        command.arg("--config").arg(fs::canonicalize(config)?);
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Keep terminal signals such as Ctrl+C away from the daemon.
+        .process_group(0)
+        .spawn()?;
+
+    // Give it a moment to fail on a bad configuration.
+    thread::sleep(Duration::from_millis(500));
+    if child.try_wait()?.is_some() || running() != Some(child.id()) {
+        return Err(io::Error::other(format!(
+            "daemon exited, see {}",
+            log_path().display()
+        )));
+    }
+    println!("latencee started (pid {})", child.id());
+    Ok(())
+}
+
+pub fn stop() -> io::Result<()> {
+    let Some(pid) = running() else {
+        println!("latencee is not running");
+        return Ok(());
+    };
+    if !kill(pid, "TERM") {
+        return Err(io::Error::other(format!("cannot stop pid {}", pid)));
+    }
+    for path in [pid_path(), socket_path(), control_path()] {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    println!("latencee stopped (pid {})", pid);
+    Ok(())
+}
+
+/// Prints whether the daemon runs and returns the LSB status exit code.
+pub fn status() -> i32 {
+    match running() {
+        Some(pid) => {
+            println!("latencee is running (pid {})", pid);
+            println!("socket: {}", socket_path().display());
+            println!("control: {}", control_path().display());
+            println!("log: {}", log_path().display());
+            0
+        }
+        None => {
+            println!("latencee is not running");
+            3
+        }
+    }
+}
@@ -0,0 +1,820 @@
+//! Targets found at runtime instead of listed by hand, from `[[discovery]]`
+//! sections.
+//!
+//! Every source is queried on its own thread, again every `refresh`. Each
+//! answer is the complete set of targets of that source: the caller starts
+//! probing the new ones and stops probing those that are gone. A failed
+//! query keeps the previous set.
+//!
+//! Kubernetes is queried with `kubectl`, which takes care of the kubeconfig
+//! and its credentials; Consul's HTTP API, and Docker's on its socket, with
+//! `curl`; tailscaled with `tailscale status`, which reads its local API;
+//! WireGuard with `wg show`; the relays of Steam and the voice regions of
+//! Discord from their public lists, with `curl`.
+//! SRV records are
+//! looked up again when their TTL runs out rather than every `refresh`.
+
+use crate::rt::channel;
+use crate::{
+    Target, dns,
+    json::{self, Json},
+    probe::Note,
+    timefmt,
+};
+use std::{
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of targets and how often it is queried.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    pub source: Source,
+    pub refresh: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum Source {
+    Kubernetes(Kubernetes),
+    Consul(Consul),
+    Srv(Srv),
+    Tailscale(Tailscale),
+    Docker(Docker),
+    WireGuard(WireGuard),
+    Steam(Steam),
+    Discord(Discord),
+}
+
+/// Services of a Kubernetes cluster matching a label selector.
+#[derive(Debug, Clone)]
+pub struct Kubernetes {
+    /// Label selector, e.g. `app=web,tier!=canary`.
+    pub selector: String,
+    /// Namespace to look in; `None` for all of them.
+    pub namespace: Option<String>,
+    pub addresses: KubernetesAddresses,
+    pub kubeconfig: Option<PathBuf>,
+    pub context: Option<String>,
+    /// Path or name of the kubectl binary.
+    pub kubectl: String,
+}
+
+/// Instances of services registered with Consul.
+#[derive(Debug, Clone)]
+pub struct Consul {
+    /// URL of the Consul agent, e.g. `http://127.0.0.1:8500`.
+    pub address: String,
+    /// Names of the services; every service in the catalog when empty.
+    pub services: Vec<String>,
+    /// A Consul filter expression the instances must match, e.g.
+    /// `"edge" in Service.Tags`.
+    pub filter: Option<String>,
+    /// Whether only instances passing their health checks are probed.
+    pub passing: bool,
+    pub datacenter: Option<String>,
+    /// ACL token; `CONSUL_HTTP_TOKEN` when not set.
+    pub token: Option<String>,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+/// The hosts and ports in the SRV records of a name.
+#[derive(Debug, Clone)]
+pub struct Srv {
+    /// The name looked up, e.g. `_sip._udp.example.com`.
+    pub name: String,
+    /// `None` for the first nameserver of the system.
+    pub nameserver: Option<SocketAddr>,
+}
+
+/// The peers of this node in its tailnet, probed at their Tailscale IPs.
+#[derive(Debug, Clone)]
+pub struct Tailscale {
+    /// Whether peers that are offline are probed too.
+    pub offline: bool,
+    /// The tailscaled socket, if not the default.
+    pub socket: Option<PathBuf>,
+    /// Path or name of the tailscale binary.
+    pub tailscale: String,
+}
+
+/// Running containers with the given labels.
+#[derive(Debug, Clone)]
+pub struct Docker {
+    /// `key` or `key=value` labels the containers must all have.
+    pub labels: Vec<String>,
+    pub addresses: DockerAddresses,
+    /// Host probed at ports published on every interface.
+    pub host: String,
+    /// Network whose address is probed; the first one when `None`.
+    pub network: Option<String>,
+    /// The Docker socket.
+    pub socket: PathBuf,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+/// Which address of a container is probed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DockerAddresses {
+    /// The first TCP port published on the host; containers without one
+    /// are skipped.
+    Published,
+    /// The container's own address on its network.
+    Container,
+}
+
+/// The peers of WireGuard interfaces, pinged at their tunnel addresses.
+#[derive(Debug, Clone)]
+pub struct WireGuard {
+    /// The interface; every one when `None`.
+    pub interface: Option<String>,
+    /// Names of peers by public key.
+    pub names: Vec<(String, String)>,
+    /// Age from which a handshake is stale.
+    pub stale: Duration,
+    /// Path or name of the wg binary.
+    pub wg: String,
+}
+
+/// The points of presence of Valve's Steam Datagram Relay network, which
+/// carries the traffic of Valve's games, pinged at their first relay.
+#[derive(Debug, Clone)]
+pub struct Steam {
+    /// The app whose network configuration is fetched, e.g. 730 for
+    /// Counter-Strike 2.
+    pub app: u32,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+/// Discord's voice regions, from the list its client picks the closest
+/// region from, pinged at their first address.
+#[derive(Debug, Clone)]
+pub struct Discord {
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+/// Where Steam publishes the relay network configuration.
+const STEAM_SDR_CONFIG: &str = "https://api.steampowered.com/ISteamApps/GetSDRConfig/v1/";
+
+/// Where Discord lists its voice regions and their addresses.
+const DISCORD_REGIONS: &str = "https://latency.discord.media/rtc";
+
+/// SRV records are looked up again no more often than this, whatever their
+/// TTL.
+const MIN_SRV_REFRESH: Duration = Duration::from_secs(5);
+
+/// How long a nameserver has to answer, however long the refresh.
+const SRV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a query of a source found.
+pub struct Found {
+    pub targets: Vec<Target>,
+    /// How long the answer holds, if the source tells; `refresh` otherwise.
+    pub ttl: Option<Duration>,
+}
+
+/// Which addresses of the matching services are probed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KubernetesAddresses {
+    /// The ready pods behind each service, named `<namespace>/<pod>`.
+    Pods,
+    /// The cluster IP of each service, named `<namespace>/<service>`.
+    Services,
+}
+
+impl Source {
+    /// Names the source in errors.
+    pub fn describe(&self) -> String {
+        match self {
+            Source::Kubernetes(kubernetes) => format!("kubernetes {}", kubernetes.selector),
+            Source::Consul(consul) => format!("consul {}", consul.address),
+            Source::Srv(srv) => format!("srv:{}", srv.name),
+            Source::Tailscale(_) => "tailscale".to_string(),
+            Source::Docker(docker) => format!("docker {}", docker.socket.display()),
+            Source::WireGuard(wireguard) => {
+                format!(
+                    "wireguard {}",
+                    wireguard.interface.as_deref().unwrap_or("all")
+                )
+            }
+            Source::Steam(steam) => format!("steam app {}", steam.app),
+            Source::Discord(_) => "discord".to_string(),
+        }
+    }
+
+    /// Every target the source currently has.
+    pub fn discover(&self, timeout: Duration) -> io::Result<Found> {
+        let targets = match self {
+            Source::Kubernetes(kubernetes) => kubernetes.discover(timeout)?,
+            Source::Consul(consul) => consul.discover(timeout)?,
+            Source::Srv(srv) => return srv.discover(timeout),
+            Source::Tailscale(tailscale) => tailscale.discover()?,
+            Source::Docker(docker) => docker.discover(timeout)?,
+            Source::WireGuard(wireguard) => wireguard.discover()?,
+            Source::Steam(steam) => steam.discover(timeout)?,
+            Source::Discord(discord) => discord.discover(timeout)?,
+        };
+        Ok(Found { targets, ttl: None })
+    }
+}
+
+impl Kubernetes {
+    fn discover(&self, timeout: Duration) -> io::Result<Vec<Target>> {
+        let resource = match self.addresses {
+            KubernetesAddresses::Pods => "endpoints",
+            KubernetesAddresses::Services => "services",
+        };
+        let mut command = Command::new(&self.kubectl);
+        command
+            .args(["get", resource, "--output", "json", "--selector"])
+            .arg(&self.selector)
+            .arg(format!("--request-timeout={}s", timeout.as_secs().max(1)));
+        match &self.namespace {
+            Some(namespace) => command.args(["--namespace", namespace]),
+            None => command.arg("--all-namespaces"),
+        };
+        if let Some(kubeconfig) = &self.kubeconfig {
+            command.arg("--kubeconfig").arg(kubeconfig);
+        }
+        if let Some(context) = &self.context {
+            command.args(["--context", context]);
+        }
+        let list = run_json(&mut command)?;
+        let items = list
+            .get("items")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("kubectl answered without items"))?;
+        Ok(match self.addresses {
+            KubernetesAddresses::Pods => items.iter().flat_map(pod_targets).collect(),
+            KubernetesAddresses::Services => items.iter().filter_map(service_target).collect(),
+        })
+    }
+}
+
+impl Consul {
+    fn discover(&self, timeout: Duration) -> io::Result<Vec<Target>> {
+        let services = if self.services.is_empty() {
+            // An object of service names and their tags.
+            match self.get("/v1/catalog/services", false, timeout)? {
+                Json::Object(services) => services
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| name != "consul")
+                    .collect(),
+                _ => return Err(invalid("Consul listed no services")),
+            }
+        } else {
+            self.services.clone()
+        };
+        let mut targets: Vec<Target> = Vec::new();
+        for service in services {
+            let path = format!("/v1/health/service/{}", service);
+            let instances = self.get(&path, true, timeout)?;
+            for instance in instances.as_array().unwrap_or_default() {
+                let Some(mut target) = consul_target(&service, instance) else {
+                    continue;
+                };
+                // Several instances of a service on one node.
+                if targets.iter().any(|t| t.name == target.name)
+                    && let Some(id) = instance
+                        .get("Service")
+                        .and_then(|s| s.get("ID"))
+                        .and_then(Json::as_str)
+                {
+                    target.name = format!("{}/{}", target.name, id);
+                }
+                targets.push(target);
+            }
+        }
+        Ok(targets)
+    }
+
+    /// GETs `path` from the agent, with the instance filters when
+    /// `instances` is set.
+    fn get(&self, path: &str, instances: bool, timeout: Duration) -> io::Result<Json> {
+        let mut command = Command::new(&self.curl);
+        command
+            .args(["--silent", "--show-error", "--fail", "--get"])
+            .arg("--max-time")
+            .arg(timeout.as_secs().max(1).to_string());
+        let token = self
+            .token
+            .clone()
+            .or_else(|| std::env::var("CONSUL_HTTP_TOKEN").ok());
+        if let Some(token) = token {
+            command
+                .arg("--header")
+                .arg(format!("X-Consul-Token: {}", token));
+        }
+        if let Some(datacenter) = &self.datacenter {
+            command.args(["--data-urlencode", &format!("dc={}", datacenter)]);
+        }
+        if instances {
+            if self.passing {
+                command.args(["--data", "passing=true"]);
+            }
+            if let Some(filter) = &self.filter {
+                command.args(["--data-urlencode", &format!("filter={}", filter)]);
+            }
+        }
+        command.arg(format!("{}{}", self.address.trim_end_matches('/'), path));
+        run_json(&mut command)
+    }
+}
+
+impl Srv {
+    fn discover(&self, timeout: Duration) -> io::Result<Found> {
+        let nameserver = match self.nameserver {
+            Some(nameserver) => nameserver,
+            None => dns::system_nameserver()?,
+        };
+        let records = dns::lookup_srv(&self.name, nameserver, timeout.min(SRV_TIMEOUT))?;
+        let ttl = records.iter().map(|record| record.ttl).min();
+        let targets = records
+            .into_iter()
+            // A target of "." says the service is not offered.
+            .filter(|record| !record.target.is_empty())
+            .map(|record| {
+                let mut target = Target::new(
+                    format!("{}:{}", record.target, record.port),
+                    record.target.as_str(),
+                );
+                target.port = Some(record.port);
+                target
+            })
+            .collect();
+        Ok(Found {
+            targets,
+            ttl: ttl.map(|ttl| ttl.max(MIN_SRV_REFRESH)),
+        })
+    }
+}
+
+impl Tailscale {
+    fn discover(&self) -> io::Result<Vec<Target>> {
+        let mut command = Command::new(&self.tailscale);
+        if let Some(socket) = &self.socket {
+            command.arg("--socket").arg(socket);
+        }
+        command.args(["status", "--json"]);
+        let status = run_json(&mut command)?;
+        if let Some(state) = status.get("BackendState").and_then(Json::as_str)
+            && state != "Running"
+        {
+            return Err(io::Error::other(format!("tailscale is {}", state)));
+        }
+        let Some(Json::Object(peers)) = status.get("Peer") else {
+            // A tailnet of one.
+            return Ok(Vec::new());
+        };
+        let mut targets: Vec<Target> = peers
+            .iter()
+            .filter(|(_, peer)| {
+                self.offline || peer.get("Online").and_then(Json::as_bool).unwrap_or(false)
+            })
+            .filter_map(|(_, peer)| tailscale_target(peer))
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(targets)
+    }
+}
+
+impl Steam {
+    fn discover(&self, timeout: Duration) -> io::Result<Vec<Target>> {
+        let url = format!("{}?appid={}", STEAM_SDR_CONFIG, self.app);
+        let config = run_json(&mut get(&self.curl, &url, timeout))?;
+        let Some(Json::Object(pops)) = config.get("pops") else {
+            return Err(invalid("Steam listed no relay locations"));
+        };
+        let mut targets: Vec<Target> = pops
+            .iter()
+            .filter_map(|(code, pop)| {
+                // Locations served by partner networks list no relays.
+                let relay = pop.get("relays")?.as_array()?.first()?;
+                let ip = relay.get("ipv4")?.as_str()?;
+                let place = pop.get("desc").and_then(Json::as_str).unwrap_or(code);
+                Some(Target::new(format!("Valve {}", place), ip))
+            })
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        targets.dedup_by(|a, b| a.name == b.name);
+        Ok(targets)
+    }
+}
+
+impl Discord {
+    fn discover(&self, timeout: Duration) -> io::Result<Vec<Target>> {
+        let regions = run_json(&mut get(&self.curl, DISCORD_REGIONS, timeout))?;
+        let regions = regions
+            .as_array()
+            .ok_or_else(|| invalid("Discord listed no voice regions"))?;
+        let mut targets: Vec<Target> = regions
+            .iter()
+            .filter_map(|region| {
+                let name = region.get("region")?.as_str()?;
+                let ip = region.get("ips")?.as_array()?.first()?.as_str()?;
+                Some(Target::new(format!("Discord {}", name), ip))
+            })
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(targets)
+    }
+}
+
+/// A curl command GETting `url`.
+fn get(curl: &str, url: &str, timeout: Duration) -> Command {
+    let mut command = Command::new(curl);
+    command
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .arg("--max-time")
+        .arg(timeout.as_secs().max(1).to_string())
+        .arg(url);
+    command
+}
+
+/// A peer by its MagicDNS name, at its first Tailscale IP, IPv4 if it has
+/// one, noting whether traffic to it goes direct or through a DERP relay.
+fn tailscale_target(peer: &Json) -> Option<Target> {
+    let ips = peer.get("TailscaleIPs")?.as_array()?;
+    let ip = ips
+        .iter()
+        .filter_map(Json::as_str)
+        .find(|ip| !ip.contains(':'))
+        .or_else(|| ips.first()?.as_str())?;
+    let field = |key| {
+        peer.get(key)
+            .and_then(Json::as_str)
+            .filter(|s| !s.is_empty())
+    };
+    let name = field("DNSName")
+        .and_then(|name| name.split('.').next())
+        .filter(|name| !name.is_empty())
+        .or_else(|| field("HostName"))
+        .unwrap_or(ip);
+    let mut target = Target::new(name, ip);
+    // Tailscale only knows the path of peers it talked to recently, which
+    // probed peers are.
+    target.note = match (field("CurAddr"), field("Relay")) {
+        (Some(address), _) => Some(Note::info(format!("direct {}", address))),
+        (None, Some(relay)) => Some(Note::warning(format!("DERP {}", relay))),
+        (None, None) => None,
+    };
+    Some(target)
+}
+
+impl Docker {
+    fn discover(&self, timeout: Duration) -> io::Result<Vec<Target>> {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| Json::from(label.as_str()))
+            .collect::<Vec<_>>();
+        let filters = Json::object([
+            ("status", Json::Array(vec![Json::from("running")])),
+            ("label", Json::Array(labels)),
+        ]);
+        let mut command = Command::new(&self.curl);
+        command
+            .args(["--silent", "--show-error", "--fail", "--get"])
+            .arg("--max-time")
+            .arg(timeout.as_secs().max(1).to_string())
+            .arg("--unix-socket")
+            .arg(&self.socket)
+            .arg("--data-urlencode")
+            .arg(format!("filters={}", filters))
+            // The host part is ignored on a socket.
+            .arg("http://docker/containers/json");
+        let containers = run_json(&mut command)?;
+        let containers = containers
+            .as_array()
+            .ok_or_else(|| invalid("Docker listed no containers"))?;
+        let mut targets: Vec<Target> = containers
+            .iter()
+            .filter_map(|container| self.target(container))
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(targets)
+    }
+
+    /// A container by its name.
+    fn target(&self, container: &Json) -> Option<Target> {
+        let name = container
+            .get("Names")?
+            .as_array()?
+            .first()?
+            .as_str()?
+            .trim_start_matches('/');
+        let ports = container
+            .get("Ports")
+            .and_then(Json::as_array)
+            .unwrap_or_default();
+        let tcp = ports
+            .iter()
+            .filter(|port| port.get("Type").and_then(Json::as_str) == Some("tcp"));
+        let number = |port: &Json, key| {
+            port.get(key)
+                .and_then(Json::as_f64)
+                .and_then(|port| u16::try_from(port as u64).ok())
+        };
+        let (host, port) = match self.addresses {
+            DockerAddresses::Published => tcp
+                .filter_map(|port| Some((port, number(port, "PublicPort")?)))
+                // IPv4 first, as Docker lists every port for both.
+                .min_by_key(|(port, _)| {
+                    port.get("IP")
+                        .and_then(Json::as_str)
+                        .unwrap_or("")
+                        .contains(':')
+                })
+                .map(|(port, public)| {
+                    let host = match port.get("IP").and_then(Json::as_str) {
+                        None | Some("" | "0.0.0.0" | "::") => self.host.clone(),
+                        Some(ip) => ip.to_string(),
+                    };
+                    (host, Some(public))
+                })?,
+            DockerAddresses::Container => {
+                let Some(Json::Object(networks)) = container
+                    .get("NetworkSettings")
+                    .and_then(|settings| settings.get("Networks"))
+                else {
+                    return None;
+                };
+                let ip = networks
+                    .iter()
+                    .filter(|(network, _)| self.network.as_ref().is_none_or(|n| n == network))
+                    .find_map(|(_, settings)| {
+                        settings
+                            .get("IPAddress")?
+                            .as_str()
+                            .filter(|ip| !ip.is_empty())
+                    })?;
+                let private = tcp.into_iter().find_map(|port| number(port, "PrivatePort"));
+                (ip.to_string(), private)
+            }
+        };
+        let mut target = Target::new(name, host);
+        target.port = port;
+        Some(target)
+    }
+}
+
+impl WireGuard {
+    /// Reads `wg show <interface> dump`: a line per interface with four
+    /// fields, then a line per peer with eight, tab-separated. With `all`
+    /// every line starts with the interface.
+    fn discover(&self) -> io::Result<Vec<Target>> {
+        let interface = self.interface.as_deref().unwrap_or("all");
+        let program = &self.wg;
+        let output = Command::new(program)
+            .args(["show", interface, "dump"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", program, e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!(
+                "{} {}: {}",
+                program,
+                output.status,
+                stderr.lines().last().unwrap_or("")
+            )));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut targets = Vec::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields: Vec<&str> = line.split('\t').collect();
+            let interface = match self.interface.as_deref() {
+                Some(interface) => interface,
+                None if fields.len() > 1 => fields.remove(0),
+                None => continue,
+            };
+            // Interface lines have fewer fields.
+            let [key, _, endpoint, allowed, handshake, ..] = fields[..] else {
+                continue;
+            };
+            if fields.len() != 8 {
+                continue;
+            }
+            let Some(address) = tunnel_address(allowed) else {
+                continue;
+            };
+            let name = match self.names.iter().find(|(k, _)| k == key) {
+                Some((_, name)) => name.clone(),
+                None => format!("{}/{}", interface, &key[..key.len().min(8)]),
+            };
+            let mut target = Target::new(name, address);
+            let endpoint = match endpoint {
+                "(none)" => "no endpoint".to_string(),
+                endpoint => format!("via {}", endpoint),
+            };
+            target.note = Some(match handshake.parse::<u64>() {
+                Ok(0) | Err(_) => Note::warning(format!("no handshake, {}", endpoint)),
+                Ok(at) => {
+                    let age = Duration::from_secs(now.saturating_sub(at));
+                    let text = format!("handshake {} ago, {}", timefmt::duration(age), endpoint);
+                    if age >= self.stale {
+                        Note::warning(format!("stale {}", text))
+                    } else {
+                        Note::info(text)
+                    }
+                }
+            });
+            targets.push(target);
+        }
+        Ok(targets)
+    }
+}
+
+/// The peer's own address in its allowed IPs: the first single address,
+/// `/32` or `/128`. Peers routing only networks have none.
+fn tunnel_address(allowed: &str) -> Option<&str> {
+    allowed.split(',').find_map(|network| {
+        let (address, prefix) = network.trim().split_once('/')?;
+        match prefix {
+            "32" if !address.contains(':') => Some(address),
+            "128" if address.contains(':') => Some(address),
+            _ => None,
+        }
+    })
+}
+
+/// An instance from Consul's health endpoint as `<service>/<node>`, at the
+/// service's address or else the node's.
+fn consul_target(service: &str, instance: &Json) -> Option<Target> {
+    let node = instance.get("Node")?;
+    let registration = instance.get("Service")?;
+    let address = registration
+        .get("Address")
+        .and_then(Json::as_str)
+        .filter(|address| !address.is_empty())
+        .or_else(|| node.get("Address")?.as_str())?;
+    let name = node.get("Node").and_then(Json::as_str).unwrap_or(address);
+    let mut target = Target::new(format!("{}/{}", service, name), address);
+    target.port = registration
+        .get("Port")
+        .and_then(Json::as_f64)
+        .and_then(|port| u16::try_from(port as u64).ok())
+        .filter(|&port| port != 0);
+    Some(target)
+}
+
+/// `<namespace>/<name>` of an object.
+fn qualified_name(object: &Json, name: &str) -> String {
+    let namespace = object
+        .get("metadata")
+        .and_then(|m| m.get("namespace"))
+        .and_then(Json::as_str)
+        .unwrap_or("default");
+    format!("{}/{}", namespace, name)
+}
+
+fn name_of(object: &Json) -> Option<&str> {
+    object.get("metadata")?.get("name")?.as_str()
+}
+
+/// The first TCP port in `ports`, for the `tcp` probe.
+fn first_port(ports: Option<&Json>) -> Option<u16> {
+    ports?
+        .as_array()?
+        .iter()
+        .filter(|port| port.get("protocol").and_then(Json::as_str).unwrap_or("TCP") == "TCP")
+        .find_map(|port| port.get("port")?.as_f64())
+        .and_then(|port| u16::try_from(port as u64).ok())
+}
+
+/// The ready addresses of an Endpoints object.
+fn pod_targets(endpoints: &Json) -> Vec<Target> {
+    let subsets = endpoints.get("subsets").and_then(Json::as_array);
+    subsets
+        .into_iter()
+        .flatten()
+        .flat_map(|subset| {
+            let port = first_port(subset.get("ports"));
+            let addresses = subset.get("addresses").and_then(Json::as_array);
+            addresses.into_iter().flatten().filter_map(move |address| {
+                let ip = address.get("ip")?.as_str()?;
+                // Addresses without a pod, such as of a headless service
+                // with manual endpoints, go by their IP.
+                let pod = address
+                    .get("targetRef")
+                    .and_then(|r| r.get("name"))
+                    .and_then(Json::as_str)
+                    .unwrap_or(ip);
+                let mut target = Target::new(qualified_name(endpoints, pod), ip);
+                target.port = port;
+                Some(target)
+            })
+        })
+        .collect()
+}
+
+/// The cluster IP of a Service, unless it is headless.
+fn service_target(service: &Json) -> Option<Target> {
+    let spec = service.get("spec")?;
+    let ip = spec.get("clusterIP")?.as_str().filter(|ip| *ip != "None")?;
+    let mut target = Target::new(qualified_name(service, name_of(service)?), ip);
+    target.port = first_port(spec.get("ports"));
+    Some(target)
+}
+
+/// Runs `command` and parses its output as JSON, failing with the last
+/// line it wrote to stderr.
+fn run_json(command: &mut Command) -> io::Result<Json> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", program, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{} {}: {}",
+            program,
+            output.status,
+            stderr.lines().last().unwrap_or("")
+        )));
+    }
+    json::parse(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| invalid(&format!("{} printed no JSON", program)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// The targets of one source, as last discovered.
+pub struct Update {
+    /// Index of the source in the configuration.
+    pub source: usize,
+    pub targets: Vec<Target>,
+}
+
+/// Queries every configured source in the background.
+pub struct Discoverer {
+    receiver: channel::Receiver<Update>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Discoverer {
+    pub fn start(sources: &[Discovery]) -> Self {
+        let (sender, receiver) = channel::unbounded();
+        let last_error = Arc::new(Mutex::new(None));
+        for (index, discovery) in sources.iter().enumerate() {
+            let discovery = discovery.clone();
+            let sender = sender.clone();
+            let last_error = Arc::clone(&last_error);
+            thread::spawn(move || {
+                loop {
+                    let mut wait = discovery.refresh;
+                    match discovery.source.discover(discovery.refresh) {
+                        Ok(found) => {
+                            wait = found.ttl.unwrap_or(wait);
+                            let update = Update {
+                                source: index,
+                                targets: found.targets,
+                            };
+                            // Ends once nobody listens any more.
+                            if sender.send_blocking(update).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            *last_error.lock().unwrap() =
+                                Some(format!("{}: {}", discovery.source.describe(), e));
+                        }
+                    }
+                    if sender.is_closed() {
+                        break;
+                    }
+                    thread::sleep(wait);
+                }
+            });
+        }
+        Discoverer {
+            receiver,
+            last_error,
+        }
+    }
+
+    /// The next update that arrived, if any.
+    pub fn try_next(&self) -> Option<Update> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// The most recent failed query, cleared once read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
@@ -0,0 +1,313 @@
+//! A minimal DNS client for SRV records, which the system resolver cannot
+//! look up.
+//!
+//! Queries go to one nameserver over UDP and are repeated over TCP when the
+//! answer was truncated. The nameserver recurses; answers are not validated
+//! beyond matching the query.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// One SRV record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    /// Host offering the service, without the trailing dot.
+    pub target: String,
+    pub ttl: Duration,
+}
+
+/// The first nameserver in `/etc/resolv.conf`.
+pub fn system_nameserver() -> io::Result<SocketAddr> {
+    let conf = fs::read_to_string("/etc/resolv.conf")?;
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|address| address.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .ok_or_else(|| invalid("no nameserver in /etc/resolv.conf"))
+}
+
+/// The SRV records of `name`, ordered by priority and then weight, highest
+/// first. A name that does not exist has none.
+pub fn lookup_srv(name: &str, nameserver: SocketAddr, timeout: Duration) -> io::Result<Vec<Srv>> {
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u16;
+    let query = query(id, name)?;
+
+    let socket = UdpSocket::bind(match nameserver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(nameserver)?;
+    socket.send(&query)?;
+    let mut buf = [0u8; 4096];
+    let answer = loop {
+        let len = socket.recv(&mut buf)?;
+        // Stray answers to earlier queries are skipped.
+        if len >= 2 && buf[..2] == id.to_be_bytes() {
+            break &buf[..len];
+        }
+    };
+    let truncated = answer.get(2).is_some_and(|flags| flags & 0x02 != 0);
+    let mut records = if truncated {
+        parse(&query_tcp(&query, nameserver, timeout)?, id)?
+    } else {
+        parse(answer, id)?
+    };
+    records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+    Ok(records)
+}
+
+fn query_tcp(query: &[u8], nameserver: SocketAddr, timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&nameserver, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed)?;
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut answer = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut answer)?;
+    Ok(answer)
+}
+
+/// A recursive query for the SRV records of `name`.
+fn query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(name.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid(&format!("invalid name '{}'", name)));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn parse(message: &[u8], id: u16) -> io::Result<Vec<Srv>> {
+    let malformed = || invalid("malformed DNS answer");
+    let u16_at = |pos: usize| {
+        message
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(malformed)
+    };
+    if message.len() < 12 || u16_at(0)? != id || message[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    match message[3] & 0x0f {
+        0 => {}
+        // NXDOMAIN
+        3 => return Ok(Vec::new()),
+        2 => return Err(io::Error::other("nameserver failure (SERVFAIL)")),
+        5 => return Err(io::Error::other("query refused (REFUSED)")),
+        rcode => return Err(io::Error::other(format!("DNS error code {}", rcode))),
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(message, pos).ok_or_else(malformed)? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(message, pos).ok_or_else(malformed)?;
+        let kind = u16_at(pos)?;
+        let ttl = (u32::from(u16_at(pos + 4)?) << 16) | u32::from(u16_at(pos + 6)?);
+        let len = usize::from(u16_at(pos + 8)?);
+        let data = pos + 10;
+        pos = data + len;
+        if pos > message.len() {
+            return Err(malformed());
+        }
+        // CNAMEs the nameserver followed come along.
+        if kind != TYPE_SRV {
+            continue;
+        }
+        let target = read_name(message, data + 6).ok_or_else(malformed)?;
+        records.push(Srv {
+            priority: u16_at(data)?,
+            weight: u16_at(data + 2)?,
+            port: u16_at(data + 4)?,
+            target,
+            ttl: Duration::from_secs(u64::from(ttl)),
+        });
+    }
+    Ok(records)
+}
+
+/// The position after the name at `pos`.
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer ends the name.
+            _ if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            _ => pos += 1 + usize::from(len),
+        }
+    }
+}
+
+/// The possibly compressed name at `pos`, without the trailing dot.
+fn read_name(message: &[u8], mut pos: usize) -> Option<String> {
+    let mut name = String::new();
+    // Bounds pointer loops in hostile answers.
+    for _ in 0..128 {
+        let len = *message.get(pos)?;
+        if len == 0 {
+            return Some(name);
+        }
+        if len & 0xc0 == 0xc0 {
+            pos = usize::from(u16::from_be_bytes([len, *message.get(pos + 1)?]) & 0x3fff);
+            continue;
+        }
+        let label = message.get(pos + 1..pos + 1 + usize::from(len))?;
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.push_str(&String::from_utf8_lossy(label));
+        pos += 1 + usize::from(len);
+    }
+    None
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An answer for `_sip._tcp.example.com`: a CNAME the nameserver
+    /// followed, then two SRV records whose targets point into the question.
+    #[rustfmt::skip]
+    const ANSWER: &[u8] = &[
+        0x12, 0x34, 0x81, 0x80, 0, 1, 0, 3, 0, 0, 0, 0,
+        // _sip._tcp.example.com, SRV, IN
+        4, b'_', b's', b'i', b'p', 4, b'_', b't', b'c', b'p',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0, 33, 0, 1,
+        // CNAME to example.com, TTL 300
+        0xc0, 12, 0, 5, 0, 1, 0, 0, 0x01, 0x2c, 0, 2, 0xc0, 22,
+        // SRV 10 20 5060 sip2.example.com, TTL 3600
+        0xc0, 12, 0, 33, 0, 1, 0, 0, 0x0e, 0x10, 0, 13,
+        0, 10, 0, 20, 0x13, 0xc4, 4, b's', b'i', b'p', b'2', 0xc0, 22,
+        // SRV 10 60 5060 sip1.example.com, TTL 3600
+        0xc0, 12, 0, 33, 0, 1, 0, 0, 0x0e, 0x10, 0, 13,
+        0, 10, 0, 60, 0x13, 0xc4, 4, b's', b'i', b'p', b'1', 0xc0, 22,
+    ];
+
+    fn srv(weight: u16, target: &str) -> Srv {
+        Srv {
+            priority: 10,
+            weight,
+            port: 5060,
+            target: target.to_string(),
+            ttl: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn queries_carry_the_name_as_labels() {
+        let query = query(0x1234, "_sip._tcp.example.com.").unwrap();
+        assert_eq!(&query[..12], &[0x12, 0x34, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..], &ANSWER[12..39]);
+        assert!(super::query(1, "a..b").is_err());
+        assert!(super::query(1, &"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn reads_srv_records_and_skips_others() {
+        assert_eq!(
+            parse(ANSWER, 0x1234).unwrap(),
+            vec![srv(20, "sip2.example.com"), srv(60, "sip1.example.com")]
+        );
+    }
+
+    #[test]
+    fn refuses_answers_to_other_queries() {
+        assert!(parse(ANSWER, 0x4321).is_err());
+        let mut query = ANSWER.to_vec();
+        query[2] &= 0x7f;
+        assert!(parse(&query, 0x1234).is_err());
+    }
+
+    #[test]
+    fn reads_the_response_code() {
+        let mut answer = ANSWER.to_vec();
+        answer[3] = 0x83;
+        assert_eq!(parse(&answer, 0x1234).unwrap(), Vec::new());
+        answer[3] = 0x82;
+        assert!(parse(&answer, 0x1234).is_err());
+        answer[3] = 0x85;
+        assert!(parse(&answer, 0x1234).is_err());
+    }
+
+    #[test]
+    fn refuses_truncated_answers() {
+        for len in 0..ANSWER.len() {
+            assert!(parse(&ANSWER[..len], 0x1234).is_err(), "{} octets", len);
+        }
+    }
+
+    #[test]
+    fn refuses_pointer_loops() {
+        let mut answer = ANSWER.to_vec();
+        // The last target points at itself.
+        let at = answer.len() - 7;
+        answer.truncate(at);
+        answer.extend_from_slice(&[0xc0, at as u8, 0, 0, 0, 0, 0]);
+        assert!(parse(&answer, 0x1234).is_err());
+        // Two names pointing at each other.
+        answer.truncate(at);
+        answer.extend_from_slice(&[0xc0, at as u8 + 2, 0xc0, at as u8, 0, 0, 0]);
+        assert!(parse(&answer, 0x1234).is_err());
+    }
+
+    #[test]
+    fn looks_up_records_in_order_of_priority_and_weight() {
+        let nameserver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = nameserver.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut query = [0u8; 512];
+            let (len, client) = nameserver.recv_from(&mut query).unwrap();
+            assert_eq!(
+                &query[2..len],
+                &self::query(0, "_sip._tcp.example.com").unwrap()[2..]
+            );
+            let mut answer = ANSWER.to_vec();
+            // A stray answer to an earlier query comes first.
+            answer[..2].copy_from_slice(&[query[0], query[1].wrapping_add(1)]);
+            nameserver.send_to(&answer, client).unwrap();
+            answer[..2].copy_from_slice(&query[..2]);
+            nameserver.send_to(&answer, client).unwrap();
+        });
+        let records = lookup_srv("_sip._tcp.example.com", address, Duration::from_secs(5));
+        server.join().unwrap();
+        assert_eq!(
+            records.unwrap(),
+            vec![srv(60, "sip1.example.com"), srv(20, "sip2.example.com")]
+        );
+    }
+}
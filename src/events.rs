@@ -0,0 +1,240 @@
+//! Events derived from the stream of probe results.
+
+use crate::{ConnectionStatus, ServerStatus, alert::Alert, json::Json, stats, timefmt};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A single probe result.
+    Sample {
+        target: Arc<str>,
+        at: SystemTime,
+        latency: Option<Duration>,
+        status: ConnectionStatus,
+    },
+    /// The classification of a target changed.
+    StatusChange {
+        target: Arc<str>,
+        at: SystemTime,
+        from: ConnectionStatus,
+        to: ConnectionStatus,
+        latency: Option<Duration>,
+    },
+    /// A target stopped answering.
+    OutageStart { target: Arc<str>, at: SystemTime },
+    /// A target answers again after an outage.
+    OutageEnd {
+        target: Arc<str>,
+        at: SystemTime,
+        duration: Duration,
+    },
+    /// Alerts that fired or resolved, grouped into one notification.
+    Alerts(Vec<Alert>),
+}
+
+impl Event {
+    /// The target the event is about; for alert groups, that of the first
+    /// alert.
+    pub fn target(&self) -> &str {
+        match self {
+            Event::Sample { target, .. }
+            | Event::StatusChange { target, .. }
+            | Event::OutageStart { target, .. }
+            | Event::OutageEnd { target, .. } => target,
+            Event::Alerts(alerts) => alerts.first().map_or("", |a| &a.target),
+        }
+    }
+
+    pub fn at(&self) -> SystemTime {
+        match self {
+            Event::Sample { at, .. }
+            | Event::StatusChange { at, .. }
+            | Event::OutageStart { at, .. }
+            | Event::OutageEnd { at, .. } => *at,
+            Event::Alerts(alerts) => alerts.first().map_or_else(SystemTime::now, |a| a.at),
+        }
+    }
+
+    /// One JSON object per event, or per alert of an alert group.
+    pub fn json(&self) -> Vec<Json> {
+        let at = |at| Json::from(timefmt::rfc3339(at));
+        let single = match self {
+            Event::Sample {
+                target,
+                at: sampled,
+                latency,
+                status,
+            } => Json::object([
+                ("type", Json::from("sample")),
+                ("target", Json::from(&**target)),
+                ("at", at(*sampled)),
+                ("status", Json::from(status.label())),
+                ("rtt_ms", Json::from(latency.map(stats::millis))),
+            ]),
+            Event::StatusChange {
+                target,
+                at: changed,
+                from,
+                to,
+                latency,
+            } => Json::object([
+                ("type", Json::from("status_change")),
+                ("target", Json::from(&**target)),
+                ("at", at(*changed)),
+                ("from", Json::from(from.label())),
+                ("to", Json::from(to.label())),
+                ("rtt_ms", Json::from(latency.map(stats::millis))),
+            ]),
+            Event::OutageStart { target, at: since } => Json::object([
+                ("type", Json::from("outage_start")),
+                ("target", Json::from(&**target)),
+                ("at", at(*since)),
+            ]),
+            Event::OutageEnd {
+                target,
+                at: ended,
+                duration,
+            } => Json::object([
+                ("type", Json::from("outage_end")),
+                ("target", Json::from(&**target)),
+                ("at", at(*ended)),
+                ("duration_s", Json::from(duration.as_secs())),
+            ]),
+            Event::Alerts(alerts) => {
+                return alerts
+                    .iter()
+                    .map(|alert| {
+                        Json::object([
+                            ("type", Json::from("alert")),
+                            ("target", Json::from(alert.target.as_str())),
+                            ("at", at(alert.at)),
+                            ("rule", Json::from(alert.rule.as_str())),
+                            ("state", Json::from(alert.state.label())),
+                            ("value", Json::from(alert.value.as_str())),
+                        ])
+                    })
+                    .collect();
+            }
+        };
+        vec![single]
+    }
+}
+
+struct TargetState {
+    status: ConnectionStatus,
+    outage_since: Option<SystemTime>,
+    last_outage: Option<Outage>,
+}
+
+/// When a target stopped answering, and for how long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outage {
+    pub start: SystemTime,
+    /// `None` while it lasts.
+    pub duration: Option<Duration>,
+}
+
+/// Remembers the last status per target to turn samples into events.
+#[derive(Default)]
+pub struct EventTracker {
+    targets: HashMap<Arc<str>, TargetState>,
+}
+
+impl EventTracker {
+    /// The events `sample` causes: the sample itself, then a status change
+    /// and the start or end of an outage, if any.
+    pub fn observe(&mut self, sample: &ServerStatus) -> impl Iterator<Item = Event> + use<> {
+        let at = sample.sampled_at;
+        let target = &sample.name;
+        let mut events = [
+            Some(Event::Sample {
+                target: Arc::clone(target),
+                at,
+                latency: sample.latency,
+                status: sample.status,
+            }),
+            None,
+            None,
+        ];
+
+        let state = match self.targets.get_mut(target) {
+            Some(state) => state,
+            None => {
+                // The first sample only establishes the baseline, unless the
+                // target is already unreachable.
+                let outage_since = (sample.status == ConnectionStatus::Timeout).then_some(at);
+                if outage_since.is_some() {
+                    events[2] = Some(Event::OutageStart {
+                        target: Arc::clone(target),
+                        at,
+                    });
+                }
+                self.targets.insert(
+                    Arc::clone(target),
+                    TargetState {
+                        status: sample.status,
+                        outage_since,
+                        last_outage: None,
+                    },
+                );
+                return events.into_iter().flatten();
+            }
+        };
+
+        if state.status != sample.status {
+            events[1] = Some(Event::StatusChange {
+                target: Arc::clone(target),
+                at,
+                from: state.status,
+                to: sample.status,
+                latency: sample.latency,
+            });
+            state.status = sample.status;
+        }
+
+        match (
+            sample.status == ConnectionStatus::Timeout,
+            state.outage_since,
+        ) {
+            (true, None) => {
+                state.outage_since = Some(at);
+                events[2] = Some(Event::OutageStart {
+                    target: Arc::clone(target),
+                    at,
+                });
+            }
+            (false, Some(since)) => {
+                let duration = at.duration_since(since).unwrap_or_default();
+                state.outage_since = None;
+                state.last_outage = Some(Outage {
+                    start: since,
+                    duration: Some(duration),
+                });
+                events[2] = Some(Event::OutageEnd {
+                    target: Arc::clone(target),
+                    at,
+                    duration,
+                });
+            }
+            _ => {}
+        }
+
+        events.into_iter().flatten()
+    }
+
+    /// The outage a target is in, or else the last one it had.
+    pub fn last_outage(&self, target: &str) -> Option<Outage> {
+        let state = self.targets.get(target)?;
+        match state.outage_since {
+            Some(start) => Some(Outage {
+                start,
+                duration: None,
+            }),
+            None => state.last_outage,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Exporting persisted history for offline analysis.
+
+use crate::{
+    ConnectionStatus,
+    parquet::{self, Column, ColumnData},
+    rrd,
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// One exported sample: a consolidated round-robin row.
+struct Record {
+    timestamp_ms: i64,
+    target: String,
+    /// The kind of probe, empty when the samples did not say.
+    probe: String,
+    rtt_ms: Option<f64>,
+    min_ms: Option<f64>,
+    max_ms: Option<f64>,
+    /// Fraction of lost probes in `0.0..=1.0`.
+    loss: f64,
+    status: ConnectionStatus,
+}
+
+/// Reads the archive with the given step (or the finest one) from every
+/// round-robin file in `dir`.
+fn collect(dir: &Path, step: Option<Duration>) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+
+    for path in rrd::list(dir)? {
+        let contents = rrd::read(&path)?;
+        let archive = match step {
+            Some(step) => contents.archives.into_iter().find(|a| a.spec.step == step),
+            None => contents.archives.into_iter().min_by_key(|a| a.spec.step),
+        };
+        let Some(archive) = archive else {
+            continue;
+        };
+
+        for row in archive.rows {
+            let answered = |ms: f64| (row.samples > row.lost && ms.is_finite()).then_some(ms);
+            let timestamp_ms = row
+                .time()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+            records.push(Record {
+                timestamp_ms,
+                target: contents.name.clone(),
+                probe: contents.probe.clone(),
+                rtt_ms: answered(row.avg),
+                min_ms: answered(row.min),
+                max_ms: answered(row.max),
+                loss: f64::from(row.lost) / f64::from(row.samples.max(1)),
+                status: row.status(),
+            });
+        }
+    }
+
+    records.sort_by(|a, b| {
+        a.timestamp_ms
+            .cmp(&b.timestamp_ms)
+            .then_with(|| a.target.cmp(&b.target))
+    });
+    Ok(records)
+}
+
+/// Writes the persisted history in `dir` to a Parquet file and returns the
+/// number of rows written.
+pub fn export_parquet(dir: &Path, step: Option<Duration>, out: &Path) -> io::Result<usize> {
+    let records = collect(dir, step)?;
+    let columns = [
+        Column {
+            name: "timestamp",
+            data: ColumnData::TimestampMillis(records.iter().map(|r| r.timestamp_ms).collect()),
+        },
+        Column {
+            name: "target",
+            data: ColumnData::String(records.iter().map(|r| r.target.clone()).collect()),
+        },
+        Column {
+            name: "probe",
+            data: ColumnData::String(records.iter().map(|r| r.probe.clone()).collect()),
+        },
+        Column {
+            name: "rtt",
+            data: ColumnData::OptionalDouble(records.iter().map(|r| r.rtt_ms).collect()),
+        },
+        Column {
+            name: "min",
+            data: ColumnData::OptionalDouble(records.iter().map(|r| r.min_ms).collect()),
+        },
+        Column {
+            name: "max",
+            data: ColumnData::OptionalDouble(records.iter().map(|r| r.max_ms).collect()),
+        },
+        Column {
+            name: "loss",
+            data: ColumnData::OptionalDouble(records.iter().map(|r| Some(r.loss)).collect()),
+        },
+        Column {
+            name: "status",
+            data: ColumnData::String(
+                records
+                    .iter()
+                    .map(|r| r.status.label().to_string())
+                    .collect(),
+            ),
+        },
+    ];
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    parquet::write(&mut writer, &columns)?;
+    Ok(records.len())
+}
@@ -0,0 +1,326 @@
+//! Probes speaking the query protocols of game servers, which answer them
+//! where ICMP is blocked or deprioritized: Valve's A2S_INFO, spoken by
+//! Source and many other engines, and the server list ping of Minecraft
+//! Java Edition. The round-trip time is that of the query, so it includes
+//! the time the server takes to answer.
+//!
+//! Both tell the players online, which the dashboard shows next to the
+//! target.
+
+use crate::{
+    Target, network,
+    probe::{Note, PING_TIMEOUT, Probe, ProbeError, ProbeFuture},
+    resolve, rt,
+};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The default query port of Source servers.
+pub const A2S_PORT: u16 = 27015;
+
+/// The default port of Minecraft servers.
+pub const MINECRAFT_PORT: u16 = 25565;
+
+const A2S_INFO: &[u8] = b"\xff\xff\xff\xffTSource Engine Query\0";
+
+/// Largest status a Minecraft server may send, JSON with its icon.
+const MAX_STATUS: usize = 256 * 1024;
+
+/// Players online and the most allowed, by target name, as last answered.
+type Players = Arc<Mutex<HashMap<String, (u32, u32)>>>;
+
+/// What a server answered a query with.
+struct Answer {
+    rtt: Duration,
+    online: u32,
+    max: u32,
+}
+
+/// Valve's A2S_INFO query over UDP.
+pub struct A2s {
+    port: u16,
+    players: Players,
+}
+
+impl A2s {
+    /// Queries `port` of targets without a port of their own.
+    pub fn new(port: u16) -> Self {
+        A2s {
+            port,
+            players: Players::default(),
+        }
+    }
+}
+
+impl Probe for A2s {
+    fn kind(&self) -> &'static str {
+        "a2s"
+    }
+
+    fn description(&self) -> &'static str {
+        "response time of Valve's A2S_INFO game server query"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        query(target, self.port, &self.players, a2s_info)
+    }
+
+    fn note(&self, target: &Target) -> Option<Note> {
+        players_note(&self.players, target)
+    }
+}
+
+/// The Minecraft server list ping over TCP.
+pub struct Minecraft {
+    port: u16,
+    players: Players,
+}
+
+impl Minecraft {
+    /// Pings `port` of targets without a port of their own.
+    pub fn new(port: u16) -> Self {
+        Minecraft {
+            port,
+            players: Players::default(),
+        }
+    }
+}
+
+impl Probe for Minecraft {
+    fn kind(&self) -> &'static str {
+        "minecraft"
+    }
+
+    fn description(&self) -> &'static str {
+        "response time of the Minecraft server list ping"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        query(target, self.port, &self.players, list_ping)
+    }
+
+    fn note(&self, target: &Target) -> Option<Note> {
+        players_note(&self.players, target)
+    }
+}
+
+/// Runs `protocol` against the target and keeps the players it tells.
+fn query<'a>(
+    target: &'a Target,
+    port: u16,
+    players: &Players,
+    protocol: fn(&str, u16) -> Result<Answer, ProbeError>,
+) -> ProbeFuture<'a> {
+    let name = target.name.clone();
+    let host = target.host.clone();
+    let port = target.port.unwrap_or(port);
+    let network = target.network.clone();
+    let players = Arc::clone(players);
+    Box::pin(rt::unblock(move || {
+        let mut answer = None;
+        let result = network::within(network.as_ref(), || {
+            let Answer { rtt, online, max } = protocol(&host, port)?;
+            answer = Some((online, max));
+            Ok(rtt)
+        });
+        let mut players = players.lock().unwrap();
+        match answer {
+            Some(answer) => players.insert(name, answer),
+            None => players.remove(&name),
+        };
+        result
+    }))
+}
+
+fn players_note(players: &Players, target: &Target) -> Option<Note> {
+    let players = players.lock().unwrap();
+    let (online, max) = players.get(&target.name)?;
+    Some(Note::info(format!("{}/{} players", online, max)))
+}
+
+/// Sends A2S_INFO, and again with the challenge if the server asks for one,
+/// timing the first answer.
+fn a2s_info(host: &str, port: u16) -> Result<Answer, ProbeError> {
+    let address = socket_address(host, port)?;
+    let socket = UdpSocket::bind(match address {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })
+    .map_err(io_error)?;
+    socket
+        .set_read_timeout(Some(PING_TIMEOUT))
+        .map_err(io_error)?;
+    socket.connect(address).map_err(io_error)?;
+    let mut request = A2S_INFO.to_vec();
+    let mut buf = [0u8; 1400];
+    let start = Instant::now();
+    let mut rtt = None;
+    for _ in 0..2 {
+        socket.send(&request).map_err(io_error)?;
+        let len = socket.recv(&mut buf).map_err(io_error)?;
+        let rtt = *rtt.get_or_insert_with(|| start.elapsed());
+        let reply = &buf[..len];
+        match reply.get(..5) {
+            // A challenge to repeat the query with, against spoofed floods.
+            Some(b"\xff\xff\xff\xffA") if len >= 9 => {
+                request.truncate(A2S_INFO.len());
+                request.extend_from_slice(&reply[5..9]);
+            }
+            Some(b"\xff\xff\xff\xffI") => {
+                let (online, max) = parse_info(&reply[5..]).ok_or(ProbeError::Timeout)?;
+                return Ok(Answer { rtt, online, max });
+            }
+            _ => return Err(ProbeError::Timeout),
+        }
+    }
+    Err(ProbeError::Timeout)
+}
+
+/// The players and maximum of an A2S_INFO answer: after the protocol
+/// version come the name, map, folder and game as C strings, and the app
+/// ID.
+fn parse_info(info: &[u8]) -> Option<(u32, u32)> {
+    let mut rest = info.get(1..)?;
+    for _ in 0..4 {
+        let end = rest.iter().position(|&b| b == 0)?;
+        rest = &rest[end + 1..];
+    }
+    let players = rest.get(2..4)?;
+    Some((u32::from(players[0]), u32::from(players[1])))
+}
+
+/// The server list ping: a handshake and a status request, answered with
+/// JSON telling the players, then a ping answered with a pong, which is
+/// timed. Servers that close the connection instead are timed by their
+/// status answer.
+fn list_ping(host: &str, port: u16) -> Result<Answer, ProbeError> {
+    let address = socket_address(host, port)?;
+    let mut stream = network::connect(&address, PING_TIMEOUT).map_err(io_error)?;
+    stream
+        .set_read_timeout(Some(PING_TIMEOUT))
+        .map_err(io_error)?;
+    stream
+        .set_write_timeout(Some(PING_TIMEOUT))
+        .map_err(io_error)?;
+
+    let mut handshake = vec![0x00];
+    // Any protocol version will do for the status, -1 by convention.
+    write_varint(&mut handshake, -1);
+    write_varint(&mut handshake, host.len() as i32);
+    handshake.extend_from_slice(host.as_bytes());
+    handshake.extend_from_slice(&port.to_be_bytes());
+    // Next state: status.
+    write_varint(&mut handshake, 1);
+    let mut packets = packet(&handshake);
+    packets.extend(packet(&[0x00]));
+    let start = Instant::now();
+    stream.write_all(&packets).map_err(io_error)?;
+    let status = read_packet(&mut stream).map_err(io_error)?;
+    let status_rtt = start.elapsed();
+    let (online, max) = parse_status(&status).ok_or(ProbeError::Timeout)?;
+
+    let mut ping = vec![0x01];
+    ping.extend_from_slice(&(status_rtt.as_nanos() as u64).to_be_bytes());
+    let start = Instant::now();
+    let pong = stream
+        .write_all(&packet(&ping))
+        .and_then(|_| read_packet(&mut stream));
+    let rtt = match pong {
+        Ok(pong) if pong == ping => start.elapsed(),
+        _ => status_rtt,
+    };
+    Ok(Answer { rtt, online, max })
+}
+
+/// `players.online` and `players.max` of a status answer: packet ID 0 and
+/// the JSON as a string.
+fn parse_status(packet: &[u8]) -> Option<(u32, u32)> {
+    let mut rest = packet;
+    if read_varint(&mut rest).ok()? != 0 {
+        return None;
+    }
+    let len = usize::try_from(read_varint(&mut rest).ok()?).ok()?;
+    let status = crate::json::parse(std::str::from_utf8(rest.get(..len)?).ok()?)?;
+    let players = status.get("players")?;
+    let count = |key| players.get(key)?.as_f64().map(|n| n as u32);
+    Some((count("online")?, count("max")?))
+}
+
+/// `body`, the packet ID and data, prefixed with its length.
+fn packet(body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn read_packet(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = 0u32;
+    for i in 0..5 {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        len |= u32::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let len = len as usize;
+    if len > MAX_STATUS {
+        return Err(io::Error::new(ErrorKind::InvalidData, "packet too large"));
+    }
+    let mut packet = vec![0u8; len];
+    stream.read_exact(&mut packet)?;
+    Ok(packet)
+}
+
+/// Minecraft's VarInt: seven bits at a time, least significant first.
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value < 0x80 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<i32, ()> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let (&byte, rest) = buf.split_first().ok_or(())?;
+        *buf = rest;
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(())
+}
+
+pub(crate) fn socket_address(host: &str, port: u16) -> Result<SocketAddr, ProbeError> {
+    (resolve::lookup(host)?.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|_| ProbeError::Dns)?
+        .next()
+        .ok_or(ProbeError::Dns)
+}
+
+/// A server not listening on the port is down, unlike for the `tcp`
+/// probe, where a refusal still proves the host answers.
+pub(crate) fn io_error(e: io::Error) -> ProbeError {
+    match e.kind() {
+        ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+        ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::NetworkUnreachable
+        | ErrorKind::HostUnreachable => ProbeError::Unreachable,
+        _ => ProbeError::Timeout,
+    }
+}
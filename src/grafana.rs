@@ -0,0 +1,180 @@
+//! Round-trip times in the shape of Grafana's JSON datasource, so Grafana
+//! can chart latencee directly without a database in between.
+//!
+//! Series combine the recent in-memory history with the round-robin files
+//! of [`crate::rrd`] when storage is configured: the files cover what is
+//! older than the history, at the finest resolution that reaches back far
+//! enough.
+
+use crate::{history::HistoryStore, json::Json, rrd, stats, timefmt};
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A point in time and the round-trip time then in milliseconds, `None`
+/// for lost probes.
+pub type Point = (SystemTime, Option<f64>);
+
+/// Every target with live or stored data, sorted.
+pub fn names(histories: &Mutex<HistoryStore>, storage: Option<&Path>) -> Vec<String> {
+    let mut names: BTreeSet<String> = histories
+        .lock()
+        .unwrap()
+        .probed()
+        .map(|status| status.name.to_string())
+        .collect();
+    if let Some(dir) = storage
+        && let Ok(paths) = rrd::list(dir)
+    {
+        names.extend(paths.iter().filter_map(|path| rrd::read_name(path).ok()));
+    }
+    names.into_iter().collect()
+}
+
+/// The round-trip times of `target` from `from` to `to`, oldest first, at
+/// most `max_points` of them unless that is zero.
+pub fn series(
+    histories: &Mutex<HistoryStore>,
+    storage: Option<&Path>,
+    target: &str,
+    from: SystemTime,
+    to: SystemTime,
+    max_points: usize,
+) -> Vec<Point> {
+    let live: Vec<Point> = match histories.lock().unwrap().get(target) {
+        Some(status) => status
+            .history
+            .iter()
+            .map(|(at, _, latency)| {
+                let age = status.last_update.saturating_duration_since(*at);
+                (status.sampled_at - age, latency.map(stats::millis))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let live_from = live.first().map_or(to, |(at, _)| *at);
+    let mut points = match storage {
+        Some(dir) if from < live_from => stored(dir, target, from, live_from),
+        _ => Vec::new(),
+    };
+    points.extend(live.into_iter().filter(|(at, _)| (from..=to).contains(at)));
+    thin(points, max_points)
+}
+
+/// Rows of the stored archive with the finest steps that still reaches
+/// back to `from`, or else of the one reaching back furthest.
+fn stored(dir: &Path, target: &str, from: SystemTime, until: SystemTime) -> Vec<Point> {
+    let Ok(contents) = rrd::read(&rrd::path(dir, target)) else {
+        return Vec::new();
+    };
+    let oldest = |archive: &rrd::Archive| archive.rows.first().map(rrd::Row::time);
+    let covering = contents
+        .archives
+        .iter()
+        .filter(|archive| oldest(archive).is_some_and(|at| at <= from))
+        .min_by_key(|archive| archive.spec.step);
+    let archive = covering.or_else(|| {
+        contents
+            .archives
+            .iter()
+            .filter(|archive| !archive.rows.is_empty())
+            .min_by_key(|archive| oldest(archive))
+    });
+    archive.map_or_else(Vec::new, |archive| {
+        archive
+            .rows
+            .iter()
+            .filter(|row| row.time() + archive.spec.step > from && row.time() < until)
+            .map(|row| (row.time(), (!row.avg.is_nan()).then_some(row.avg)))
+            .collect()
+    })
+}
+
+/// Averages runs of neighbouring points down to at most `max` points.
+fn thin(points: Vec<Point>, max: usize) -> Vec<Point> {
+    if max == 0 || points.len() <= max {
+        return points;
+    }
+    points
+        .chunks(points.len().div_ceil(max))
+        .map(|run| {
+            let answered: Vec<f64> = run.iter().filter_map(|(_, ms)| *ms).collect();
+            let avg = (!answered.is_empty())
+                .then(|| answered.iter().sum::<f64>() / answered.len() as f64);
+            (run[0].0, avg)
+        })
+        .collect()
+}
+
+/// Milliseconds since the Unix epoch, as Grafana counts time.
+pub fn unix_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// A Grafana time series: `{"target": ..., "datapoints": [[rtt, time]]}`.
+pub fn timeserie(target: &str, points: &[Point]) -> Json {
+    let datapoints = points
+        .iter()
+        .map(|(at, ms)| Json::Array(vec![Json::from(*ms), Json::from(unix_millis(*at))]))
+        .collect();
+    Json::object([
+        ("target", Json::from(target)),
+        ("datapoints", Json::Array(datapoints)),
+    ])
+}
+
+/// Table rows, one object per point, for datasources such as Infinity that
+/// turn an array of objects into columns.
+pub fn rows(target: &str, points: &[Point]) -> Json {
+    Json::Array(
+        points
+            .iter()
+            .map(|(at, ms)| {
+                Json::object([
+                    ("time", Json::from(timefmt::rfc3339(*at))),
+                    ("target", Json::from(target)),
+                    ("rtt_ms", Json::from(*ms)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// The targets and time range of a `/query` request body.
+pub struct Query {
+    pub targets: Vec<String>,
+    pub from: SystemTime,
+    pub to: SystemTime,
+    pub max_points: usize,
+}
+
+impl Query {
+    /// Reads the body Grafana posts; `None` if it is not one.
+    pub fn parse(body: &Json) -> Option<Self> {
+        let range = body.get("range")?;
+        let time = |key| timefmt::parse_rfc3339(range.get(key)?.as_str()?);
+        let targets = body
+            .get("targets")?
+            .as_array()?
+            .iter()
+            .filter(|target| target.get("hide") != Some(&Json::Bool(true)))
+            .filter_map(|target| target.get("target")?.as_str())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        Some(Query {
+            targets,
+            from: time("from")?,
+            to: time("to")?,
+            max_points: body
+                .get("maxDataPoints")
+                .and_then(Json::as_f64)
+                .map_or(0, |n| n as usize),
+        })
+    }
+}
@@ -0,0 +1,804 @@
+//! The gRPC service: `WatchTargets` of `latencee.Latencee`, described in
+//! `proto/latencee.proto`, streams every sample, status change, outage and
+//! alert to the caller as it happens.
+//!
+//! Served over cleartext HTTP/2 with prior knowledge ("h2c"), which is how
+//! gRPC clients talk to plaintext endpoints. The HTTP/2 side is a small
+//! implementation of its own, like the other protocols latencee speaks:
+//! the frames, flow control and [`crate::hpack`] a gRPC server needs,
+//! without TLS, server push or priorities.
+
+use crate::{
+    ConnectionStatus,
+    alert::{Alert, AlertState},
+    config::GrpcConfig,
+    events::Event,
+    hpack,
+    rt::channel,
+    sinks::QUEUE,
+    stats,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The method streaming events.
+const WATCH_TARGETS: &str = "/latencee.Latencee/WatchTargets";
+
+/// What a client sends first.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Connections served at once; more are refused until one ends.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Calls a connection may have open at once, as told in our settings.
+const MAX_STREAMS: usize = 16;
+
+/// The largest frame either side sends until told otherwise.
+const DEFAULT_FRAME_SIZE: usize = 16_384;
+
+/// The flow-control window of a connection and its streams until told
+/// otherwise.
+const DEFAULT_WINDOW: i64 = 65_535;
+
+/// The largest request message accepted.
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// The largest header block accepted, CONTINUATION frames included.
+const MAX_HEADERS: usize = 16 * 1024;
+
+/// Encoded events a call holds back while the client's window is full;
+/// beyond that, events wait in its queue, which drops the oldest.
+const MAX_PENDING: usize = 64 * 1024;
+
+/// How long to wait for frames before sending the events that arrived.
+const POLL: Duration = Duration::from_millis(50);
+
+/// How long a client may take to send its preface, and a write may block.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Frame types.
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// Frame flags.
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// Settings.
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+// HTTP/2 error codes.
+const PROTOCOL_ERROR: u32 = 0x1;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+// gRPC status codes.
+const INVALID_ARGUMENT: u32 = 3;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+
+/// A `WatchTargets` call and what it asked for.
+struct Subscriber {
+    sender: channel::Sender<Event>,
+    request: WatchRequest,
+}
+
+/// Shared with the connection threads.
+struct State {
+    subscribers: Mutex<Vec<Subscriber>>,
+    connections: AtomicUsize,
+    /// Events calls lost because their client fell behind.
+    dropped: AtomicU64,
+}
+
+pub struct Server {
+    state: Arc<State>,
+}
+
+impl Server {
+    /// Binds the configured address and serves calls on background
+    /// threads, one per connection.
+    pub fn start(config: &GrpcConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(&config.listen)
+            .map_err(|e| io::Error::new(e.kind(), format!("grpc: {}: {}", config.listen, e)))?;
+        let state = Arc::new(State {
+            subscribers: Mutex::default(),
+            connections: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if shared.connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    shared.connections.fetch_sub(1, Ordering::Relaxed);
+                    refuse(stream);
+                    continue;
+                }
+                let state = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let _ = Connection::new(stream, &state).and_then(|mut c| c.serve());
+                    state.connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Ok(Server { state })
+    }
+
+    /// Queues `event` for every call watching its target. A call whose
+    /// queue is full loses its oldest event instead.
+    pub fn publish(&self, event: &Event) {
+        let mut subscribers = self.state.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            if !subscriber.request.wants(event) {
+                return !subscriber.sender.is_closed();
+            }
+            match subscriber.sender.force_send(event.clone()) {
+                Ok(Some(_)) => {
+                    self.state.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Ok(None) => true,
+                Err(_) => false,
+            }
+        });
+    }
+
+    /// Events calls dropped so far because their clients fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Tells a client over the connection limit to go away and come back.
+fn refuse(mut stream: TcpStream) {
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    let mut goaway = Vec::new();
+    write_frame(
+        &mut goaway,
+        GOAWAY,
+        0,
+        0,
+        &[0, 0, 0, 0, 0, 0, 0, REFUSED_STREAM as u8],
+    );
+    let _ = stream.write_all(&goaway);
+}
+
+fn write_frame(out: &mut Vec<u8>, kind: u8, flags: u8, stream: u32, payload: &[u8]) {
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    out.push(kind);
+    out.push(flags);
+    out.extend_from_slice(&stream.to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// What a `WatchTargets` call asks for.
+#[derive(Default)]
+struct WatchRequest {
+    /// Only events of these targets; all when empty.
+    targets: Vec<String>,
+    /// Leaves out samples.
+    skip_samples: bool,
+}
+
+impl WatchRequest {
+    /// Decodes the protobuf message, skipping fields it does not know.
+    fn decode(mut message: &[u8]) -> Option<Self> {
+        let mut request = WatchRequest::default();
+        while !message.is_empty() {
+            let key = read_varint(&mut message)?;
+            match (key >> 3, key & 7) {
+                (1, 2) => {
+                    let target = read_bytes(&mut message)?;
+                    request
+                        .targets
+                        .push(String::from_utf8(target.to_vec()).ok()?);
+                }
+                (2, 0) => request.skip_samples = read_varint(&mut message)? != 0,
+                (_, 0) => {
+                    read_varint(&mut message)?;
+                }
+                (_, 1) => message = message.get(8..)?,
+                (_, 2) => {
+                    read_bytes(&mut message)?;
+                }
+                (_, 5) => message = message.get(4..)?,
+                _ => return None,
+            }
+        }
+        Some(request)
+    }
+
+    fn wants(&self, event: &Event) -> bool {
+        let watched =
+            |target: &str| self.targets.is_empty() || self.targets.iter().any(|t| t == target);
+        match event {
+            Event::Sample { .. } if self.skip_samples => false,
+            Event::Alerts(alerts) => alerts.iter().any(|alert| watched(&alert.target)),
+            event => watched(event.target()),
+        }
+    }
+}
+
+fn read_varint(message: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = message.split_first()?;
+        *message = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_bytes<'a>(message: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = usize::try_from(read_varint(message)?).ok()?;
+    if length > message.len() {
+        return None;
+    }
+    let (bytes, rest) = message.split_at(length);
+    *message = rest;
+    Some(bytes)
+}
+
+/// An open call on a connection.
+struct Call {
+    id: u32,
+    /// The request message as received so far.
+    request: Vec<u8>,
+    /// The events to stream, once the request is complete.
+    events: Option<channel::Receiver<Event>>,
+    /// How much more the client accepts on this stream.
+    window: i64,
+    /// Encoded messages not sent yet.
+    pending: VecDeque<u8>,
+}
+
+/// One client connection and its calls.
+struct Connection<'a> {
+    stream: TcpStream,
+    state: &'a State,
+    input: Vec<u8>,
+    decoder: hpack::Decoder,
+    /// A header block continued in further frames: its stream, whether it
+    /// ends the stream, and its fragments so far.
+    continued: Option<(u32, bool, Vec<u8>)>,
+    calls: Vec<Call>,
+    /// The highest stream the client opened.
+    last_stream: u32,
+    /// How much more the client accepts on the whole connection.
+    window: i64,
+    /// The window new streams start with.
+    initial_window: i64,
+    /// The largest frame the client accepts.
+    frame_size: usize,
+    /// Frames to send.
+    output: Vec<u8>,
+}
+
+impl<'a> Connection<'a> {
+    fn new(stream: TcpStream, state: &'a State) -> io::Result<Self> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Connection {
+            stream,
+            state,
+            input: Vec::new(),
+            decoder: hpack::Decoder::default(),
+            continued: None,
+            calls: Vec::new(),
+            last_stream: 0,
+            window: DEFAULT_WINDOW,
+            initial_window: DEFAULT_WINDOW,
+            frame_size: DEFAULT_FRAME_SIZE,
+            output: Vec::new(),
+        })
+    }
+
+    /// Serves the connection until the client closes it or breaks the
+    /// protocol.
+    fn serve(&mut self) -> io::Result<()> {
+        let mut preface = [0; PREFACE.len()];
+        self.stream.read_exact(&mut preface)?;
+        if preface != PREFACE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not an HTTP/2 client",
+            ));
+        }
+        let mut settings = Vec::new();
+        for (id, value) in [
+            (SETTINGS_MAX_CONCURRENT_STREAMS, MAX_STREAMS as u32),
+            (SETTINGS_HEADER_TABLE_SIZE, hpack::DEFAULT_TABLE_SIZE as u32),
+        ] {
+            settings.extend_from_slice(&id.to_be_bytes());
+            settings.extend_from_slice(&value.to_be_bytes());
+        }
+        write_frame(&mut self.output, SETTINGS, 0, 0, &settings);
+        self.flush()?;
+        self.stream.set_read_timeout(Some(POLL))?;
+
+        let mut chunk = [0; 16_384];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.input.extend_from_slice(&chunk[..n]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            }
+            while let Some(length) = self.next_frame_length() {
+                if length > DEFAULT_FRAME_SIZE {
+                    return Err(self.fail(FRAME_SIZE_ERROR, "frame too large"));
+                }
+                if self.input.len() < 9 + length {
+                    break;
+                }
+                let frame: Vec<u8> = self.input.drain(..9 + length).collect();
+                let id = u32::from_be_bytes([frame[5], frame[6], frame[7], frame[8]]) & 0x7fff_ffff;
+                if !self.frame(frame[3], frame[4], id, &frame[9..])? {
+                    self.flush()?;
+                    return Ok(());
+                }
+            }
+            self.send_events();
+            self.flush()?;
+        }
+    }
+
+    fn next_frame_length(&self) -> Option<usize> {
+        let header = self.input.get(..3)?;
+        Some(u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.output.is_empty() {
+            self.stream.write_all(&self.output)?;
+            self.output.clear();
+        }
+        Ok(())
+    }
+
+    /// Ends the connection with `code`, telling the client why.
+    fn fail(&mut self, code: u32, why: &str) -> io::Error {
+        let mut payload = self.last_stream.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        write_frame(&mut self.output, GOAWAY, 0, 0, &payload);
+        let _ = self.flush();
+        io::Error::new(ErrorKind::InvalidData, format!("grpc: {}", why))
+    }
+
+    /// Handles one frame; false once the client goes away.
+    fn frame(&mut self, kind: u8, flags: u8, id: u32, payload: &[u8]) -> io::Result<bool> {
+        if let Some((continued, _, _)) = &self.continued
+            && (kind != CONTINUATION || id != *continued)
+        {
+            return Err(self.fail(PROTOCOL_ERROR, "header block interrupted"));
+        }
+        match kind {
+            DATA => {
+                let Some(data) = unpadded(flags, payload) else {
+                    return Err(self.fail(PROTOCOL_ERROR, "bad padding"));
+                };
+                if id == 0 {
+                    return Err(self.fail(PROTOCOL_ERROR, "data on stream 0"));
+                }
+                // Whatever arrives is consumed at once.
+                if !payload.is_empty() {
+                    let increment = (payload.len() as u32).to_be_bytes();
+                    write_frame(&mut self.output, WINDOW_UPDATE, 0, 0, &increment);
+                    if flags & END_STREAM == 0 {
+                        write_frame(&mut self.output, WINDOW_UPDATE, 0, id, &increment);
+                    }
+                }
+                if let Some(index) = self.calls.iter().position(|c| c.id == id) {
+                    let call = &mut self.calls[index];
+                    if call.events.is_none() {
+                        call.request.extend_from_slice(data);
+                        if call.request.len() > MAX_REQUEST + 5 {
+                            self.reject(index, RESOURCE_EXHAUSTED, "request too large");
+                            return Ok(true);
+                        }
+                        if flags & END_STREAM != 0 {
+                            self.start(index);
+                        }
+                    }
+                }
+            }
+            HEADERS => {
+                let Some(mut block) = unpadded(flags, payload) else {
+                    return Err(self.fail(PROTOCOL_ERROR, "bad padding"));
+                };
+                if flags & PRIORITY != 0 {
+                    let Some(rest) = block.get(5..) else {
+                        return Err(self.fail(FRAME_SIZE_ERROR, "short priority"));
+                    };
+                    block = rest;
+                }
+                if block.len() > MAX_HEADERS {
+                    return Err(self.fail(ENHANCE_YOUR_CALM, "header block too large"));
+                }
+                let ends = flags & END_STREAM != 0;
+                if flags & END_HEADERS != 0 {
+                    self.headers(id, ends, block)?;
+                } else {
+                    self.continued = Some((id, ends, block.to_vec()));
+                }
+            }
+            CONTINUATION => {
+                let Some((id, ends, mut block)) = self.continued.take() else {
+                    return Err(self.fail(PROTOCOL_ERROR, "unexpected continuation"));
+                };
+                if block.len() + payload.len() > MAX_HEADERS {
+                    return Err(self.fail(ENHANCE_YOUR_CALM, "header block too large"));
+                }
+                block.extend_from_slice(payload);
+                if flags & END_HEADERS != 0 {
+                    self.headers(id, ends, &block)?;
+                } else {
+                    self.continued = Some((id, ends, block));
+                }
+            }
+            RST_STREAM => self.calls.retain(|call| call.id != id),
+            SETTINGS if flags & ACK != 0 => {}
+            SETTINGS => {
+                if id != 0 || !payload.len().is_multiple_of(6) {
+                    return Err(self.fail(FRAME_SIZE_ERROR, "bad settings"));
+                }
+                for setting in payload.chunks(6) {
+                    let value =
+                        u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+                    match u16::from_be_bytes([setting[0], setting[1]]) {
+                        SETTINGS_INITIAL_WINDOW_SIZE => {
+                            if value > 0x7fff_ffff {
+                                return Err(self.fail(FLOW_CONTROL_ERROR, "window too large"));
+                            }
+                            let delta = i64::from(value) - self.initial_window;
+                            for call in &mut self.calls {
+                                call.window += delta;
+                            }
+                            self.initial_window = i64::from(value);
+                        }
+                        SETTINGS_MAX_FRAME_SIZE => {
+                            if !(16_384..=16_777_215).contains(&value) {
+                                return Err(self.fail(PROTOCOL_ERROR, "bad frame size"));
+                            }
+                            self.frame_size = value as usize;
+                        }
+                        // Our encoder keeps no table, so its size is moot.
+                        _ => {}
+                    }
+                }
+                write_frame(&mut self.output, SETTINGS, ACK, 0, &[]);
+            }
+            PUSH_PROMISE => return Err(self.fail(PROTOCOL_ERROR, "push from a client")),
+            PING if flags & ACK == 0 => {
+                if payload.len() != 8 {
+                    return Err(self.fail(FRAME_SIZE_ERROR, "bad ping"));
+                }
+                write_frame(&mut self.output, PING, ACK, 0, payload);
+            }
+            GOAWAY => return Ok(false),
+            WINDOW_UPDATE => {
+                let Ok(bytes) = <[u8; 4]>::try_from(payload) else {
+                    return Err(self.fail(FRAME_SIZE_ERROR, "bad window update"));
+                };
+                let increment = i64::from(u32::from_be_bytes(bytes) & 0x7fff_ffff);
+                if id == 0 {
+                    self.window += increment;
+                } else if let Some(call) = self.calls.iter_mut().find(|c| c.id == id) {
+                    call.window += increment;
+                }
+            }
+            // PRIORITY, PING acks and unknown frames mean nothing here.
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// Opens a call for a complete header block.
+    fn headers(&mut self, id: u32, ends: bool, block: &[u8]) -> io::Result<()> {
+        let Ok(headers) = self.decoder.decode(block) else {
+            return Err(self.fail(COMPRESSION_ERROR, "bad header block"));
+        };
+        if self.calls.iter().any(|call| call.id == id) {
+            // Trailers of a request; gRPC requests have none.
+            return Ok(());
+        }
+        if id.is_multiple_of(2) || id <= self.last_stream {
+            return Err(self.fail(PROTOCOL_ERROR, "bad stream"));
+        }
+        self.last_stream = id;
+        if self.calls.len() >= MAX_STREAMS {
+            write_frame(
+                &mut self.output,
+                RST_STREAM,
+                0,
+                id,
+                &REFUSED_STREAM.to_be_bytes(),
+            );
+            return Ok(());
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.as_str())
+        };
+        self.calls.push(Call {
+            id,
+            request: Vec::new(),
+            events: None,
+            window: self.initial_window,
+            pending: VecDeque::new(),
+        });
+        let index = self.calls.len() - 1;
+        if header(":method") != Some("POST") || header(":path") != Some(WATCH_TARGETS) {
+            let method = header(":path").unwrap_or("").to_string();
+            self.reject(index, UNIMPLEMENTED, &format!("unknown method {}", method));
+        } else if ends {
+            self.start(index);
+        }
+        Ok(())
+    }
+
+    /// Answers the complete request of a call and starts streaming.
+    fn start(&mut self, index: usize) {
+        let request = std::mem::take(&mut self.calls[index].request);
+        let message = match request.as_slice() {
+            [] => Some(&[][..]),
+            // Uncompressed, and one message.
+            [0, length @ ..] if length.len() >= 4 => {
+                let size = u32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+                (size as usize == length.len() - 4).then(|| &length[4..])
+            }
+            _ => None,
+        };
+        let Some(request) = message.and_then(WatchRequest::decode) else {
+            self.reject(index, INVALID_ARGUMENT, "bad WatchRequest");
+            return;
+        };
+        let (sender, receiver) = channel::bounded(QUEUE);
+        self.state
+            .subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { sender, request });
+        let mut block = Vec::new();
+        hpack::encode(&mut block, ":status", "200");
+        hpack::encode(&mut block, "content-type", "application/grpc");
+        let call = &mut self.calls[index];
+        write_frame(&mut self.output, HEADERS, END_HEADERS, call.id, &block);
+        call.events = Some(receiver);
+    }
+
+    /// Ends a call with a gRPC error, in a response of trailers only.
+    fn reject(&mut self, index: usize, status: u32, message: &str) {
+        let call = self.calls.remove(index);
+        let mut block = Vec::new();
+        hpack::encode(&mut block, ":status", "200");
+        hpack::encode(&mut block, "content-type", "application/grpc");
+        hpack::encode(&mut block, "grpc-status", &status.to_string());
+        hpack::encode(&mut block, "grpc-message", &percent_encode(message));
+        write_frame(
+            &mut self.output,
+            HEADERS,
+            END_HEADERS | END_STREAM,
+            call.id,
+            &block,
+        );
+    }
+
+    /// Encodes the events that arrived and sends as much as the client's
+    /// windows allow.
+    fn send_events(&mut self) {
+        for call in &mut self.calls {
+            let Some(events) = &call.events else {
+                continue;
+            };
+            while call.pending.len() < MAX_PENDING
+                && let Ok(event) = events.try_recv()
+            {
+                for message in encode_event(&event) {
+                    call.pending.push_back(0);
+                    call.pending.extend((message.len() as u32).to_be_bytes());
+                    call.pending.extend(message);
+                }
+            }
+            while !call.pending.is_empty() && self.window > 0 && call.window > 0 {
+                let size = call
+                    .pending
+                    .len()
+                    .min(self.frame_size)
+                    .min(self.window as usize)
+                    .min(call.window as usize);
+                let data: Vec<u8> = call.pending.drain(..size).collect();
+                write_frame(&mut self.output, DATA, 0, call.id, &data);
+                self.window -= size as i64;
+                call.window -= size as i64;
+            }
+        }
+    }
+}
+
+/// The payload of a DATA or HEADERS frame without its padding.
+fn unpadded(flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    if flags & PADDED == 0 {
+        return Some(payload);
+    }
+    let (&padding, rest) = payload.split_first()?;
+    rest.len()
+        .checked_sub(usize::from(padding))
+        .map(|end| &rest[..end])
+}
+
+/// `grpc-message` is percent-encoded beyond printable ASCII.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The `TargetEvent` messages of an event: one, or one per alert.
+fn encode_event(event: &Event) -> Vec<Vec<u8>> {
+    let header = |target: &str, at: SystemTime| {
+        let mut message = Vec::new();
+        string_field(&mut message, 1, target);
+        let millis = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        varint_field(&mut message, 2, millis as u64);
+        message
+    };
+    let single = match event {
+        Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } => {
+            let mut sample = Vec::new();
+            varint_field(&mut sample, 1, status_number(*status));
+            rtt_field(&mut sample, 2, *latency);
+            let mut message = header(target, *at);
+            bytes_field(&mut message, 3, &sample);
+            message
+        }
+        Event::StatusChange {
+            target,
+            at,
+            from,
+            to,
+            latency,
+        } => {
+            let mut change = Vec::new();
+            varint_field(&mut change, 1, status_number(*from));
+            varint_field(&mut change, 2, status_number(*to));
+            rtt_field(&mut change, 3, *latency);
+            let mut message = header(target, *at);
+            bytes_field(&mut message, 4, &change);
+            message
+        }
+        Event::OutageStart { target, at } => {
+            let mut message = header(target, *at);
+            bytes_field(&mut message, 5, &[]);
+            message
+        }
+        Event::OutageEnd {
+            target,
+            at,
+            duration,
+        } => {
+            let mut end = Vec::new();
+            double_field(&mut end, 1, duration.as_secs_f64());
+            let mut message = header(target, *at);
+            bytes_field(&mut message, 6, &end);
+            message
+        }
+        Event::Alerts(alerts) => {
+            return alerts
+                .iter()
+                .map(|alert| {
+                    let mut message = header(&alert.target, alert.at);
+                    bytes_field(&mut message, 7, &encode_alert(alert));
+                    message
+                })
+                .collect();
+        }
+    };
+    vec![single]
+}
+
+fn encode_alert(alert: &Alert) -> Vec<u8> {
+    let mut message = Vec::new();
+    string_field(&mut message, 1, &alert.rule);
+    varint_field(
+        &mut message,
+        2,
+        u64::from(alert.state == AlertState::Resolved),
+    );
+    string_field(&mut message, 3, &alert.condition);
+    string_field(&mut message, 4, &alert.value);
+    varint_field(&mut message, 5, status_number(alert.status));
+    rtt_field(&mut message, 6, alert.latency);
+    message
+}
+
+/// The `Status` enum of the proto file.
+fn status_number(status: ConnectionStatus) -> u64 {
+    match status {
+        ConnectionStatus::Good => 1,
+        ConnectionStatus::Fair => 2,
+        ConnectionStatus::Poor => 3,
+        ConnectionStatus::Timeout => 4,
+    }
+}
+
+fn varint(message: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        message.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    message.push(value as u8);
+}
+
+/// A varint field, left out at 0 as proto3 does.
+fn varint_field(message: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        varint(message, field << 3);
+        varint(message, value);
+    }
+}
+
+fn double_field(message: &mut Vec<u8>, field: u64, value: f64) {
+    varint(message, field << 3 | 1);
+    message.extend_from_slice(&value.to_le_bytes());
+}
+
+/// An `optional double` of milliseconds, left out for lost probes.
+fn rtt_field(message: &mut Vec<u8>, field: u64, latency: Option<Duration>) {
+    if let Some(latency) = latency {
+        double_field(message, field, stats::millis(latency));
+    }
+}
+
+fn bytes_field(message: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(message, field << 3 | 2);
+    varint(message, bytes.len() as u64);
+    message.extend_from_slice(bytes);
+}
+
+fn string_field(message: &mut Vec<u8>, field: u64, text: &str) {
+    if !text.is_empty() {
+        bytes_field(message, field, text.as_bytes());
+    }
+}
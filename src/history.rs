@@ -0,0 +1,369 @@
+//! Recent probe results: the [`History`] of a target and the
+//! [`HistoryStore`] keeping those of all targets within a fixed memory
+//! budget.
+//!
+//! Histories in the store are ring buffers allocated once at their full
+//! size, so memory does not grow with uptime, and the budget is shared out
+//! between targets: with hundreds of them or long windows, each keeps its
+//! newest samples up to its share instead of the whole window.
+
+use crate::probe::{ConnectionStatus, ServerStatus};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// One sample: when it was taken, its class and its round-trip time.
+pub type Sample = (Instant, ConnectionStatus, Option<Duration>);
+
+/// Memory one sample takes in a history.
+pub const SAMPLE_BYTES: usize = size_of::<Sample>();
+
+/// Memory the histories of all targets may take unless configured.
+pub const DEFAULT_MEMORY: usize = 64 << 20;
+
+/// Probe results of the last `window`, oldest first.
+#[derive(Clone, Debug)]
+pub struct History {
+    window: Duration,
+    samples: VecDeque<Sample>,
+    /// The most samples kept, `None` for as many as the window holds.
+    capacity: Option<usize>,
+}
+
+impl History {
+    /// A history growing as needed, such as for a single result.
+    pub fn new(window: Duration) -> Self {
+        History {
+            window,
+            samples: VecDeque::new(),
+            capacity: None,
+        }
+    }
+
+    /// A ring buffer of `capacity` samples, allocated up front; when full,
+    /// the oldest sample makes room for the newest.
+    pub fn with_capacity(window: Duration, capacity: usize) -> Self {
+        History {
+            window,
+            samples: VecDeque::with_capacity(capacity),
+            capacity: Some(capacity),
+        }
+    }
+
+    /// Records a sample taken at `at` with its final `status`, as
+    /// processors and the script left it, and forgets those older than the
+    /// window.
+    pub fn push(&mut self, at: Instant, status: ConnectionStatus, latency: Option<Duration>) {
+        if self.capacity == Some(0) {
+            return;
+        }
+        if Some(self.samples.len()) == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, status, latency));
+        while let Some((timestamp, _, _)) = self.samples.front() {
+            if at.duration_since(*timestamp) > self.window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Resizes the buffer to `capacity` samples, keeping the newest.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        if self.samples.len() > capacity {
+            self.samples.drain(..self.samples.len() - capacity);
+        }
+        if capacity < self.samples.capacity() {
+            self.samples.shrink_to(capacity);
+        } else {
+            self.samples.reserve_exact(capacity - self.samples.len());
+        }
+        self.capacity = Some(capacity);
+    }
+
+    /// The most samples kept, `None` if unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Sample> {
+        self.samples.iter()
+    }
+
+    /// Forgets every sample, keeping the buffer.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Splits the `span` before `now` into as many columns as `columns`
+    /// has and sets each to the class of its latest sample, or `None`.
+    pub fn columns(&self, now: Instant, span: Duration, columns: &mut [Option<ConnectionStatus>]) {
+        columns.fill(None);
+        if self.samples.is_empty() || columns.is_empty() {
+            return;
+        }
+        let start = now.checked_sub(span).unwrap_or(now);
+        let per_column = span.as_secs_f64() / columns.len() as f64;
+        for (at, status, _) in &self.samples {
+            if *at >= start {
+                let column = (at.duration_since(start).as_secs_f64() / per_column) as usize;
+                if let Some(slot) = columns.get_mut(column) {
+                    *slot = Some(*status);
+                }
+            }
+        }
+    }
+}
+
+/// Samples a target probed every `interval` needs to cover `window`.
+fn wanted(window: Duration, interval: Duration) -> usize {
+    let interval = interval.max(Duration::from_millis(1));
+    (window.as_millis() / interval.as_millis()) as usize + 1
+}
+
+/// The latest status and history of every target, in the order they were
+/// added, followed by those archived.
+pub struct HistoryStore {
+    statuses: Vec<ServerStatus>,
+    /// Whether each status holds a result rather than a placeholder.
+    probed: Vec<bool>,
+    /// How many statuses at the end are archived: no longer probed, but
+    /// kept for their history.
+    archived: usize,
+    /// Names in the order their rows are wanted in, see
+    /// [`HistoryStore::arrange`].
+    order: Vec<String>,
+    window: Duration,
+    /// Samples a target needs to cover the window.
+    wanted: usize,
+    /// Samples all targets may keep together.
+    budget: usize,
+}
+
+impl HistoryStore {
+    /// Keeps the results of the last `window` of targets probed every
+    /// `interval`, in at most `memory` bytes of samples.
+    pub fn new(window: Duration, interval: Duration, memory: usize) -> Self {
+        HistoryStore {
+            statuses: Vec::new(),
+            probed: Vec::new(),
+            archived: 0,
+            order: Vec::new(),
+            window,
+            wanted: wanted(window, interval),
+            budget: memory / SAMPLE_BYTES,
+        }
+    }
+
+    /// Resizes every history for targets probed every `interval` from now
+    /// on, within the same memory: a shorter interval needs more samples to
+    /// cover the window, a longer one frees memory.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.wanted = wanted(self.window, interval);
+        self.rebalance();
+    }
+
+    /// Every target, including those not probed yet.
+    pub fn statuses(&self) -> &[ServerStatus] {
+        &self.statuses
+    }
+
+    /// The targets that have results.
+    pub fn probed(&self) -> impl Iterator<Item = &ServerStatus> {
+        self.statuses
+            .iter()
+            .zip(&self.probed)
+            .filter(|(_, probed)| **probed)
+            .map(|(status, _)| status)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ServerStatus> {
+        self.statuses.iter().find(|s| *s.name == *name)
+    }
+
+    /// Adds a placeholder for a target not probed yet, so that it keeps its
+    /// place whenever its first result arrives.
+    pub fn add(&mut self, name: &str) {
+        if self.get(name).is_none() {
+            self.insert(name);
+        }
+    }
+
+    /// Absorbs `update` into the status of its target, adding one for a new
+    /// target, and returns it. Archived targets stay as they were archived.
+    pub fn track(&mut self, update: &ServerStatus) -> &ServerStatus {
+        let index = match self.statuses.iter().position(|s| s.name == update.name) {
+            Some(index) if self.is_archived(index) => return &self.statuses[index],
+            Some(index) => index,
+            None => self.insert(Arc::clone(&update.name)),
+        };
+        self.probed[index] = true;
+        let status = &mut self.statuses[index];
+        status.absorb(update);
+        status
+    }
+
+    /// Forgets a target that is no longer monitored; the others get its
+    /// share of memory.
+    pub fn remove(&mut self, name: &str) {
+        if let Some(index) = self.statuses.iter().position(|s| *s.name == *name) {
+            if self.is_archived(index) {
+                self.archived -= 1;
+            }
+            self.statuses.remove(index);
+            self.probed.remove(index);
+            self.rebalance();
+        }
+    }
+
+    /// Forgets the samples of a target, and with them its statistics,
+    /// keeping its latest result; false if there is no such target.
+    pub fn clear(&mut self, name: &str) -> bool {
+        match self.statuses.iter_mut().find(|s| *s.name == *name) {
+            Some(status) => {
+                status.history.clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keeps the history of a target that is no longer probed, moved to the
+    /// end among the other archived targets; false if there is no such
+    /// target or it is archived already.
+    pub fn archive(&mut self, name: &str) -> bool {
+        let Some(index) = self.statuses.iter().position(|s| *s.name == *name) else {
+            return false;
+        };
+        if self.is_archived(index) {
+            return false;
+        }
+        let status = self.statuses.remove(index);
+        let probed = self.probed.remove(index);
+        let at = self.statuses.len() - self.archived;
+        self.statuses.insert(at, status);
+        self.probed.insert(at, probed);
+        self.archived += 1;
+        true
+    }
+
+    /// Whether the status at `index` of [`HistoryStore::statuses`] is
+    /// archived.
+    pub fn is_archived(&self, index: usize) -> bool {
+        index >= self.statuses.len() - self.archived
+    }
+
+    /// How many statuses at the end of [`HistoryStore::statuses`] are
+    /// archived.
+    pub fn archived(&self) -> usize {
+        self.archived
+    }
+
+    /// Puts the targets named in `order` first, in that order, followed by
+    /// the others as they were; targets added later take their place in
+    /// it too.
+    pub fn arrange(&mut self, order: Vec<String>) {
+        self.order = order;
+        let probed = self.statuses.len() - self.archived;
+        let mut rows: Vec<_> = self
+            .statuses
+            .drain(..probed)
+            .zip(self.probed.drain(..probed))
+            .collect();
+        rows.sort_by_key(|(status, _)| self.rank(&status.name));
+        let (statuses, probed): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+        self.statuses.splice(..0, statuses);
+        self.probed.splice(..0, probed);
+    }
+
+    /// Swaps the target at `index` with the one above it, or below if
+    /// `down`, and returns where it went; archived targets stay put.
+    pub fn shift(&mut self, index: usize, down: bool) -> Option<usize> {
+        let probed = self.statuses.len() - self.archived;
+        let to = if down {
+            index + 1
+        } else {
+            index.checked_sub(1)?
+        };
+        if index >= probed || to >= probed {
+            return None;
+        }
+        self.statuses.swap(index, to);
+        self.probed.swap(index, to);
+        // Names not shown now, such as those of an agent that did not
+        // report yet, keep their place at the end.
+        let shown: Vec<String> = self.statuses[..probed]
+            .iter()
+            .map(|s| s.name.to_string())
+            .collect();
+        let rest = self.order.iter().filter(|name| !shown.contains(name));
+        self.order = shown.iter().chain(rest).cloned().collect();
+        Some(to)
+    }
+
+    /// The order of the rows, as last arranged or shifted.
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// Where the target called `name` comes in the wanted order; after all
+    /// of them if it is not in there.
+    fn rank(&self, name: &str) -> usize {
+        self.order
+            .iter()
+            .position(|n| n == name)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// The samples each target keeps at most.
+    pub fn capacity(&self) -> usize {
+        self.share(self.statuses.len().max(1))
+    }
+
+    fn share(&self, targets: usize) -> usize {
+        self.wanted.min(self.budget / targets)
+    }
+
+    fn insert(&mut self, name: impl Into<Arc<str>>) -> usize {
+        let name = name.into();
+        let capacity = self.share(self.statuses.len() + 1);
+        let mut status = ServerStatus::pending(Arc::clone(&name), self.window);
+        status.history = History::with_capacity(self.window, capacity);
+        // Before the targets wanted after it, and the archived ones.
+        let rank = self.rank(&name);
+        let index = self.statuses[..self.statuses.len() - self.archived]
+            .iter()
+            .position(|s| self.rank(&s.name) > rank)
+            .unwrap_or(self.statuses.len() - self.archived);
+        self.statuses.insert(index, status);
+        self.probed.insert(index, false);
+        self.rebalance();
+        index
+    }
+
+    /// Gives every target the same share of the budget.
+    fn rebalance(&mut self) {
+        let capacity = self.capacity();
+        for status in &mut self.statuses {
+            if status.history.capacity() != Some(capacity) {
+                status.history.set_capacity(capacity);
+            }
+        }
+    }
+}
@@ -0,0 +1,748 @@
+//! HPACK, the header compression of HTTP/2 (RFC 7541), as far as the gRPC
+//! server needs it: decoding every form of header block a client sends,
+//! and encoding the few headers of a response as plain literals.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::LazyLock;
+
+/// The headers every block may refer to by index (RFC 7541, Appendix A).
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The size of the dynamic table until the peer's settings say otherwise.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// Why a header block could not be decoded: a connection error of type
+/// `COMPRESSION_ERROR`.
+#[derive(Debug)]
+pub struct DecodeError;
+
+/// Decodes the header blocks of one connection, which share the dynamic
+/// table.
+pub struct Decoder {
+    /// Newest first, as indexed.
+    table: VecDeque<(String, String)>,
+    size: usize,
+    /// The largest size the table may be set to, as in our settings.
+    max_size: usize,
+    /// The size set by the peer within `max_size`.
+    limit: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+            limit: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    /// The headers of a complete block, in order.
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<(String, String)>, DecodeError> {
+        let mut headers = Vec::new();
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                // Indexed header field.
+                let index = integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0x40 != 0 {
+                // Literal with incremental indexing.
+                let header = self.literal(&mut block, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if first & 0x20 != 0 {
+                // Dynamic table size update.
+                let size = integer(&mut block, 5)?;
+                if size > self.max_size {
+                    return Err(DecodeError);
+                }
+                self.limit = size;
+                self.evict(0);
+            } else {
+                // Literal without indexing, or never indexed.
+                headers.push(self.literal(&mut block, 4)?);
+            }
+        }
+        Ok(headers)
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), DecodeError> {
+        match index {
+            0 => Err(DecodeError),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => self.table.get(index - 62).cloned().ok_or(DecodeError),
+        }
+    }
+
+    /// A literal field whose name is indexed by a `prefix`-bit integer, or
+    /// follows as a string when that is 0.
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<(String, String), DecodeError> {
+        let index = integer(block, prefix)?;
+        let name = match index {
+            0 => string(block)?,
+            _ => self.entry(index)?.0,
+        };
+        Ok((name, string(block)?))
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        let size = entry_size(&header);
+        self.evict(size);
+        // An entry larger than the table empties it and is not added.
+        if size <= self.limit {
+            self.size += size;
+            self.table.push_front(header);
+        }
+    }
+
+    /// Evicts the oldest entries until `room` more octets fit.
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.limit {
+            match self.table.pop_back() {
+                Some(old) => self.size -= entry_size(&old),
+                None => break,
+            }
+        }
+    }
+}
+
+/// The size an entry counts for in the table: its octets plus 32.
+fn entry_size((name, value): &(String, String)) -> usize {
+    name.len() + value.len() + 32
+}
+
+/// An integer with an `prefix`-bit prefix (RFC 7541, 5.1).
+fn integer(block: &mut &[u8], prefix: u8) -> Result<usize, DecodeError> {
+    let (&first, rest) = block.split_first().ok_or(DecodeError)?;
+    *block = rest;
+    let max = (1usize << prefix) - 1;
+    let mut value = usize::from(first) & max;
+    if value < max {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(DecodeError)?;
+        *block = rest;
+        if shift > 28 {
+            return Err(DecodeError);
+        }
+        value += usize::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// A string literal, Huffman coded or not (RFC 7541, 5.2).
+fn string(block: &mut &[u8]) -> Result<String, DecodeError> {
+    let huffman = block.first().ok_or(DecodeError)? & 0x80 != 0;
+    let length = integer(block, 7)?;
+    if length > block.len() {
+        return Err(DecodeError);
+    }
+    let (octets, rest) = block.split_at(length);
+    *block = rest;
+    let octets = if huffman {
+        huffman_decode(octets)?
+    } else {
+        octets.to_vec()
+    };
+    String::from_utf8(octets).map_err(|_| DecodeError)
+}
+
+/// The octets of the Huffman codes, by length and code.
+static HUFFMAN_OCTETS: LazyLock<HashMap<(u8, u32), u8>> = LazyLock::new(|| {
+    HUFFMAN
+        .iter()
+        .enumerate()
+        .map(|(octet, &(code, length))| ((length, code), octet as u8))
+        .collect()
+});
+
+fn huffman_decode(coded: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut octets = Vec::with_capacity(coded.len() * 8 / 5);
+    let mut code = 0u32;
+    let mut length = 0u8;
+    for byte in coded {
+        for bit in (0..8).rev() {
+            code = code << 1 | u32::from(byte >> bit & 1);
+            length += 1;
+            if let Some(&octet) = HUFFMAN_OCTETS.get(&(length, code)) {
+                octets.push(octet);
+                code = 0;
+                length = 0;
+            } else if length >= 30 {
+                // Longer than any code: EOS or garbage.
+                return Err(DecodeError);
+            }
+        }
+    }
+    // Padding is the most significant bits of EOS: at most 7 ones.
+    if length > 7 || code != (1 << length) - 1 {
+        return Err(DecodeError);
+    }
+    Ok(octets)
+}
+
+/// Appends `name: value` to a header block as a literal that is never
+/// indexed, with neither string Huffman coded.
+pub fn encode(block: &mut Vec<u8>, name: &str, value: &str) {
+    block.push(0x10);
+    for text in [name, value] {
+        encode_integer(block, text.len(), 7, 0);
+        block.extend_from_slice(text.as_bytes());
+    }
+}
+
+fn encode_integer(block: &mut Vec<u8>, mut value: usize, prefix: u8, flags: u8) {
+    let max = (1usize << prefix) - 1;
+    if value < max {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | max as u8);
+    value -= max;
+    while value >= 0x80 {
+        block.push(value as u8 & 0x7f | 0x80);
+        value >>= 7;
+    }
+    block.push(value as u8);
+}
+
+/// The Huffman code of every octet, and its length in bits (RFC 7541,
+/// Appendix B).
+const HUFFMAN: [(u32, u8); 256] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The octets of `text`, hex digits separated by any whitespace.
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Decodes the three requests of RFC 7541, C.3 or C.4, checking the
+    /// headers and dynamic table after each.
+    fn decode_requests(blocks: [&str; 3]) {
+        let mut decoder = Decoder::default();
+        let first = [
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+        ];
+        assert_eq!(decoder.decode(&hex(blocks[0])).unwrap(), headers(&first));
+        assert_eq!(decoder.table, headers(&[(":authority", "www.example.com")]));
+        assert_eq!(decoder.size, 57);
+
+        let second = [
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+            ("cache-control", "no-cache"),
+        ];
+        assert_eq!(decoder.decode(&hex(blocks[1])).unwrap(), headers(&second));
+        assert_eq!(decoder.size, 110);
+
+        let third = [
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":path", "/index.html"),
+            (":authority", "www.example.com"),
+            ("custom-key", "custom-value"),
+        ];
+        assert_eq!(decoder.decode(&hex(blocks[2])).unwrap(), headers(&third));
+        assert_eq!(
+            decoder.table,
+            headers(&[
+                ("custom-key", "custom-value"),
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.size, 164);
+    }
+
+    #[test]
+    fn decodes_requests_without_huffman_coding() {
+        decode_requests([
+            "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+            "8286 84be 5808 6e6f 2d63 6163 6865",
+            "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65",
+        ]);
+    }
+
+    #[test]
+    fn decodes_requests_with_huffman_coding() {
+        decode_requests([
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_entries_when_the_table_is_full() {
+        // The responses of RFC 7541, C.5, with the table shrunk to 256
+        // octets by a size update in front of the first.
+        let mut decoder = Decoder::default();
+        let first = [
+            "3fe1 01",
+            "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120",
+            "4f63 7420 3230 3133 2032 303a 3133 3a32 3120 474d 546e 1768",
+            "7474 7073 3a2f 2f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+        ];
+        assert_eq!(
+            decoder.decode(&hex(&first.concat())).unwrap(),
+            headers(&[
+                (":status", "302"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.size, 222);
+
+        assert_eq!(
+            decoder.decode(&hex("4803 3330 37c1 c0bf")).unwrap(),
+            headers(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+        // ":status: 302" made room for ":status: 307".
+        assert_eq!(
+            decoder.table,
+            headers(&[
+                (":status", "307"),
+                ("location", "https://www.example.com"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("cache-control", "private"),
+            ])
+        );
+        assert_eq!(decoder.size, 222);
+
+        let third = [
+            "88c1 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a",
+            "3133 3a32 3220 474d 54c0 5a04 677a 6970 7738 666f 6f3d 4153",
+            "444a 4b48 514b 425a 584f 5157 454f 5049 5541 5851 5745 4f49",
+            "553b 206d 6178 2d61 6765 3d33 3630 303b 2076 6572 7369 6f6e",
+            "3d31",
+        ];
+        let cookie = "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1";
+        assert_eq!(
+            decoder.decode(&hex(&third.concat())).unwrap(),
+            headers(&[
+                (":status", "200"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+                ("location", "https://www.example.com"),
+                ("content-encoding", "gzip"),
+                ("set-cookie", cookie),
+            ])
+        );
+        assert_eq!(
+            decoder.table,
+            headers(&[
+                ("set-cookie", cookie),
+                ("content-encoding", "gzip"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+            ])
+        );
+        assert_eq!(decoder.size, 215);
+    }
+
+    #[test]
+    fn refuses_truncated_and_oversized_integers() {
+        for block in [
+            // A prefix saying more follows, then nothing.
+            &[0xff][..],
+            &[0xff, 0x80],
+            // More continuation octets than any header needs.
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            &[0x3f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        ] {
+            assert!(Decoder::default().decode(block).is_err(), "{:02x?}", block);
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_blocks() {
+        for block in [
+            // Index 0, and an index past both tables.
+            &[0x80][..],
+            &[0xc0],
+            // A name longer than the block.
+            &[0x40, 0x05, b'a'],
+            // A literal with its value missing.
+            &[0x40, 0x01, b'a'],
+            // Huffman padding that is not all ones.
+            &[0x40, 0x81, 0x00, 0x00],
+            // A size update beyond the table's 4096 octets.
+            &[0x3f, 0xe2, 0x1f],
+            // A name that is not UTF-8.
+            &[0x40, 0x01, 0xff, 0x00],
+        ] {
+            assert!(Decoder::default().decode(block).is_err(), "{:02x?}", block);
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let long = "x".repeat(300);
+        let mut block = Vec::new();
+        encode(&mut block, ":status", "200");
+        encode(&mut block, "content-type", "application/grpc");
+        encode(&mut block, "grpc-message", &long);
+        assert_eq!(
+            Decoder::default().decode(&block).unwrap(),
+            headers(&[
+                (":status", "200"),
+                ("content-type", "application/grpc"),
+                ("grpc-message", &long),
+            ])
+        );
+    }
+}
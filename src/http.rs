@@ -0,0 +1,490 @@
+//! Embedded HTTP server exposing the live state as JSON, plus a small web
+//! dashboard at `/` that renders the same endpoints in a browser, a
+//! `/watch` stream of events as they happen and a `/samples` stream of local
+//! results for cluster peers.
+//!
+//! `/search` and `/query` serve the live and stored history to Grafana, see
+//! [`crate::grafana`].
+//!
+//! A deliberately small HTTP/1.1 implementation: `GET`, plus `POST` for the
+//! Grafana endpoints, one request per connection, no keep-alive. That is all
+//! local tools need to poll latencee without pulling in a web framework.
+
+use crate::{
+    ServerStatus,
+    config::HttpConfig,
+    events::Event,
+    grafana::{self, Query},
+    history::HistoryStore,
+    json::{self, Json},
+    rt::channel,
+    sinks::QUEUE,
+    stats, timefmt, wire,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The browser dashboard; it polls the JSON endpoints below.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest request line and headers accepted, together.
+const MAX_HEAD: u64 = 8 * 1024;
+
+/// The largest request body accepted.
+const MAX_BODY: usize = 1 << 20;
+
+/// How long a stalled `/watch` client may block its handler before it is
+/// dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections served at once, each on its own thread; more are answered
+/// with 503 until one ends.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Shared with the request handlers.
+struct State {
+    /// The latest status and history of every target.
+    histories: Arc<Mutex<HistoryStore>>,
+    /// The directory of the round-robin files, if history is stored.
+    storage: Option<PathBuf>,
+    /// One queue per `/watch` client, receiving JSON lines.
+    watchers: Mutex<Vec<channel::Sender<String>>>,
+    /// One queue per `/samples` client, receiving [`wire`] lines.
+    followers: Mutex<Vec<channel::Sender<String>>>,
+    connections: AtomicUsize,
+    /// Lines stream clients lost because they fell behind.
+    dropped: AtomicU64,
+}
+
+pub struct Server {
+    state: Arc<State>,
+}
+
+impl Server {
+    /// Binds the configured address and serves requests on background
+    /// threads, answering from `histories` and, for older data, the
+    /// round-robin files in `storage`.
+    pub fn start(
+        config: &HttpConfig,
+        histories: Arc<Mutex<HistoryStore>>,
+        storage: Option<PathBuf>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(&config.listen)
+            .map_err(|e| io::Error::new(e.kind(), format!("http: {}: {}", config.listen, e)))?;
+        let state = Arc::new(State {
+            histories,
+            storage,
+            watchers: Mutex::default(),
+            followers: Mutex::default(),
+            connections: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if shared.connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    shared.connections.fetch_sub(1, Ordering::Relaxed);
+                    busy(stream);
+                    continue;
+                }
+                let state = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let _ = handle(stream, &state);
+                    state.connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Ok(Server { state })
+    }
+
+    /// Streams a local result to `/samples` clients.
+    pub fn update(&self, status: &ServerStatus) {
+        // Peers only get what was measured here, so results never travel in
+        // circles between instances following each other.
+        if status.source.is_some() {
+            return;
+        }
+        let mut followers = self.state.followers.lock().unwrap();
+        if !followers.is_empty() {
+            let line = wire::encode_status(status);
+            self.state.send(&mut followers, &line);
+        }
+    }
+
+    /// Sends an event to every `/watch` client, forgetting those that are
+    /// gone.
+    pub fn publish(&self, event: &Event) {
+        let mut watchers = self.state.watchers.lock().unwrap();
+        if watchers.is_empty() {
+            return;
+        }
+        let lines: String = event
+            .json()
+            .into_iter()
+            .map(|json| format!("{}\n", json))
+            .collect();
+        self.state.send(&mut watchers, &lines);
+    }
+
+    /// Lines `/watch` and `/samples` clients dropped so far because they
+    /// fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl State {
+    /// Queues `lines` for every client in `subscribers`, forgetting those
+    /// that are gone. A client whose queue is full loses its oldest lines
+    /// instead, as sinks do.
+    fn send(&self, subscribers: &mut Vec<channel::Sender<String>>, lines: &str) {
+        subscribers.retain(
+            |subscriber| match subscriber.force_send(lines.to_string()) {
+                Ok(Some(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Ok(None) => true,
+                Err(_) => false,
+            },
+        );
+    }
+}
+
+/// Turns away a client over the connection limit.
+fn busy(mut stream: TcpStream) {
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let _ = stream.write_all(
+        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    );
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(body: Json) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn html(body: &str) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: Json::object([("error", Json::from(status))]).to_string(),
+        }
+    }
+}
+
+fn handle(stream: TcpStream, state: &State) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // The read timeout only bounds the pauses of a client, not how much
+    // it sends.
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Only the length of a body is of interest.
+    let mut length = 0;
+    let mut header = String::new();
+    let mut complete = false;
+    while reader.read_line(&mut header)? > 0 {
+        if header.trim_end().is_empty() {
+            complete = true;
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().unwrap_or(0);
+        }
+        header.clear();
+    }
+    let too_large = !complete && reader.get_ref().limit() == 0;
+    reader.get_mut().set_limit(MAX_BODY as u64);
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if too_large => Response::error("431 Request Header Fields Too Large"),
+        (Some("GET"), Some(target)) if is_stream(target, "/watch") => {
+            return stream_lines(&stream, &state.watchers, "application/x-ndjson", "");
+        }
+        (Some("GET"), Some(target)) if is_stream(target, "/samples") => {
+            // Start with the recent history so that peers draw full charts.
+            let history: String = state
+                .histories
+                .lock()
+                .unwrap()
+                .probed()
+                .filter(|s| s.source.is_none())
+                .map(wire::encode_history)
+                .collect();
+            return stream_lines(
+                &stream,
+                &state.followers,
+                "text/tab-separated-values",
+                &history,
+            );
+        }
+        (Some("GET"), Some(target)) => route(target, state),
+        (Some("POST"), Some(_)) if length > MAX_BODY => Response::error("413 Content Too Large"),
+        (Some("POST"), Some(target)) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            route_post(target, &String::from_utf8_lossy(&body), state)
+        }
+        (Some(_), Some(_)) => Response::error("405 Method Not Allowed"),
+        _ => Response::error("400 Bad Request"),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+fn is_stream(target: &str, endpoint: &str) -> bool {
+    let path = target.split('?').next().unwrap_or(target);
+    path.trim_end_matches('/') == endpoint
+}
+
+/// Streams `initial`, then the lines sent to a new subscriber of
+/// `subscribers` until the client goes away. Without a content length, the
+/// end of the connection ends the body.
+fn stream_lines(
+    mut stream: &TcpStream,
+    subscribers: &Mutex<Vec<channel::Sender<String>>>,
+    content_type: &str,
+    initial: &str,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (sender, receiver) = channel::bounded(QUEUE);
+    subscribers.lock().unwrap().push(sender);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        content_type
+    )?;
+    stream.write_all(initial.as_bytes())?;
+    stream.flush()?;
+    while let Ok(line) = receiver.recv_blocking() {
+        stream.write_all(line.as_bytes())?;
+        stream.flush()?;
+    }
+    Ok(())
+}
+
+fn route(target: &str, state: &State) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path.trim_end_matches('/') {
+        "/search" => return search(state),
+        "/query" => return query_rows(query, state),
+        _ => {}
+    }
+    let histories = state.histories.lock().unwrap();
+    let statuses = || histories.probed();
+    match path.trim_end_matches('/') {
+        "" => Response::html(DASHBOARD),
+        "/targets" => Response::json(Json::Array(statuses().map(target_json).collect())),
+        "/summary" => Response::json(Json::Array(statuses().map(summary_json).collect())),
+        other => {
+            let name = other
+                .strip_prefix("/targets/")
+                .and_then(|rest| rest.strip_suffix("/history"))
+                .and_then(percent_decode);
+            match name.and_then(|name| statuses().find(|s| *s.name == *name)) {
+                Some(status) => Response::json(history_json(status)),
+                None => Response::error("404 Not Found"),
+            }
+        }
+    }
+}
+
+/// The Grafana JSON datasource endpoints, which post their parameters.
+fn route_post(target: &str, body: &str, state: &State) -> Response {
+    let path = target.split('?').next().unwrap_or(target);
+    match path.trim_end_matches('/') {
+        "/search" => search(state),
+        // Newer versions of the datasource list targets as metrics.
+        "/metrics" => {
+            let names = grafana::names(&state.histories, state.storage.as_deref());
+            Response::json(Json::Array(
+                names
+                    .into_iter()
+                    .map(|name| {
+                        Json::object([
+                            ("label", Json::from(name.as_str())),
+                            ("value", Json::from(name)),
+                        ])
+                    })
+                    .collect(),
+            ))
+        }
+        "/query" => match json::parse(body).as_ref().and_then(Query::parse) {
+            Some(query) => Response::json(Json::Array(
+                query
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        let points = grafana::series(
+                            &state.histories,
+                            state.storage.as_deref(),
+                            target,
+                            query.from,
+                            query.to,
+                            query.max_points,
+                        );
+                        grafana::timeserie(target, &points)
+                    })
+                    .collect(),
+            )),
+            None => Response::error("400 Bad Request"),
+        },
+        "/annotations" | "/tag-keys" | "/tag-values" => Response::json(Json::Array(Vec::new())),
+        _ => Response::error("405 Method Not Allowed"),
+    }
+}
+
+fn search(state: &State) -> Response {
+    Response::json(Json::from(grafana::names(
+        &state.histories,
+        state.storage.as_deref(),
+    )))
+}
+
+/// `GET /query?target=...&from=...&to=...`, with times in Unix
+/// milliseconds as Grafana's `${__from}` and `${__to}` give them; the last
+/// hour unless given.
+fn query_rows(query: &str, state: &State) -> Response {
+    let param = |key: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, value)| percent_decode(&value.replace('+', " ")))
+    };
+    let time = |key| {
+        param(key)
+            .and_then(|ms| ms.parse().ok())
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+    };
+    let Some(target) = param("target") else {
+        return Response::error("400 Bad Request");
+    };
+    let to = time("to").unwrap_or_else(SystemTime::now);
+    let from = time("from").unwrap_or(to - Duration::from_secs(3600));
+    let points = grafana::series(
+        &state.histories,
+        state.storage.as_deref(),
+        &target,
+        from,
+        to,
+        0,
+    );
+    Response::json(grafana::rows(&target, &points))
+}
+
+/// Decodes `%XX` escapes, so that `Google%20DNS` names `Google DNS`.
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.bytes();
+    while let Some(b) = rest.next() {
+        if b == b'%' {
+            let hex = [rest.next()?, rest.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn target_json(status: &ServerStatus) -> Json {
+    Json::object([
+        ("name", Json::from(&*status.name)),
+        ("source", Json::from(status.source.as_deref())),
+        ("status", Json::from(status.status.label())),
+        ("rtt_ms", Json::from(status.latency.map(stats::millis))),
+        ("error", Json::from(status.error.map(|e| e.name()))),
+        ("at", Json::from(timefmt::rfc3339(status.sampled_at))),
+        (
+            "metrics",
+            Json::object(
+                status
+                    .metrics
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), Json::from(*value))),
+            ),
+        ),
+    ])
+}
+
+fn history_json(status: &ServerStatus) -> Json {
+    let samples = status
+        .history
+        .iter()
+        .map(|(at, class, latency)| {
+            let age = status.last_update.saturating_duration_since(*at);
+            Json::object([
+                ("at", Json::from(timefmt::rfc3339(status.sampled_at - age))),
+                ("status", Json::from(class.label())),
+                ("rtt_ms", Json::from(latency.map(stats::millis))),
+            ])
+        })
+        .collect();
+    Json::object([
+        ("name", Json::from(&*status.name)),
+        ("window_s", Json::from(status.history.window().as_secs())),
+        ("samples", Json::Array(samples)),
+    ])
+}
+
+fn summary_json(status: &ServerStatus) -> Json {
+    let summary = stats::summarize(status.history.iter().map(|(_, _, latency)| *latency));
+    let ms = |latency: Option<Duration>| Json::from(latency.map(stats::millis));
+    Json::object([
+        ("name", Json::from(&*status.name)),
+        ("status", Json::from(status.status.label())),
+        ("samples", Json::from(summary.samples)),
+        ("lost", Json::from(summary.lost)),
+        ("loss", Json::from(summary.loss())),
+        ("min_ms", ms(summary.min)),
+        ("avg_ms", ms(summary.avg)),
+        ("p50_ms", ms(summary.p50)),
+        ("p95_ms", ms(summary.p95)),
+        ("max_ms", ms(summary.max)),
+        ("jitter_ms", ms(summary.jitter)),
+    ])
+}
@@ -0,0 +1,99 @@
+//! A probe timing HTTPS requests through curl: the `https` probe sends a
+//! GET for `/` and measures from the request leaving to the first byte of
+//! the answer, leaving out DNS, the TCP handshake and TLS.
+//!
+//! This is the latency to where the request is served rather than to
+//! where the connection ends. Services behind anycast front ends, such as
+//! Google Cloud Run, accept connections at the edge closest to the client,
+//! so a TCP handshake times the edge; the request itself still travels on
+//! to the region serving it. Any HTTP status counts as an answer.
+
+use crate::{
+    Target, network,
+    probe::{Probe, ProbeError, ProbeFuture},
+    rt,
+};
+use std::{
+    io::ErrorKind,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// The default HTTPS port.
+pub const HTTPS_PORT: u16 = 443;
+
+/// How long a request may take in all, TLS included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A GET request sent with curl.
+pub struct Https {
+    port: u16,
+    curl: String,
+}
+
+impl Https {
+    /// Connects to `port` of targets without a port of their own, running
+    /// the curl binary `curl`.
+    pub fn new(port: u16, curl: String) -> Self {
+        Https { port, curl }
+    }
+}
+
+impl Probe for Https {
+    fn kind(&self) -> &'static str {
+        "https"
+    }
+
+    fn description(&self) -> &'static str {
+        "time from an HTTPS request to the first byte of its answer, via curl"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let host = if target.host.contains(':') {
+            format!("[{}]", target.host)
+        } else {
+            target.host.clone()
+        };
+        let url = format!("https://{}:{}/", host, target.port.unwrap_or(self.port));
+        let curl = self.curl.clone();
+        let network = target.network.clone();
+        Box::pin(rt::unblock(move || {
+            network::within(network.as_ref(), || request(&curl, &url))
+        }))
+    }
+}
+
+fn request(curl: &str, url: &str) -> Result<Duration, ProbeError> {
+    let output = Command::new(curl)
+        .args(["--silent", "--output"])
+        .arg(if cfg!(windows) { "NUL" } else { "/dev/null" })
+        .args(["--write-out", "%{time_pretransfer} %{time_starttransfer}"])
+        .arg("--max-time")
+        .arg(REQUEST_TIMEOUT.as_secs().to_string())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => ProbeError::BinaryMissing,
+            ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Timeout,
+        })?;
+    // curl's exit codes for failed name resolution, connect and timeout.
+    match output.status.code() {
+        Some(0) => {}
+        Some(6) => return Err(ProbeError::Dns),
+        Some(7) => return Err(ProbeError::Unreachable),
+        _ => return Err(ProbeError::Timeout),
+    }
+    let timings = String::from_utf8_lossy(&output.stdout);
+    let mut seconds = timings
+        .split_whitespace()
+        .map(|value| value.parse::<f64>().ok());
+    match (seconds.next().flatten(), seconds.next().flatten()) {
+        (Some(sent), Some(answered)) if answered >= sent => {
+            Ok(Duration::from_secs_f64(answered - sent))
+        }
+        _ => Err(ProbeError::Timeout),
+    }
+}
@@ -0,0 +1,492 @@
+//! ICMP echo without privileges: over the datagram sockets Linux, Android
+//! and macOS offer for pinging, and with the ICMP helper API of Windows.
+//!
+//! Unlike raw sockets these need neither root nor `CAP_NET_RAW`, and unlike
+//! the system `ping` no process is started per probe, so no console window
+//! flashes on Windows either. On Linux the sockets are limited to the groups
+//! in `net.ipv4.ping_group_range`, which many distributions open to every
+//! group; elsewhere raw sockets are used when latencee runs as root or has
+//! `CAP_NET_RAW` (see `latencee setup-caps`). Where neither works,
+//! [`crate::probe::Icmp`] falls back to the system `ping`.
+
+use crate::probe::{ProbeError, ProbeResult};
+use std::{sync::OnceLock, time::Duration};
+
+/// How echo requests are sent, or why they cannot be; checked once.
+fn state() -> &'static Result<sys::Backend, String> {
+    static STATE: OnceLock<Result<sys::Backend, String>> = OnceLock::new();
+    STATE.get_or_init(sys::check)
+}
+
+/// What latencee sends echo requests with, e.g. "unprivileged ICMP
+/// sockets"; `None` when it cannot.
+pub fn backend() -> Option<&'static str> {
+    state().as_ref().ok().map(|backend| backend.describe())
+}
+
+/// Whether echo requests go out without privileges, unlike raw sockets,
+/// which need root or `CAP_NET_RAW`.
+pub fn unprivileged() -> bool {
+    state().as_ref().is_ok_and(|backend| backend.unprivileged())
+}
+
+/// Why latencee cannot send echo requests itself; `None` when it can.
+pub fn unavailable_reason() -> Option<&'static str> {
+    state().as_ref().err().map(String::as_str)
+}
+
+/// Sends one echo request to `host` and waits up to `timeout` for the reply.
+pub fn echo(host: &str, timeout: Duration) -> ProbeResult {
+    match state() {
+        Ok(backend) => sys::echo(*backend, host, timeout),
+        Err(_) => Err(ProbeError::PermissionDenied),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+mod sys {
+    use super::{ProbeError, ProbeResult};
+    use std::{
+        io::{self, ErrorKind},
+        net::{ToSocketAddrs, UdpSocket},
+        os::fd::FromRawFd,
+        process,
+        sync::atomic::{AtomicU16, Ordering},
+        time::{Duration, Instant},
+    };
+
+    const ECHO_REQUEST_V4: u8 = 8;
+    const ECHO_REPLY_V4: u8 = 0;
+    const ECHO_REQUEST_V6: u8 = 128;
+    const ECHO_REPLY_V6: u8 = 129;
+
+    /// Carried by every request and expected back in the reply.
+    const PAYLOAD: &[u8; 16] = b"latencee-probe!!";
+
+    /// Sequence number of the next request, shared by all targets so that
+    /// replies cannot be mistaken for each other.
+    static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+    #[derive(Clone, Copy)]
+    pub enum Backend {
+        Datagram,
+        /// Receives every ICMP packet of the host, including the IPv4 header.
+        Raw,
+    }
+
+    impl Backend {
+        pub fn describe(self) -> &'static str {
+            match self {
+                Backend::Datagram => "unprivileged ICMP sockets",
+                Backend::Raw => "raw ICMP sockets",
+            }
+        }
+
+        pub fn unprivileged(self) -> bool {
+            matches!(self, Backend::Datagram)
+        }
+
+        fn socket_type(self) -> libc::c_int {
+            match self {
+                Backend::Datagram => libc::SOCK_DGRAM,
+                Backend::Raw => libc::SOCK_RAW,
+            }
+        }
+    }
+
+    pub fn check() -> Result<Backend, String> {
+        let Err(e) = open(Backend::Datagram, false) else {
+            return Ok(Backend::Datagram);
+        };
+        if open(Backend::Raw, false).is_ok() {
+            return Ok(Backend::Raw);
+        }
+        Err(match group_range() {
+            Some(range) if e.kind() == ErrorKind::PermissionDenied => format!(
+                "unprivileged ICMP sockets are not permitted, net.ipv4.ping_group_range \
+                 ({}) excludes our groups",
+                range
+            ),
+            _ => format!("cannot open an unprivileged ICMP socket: {}", e),
+        })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn group_range() -> Option<String> {
+        std::fs::read_to_string("/proc/sys/net/ipv4/ping_group_range")
+            .ok()
+            .map(|range| range.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn group_range() -> Option<String> {
+        None
+    }
+
+    fn open(backend: Backend, v6: bool) -> io::Result<UdpSocket> {
+        let (domain, protocol) = if v6 {
+            (libc::AF_INET6, libc::IPPROTO_ICMPV6)
+        } else {
+            (libc::AF_INET, libc::IPPROTO_ICMP)
+        };
+        // SAFETY: plain system calls; the descriptor is valid when
+        // non-negative and the socket takes ownership of it.
+        unsafe {
+            let fd = libc::socket(domain, backend.socket_type(), protocol);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = UdpSocket::from_raw_fd(fd);
+            // Not inherited by the commands hooks and sinks run.
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            #[cfg(target_os = "linux")]
+            if let Some(device) = crate::network::bound_device() {
+                crate::network::sys::bind_to_device(fd, &device)?;
+            }
+            Ok(socket)
+        }
+    }
+
+    pub fn echo(backend: Backend, host: &str, timeout: Duration) -> ProbeResult {
+        let address = (host, 0)
+            .to_socket_addrs()
+            .map_err(|_| ProbeError::Dns)?
+            .next()
+            .ok_or(ProbeError::Dns)?;
+        let v6 = address.is_ipv6();
+        let socket = open(backend, v6).map_err(classify)?;
+
+        // Datagram sockets of Linux and Android replace the identifier with
+        // the socket's own and match replies by it; otherwise it is up to us.
+        let kernel_matched = matches!(backend, Backend::Datagram)
+            && cfg!(any(target_os = "linux", target_os = "android"));
+        let identifier = process::id() as u16;
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let mut request = [0u8; 8 + PAYLOAD.len()];
+        request[0] = if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 };
+        request[4..6].copy_from_slice(&identifier.to_be_bytes());
+        request[6..8].copy_from_slice(&sequence.to_be_bytes());
+        request[8..].copy_from_slice(PAYLOAD);
+        // The kernel computes ICMPv6 checksums, which cover the IP header.
+        if !v6 {
+            let sum = checksum(&request);
+            request[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        let start = Instant::now();
+        socket.send_to(&request, address).map_err(classify)?;
+        let deadline = start + timeout;
+        let mut buffer = [0u8; 1500];
+        let expected = (!kernel_matched).then_some(identifier);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ProbeError::Timeout);
+            }
+            socket
+                .set_read_timeout(Some(remaining.max(Duration::from_millis(1))))
+                .map_err(classify)?;
+            let (len, from) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Err(ProbeError::Timeout);
+                }
+                Err(e) => return Err(classify(e)),
+            };
+            if from.ip() == address.ip() && is_reply(&buffer[..len], v6, expected, sequence) {
+                return Ok(start.elapsed());
+            }
+        }
+    }
+
+    fn is_reply(packet: &[u8], v6: bool, identifier: Option<u16>, sequence: u16) -> bool {
+        // Raw sockets, and datagram sockets on macOS, pass IPv4 replies on
+        // with their IP header.
+        let packet = match packet.first() {
+            Some(&first) if !v6 && first >> 4 == 4 => {
+                packet.get(usize::from(first & 0x0f) * 4..).unwrap_or(&[])
+            }
+            _ => packet,
+        };
+        let Some(header) = packet.get(..8) else {
+            return false;
+        };
+        let reply = if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 };
+        header[0] == reply
+            && identifier.is_none_or(|identifier| header[4..6] == identifier.to_be_bytes())
+            && header[6..8] == sequence.to_be_bytes()
+            && &packet[8..] == PAYLOAD
+    }
+
+    /// The Internet checksum of RFC 1071.
+    fn checksum(data: &[u8]) -> u16 {
+        let mut sum: u32 = data
+            .chunks(2)
+            .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+            .sum();
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    fn classify(e: io::Error) -> ProbeError {
+        match e.kind() {
+            ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            ErrorKind::NetworkUnreachable
+            | ErrorKind::HostUnreachable
+            | ErrorKind::ConnectionRefused => ProbeError::Unreachable,
+            _ => ProbeError::Timeout,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::{ProbeError, ProbeResult};
+    use std::{
+        ffi::c_void,
+        io,
+        mem::size_of,
+        net::{IpAddr, ToSocketAddrs},
+        ptr,
+        time::{Duration, Instant},
+    };
+
+    type Handle = *mut c_void;
+
+    const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+    const AF_INET6: u16 = 23;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const IP_DEST_NET_UNREACHABLE: u32 = 11002;
+    const IP_DEST_PORT_UNREACHABLE: u32 = 11005;
+    const IP_DEST_UNREACHABLE: u32 = 11040;
+
+    /// Offset of `Status` in `ICMPV6_ECHO_REPLY`, after the packed 26 byte
+    /// address; in `ICMP_ECHO_REPLY` it follows the 4 byte address.
+    const STATUS_OFFSET_V6: usize = 28;
+    const STATUS_OFFSET_V4: usize = 4;
+
+    const PAYLOAD: &[u8; 16] = b"latencee-probe!!";
+
+    #[repr(C)]
+    struct SockaddrIn6 {
+        family: u16,
+        port: u16,
+        flowinfo: u32,
+        addr: [u8; 16],
+        scope_id: u32,
+    }
+
+    #[link(name = "iphlpapi")]
+    unsafe extern "system" {
+        fn IcmpCreateFile() -> Handle;
+        fn Icmp6CreateFile() -> Handle;
+        fn IcmpCloseHandle(handle: Handle) -> i32;
+        fn IcmpSendEcho2(
+            handle: Handle,
+            event: Handle,
+            apc_routine: *mut c_void,
+            apc_context: *mut c_void,
+            destination: u32,
+            request: *const c_void,
+            request_size: u16,
+            options: *const c_void,
+            reply: *mut c_void,
+            reply_size: u32,
+            timeout: u32,
+        ) -> u32;
+        fn Icmp6SendEcho2(
+            handle: Handle,
+            event: Handle,
+            apc_routine: *mut c_void,
+            apc_context: *mut c_void,
+            source: *const SockaddrIn6,
+            destination: *const SockaddrIn6,
+            request: *const c_void,
+            request_size: u16,
+            options: *const c_void,
+            reply: *mut c_void,
+            reply_size: u32,
+            timeout: u32,
+        ) -> u32;
+    }
+
+    /// An ICMP handle, closed when dropped.
+    struct Icmp(Handle);
+
+    impl Icmp {
+        fn open(v6: bool) -> io::Result<Self> {
+            // SAFETY: no preconditions; failure is reported by the value.
+            let handle = unsafe {
+                if v6 {
+                    Icmp6CreateFile()
+                } else {
+                    IcmpCreateFile()
+                }
+            };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Icmp(handle))
+        }
+    }
+
+    impl Drop for Icmp {
+        fn drop(&mut self) {
+            // SAFETY: the handle is valid and closed only here.
+            unsafe { IcmpCloseHandle(self.0) };
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct Backend;
+
+    impl Backend {
+        pub fn describe(self) -> &'static str {
+            "the Windows ICMP API"
+        }
+
+        pub fn unprivileged(self) -> bool {
+            true
+        }
+    }
+
+    pub fn check() -> Result<Backend, String> {
+        Icmp::open(false)
+            .map(|_| Backend)
+            .map_err(|e| format!("cannot open an ICMP handle: {}", e))
+    }
+
+    pub fn echo(_: Backend, host: &str, timeout: Duration) -> ProbeResult {
+        let address = (host, 0)
+            .to_socket_addrs()
+            .map_err(|_| ProbeError::Dns)?
+            .next()
+            .ok_or(ProbeError::Dns)?;
+        let icmp = Icmp::open(address.is_ipv6()).map_err(|e| classify_error(&e))?;
+        let timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        // Room for the reply structure, the echoed payload and an ICMP error
+        // message; u64 for the alignment of the structure's pointers.
+        let mut reply = [0u64; 64];
+        let reply_size = size_of::<[u64; 64]>() as u32;
+        let start = Instant::now();
+        // SAFETY: every pointer is valid for the duration of the call, which
+        // is synchronous without an event or APC routine.
+        let (replies, status_offset) = unsafe {
+            match address.ip() {
+                IpAddr::V4(ip) => (
+                    IcmpSendEcho2(
+                        icmp.0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        u32::from_ne_bytes(ip.octets()),
+                        PAYLOAD.as_ptr().cast(),
+                        PAYLOAD.len() as u16,
+                        ptr::null(),
+                        reply.as_mut_ptr().cast(),
+                        reply_size,
+                        timeout,
+                    ),
+                    STATUS_OFFSET_V4,
+                ),
+                IpAddr::V6(ip) => {
+                    let source = SockaddrIn6 {
+                        family: AF_INET6,
+                        port: 0,
+                        flowinfo: 0,
+                        addr: [0; 16],
+                        scope_id: 0,
+                    };
+                    let destination = SockaddrIn6 {
+                        addr: ip.octets(),
+                        ..source
+                    };
+                    (
+                        Icmp6SendEcho2(
+                            icmp.0,
+                            ptr::null_mut(),
+                            ptr::null_mut(),
+                            ptr::null_mut(),
+                            &source,
+                            &destination,
+                            PAYLOAD.as_ptr().cast(),
+                            PAYLOAD.len() as u16,
+                            ptr::null(),
+                            reply.as_mut_ptr().cast(),
+                            reply_size,
+                            timeout,
+                        ),
+                        STATUS_OFFSET_V6,
+                    )
+                }
+            }
+        };
+        // The reply's own round-trip time only has millisecond resolution.
+        let elapsed = start.elapsed();
+        if replies == 0 {
+            return Err(classify_error(&io::Error::last_os_error()));
+        }
+        let bytes: Vec<u8> = reply.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        let status = u32::from_ne_bytes(
+            bytes[status_offset..status_offset + 4]
+                .try_into()
+                .expect("four bytes"),
+        );
+        match status {
+            0 => Ok(elapsed),
+            status => Err(classify_status(status)),
+        }
+    }
+
+    /// Failures are reported as `IP_*` status codes, or as system errors.
+    fn classify_error(e: &io::Error) -> ProbeError {
+        match e.raw_os_error() {
+            Some(ERROR_ACCESS_DENIED) => ProbeError::PermissionDenied,
+            Some(code) => classify_status(code as u32),
+            None => ProbeError::Timeout,
+        }
+    }
+
+    fn classify_status(status: u32) -> ProbeError {
+        match status {
+            IP_DEST_NET_UNREACHABLE..=IP_DEST_PORT_UNREACHABLE | IP_DEST_UNREACHABLE => {
+                ProbeError::Unreachable
+            }
+            _ => ProbeError::Timeout,
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    windows
+)))]
+mod sys {
+    use super::ProbeResult;
+    use std::time::Duration;
+
+    #[derive(Clone, Copy)]
+    pub enum Backend {}
+
+    impl Backend {
+        pub fn describe(self) -> &'static str {
+            match self {}
+        }
+
+        pub fn unprivileged(self) -> bool {
+            match self {}
+        }
+    }
+
+    pub fn check() -> Result<Backend, String> {
+        Err("unprivileged ICMP sockets are not supported on this platform".to_string())
+    }
+
+    pub fn echo(backend: Backend, _host: &str, _timeout: Duration) -> ProbeResult {
+        match backend {}
+    }
+}
@@ -0,0 +1,456 @@
+//! A tiny JSON value type for building payloads, and a parser for the few
+//! requests that come with one.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from key/value pairs, keeping their order.
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// The value of `key` in an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a JSON document; anything malformed yields `None`.
+pub fn parse(text: &str) -> Option<Json> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    (parser.pos == parser.bytes.len()).then_some(value)
+}
+
+/// Nesting deeper than this is refused rather than recursed into.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, literal: &str) -> bool {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        match self.peek()? {
+            b'{' => self.nested(Parser::object),
+            b'[' => self.nested(Parser::array),
+            b'"' => self.string().map(Json::String),
+            b't' => self.eat("true").then_some(Json::Bool(true)),
+            b'f' => self.eat("false").then_some(Json::Bool(false)),
+            b'n' => self.eat("null").then_some(Json::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Option<Json>) -> Option<Json> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        if self.peek()? == b'}' {
+            self.pos += 1;
+            return Some(Json::Object(fields));
+        }
+        loop {
+            if self.peek()? != b'"' {
+                return None;
+            }
+            let key = self.string()?;
+            if self.peek()? != b':' {
+                return None;
+            }
+            self.pos += 1;
+            fields.push((key, self.value()?));
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b'}' => {
+                    self.pos += 1;
+                    return Some(Json::Object(fields));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        if self.peek()? == b']' {
+            self.pos += 1;
+            return Some(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b']' => {
+                    self.pos += 1;
+                    return Some(Json::Array(items));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while !matches!(self.bytes.get(self.pos)?, b'"' | b'\\') {
+                self.pos += 1;
+            }
+            out.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).ok()?);
+            let end = self.bytes[self.pos];
+            self.pos += 1;
+            if end == b'"' {
+                return Some(out);
+            }
+            let escape = *self.bytes.get(self.pos)?;
+            self.pos += 1;
+            out.push(match escape {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    let high = self.hex4()?;
+                    if (0xd800..0xdc00).contains(&high) && self.eat("\\u") {
+                        let low = self.hex4()?;
+                        char::from_u32(
+                            0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)?),
+                        )?
+                    } else {
+                        char::from_u32(high).unwrap_or('\u{fffd}')
+                    }
+                }
+                _ => return None,
+            });
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        text.parse().ok().map(Json::Number)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(value: Vec<T>) -> Self {
+        Json::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+pub fn write_escaped(f: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if !n.is_finite() => f.write_str("null"),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_escaped(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_quotes_backslashes_and_control_characters() {
+        let text = Json::from("a\"b\\c\nd\re\tf\u{0}\u{1f}\u{7f}").to_string();
+        // DEL is no control character to JSON.
+        assert_eq!(
+            text,
+            concat!(r#""a\"b\\c\nd\re\tf\u0000\u001f"#, "\u{7f}\"")
+        );
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        for s in [
+            "",
+            "plain",
+            "quote \" and backslash \\",
+            "controls \u{0}\u{8}\u{c}\n\r\t\u{1b}",
+            "Zeit 時間 😀 𝄞",
+        ] {
+            assert_eq!(
+                parse(&Json::from(s).to_string()),
+                Some(Json::from(s)),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn reads_escapes_and_surrogate_pairs() {
+        assert_eq!(
+            parse(r#""\"\\\/\b\f\n\r\té😀""#),
+            Some(Json::from("\"\\/\u{8}\u{c}\n\r\té😀"))
+        );
+        // A high surrogate on its own cannot be a char, nor can it pair
+        // with anything but a low one.
+        assert_eq!(parse(r#""\ud83dA""#), Some(Json::from("\u{fffd}A")));
+        assert_eq!(parse(r#""\ud83d\u0041""#), None);
+        assert_eq!(parse(r#""\x""#), None);
+        assert_eq!(parse(r#""\u12""#), None);
+    }
+
+    #[test]
+    fn formats_numbers() {
+        let text = |n: f64| Json::from(n).to_string();
+        assert_eq!(text(0.0), "0");
+        assert_eq!(text(42.0), "42");
+        assert_eq!(text(-3.0), "-3");
+        assert_eq!(text(0.5), "0.5");
+        assert_eq!(text(-12.25), "-12.25");
+        assert_eq!(text(999_999_999_999_999.0), "999999999999999");
+        assert_eq!(text(1e15), "1000000000000000");
+        assert_eq!(text(f64::NAN), "null");
+        assert_eq!(text(f64::INFINITY), "null");
+        assert_eq!(Json::from(u64::MAX).to_string(), "18446744073709552000");
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(parse("12"), Some(Json::Number(12.0)));
+        assert_eq!(parse("-0.25"), Some(Json::Number(-0.25)));
+        assert_eq!(parse("1e3"), Some(Json::Number(1000.0)));
+        assert_eq!(parse("2.5E-1"), Some(Json::Number(0.25)));
+        assert_eq!(parse("1.2.3"), None);
+        assert_eq!(parse("-"), None);
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let value = Json::object([
+            ("name", Json::from("Google DNS")),
+            ("rtt_ms", Json::from(12.5)),
+            ("lost", Json::from(false)),
+            ("error", Json::Null),
+            (
+                "history",
+                Json::Array(vec![
+                    Json::from(1.0),
+                    Json::Null,
+                    Json::object([("a", Json::from("b"))]),
+                ]),
+            ),
+        ]);
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            r#"{"name":"Google DNS","rtt_ms":12.5,"lost":false,"error":null,"history":[1,null,{"a":"b"}]}"#
+        );
+        assert_eq!(parse(&text), Some(value));
+        assert_eq!(
+            parse(" { \"a\" : [ 1 , 2 ] } \n"),
+            Some(Json::object([(
+                "a",
+                Json::Array(vec![Json::from(1.0), Json::from(2.0)])
+            )]))
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_and_deep_documents() {
+        for text in [
+            "",
+            "[1,]",
+            "[1 2]",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "{a:1}",
+            "\"open",
+            "1 2",
+            "tru",
+            "nul",
+        ] {
+            assert_eq!(parse(text), None, "{:?}", text);
+        }
+        let deep = |n: usize| format!("{}{}", "[".repeat(n), "]".repeat(n));
+        assert!(parse(&deep(MAX_DEPTH)).is_some());
+        assert_eq!(parse(&deep(MAX_DEPTH + 1)), None);
+    }
+}
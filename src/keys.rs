@@ -0,0 +1,453 @@
+//! The keys of the dashboard and the actions they are bound to, which the
+//! `[keys]` section of the configuration may rebind:
+//!
+//! ```toml
+//! [keys]
+//! select_down = ["ctrl+n", "down"]
+//! select_first = "g g"
+//! move_down = "ctrl+j"
+//! ```
+//!
+//! A key is a character, case-sensitive, or one of the names `up`, `down`,
+//! `left`, `right`, `enter`, `esc`, `tab`, `backtab`, `space`, `home`,
+//! `end`, `pageup`, `pagedown`, `insert`, `delete`, `backspace` and `f1` to
+//! `f12`, optionally prefixed with `ctrl+`, `alt+` and `shift+`. Keys
+//! separated by spaces are pressed one after the other, as `g g`. Binding
+//! an action replaces its default keys; no key may be bound to two
+//! actions, nor begin the keys of another.
+
+use std::{fmt, str::FromStr};
+
+/// Something a key does on the dashboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Add,
+    Remove,
+    Archive,
+    Rename,
+    Clear,
+    MoveDown,
+    MoveUp,
+    Pause,
+    ProbeNow,
+    Copy,
+    Faster,
+    Slower,
+    TargetFaster,
+    TargetSlower,
+    SelectUp,
+    SelectDown,
+    SelectFirst,
+    SelectLast,
+    Visual,
+    Mark,
+    Group,
+    Silence,
+    Unsilence,
+    Mute,
+    SpeedTest,
+    Snapshot,
+    View,
+    Palette,
+    Alerts,
+    FilterAlerts,
+    Back,
+}
+
+/// Every action, with its name in `[keys]` and its default keys.
+pub const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::Quit, "quit", &["q"]),
+    (Action::Add, "add", &["a"]),
+    (Action::Remove, "remove", &["d"]),
+    (Action::Archive, "archive", &["D"]),
+    (Action::Rename, "rename", &["e"]),
+    (Action::Clear, "clear", &["c"]),
+    (Action::MoveDown, "move_down", &["J", "shift+down"]),
+    (Action::MoveUp, "move_up", &["K", "shift+up"]),
+    (Action::Pause, "pause", &["p"]),
+    (Action::ProbeNow, "probe_now", &["r"]),
+    (Action::Copy, "copy", &["y"]),
+    (Action::Faster, "faster", &["+", "="]),
+    (Action::Slower, "slower", &["-"]),
+    (Action::TargetFaster, "target_faster", &[">"]),
+    (Action::TargetSlower, "target_slower", &["<"]),
+    (Action::SelectUp, "select_up", &["up", "k"]),
+    (Action::SelectDown, "select_down", &["down", "j"]),
+    (Action::SelectFirst, "select_first", &["g g", "home"]),
+    (Action::SelectLast, "select_last", &["G", "end"]),
+    (Action::Visual, "visual", &["V"]),
+    (Action::Mark, "mark", &["space"]),
+    (Action::Group, "group", &["ctrl+g"]),
+    (Action::Silence, "silence", &["z"]),
+    (Action::Unsilence, "unsilence", &["Z"]),
+    (Action::Mute, "mute", &["m"]),
+    (Action::SpeedTest, "speed_test", &["b"]),
+    (Action::Snapshot, "snapshot", &["s"]),
+    (Action::View, "view", &["tab", "v"]),
+    (Action::Palette, "palette", &[":"]),
+    (Action::Alerts, "alerts", &["A"]),
+    (Action::FilterAlerts, "filter_alerts", &["t"]),
+    (Action::Back, "back", &["esc"]),
+];
+
+impl Action {
+    /// The action called `name` in `[keys]`.
+    pub fn named(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, n, _)| *n == name)
+            .map(|(action, _, _)| *action)
+    }
+
+    /// The name of the action in `[keys]`.
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, name, _)| name)
+    }
+
+    /// What the action does, as the command palette describes it. Actions
+    /// on "the selected targets" apply to the marked ones when there are.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Add => "add a target",
+            Action::Remove => "stop probing the selected targets and remove them",
+            Action::Archive => "stop probing the selected targets and keep their rows",
+            Action::Rename => "show the selected target under another name",
+            Action::Clear => "clear the history of the selected targets",
+            Action::MoveDown => "move the selected target down",
+            Action::MoveUp => "move the selected target up",
+            Action::Pause => "pause or resume probing the selected targets",
+            Action::ProbeNow => "probe the selected targets at once",
+            Action::Copy => "copy the figures of the selected targets",
+            Action::Faster => "probe all targets more often",
+            Action::Slower => "probe all targets less often",
+            Action::TargetFaster => "probe the selected targets more often",
+            Action::TargetSlower => "probe the selected targets less often",
+            Action::SelectUp => "select the target above",
+            Action::SelectDown => "select the target below",
+            Action::SelectFirst => "select the first target",
+            Action::SelectLast => "select the last target",
+            Action::Visual => "mark a range of targets",
+            Action::Mark => "mark or unmark the selected target",
+            Action::Group => "move the marked targets next to each other",
+            Action::Silence => "silence the alerts of the selected targets",
+            Action::Unsilence => "lift the silence of the selected targets",
+            Action::Mute => "mute or unmute sound",
+            Action::SpeedTest => "run a speed test",
+            Action::Snapshot => "save an incident snapshot",
+            Action::View => "switch between graph, table and summary",
+            Action::Palette => "open the command palette",
+            Action::Alerts => "show or hide the alert history",
+            Action::FilterAlerts => "filter the alert history by target",
+            Action::Back => "go back, or clear the marks",
+        }
+    }
+}
+
+/// A key other than a character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Named {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Esc,
+    Tab,
+    BackTab,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Backspace,
+    F(u8),
+}
+
+/// Named keys, with their names in `[keys]` and their labels in hints.
+const NAMES: &[(Named, &str, &str)] = &[
+    (Named::Up, "up", "Up"),
+    (Named::Down, "down", "Down"),
+    (Named::Left, "left", "Left"),
+    (Named::Right, "right", "Right"),
+    (Named::Enter, "enter", "Enter"),
+    (Named::Esc, "esc", "Esc"),
+    (Named::Tab, "tab", "Tab"),
+    (Named::BackTab, "backtab", "Shift+Tab"),
+    (Named::Home, "home", "Home"),
+    (Named::End, "end", "End"),
+    (Named::PageUp, "pageup", "PgUp"),
+    (Named::PageDown, "pagedown", "PgDn"),
+    (Named::Insert, "insert", "Ins"),
+    (Named::Delete, "delete", "Del"),
+    (Named::Backspace, "backspace", "Backspace"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCode {
+    /// A character as typed, so `J` rather than `shift+j`.
+    Char(char),
+    Named(Named),
+}
+
+/// A key and the modifiers held with it. Shift is only told for named
+/// keys, as it is part of the character otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl Key {
+    pub fn new(code: KeyCode) -> Self {
+        Key {
+            code,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    /// The key as hints show it: characters in quotes, such as `'q'`,
+    /// named keys as they are labelled on keyboards, such as `Esc`.
+    pub fn hint(&self) -> String {
+        if let KeyCode::Char(c) = self.code
+            && !self.ctrl
+            && !self.alt
+        {
+            return format!("'{}'", c);
+        }
+        let mut hint = String::new();
+        for (held, label) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.shift, "Shift+"),
+        ] {
+            if held {
+                hint.push_str(label);
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => hint.push_str("Space"),
+            KeyCode::Char(c) => hint.push(c),
+            KeyCode::Named(Named::F(n)) => hint.push_str(&format!("F{}", n)),
+            KeyCode::Named(named) => hint.push_str(
+                NAMES
+                    .iter()
+                    .find(|(n, _, _)| *n == named)
+                    .map_or("", |(_, _, label)| label),
+            ),
+        }
+        hint
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut key = Key::new(KeyCode::Char(' '));
+        let mut rest = text;
+        // A lone "+" is the key itself, as is the last one of "ctrl++".
+        while rest.len() > 1
+            && let Some((modifier, tail)) = rest.split_once('+')
+        {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => key.ctrl = true,
+                "alt" | "meta" => key.alt = true,
+                "shift" => key.shift = true,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+            }
+            rest = tail;
+        }
+        let lower = rest.to_ascii_lowercase();
+        let mut chars = rest.chars();
+        key.code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ if lower == "space" => KeyCode::Char(' '),
+            _ => match NAMES.iter().find(|(_, name, _)| *name == lower) {
+                Some((named, _, _)) => KeyCode::Named(*named),
+                None => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::Named(Named::F(n)),
+                    _ => return Err(format!("unknown key '{}'", text)),
+                },
+            },
+        };
+        if let KeyCode::Char(c) = key.code
+            && key.shift
+        {
+            // Typed with shift, the character is its uppercase one.
+            key.shift = false;
+            key.code = KeyCode::Char(c.to_uppercase().next().unwrap_or(c));
+        }
+        if key.ctrl
+            && let KeyCode::Char(c) = key.code
+        {
+            // Terminals cannot tell ctrl+J from ctrl+j.
+            key.code = KeyCode::Char(c.to_ascii_lowercase());
+        }
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "alt+")?;
+        }
+        if self.shift {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Named(Named::F(n)) => write!(f, "f{}", n),
+            KeyCode::Named(named) => {
+                let name = NAMES
+                    .iter()
+                    .find(|(n, _, _)| *n == named)
+                    .map_or("", |(_, name, _)| name);
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+/// Keys pressed one after the other, parsed from keys separated by
+/// spaces, such as `g g`.
+pub fn parse_sequence(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return Err("no key given".to_string());
+    }
+    Ok(keys)
+}
+
+/// Keys as hints show them: `'gg'` for characters, `Ctrl+W 'q'` otherwise.
+fn sequence_hint(keys: &[Key]) -> String {
+    let chars: Option<String> = keys
+        .iter()
+        .map(|key| match key.code {
+            KeyCode::Char(c) if c != ' ' && !key.ctrl && !key.alt => Some(c),
+            _ => None,
+        })
+        .collect();
+    match chars {
+        Some(chars) => format!("'{}'", chars),
+        None => keys.iter().map(Key::hint).collect::<Vec<_>>().join(" "),
+    }
+}
+
+fn sequence_text(keys: &[Key]) -> String {
+    keys.iter()
+        .map(Key::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What the keys pressed so far do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// They begin the keys of an action; wait for the next one.
+    Pending,
+    Unbound,
+}
+
+/// Which action each key, or keys pressed one after the other, is bound
+/// to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            bindings: ACTIONS
+                .iter()
+                .flat_map(|(action, _, keys)| {
+                    keys.iter()
+                        .map(|keys| (parse_sequence(keys).expect("default keys parse"), *action))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default keys, with the actions in `bindings` bound to the keys
+    /// given instead; an error if keys end up bound to two actions, or
+    /// begin those of another so that it cannot be reached.
+    pub fn with_bindings(bindings: &[(Action, Vec<Vec<Key>>)]) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        for (action, keys) in bindings {
+            keymap.bindings.retain(|(_, a)| a != action);
+            keymap
+                .bindings
+                .extend(keys.iter().map(|keys| (keys.clone(), *action)));
+        }
+        for (i, (keys, action)) in keymap.bindings.iter().enumerate() {
+            for (other_keys, other) in &keymap.bindings[..i] {
+                if keys == other_keys {
+                    return Err(format!(
+                        "'{}' is bound to both {} and {}",
+                        sequence_text(keys),
+                        other.name(),
+                        action.name()
+                    ));
+                }
+                let (short, long, long_action) = if keys.len() < other_keys.len() {
+                    (keys, other_keys, other)
+                } else {
+                    (other_keys, keys, action)
+                };
+                if long.starts_with(short) {
+                    return Err(format!(
+                        "'{}' begins '{}' of {}",
+                        sequence_text(short),
+                        sequence_text(long),
+                        long_action.name()
+                    ));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// What `keys`, pressed one after the other, are bound to.
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut lookup = Lookup::Unbound;
+        for (bound, action) in &self.bindings {
+            if bound == keys {
+                return Lookup::Action(*action);
+            }
+            if bound.starts_with(keys) {
+                lookup = Lookup::Pending;
+            }
+        }
+        lookup
+    }
+
+    /// The first keys bound to `action`, as hints show them; `None` if it
+    /// is not bound.
+    pub fn hint(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|(_, a)| *a == action)
+            .map(|(keys, _)| sequence_hint(keys))
+    }
+}
@@ -0,0 +1,80 @@
+//! Network latency monitoring: probing, classification, history and
+//! statistics, plus the storage, reporting and alerting built on them.
+//!
+//! The `latencee` binary is a terminal UI on top of this crate. To embed the
+//! probing engine, run a [`Prober`] per [`Target`] and consume the
+//! [`ServerStatus`] it produces:
+//!
+//! ```no_run
+//! use latencee::{Prober, Target, stats};
+//! use std::time::Duration;
+//!
+//! let prober = Prober::new(
+//!     Target::new("Cloudflare DNS", "1.1.1.1"),
+//!     Duration::from_secs(2),
+//!     Duration::from_secs(600),
+//! );
+//! // Each result carries only its own sample; keep the history here.
+//! let mut status = prober.probe();
+//! for _ in 0..10 {
+//!     status.absorb(&prober.probe());
+//! }
+//! let summary = stats::summarize(status.history.iter().map(|(_, _, latency)| *latency));
+//! println!("{}: avg {}", status.name, stats::format_ms(summary.avg));
+//! ```
+
+pub mod agent;
+pub mod alert;
+#[cfg(unix)]
+pub mod attach;
+pub mod central;
+pub mod cluster;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
+pub mod discovery;
+pub mod dns;
+pub mod events;
+pub mod export;
+pub mod game;
+pub mod grafana;
+pub mod grpc;
+pub mod history;
+pub mod host;
+pub mod hpack;
+pub mod http;
+pub mod https;
+pub mod icmp;
+pub mod json;
+pub mod keys;
+pub mod mail;
+pub mod monitor;
+pub mod mqtt;
+pub mod network;
+pub mod parquet;
+pub mod ping;
+pub mod pipeline;
+pub mod presets;
+pub mod probe;
+pub mod proxy;
+pub mod report;
+pub mod resolve;
+pub mod router;
+pub mod rrd;
+pub mod rt;
+pub mod schedule;
+pub mod script;
+pub mod simulate;
+pub mod sinks;
+pub mod snapshot;
+pub mod speedtest;
+pub mod stats;
+pub mod timefmt;
+pub mod wasm;
+pub mod wire;
+
+pub use history::History;
+pub use probe::{
+    ConnectionStatus, Probe, ProbeError, Prober, ServerStatus, Target, classify_latency,
+    default_targets, echo, ping_host,
+};
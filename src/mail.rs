@@ -0,0 +1,192 @@
+//! Probes timing the greeting of mail servers: the `smtp` probe waits for
+//! the 220 greeting and completes an EHLO exchange, the `imap` probe waits
+//! for the `* OK` greeting and logs out. The round-trip time is the time to
+//! greeting, from the start of the connect, so it includes the time the
+//! server takes to accept the session, such as a busy MTA's queue or a slow
+//! DNS lookup of the client.
+//!
+//! Only plain-text ports are spoken; the greeting comes before STARTTLS.
+
+use crate::{
+    Target,
+    game::{io_error, socket_address},
+    host, network,
+    probe::{Probe, ProbeError, ProbeFuture},
+    rt,
+};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// The default SMTP port, that of MTAs receiving mail.
+pub const SMTP_PORT: u16 = 25;
+
+/// The default IMAP port.
+pub const IMAP_PORT: u16 = 143;
+
+/// How long servers may take to greet. Longer than for other probes, as
+/// MTAs often delay the greeting on purpose to catch clients that talk
+/// first.
+const GREETING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest reply line read.
+const MAX_LINE: u64 = 4096;
+
+/// An SMTP greeting and EHLO.
+pub struct Smtp {
+    port: u16,
+}
+
+impl Smtp {
+    /// Connects to `port` of targets without a port of their own.
+    pub fn new(port: u16) -> Self {
+        Smtp { port }
+    }
+}
+
+impl Probe for Smtp {
+    fn kind(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn description(&self) -> &'static str {
+        "time to an SMTP server's greeting, then EHLO"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        greet(target, self.port, smtp)
+    }
+}
+
+/// An IMAP greeting.
+pub struct Imap {
+    port: u16,
+}
+
+impl Imap {
+    /// Connects to `port` of targets without a port of their own.
+    pub fn new(port: u16) -> Self {
+        Imap { port }
+    }
+}
+
+impl Probe for Imap {
+    fn kind(&self) -> &'static str {
+        "imap"
+    }
+
+    fn description(&self) -> &'static str {
+        "time to an IMAP server's greeting"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        greet(target, self.port, imap)
+    }
+}
+
+/// Connects to the target and runs `protocol` on the connection, which
+/// tells the time to greeting.
+fn greet<'a>(
+    target: &'a Target,
+    port: u16,
+    protocol: fn(&mut Session, Instant) -> Result<Duration, ProbeError>,
+) -> ProbeFuture<'a> {
+    let host = target.host.clone();
+    let port = target.port.unwrap_or(port);
+    let network = target.network.clone();
+    Box::pin(rt::unblock(move || {
+        network::within(network.as_ref(), || {
+            let address = socket_address(&host, port)?;
+            let start = Instant::now();
+            let stream = network::connect(&address, GREETING_TIMEOUT).map_err(io_error)?;
+            stream
+                .set_read_timeout(Some(GREETING_TIMEOUT))
+                .map_err(io_error)?;
+            stream
+                .set_write_timeout(Some(GREETING_TIMEOUT))
+                .map_err(io_error)?;
+            let mut session = Session {
+                reader: BufReader::new(stream.try_clone().map_err(io_error)?),
+                stream,
+            };
+            protocol(&mut session, start)
+        })
+    }))
+}
+
+struct Session {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+impl Session {
+    fn line(&mut self) -> Result<String, ProbeError> {
+        let mut line = String::new();
+        let read = (&mut self.reader)
+            .take(MAX_LINE)
+            .read_line(&mut line)
+            .map_err(io_error)?;
+        if read == 0 {
+            // Closed without a word: the server turned the session down.
+            return Err(ProbeError::Unreachable);
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    /// The code of an SMTP reply, reading all of its lines.
+    fn reply(&mut self) -> Result<u16, ProbeError> {
+        loop {
+            let line = self.line()?;
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse().ok())
+                .ok_or(ProbeError::Timeout)?;
+            // "220-" continues, "220 " ends.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(code);
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), ProbeError> {
+        self.stream
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .map_err(io_error)
+    }
+}
+
+/// Waits for the 220 greeting, which is timed, says EHLO and expects 250,
+/// and quits.
+fn smtp(session: &mut Session, start: Instant) -> Result<Duration, ProbeError> {
+    let greeting = session.reply()?;
+    let rtt = start.elapsed();
+    if greeting != 220 {
+        // 554: the server does not take mail from here.
+        return Err(ProbeError::Unreachable);
+    }
+    let mut hostname = host::hostname();
+    if hostname.is_empty() {
+        hostname = "localhost".to_string();
+    }
+    session.send(&format!("EHLO {}", hostname))?;
+    if session.reply()? != 250 {
+        return Err(ProbeError::Unreachable);
+    }
+    let _ = session.send("QUIT");
+    Ok(rtt)
+}
+
+/// Waits for the `* OK` or `* PREAUTH` greeting, which is timed, and logs
+/// out.
+fn imap(session: &mut Session, start: Instant) -> Result<Duration, ProbeError> {
+    let greeting = session.line()?;
+    let rtt = start.elapsed();
+    if !(greeting.starts_with("* OK") || greeting.starts_with("* PREAUTH")) {
+        // "* BYE": the server does not take connections now.
+        return Err(ProbeError::Unreachable);
+    }
+    let _ = session.send("a1 LOGOUT");
+    Ok(rtt)
+}
@@ -1,46 +1,111 @@
+mod check;
+mod cli;
+#[cfg(unix)]
+mod daemon;
+mod palette;
+mod prompt;
+mod replay;
+mod screen;
+#[cfg(windows)]
+mod service;
+mod session;
+mod setup;
+mod state;
+#[cfg(unix)]
+mod systemd;
+mod term;
+mod theme;
+mod views;
+
 use crossterm::{
-    cursor,
-    event::{self, Event, KeyCode},
-    execute,
-    style::{Color, ResetColor, SetForegroundColor},
-    terminal::{self, ClearType},
+    event::{Event, KeyCode, KeyModifiers},
+    queue,
+    style::{Attribute, Color, ResetColor, SetAttribute, SetForegroundColor},
+};
+use latencee::{
+    ConnectionStatus, History, Probe, ProbeError, Prober, ServerStatus, Target,
+    agent::Uplink,
+    alert,
+    central::Central,
+    cluster::Cluster,
+    config, default_targets,
+    discovery::Discoverer,
+    events::Outage,
+    export,
+    game::{self, A2s, Minecraft},
+    history::{self, HistoryStore},
+    https::{self, Https},
+    keys::{Action, Keymap, Lookup},
+    mail::{self, Imap, Smtp},
+    monitor::Monitor,
+    network::{self, NetworkRoute},
+    ping::Ping,
+    presets,
+    probe::{self, IcmpBackend, Note, Registry, Tcp},
+    resolve,
+    router::RouterPing,
+    rt,
+    simulate::Simulate,
+    sinks::netdata::NetdataSink,
+    snapshot, speedtest, stats, timefmt,
+    wasm::WasmProbe,
 };
-use smol::{channel, Timer};
+#[cfg(unix)]
+use latencee::{attach, control, json::Json};
+use palette::{Command, Palette};
+use prompt::{Outcome, Prompt};
+use screen::Screen;
+use session::Session;
+use smol::{Timer, channel, future};
+use state::DashboardState;
 use std::{
-    collections::VecDeque,
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeMap, HashMap, HashSet},
     io::{self, Write},
-    process::Command,
-    time::{Duration, Instant},
+    path::PathBuf,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
 };
+use theme::Theme;
+use views::View;
 
+/// Width of the graphs on terminals wide enough for them; narrower ones,
+/// such as a phone's, get graphs down to [`MIN_GRAPH_WIDTH`].
 const GRAPH_WIDTH: usize = 60;
+const MIN_GRAPH_WIDTH: usize = 10;
+/// Below this many columns the dashboard uses narrower columns and a
+/// shorter legend.
+const COMPACT_COLUMNS: usize = 80;
 const GRAPH_HISTORY_MINUTES: usize = 10;
+const GRAPH_WINDOW: Duration = Duration::from_secs(GRAPH_HISTORY_MINUTES as u64 * 60);
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+/// Results waiting for the dashboard or the headless loop; probers wait
+/// when it is full instead of piling up results.
+const RESULT_QUEUE: usize = 1024;
+/// Results older than this show their age, which the dashboard updates
+/// once per [`TICK`].
+const STALE: Duration = Duration::from_secs(5);
+/// How often the dashboard wakes up without input or results.
+const TICK: Duration = Duration::from_secs(1);
+/// Without a key pressed for this long, or while the terminal is not
+/// focused, the dashboard is idle and redraws only every [`IDLE_REDRAW`].
+const IDLE_AFTER: Duration = Duration::from_secs(60);
+const IDLE_REDRAW: Duration = Duration::from_secs(10);
 
-#[derive(Clone)]
-pub struct ServerStatus {
-    pub name: String,
-    pub latency: Option<Duration>,
-    pub last_update: Instant,
-    pub status: ConnectionStatus,
-    pub history: VecDeque<(Instant, ConnectionStatus)>,
-}
-
-#[derive(Clone, PartialEq)]
-pub enum ConnectionStatus {
-    Good,    // < 50ms
-    Fair,    // 50-150ms
-    Poor,    // 150-500ms
-    Timeout, // > 500ms or failed
+/// How the dashboard shows each status.
+trait Appearance {
+    fn color(&self) -> Color;
+    fn symbol(&self) -> &str;
 }
 
-impl ConnectionStatus {
+impl Appearance for ConnectionStatus {
     fn color(&self) -> Color {
-        match self {
-            ConnectionStatus::Good => Color::Green,
-            ConnectionStatus::Fair => Color::Yellow,
-            ConnectionStatus::Poor => Color::Red,
-            ConnectionStatus::Timeout => Color::DarkRed,
-        }
+        Theme::current().color(*self)
     }
 
     fn symbol(&self) -> &str {
@@ -53,256 +118,2466 @@ impl ConnectionStatus {
     }
 }
 
-pub fn ping_host(host: &str) -> Option<Duration> {
-    let start = Instant::now();
+/// The class of the latest sample in each of the first `width` columns of
+/// the graph, `None` where there is none.
+fn draw_graph(history: &History, width: usize) -> [Option<ConnectionStatus>; GRAPH_WIDTH] {
+    let mut graph = [None; GRAPH_WIDTH];
+    let width = width.min(GRAPH_WIDTH);
+    history.columns(Instant::now(), GRAPH_WINDOW, &mut graph[..width]);
+    graph
+}
 
-    // Simple ping using system ping command
-    let output = Command::new("ping")
-        .arg("-c")
-        .arg("1")
-        .arg("-W")
-        .arg("1000") // 1 second timeout
-        .arg(host)
-        .output()
-        .ok()?;
+/// `text` cut to `width` characters, ending in `…` when cut.
+fn fit(text: &str, width: usize) -> Cow<'_, str> {
+    if text.chars().count() <= width {
+        return Cow::Borrowed(text);
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    Cow::Owned(cut)
+}
 
-    if output.status.success() {
-        Some(start.elapsed())
+/// A one-line message shown below the legend.
+struct Notice {
+    text: String,
+    color: Color,
+}
+
+impl Notice {
+    fn info(text: impl Into<String>) -> Self {
+        Notice {
+            text: text.into(),
+            color: Color::DarkGrey,
+        }
+    }
+
+    fn error(text: impl Into<String>) -> Self {
+        Notice {
+            text: text.into(),
+            color: Color::DarkRed,
+        }
+    }
+}
+
+/// Lists past alerts, newest first, instead of the graphs.
+fn draw_alert_log(
+    screen: &mut Screen,
+    log: &alert::AlertLog,
+    target: Option<&str>,
+    keys: &str,
+) -> io::Result<()> {
+    let (_, rows) = screen.begin()?;
+
+    write!(
+        screen.row(0),
+        "🔔 Alert history ({})",
+        target.unwrap_or("all targets")
+    )?;
+    write!(screen.row(1), "{}", keys)?;
+
+    let mut alerts = log.newest(target).peekable();
+    if alerts.peek().is_none() {
+        let line = screen.row(3);
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        write!(line, "No alerts yet")?;
+        queue!(line, ResetColor)?;
+    }
+    for (row, alert) in (3..rows).zip(alerts) {
+        let line = screen.row(row);
+        write!(line, "{}  ", timefmt::long(alert.at))?;
+        let color = match alert.state {
+            alert::AlertState::Firing => ConnectionStatus::Poor.color(),
+            alert::AlertState::Resolved => ConnectionStatus::Good.color(),
+        };
+        queue!(line, SetForegroundColor(color))?;
+        write!(line, "{:<9}", alert.state.label())?;
+        queue!(line, ResetColor)?;
+        write!(
+            line,
+            "{:<20}{:<16}{} ({})",
+            alert.target, alert.rule, alert.condition, alert.value
+        )?;
+        if let Some(duration) = alert.duration {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "  after {}", timefmt::duration(duration))?;
+            queue!(line, ResetColor)?;
+        }
+    }
+
+    screen.present()
+}
+
+/// The probe intervals `+`/`-` and `>`/`<` step through.
+const INTERVALS: [Duration; 6] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// The interval after `interval` in [`INTERVALS`], the shorter one if
+/// `faster`; `None` at either end.
+fn step_interval(interval: Duration, faster: bool) -> Option<Duration> {
+    if faster {
+        INTERVALS.iter().rev().find(|&&i| i < interval).copied()
     } else {
-        None
+        INTERVALS.iter().find(|&&i| i > interval).copied()
     }
 }
 
-pub fn classify_latency(latency: Option<Duration>) -> ConnectionStatus {
-    match latency {
-        Some(lat) if lat < Duration::from_millis(50) => ConnectionStatus::Good,
-        Some(lat) if lat < Duration::from_millis(150) => ConnectionStatus::Fair,
-        Some(lat) if lat < Duration::from_millis(500) => ConnectionStatus::Poor,
-        _ => ConnectionStatus::Timeout,
+/// An interval as it is shown, such as `250ms` or `2s`.
+fn interval_text(interval: Duration) -> String {
+    if interval.subsec_millis() == 0 {
+        format!("{}s", interval.as_secs())
+    } else if interval < Duration::from_secs(1) {
+        format!("{}ms", interval.as_millis())
+    } else {
+        format!("{:.1}s", interval.as_secs_f64())
     }
 }
 
-async fn monitor_server(name: String, host: String, sender: channel::Sender<ServerStatus>) {
-    let mut history = VecDeque::new();
+/// Key hints of the live dashboard: the actions, and what their keys do.
+const DASHBOARD_HINTS: &[(&[Action], &str)] = &[
+    (&[Action::Quit], "to quit"),
+    (&[Action::Add], "to add a target"),
+    (&[Action::Remove, Action::Archive], "to remove/archive"),
+    (&[Action::Rename], "to rename"),
+    (&[Action::Clear], "to clear history"),
+    (&[Action::MoveDown, Action::MoveUp], "to move"),
+    (&[Action::Pause], "to pause"),
+    (&[Action::ProbeNow], "to probe now"),
+    (&[Action::Copy], "to copy stats"),
+    (&[Action::Mark, Action::Visual], "to mark"),
+    (&[Action::Group], "to group marked"),
+    (&[Action::Faster, Action::Slower], "to change the interval"),
+    (
+        &[Action::TargetFaster, Action::TargetSlower],
+        "to change it for one target",
+    ),
+    (&[Action::Snapshot], "to save a snapshot"),
+    (&[Action::Mute], "to mute"),
+    (&[Action::View], "to switch view"),
+    (&[Action::Palette], "for commands"),
+    (&[Action::Alerts], "for alerts"),
+    (&[Action::Silence], "to silence"),
+];
 
-    loop {
-        let latency = ping_host(&host);
-        let status = classify_latency(latency);
-        let now = Instant::now();
+/// Key hints of the alert history.
+const ALERT_LOG_HINTS: &[(&[Action], &str)] = &[
+    (&[Action::Alerts, Action::Back], "to go back"),
+    (&[Action::FilterAlerts], "to filter by target"),
+];
 
-        // Add to history
-        history.push_back((now, status.clone()));
+/// `hints` with the keys `keymap` binds to their actions, such as
+/// "Press 'q' to quit"; actions without keys are left out.
+fn key_hints(keymap: &Keymap, hints: &[(&[Action], &str)]) -> String {
+    let hints: Vec<String> = hints
+        .iter()
+        .filter_map(|(actions, text)| {
+            let keys: Vec<String> = actions.iter().filter_map(|a| keymap.hint(*a)).collect();
+            (!keys.is_empty()).then(|| format!("{} {}", keys.join("/"), text))
+        })
+        .collect();
+    format!("Press {}", hints.join(", "))
+}
 
-        // Keep only last N minutes of history
-        let cutoff = now - Duration::from_secs(GRAPH_HISTORY_MINUTES as u64 * 60);
-        while let Some((timestamp, _)) = history.front() {
-            if *timestamp < cutoff {
-                history.pop_front();
-            } else {
-                break;
+/// What the second row of the dashboard shows.
+pub enum Header<'a> {
+    Keys(&'a str),
+    /// A prompt being typed into.
+    Prompt(&'a Prompt),
+    /// The command palette, whose matches replace the row below.
+    Palette(&'a Palette),
+}
+
+/// The target rows of the dashboard.
+pub struct Rows<'a> {
+    pub statuses: &'a [ServerStatus],
+    /// How many statuses at the end are archived, no longer probed.
+    pub archived: usize,
+    pub selected: usize,
+    /// Names of the targets marked, which actions apply to.
+    pub marked: &'a HashSet<String>,
+    /// Whether a visual range is being marked.
+    pub visual: bool,
+    /// Names shown instead of those of targets, by target name.
+    pub labels: &'a BTreeMap<String, String>,
+    /// Names of the targets whose probing is paused.
+    pub paused: &'a HashSet<String>,
+    /// How often targets are probed, `None` when they are not probed here.
+    pub interval: Option<Duration>,
+    /// The intervals of targets probed at another one, by name.
+    pub intervals: &'a HashMap<String, Duration>,
+}
+
+/// The graph of each target, with its latest result, and a legend; the
+/// row below them.
+fn draw_graphs(
+    screen: &mut Screen,
+    rows: &Rows,
+    silencer: &alert::Silencer,
+    notes: &HashMap<String, Note>,
+    columns: usize,
+    height: u16,
+) -> io::Result<u16> {
+    let &Rows {
+        statuses: servers,
+        archived,
+        selected,
+        marked,
+        labels,
+        paused,
+        interval,
+        intervals,
+        ..
+    } = rows;
+    let compact = columns < COMPACT_COLUMNS;
+    let (source_width, name_width) = if compact { (8, 12) } else { (12, 20) };
+    let graph_width = columns
+        .saturating_sub(2 + " [10 min]".len())
+        .clamp(MIN_GRAPH_WIDTH, GRAPH_WIDTH);
+
+    // Only the targets that fit are drawn, scrolled to keep the selected
+    // one in view; the header, legend and notice take eight rows.
+    let fitting = (usize::from(height).saturating_sub(8) / 3).max(1);
+    let first = (selected + 1).saturating_sub(fitting);
+    let shown = servers.len().saturating_sub(first).min(fitting);
+    if shown < servers.len() {
+        let line = screen.row(2);
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        write!(
+            line,
+            "Targets {}-{} of {}",
+            first + 1,
+            first + shown,
+            servers.len()
+        )?;
+        queue!(line, ResetColor)?;
+    }
+
+    let with_sources = servers.iter().any(|s| s.source.is_some());
+    for (slot, server) in servers.iter().skip(first).take(shown).enumerate() {
+        let i = first + slot;
+        let row = (slot * 3 + 3) as u16;
+        let line = screen.row(row);
+        let is_archived = i >= servers.len() - archived;
+
+        // Server name and current status
+        let color = if is_archived {
+            Color::DarkGrey
+        } else {
+            server.status.color()
+        };
+        queue!(line, SetForegroundColor(color))?;
+        write!(line, "{} ", server.status.symbol())?;
+        queue!(line, ResetColor)?;
+
+        // Targets of agents are named `<agent>/<target>`; show the agent in
+        // its own column instead.
+        let mut name = &*server.name;
+        if with_sources {
+            let source = server.source.as_deref().unwrap_or("local");
+            name = name
+                .strip_prefix(source)
+                .and_then(|n| n.strip_prefix('/'))
+                .unwrap_or(name);
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "{:<source_width$}", fit(source, source_width - 1))?;
+            queue!(line, ResetColor)?;
+        }
+        if let Some(label) = labels.get(&*server.name) {
+            name = label;
+        }
+        if i == selected {
+            queue!(line, SetAttribute(Attribute::Reverse))?;
+        }
+        if marked.contains(&*server.name) {
+            queue!(line, SetAttribute(Attribute::Underlined))?;
+        }
+        if is_archived {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        }
+        write!(line, "{:<name_width$}", fit(name, name_width - 1))?;
+        queue!(
+            line,
+            SetAttribute(Attribute::NoReverse),
+            SetAttribute(Attribute::NoUnderline),
+            ResetColor
+        )?;
+
+        match server.latency {
+            Some(lat) => {
+                queue!(line, SetForegroundColor(server.status.color()))?;
+                write!(line, "{:>8.0}ms", lat.as_millis())?;
+                queue!(line, ResetColor)?;
+            }
+            None => {
+                let label = server.error.unwrap_or(ProbeError::Timeout).label();
+                queue!(line, SetForegroundColor(Color::DarkRed))?;
+                write!(line, "{:>10}", label)?;
+                queue!(line, ResetColor)?;
             }
         }
 
-        let server_status = ServerStatus {
-            name: name.clone(),
-            latency,
-            last_update: now,
-            status,
-            history: history.clone(),
-        };
+        let age = server.last_update.elapsed();
+        if is_archived {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "  archived")?;
+            queue!(line, ResetColor)?;
+        } else if paused.contains(&*server.name) {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "  paused")?;
+            queue!(line, ResetColor)?;
+        } else if age
+            > STALE.max(
+                2 * intervals
+                    .get(&*server.name)
+                    .copied()
+                    .or(interval)
+                    .unwrap_or_default(),
+            )
+        {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, " ({}s ago)", age.as_secs())?;
+            queue!(line, ResetColor)?;
+        }
+        if let Some(own) = intervals.get(&*server.name) {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "  every {}", interval_text(*own))?;
+            queue!(line, ResetColor)?;
+        }
+        if let Some(until) = silencer.silenced_until(&server.name, SystemTime::now()) {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "  silenced until {}", timefmt::short(until))?;
+            queue!(line, ResetColor)?;
+        }
+        if let Some(note) = notes.get(&*server.name) {
+            let color = if note.warning {
+                Color::Yellow
+            } else {
+                Color::DarkGrey
+            };
+            queue!(line, SetForegroundColor(color))?;
+            write!(line, "  {}", note.text)?;
+            queue!(line, ResetColor)?;
+        }
 
-        if sender.send(server_status).await.is_err() {
-            break;
+        // Graph line
+        let line = screen.row(row + 1);
+        write!(line, "  ")?;
+        let graph = draw_graph(&server.history, graph_width);
+
+        // Draw graph with colors, changing color only between runs.
+        let mut current = None;
+        for status in &graph[..graph_width] {
+            let (ch, color) = match status {
+                Some(status) => (status.symbol(), Some(status.color())),
+                None => ("·", None),
+            };
+            if color != current {
+                match color {
+                    Some(color) => queue!(line, SetForegroundColor(color))?,
+                    None => queue!(line, ResetColor)?,
+                }
+                current = color;
+            }
+            write!(line, "{}", ch)?;
+        }
+        if current.is_some() {
+            queue!(line, ResetColor)?;
         }
 
-        Timer::after(Duration::from_secs(2)).await;
+        write!(line, " [{} min]", GRAPH_HISTORY_MINUTES)?;
+    }
+
+    let legend_row = (shown * 3 + 5) as u16;
+    write!(screen.row(legend_row), "Legend:")?;
+    let legend = if compact {
+        ["● <50ms ", "◐ <150ms ", "◑ <500ms ", "○ lost"]
+    } else {
+        [
+            "● Good (<50ms)  ",
+            "◐ Fair (50-150ms)  ",
+            "◑ Poor (150-500ms)  ",
+            "○ Timeout (>500ms)",
+        ]
+    };
+    let colors = [
+        ConnectionStatus::Good,
+        ConnectionStatus::Fair,
+        ConnectionStatus::Poor,
+        ConnectionStatus::Timeout,
+    ]
+    .map(|status| status.color());
+    let line = screen.row(legend_row + 1);
+    for (text, color) in legend.into_iter().zip(colors) {
+        queue!(line, SetForegroundColor(color))?;
+        write!(line, "{}", text)?;
     }
+    queue!(line, ResetColor)?;
+
+    Ok(legend_row + 2)
 }
 
-fn draw_graph(history: &VecDeque<(Instant, ConnectionStatus)>) -> String {
-    if history.is_empty() {
-        return " ".repeat(GRAPH_WIDTH);
+fn draw_ui(
+    screen: &mut Screen,
+    rows: Rows,
+    view: View,
+    silencer: &alert::Silencer,
+    notes: &HashMap<String, Note>,
+    notice: Option<&Notice>,
+    header: Header,
+) -> io::Result<()> {
+    let (columns, height) = screen.begin()?;
+    let columns = usize::from(columns);
+
+    let line = screen.row(0);
+    write!(line, "🌐 Latencee - Network Latency Monitor")?;
+    if let Some(interval) = rows.interval {
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        write!(line, "  probing every {}", interval_text(interval))?;
+        queue!(line, ResetColor)?;
+    }
+    if rows.visual {
+        write!(line, "  -- VISUAL --")?;
+    }
+    if !rows.marked.is_empty() {
+        write!(line, "  {} marked", rows.marked.len())?;
+    }
+    match header {
+        Header::Keys(keys) => write!(screen.row(1), "{}", fit(keys, columns))?,
+        Header::Prompt(prompt) => prompt.draw(screen.row(1), columns)?,
+        Header::Palette(palette) => palette.draw(screen.row(1), columns)?,
+    }
+
+    let notice_row = match view {
+        View::Graph => draw_graphs(screen, &rows, silencer, notes, columns, height)?,
+        View::Table => views::draw_table(screen, &rows, silencer, columns, height)?,
+        View::Summary => views::draw_summary(screen, &rows, columns)?,
+    };
+    if let Header::Palette(palette) = header {
+        let line = screen.row(2);
+        line.clear();
+        palette.draw_matches(line, columns)?;
     }
 
-    let now = Instant::now();
-    let start_time = now - Duration::from_secs(GRAPH_HISTORY_MINUTES as u64 * 60);
-    let time_per_char = Duration::from_secs(GRAPH_HISTORY_MINUTES as u64 * 60) / GRAPH_WIDTH as u32;
+    if let Some(notice) = notice {
+        let line = screen.row(notice_row);
+        queue!(line, SetForegroundColor(notice.color))?;
+        write!(line, "{}", fit(&notice.text, columns))?;
+        queue!(line, ResetColor)?;
+    }
 
-    let mut graph = vec![' '; GRAPH_WIDTH];
+    screen.present()
+}
 
-    for (timestamp, status) in history {
-        if *timestamp >= start_time {
-            let elapsed = timestamp.duration_since(start_time);
-            let pos = (elapsed.as_secs_f64() / time_per_char.as_secs_f64()) as usize;
-            if pos < GRAPH_WIDTH {
-                graph[pos] = match status {
-                    ConnectionStatus::Good => '●',
-                    ConnectionStatus::Fair => '◐',
-                    ConnectionStatus::Poor => '◑',
-                    ConnectionStatus::Timeout => '○',
-                };
+fn main() -> io::Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    // Linked into Netdata's plugins.d as `latencee.plugin`, which Netdata
+    // runs with the update frequency as the only argument.
+    if args
+        .first()
+        .is_some_and(|program| program.ends_with(".plugin"))
+    {
+        args[0] = "--netdata-plugin".to_string();
+    } else if !args.is_empty() {
+        args.remove(0);
+    }
+    let options = match cli::parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            // Nagios takes 2 for critical; plugins report bad arguments as
+            // unknown.
+            let nagios = std::env::args().collect::<Vec<_>>().windows(2).any(|pair| {
+                pair[0] == "--format" && matches!(pair[1].as_str(), "nagios" | "icinga")
+            });
+            process::exit(if nagios { 3 } else { 2 });
+        }
+    };
+    match options.command {
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        cli::Command::Version => {
+            println!("latencee {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        cli::Command::Check(check) => process::exit(check::run(&check) as i32),
+        cli::Command::Replay(replay) => return replay::run(&replay),
+        cli::Command::SetupCaps(setup) => return setup::run(&setup),
+        #[cfg(unix)]
+        cli::Command::Daemon(cli::DaemonAction::Start) => {
+            return daemon::start(options.config.as_deref());
+        }
+        #[cfg(unix)]
+        cli::Command::Daemon(cli::DaemonAction::Stop) => return daemon::stop(),
+        #[cfg(unix)]
+        cli::Command::Daemon(cli::DaemonAction::Status) => process::exit(daemon::status()),
+        #[cfg(not(unix))]
+        cli::Command::Daemon(_) | cli::Command::Attach => {
+            eprintln!("latencee: daemon mode needs a Unix system");
+            process::exit(2);
+        }
+        #[cfg(windows)]
+        cli::Command::Service(cli::ServiceAction::Install) => {
+            return service::install(options.config.as_deref());
+        }
+        #[cfg(windows)]
+        cli::Command::Service(cli::ServiceAction::Uninstall) => return service::uninstall(),
+        #[cfg(not(windows))]
+        cli::Command::Service(_) => {
+            eprintln!("latencee: service mode needs Windows; use daemon mode instead");
+            process::exit(2);
+        }
+        _ => {}
+    }
+
+    // As a Netdata plugin, the configuration may sit with Netdata's own.
+    let netdata_config = options
+        .netdata_plugin
+        .and_then(|_| std::env::var_os("NETDATA_USER_CONFIG_DIR"))
+        .map(|dir| PathBuf::from(dir).join("latencee.toml"))
+        .filter(|path| path.exists());
+    let mut config = match options.config.as_ref().or(netdata_config.as_ref()) {
+        Some(path) => match config::Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("latencee: {}: {}", path.display(), e);
+                process::exit(2);
+            }
+        },
+        None => config::Config::default(),
+    };
+
+    if let cli::Command::Export(export) = &options.command {
+        let Some(storage) = &config.storage else {
+            eprintln!("latencee: export needs a [storage] section in the configuration");
+            process::exit(2);
+        };
+        let rows = export::export_parquet(&storage.path, export.step, &export.parquet)?;
+        println!("Wrote {} rows to {}", rows, export.parquet.display());
+        return Ok(());
+    }
+
+    // Configured and preset targets, or discovered ones, take the place of
+    // the defaults.
+    let preset_probe = config.probe.as_deref().unwrap_or(presets::PROBE);
+    let mut servers = config.targets.clone();
+    for target in options
+        .presets
+        .iter()
+        .flat_map(|preset| preset.targets(preset_probe))
+    {
+        if !servers.iter().any(|t| t.name == target.name) {
+            servers.push(target);
+        }
+    }
+    for preset in &options.presets {
+        config.discovery.extend(preset.discovery.iter().cloned());
+    }
+    if servers.is_empty() && config.discovery.is_empty() {
+        servers = default_targets();
+    }
+
+    #[cfg(unix)]
+    if let cli::Command::Attach = options.command {
+        let path = daemon::socket_path();
+        let receiver = match attach::subscribe(&path, GRAPH_WINDOW) {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                eprintln!("latencee: cannot attach to {}: {}", path.display(), e);
+                process::exit(2);
             }
+        };
+        let inputs = Inputs::attached(receiver);
+        // The daemon stores and notifies; the dashboard only shows results.
+        let monitor = Monitor::new(&config::Config::default(), history_store(&config))?;
+        return run_dashboard(&options, &config, &servers, monitor, inputs);
+    }
+
+    let uplink = match (&options.command, &config.agent) {
+        (cli::Command::Agent, Some(agent)) => Some(Uplink::new(agent.clone())),
+        (cli::Command::Agent, None) => {
+            eprintln!("latencee: agent needs an [agent] section in the configuration");
+            process::exit(2);
         }
+        _ => None,
+    };
+
+    let mut monitor = Monitor::new(&config, history_store(&config))?;
+    if let Some(path) = &options.record {
+        monitor.record_to(path)?;
+    }
+    if let Some(every) = options.netdata_plugin {
+        // Updates more often than probes would only have gaps to show.
+        monitor.add_sink(Box::new(NetdataSink::new(every.max(PROBE_INTERVAL))));
+    }
+    let inputs = Inputs::start(&servers, &config)?;
+
+    #[cfg(unix)]
+    if let cli::Command::Daemon(cli::DaemonAction::Run) = options.command {
+        let sockets = daemon::Sockets::bind()?;
+        daemon::write_pidfile()?;
+        return run_headless(monitor, inputs, Some(sockets), uplink);
+    }
+
+    if options.headless || options.netdata_plugin.is_some() || uplink.is_some() {
+        return run_headless(
+            monitor,
+            inputs,
+            #[cfg(unix)]
+            None,
+            uplink,
+        );
     }
 
-    graph.into_iter().collect()
+    run_dashboard(&options, &config, &servers, monitor, inputs)
 }
 
-fn draw_ui(servers: &[ServerStatus]) -> io::Result<()> {
-    execute!(
-        io::stdout(),
-        terminal::Clear(ClearType::All),
-        cursor::MoveTo(0, 0)
-    )?;
+/// Where probe results come from: local probes, remote agents on a central
+/// instance, and cluster peers.
+struct Inputs {
+    receiver: channel::Receiver<ServerStatus>,
+    central: Option<Central>,
+    cluster: Option<Cluster>,
+    /// What new local probers send to and measure with; `None` when
+    /// attached to a daemon.
+    local: Option<(channel::Sender<ServerStatus>, Arc<dyn Probe>)>,
+    /// The probes targets may pick from.
+    registry: Registry,
+    /// Kinds that cannot probe here, measured with TCP connects instead.
+    substituted: Vec<&'static str>,
+    probing: Vec<Probing>,
+    /// Told once, as the first error: why local probing differs from what
+    /// was configured.
+    notice: Cell<Option<String>>,
+    /// How ICMP probes are sent, for the log.
+    method: Option<String>,
+    /// Network namespaces and VRFs of local targets, by name.
+    networks: Vec<NetworkRoute>,
+    /// Machines and routers local targets are also pinged from.
+    remotes: Vec<Remote>,
+    discoverer: Option<Discoverer>,
+    /// Names of the targets each discovery source added.
+    discovered: Vec<Vec<String>>,
+    /// The latest notes of discovered targets, by name.
+    notes: HashMap<String, Note>,
+    /// Warnings in notes not told yet.
+    warnings: Cell<Vec<String>>,
+    /// How often targets are probed unless set for them alone.
+    interval: Duration,
+}
 
-    println!("🌐 Latencee - Network Latency Monitor");
-    println!("Press 'q' to quit\n");
+/// A machine or router local targets are also pinged from, and its probe.
+struct Remote {
+    name: String,
+    /// Names of the targets pinged from there; all when empty.
+    targets: Vec<String>,
+    probe: Arc<dyn Probe>,
+}
 
-    for (i, server) in servers.iter().enumerate() {
-        let row = (i * 3 + 3) as u16;
-        execute!(io::stdout(), cursor::MoveTo(0, row))?;
+impl Remote {
+    fn covers(&self, target: &str) -> bool {
+        self.targets.is_empty() || self.targets.iter().any(|t| t == target)
+    }
 
-        // Server name and current status
-        execute!(io::stdout(), SetForegroundColor(server.status.color()))?;
-        print!("{} ", server.status.symbol());
-        execute!(io::stdout(), ResetColor)?;
+    /// The name of `target` pinged from there: `<remote>/<target>`.
+    fn twin_name(&self, target: &str) -> String {
+        format!("{}/{}", self.name, target)
+    }
+}
 
-        print!("{:<20}", server.name);
+/// A local target and its running prober.
+struct Probing {
+    target: Target,
+    probe: Arc<dyn Probe>,
+    paused: Arc<std::sync::atomic::AtomicBool>,
+    trigger: probe::Trigger,
+    /// Milliseconds between probes.
+    interval: Arc<AtomicU64>,
+    /// Dropping the task stops the prober.
+    _task: rt::Task<()>,
+}
 
-        match server.latency {
-            Some(lat) => {
-                execute!(io::stdout(), SetForegroundColor(server.status.color()))?;
-                print!("{:>8.0}ms", lat.as_millis());
-                execute!(io::stdout(), ResetColor)?;
+impl Inputs {
+    fn start(servers: &[Target], config: &config::Config) -> io::Result<Self> {
+        let kind = config.probe.as_deref().unwrap_or("icmp");
+        if let Some(every) = config.dns_refresh {
+            resolve::set_refresh(every);
+        }
+        // `[probe] port` is that of the probe configured for all targets;
+        // the others keep their own unless a target sets one.
+        let port = |kinds: &[&str], default: u16| match config.tcp_port {
+            Some(port) if kinds.contains(&kind) => port,
+            _ => default,
+        };
+        let mut registry = Registry::default();
+        registry.register(Arc::new(Simulate::new(config.simulations.clone())));
+        let tcp_port = port(&["icmp", "ping", "tcp"], probe::DEFAULT_TCP_PORT);
+        registry.register(Arc::new(
+            Tcp::new(tcp_port).with_proxies(config.proxies.clone()),
+        ));
+        registry.register(Arc::new(Ping::new(PROBE_INTERVAL)));
+        registry.register(Arc::new(A2s::new(port(&["a2s"], game::A2S_PORT))));
+        registry.register(Arc::new(Minecraft::new(port(
+            &["minecraft"],
+            game::MINECRAFT_PORT,
+        ))));
+        registry.register(Arc::new(Smtp::new(port(&["smtp"], mail::SMTP_PORT))));
+        registry.register(Arc::new(Imap::new(port(&["imap"], mail::IMAP_PORT))));
+        registry.register(Arc::new(Https::new(
+            port(&["https"], https::HTTPS_PORT),
+            "curl".to_string(),
+        )));
+        if let Some(module) = &config.wasm_probe {
+            registry.register(Arc::new(WasmProbe::new(module.clone())));
+        }
+        // Without a working ping every target would only show NO TOOL or
+        // NO PERM; a TCP handshake still gives a useful latency. Each kind
+        // in use is checked once.
+        let mut kinds = vec![kind];
+        for target in servers {
+            if let Some(own) = target.probe.as_deref() {
+                if registry.get(own).is_none() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: unknown probe kind {:?}", target.name, own),
+                    ));
+                }
+                if !kinds.contains(&own) {
+                    kinds.push(own);
+                }
             }
-            None => {
-                execute!(io::stdout(), SetForegroundColor(Color::DarkRed))?;
-                print!("{:>8}", "TIMEOUT");
-                execute!(io::stdout(), ResetColor)?;
+        }
+        let mut notices = Vec::new();
+        let mut method = None;
+        let mut substituted = Vec::new();
+        let fallback = |why: &dyn std::fmt::Display| {
+            format!(
+                "{}; probing with TCP connects to port {} instead{}",
+                why,
+                tcp_port,
+                if cfg!(target_os = "linux") {
+                    " (see latencee setup-caps)"
+                } else {
+                    ""
+                }
+            )
+        };
+        for kind in kinds {
+            match kind {
+                "icmp" => match probe::icmp_backend() {
+                    backend @ IcmpBackend::Unusable { .. } => {
+                        notices.push(fallback(&backend));
+                        substituted.push("icmp");
+                    }
+                    backend => {
+                        method.get_or_insert(backend.to_string());
+                    }
+                },
+                "ping" => match probe::diagnose_ping() {
+                    Some(why) => {
+                        notices.push(fallback(&why));
+                        substituted.push("ping");
+                    }
+                    None => {
+                        method.get_or_insert("a long-running system ping per target".to_string());
+                    }
+                },
+                _ => {}
             }
         }
+        let probe = registry
+            .get(if substituted.contains(&kind) {
+                "tcp"
+            } else {
+                kind
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown probe kind {:?}", kind),
+                )
+            })?;
+        let (sender, receiver) = channel::bounded::<ServerStatus>(RESULT_QUEUE);
+        let central = config
+            .central
+            .as_ref()
+            .map(|central| Central::start(central, GRAPH_WINDOW, sender.clone()));
+        let cluster = config.cluster.as_ref().map(|cluster| {
+            Cluster::start(cluster, config.http.as_ref(), GRAPH_WINDOW, sender.clone())
+        });
+        let mut inputs = Inputs {
+            receiver,
+            central,
+            cluster,
+            local: Some((sender, probe)),
+            registry,
+            substituted,
+            probing: Vec::new(),
+            notice: Cell::new((!notices.is_empty()).then(|| notices.join("; "))),
+            method,
+            networks: config.networks.clone(),
+            remotes: config
+                .jumps
+                .iter()
+                .map(|jump| Remote {
+                    name: jump.name.clone(),
+                    targets: jump.targets.clone(),
+                    probe: Arc::new(Ping::through(jump.clone(), PROBE_INTERVAL)),
+                })
+                .chain(config.routers.iter().map(|router| Remote {
+                    name: router.name.clone(),
+                    targets: router.targets.clone(),
+                    probe: Arc::new(RouterPing::new(router.clone())),
+                }))
+                .collect(),
+            discoverer: (!config.discovery.is_empty())
+                .then(|| Discoverer::start(&config.discovery)),
+            discovered: vec![Vec::new(); config.discovery.len()],
+            notes: HashMap::new(),
+            warnings: Cell::default(),
+            interval: PROBE_INTERVAL,
+        };
+        for target in servers {
+            inputs.add(target.clone());
+        }
+        Ok(inputs)
+    }
+
+    /// Results of a daemon's probes, without probing locally.
+    #[cfg(unix)]
+    fn attached(receiver: channel::Receiver<ServerStatus>) -> Self {
+        Inputs {
+            receiver,
+            central: None,
+            cluster: None,
+            local: None,
+            registry: Registry::empty(),
+            substituted: Vec::new(),
+            probing: Vec::new(),
+            notice: Cell::new(None),
+            method: None,
+            networks: Vec::new(),
+            remotes: Vec::new(),
+            discoverer: None,
+            discovered: Vec::new(),
+            notes: HashMap::new(),
+            warnings: Cell::default(),
+            interval: PROBE_INTERVAL,
+        }
+    }
 
-        let age = server.last_update.elapsed().as_secs();
-        if age > 5 {
-            execute!(io::stdout(), SetForegroundColor(Color::DarkGrey))?;
-            print!(" ({}s ago)", age);
-            execute!(io::stdout(), ResetColor)?;
+    /// The probe of kind `kind`, or of the kind configured for all targets.
+    fn probe_for(&self, kind: Option<&str>) -> Result<Arc<dyn Probe>, String> {
+        let Some((_, default)) = &self.local else {
+            return Err("Attached to a daemon; add targets with its control socket".to_string());
+        };
+        match kind {
+            None => Ok(Arc::clone(default)),
+            Some(kind) if self.substituted.contains(&kind) => self
+                .registry
+                .get("tcp")
+                .ok_or_else(|| "No tcp probe".to_string()),
+            Some(kind) => self
+                .registry
+                .get(kind)
+                .ok_or_else(|| format!("Unknown probe {:?}", kind)),
         }
+    }
+
+    /// Starts probing `target` with the probe of its kind, and pinging it
+    /// from every machine it is also measured from.
+    fn add(&mut self, target: Target) {
+        let Ok(probe) = self.probe_for(target.probe.as_deref()) else {
+            return;
+        };
+        self.add_with(target, probe);
+    }
 
-        println!();
+    /// Like [`Inputs::add`], measuring `target` with `probe`.
+    fn add_with(&mut self, target: Target, probe: Arc<dyn Probe>) {
+        let twins: Vec<_> = self
+            .remotes
+            .iter()
+            .filter(|remote| remote.covers(&target.name))
+            .map(|remote| {
+                let mut twin = target.clone();
+                twin.name = remote.twin_name(&target.name);
+                (twin, Arc::clone(&remote.probe))
+            })
+            .collect();
+        self.spawn(target, probe);
+        for (twin, probe) in twins {
+            self.spawn(twin, probe);
+        }
+    }
 
-        // Graph line
-        execute!(io::stdout(), cursor::MoveTo(2, row + 1))?;
-        let graph = draw_graph(&server.history);
-
-        // Draw graph with colors
-        for ch in graph.chars() {
-            if ch != ' ' {
-                let color = match ch {
-                    '●' => Color::Green,
-                    '◐' => Color::Yellow,
-                    '◑' => Color::Red,
-                    '○' => Color::DarkRed,
-                    _ => Color::White,
-                };
-                execute!(io::stdout(), SetForegroundColor(color))?;
-                print!("{}", ch);
-                execute!(io::stdout(), ResetColor)?;
-            } else {
-                print!("·");
+    fn spawn(&mut self, mut target: Target, probe: Arc<dyn Probe>) {
+        let Some((sender, _)) = &self.local else {
+            return;
+        };
+        target.network = network::for_target(&self.networks, &target.name).cloned();
+        resolve::prefetch(&target.host);
+        probe.set_interval(&target, self.interval);
+        let prober = Prober::with_probe(
+            target.clone(),
+            Arc::clone(&probe),
+            self.interval,
+            GRAPH_WINDOW,
+        )
+        .with_delay(stagger(self.probing.len()));
+        let paused = prober.pause_switch();
+        let trigger = prober.trigger();
+        let interval = prober.interval_setting();
+        let task = rt::spawn(prober.run(sender.clone()));
+        self.probing.push(Probing {
+            target,
+            probe,
+            paused,
+            trigger,
+            interval,
+            _task: task,
+        });
+    }
+
+    fn find(&self, name: &str) -> Option<&Probing> {
+        self.probing.iter().find(|p| p.target.name == name)
+    }
+
+    /// The prober of a target and those pinging it from other machines.
+    fn with_twins<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a Probing> {
+        let twins: Vec<String> = self
+            .remotes
+            .iter()
+            .map(|remote| remote.twin_name(name))
+            .collect();
+        let name = name.to_string();
+        self.probing
+            .iter()
+            .filter(move |p| p.target.name == name || twins.contains(&p.target.name))
+    }
+
+    /// Pauses probing a target and pinging it from other machines, or
+    /// resumes it; false if it is not probed locally.
+    fn set_paused(&self, name: &str, pause: bool) -> bool {
+        if self.find(name).is_none() {
+            return false;
+        }
+        for probing in self.with_twins(name) {
+            probing.paused.store(pause, Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// Probes a target at once, and pings it from other machines; false if
+    /// it is not probed locally.
+    fn probe_now(&self, name: &str) -> bool {
+        let mut found = false;
+        for probing in self.with_twins(name) {
+            probing.trigger.fire();
+            found = true;
+        }
+        found
+    }
+
+    /// Probes every target every `interval`, including those set to
+    /// their own.
+    fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+        for probing in &self.probing {
+            probing
+                .interval
+                .store(interval.as_millis() as u64, Ordering::Relaxed);
+            probing.probe.set_interval(&probing.target, interval);
+        }
+    }
+
+    /// How often a target is probed, `None` if it is not probed locally.
+    fn interval_of(&self, name: &str) -> Option<Duration> {
+        let millis = self.find(name)?.interval.load(Ordering::Relaxed);
+        Some(Duration::from_millis(millis))
+    }
+
+    /// Probes a target, and pings it from other machines, every
+    /// `interval`.
+    fn set_target_interval(&self, name: &str, interval: Duration) {
+        for probing in self.with_twins(name) {
+            probing
+                .interval
+                .store(interval.as_millis() as u64, Ordering::Relaxed);
+            probing.probe.set_interval(&probing.target, interval);
+        }
+    }
+
+    /// The shortest interval any target is probed at, which the histories
+    /// are sized for.
+    fn fastest_interval(&self) -> Duration {
+        self.probing
+            .iter()
+            .map(|p| Duration::from_millis(p.interval.load(Ordering::Relaxed)))
+            .min()
+            .unwrap_or(self.interval)
+    }
+
+    /// The intervals of the targets probed at another than the usual one.
+    fn own_intervals(&self) -> HashMap<String, Duration> {
+        self.probing
+            .iter()
+            .map(|p| {
+                let millis = p.interval.load(Ordering::Relaxed);
+                (p.target.name.clone(), Duration::from_millis(millis))
+            })
+            .filter(|(_, interval)| *interval != self.interval)
+            .collect()
+    }
+
+    /// Names of the targets whose probing is paused.
+    fn paused(&self) -> HashSet<String> {
+        self.probing
+            .iter()
+            .filter(|p| p.paused.load(Ordering::Relaxed))
+            .map(|p| p.target.name.clone())
+            .collect()
+    }
+
+    /// Stops probing a target and pinging it from other machines; the
+    /// names no longer probed, none if it is not probed locally.
+    fn remove(&mut self, name: &str) -> Vec<String> {
+        let twins: Vec<String> = self
+            .remotes
+            .iter()
+            .map(|remote| remote.twin_name(name))
+            .collect();
+        let mut removed = Vec::new();
+        self.probing.retain(|p| {
+            let gone = p.target.name == name || twins.contains(&p.target.name);
+            if gone {
+                removed.push(p.target.name.clone());
             }
+            !gone
+        });
+        removed
+    }
+
+    /// Applies what discovery found since the last call: probes the new
+    /// targets of a source and forgets those it no longer has. Targets of
+    /// the same name from elsewhere are left alone.
+    fn discover(&mut self, monitor: &mut Monitor) {
+        let Some(discoverer) = &self.discoverer else {
+            return;
+        };
+        let mut updates = Vec::new();
+        while let Some(update) = discoverer.try_next() {
+            updates.push(update);
         }
+        for update in updates {
+            let previous = std::mem::take(&mut self.discovered[update.source]);
+            for name in &previous {
+                if !update.targets.iter().any(|t| t.name == *name) {
+                    for removed in self.remove(name) {
+                        monitor.forget(&removed);
+                    }
+                    self.notes.remove(name);
+                }
+            }
+            let mut current = Vec::new();
+            for target in update.targets {
+                if current.contains(&target.name) {
+                    continue;
+                }
+                if !previous.contains(&target.name) {
+                    if self.find(&target.name).is_some() {
+                        continue;
+                    }
+                    self.add(target.clone());
+                }
+                let warned = self.notes.get(&target.name).is_some_and(|n| n.warning);
+                match target.note {
+                    Some(note) => {
+                        // Told when it turns into a warning, not again
+                        // while it stays one.
+                        if note.warning && !warned {
+                            let mut warnings = self.warnings.take();
+                            warnings.push(format!("{}: {}", target.name, note.text));
+                            self.warnings.set(warnings);
+                        }
+                        self.notes.insert(target.name.clone(), note);
+                    }
+                    None => {
+                        self.notes.remove(&target.name);
+                    }
+                }
+                current.push(target.name);
+            }
+            self.discovered[update.source] = current;
+        }
+    }
 
-        println!(" [{} min]", GRAPH_HISTORY_MINUTES);
+    /// The notes of discovery and of the probes, by target name.
+    fn notes(&self) -> HashMap<String, Note> {
+        let mut notes = self.notes.clone();
+        for probing in &self.probing {
+            let Some(note) = probing.probe.note(&probing.target) else {
+                continue;
+            };
+            let name = probing.target.name.clone();
+            match notes.get_mut(&name) {
+                Some(existing) => {
+                    existing.text = format!("{}, {}", existing.text, note.text);
+                    existing.warning |= note.warning;
+                }
+                None => {
+                    notes.insert(name, note);
+                }
+            }
+        }
+        notes
     }
 
-    let legend_row = (servers.len() * 3 + 5) as u16;
-    execute!(io::stdout(), cursor::MoveTo(0, legend_row))?;
-    println!("Legend:");
-    execute!(io::stdout(), SetForegroundColor(Color::Green))?;
-    print!("● Good (<50ms)  ");
-    execute!(io::stdout(), SetForegroundColor(Color::Yellow))?;
-    print!("◐ Fair (50-150ms)  ");
-    execute!(io::stdout(), SetForegroundColor(Color::Red))?;
-    print!("◑ Poor (150-500ms)  ");
-    execute!(io::stdout(), SetForegroundColor(Color::DarkRed))?;
-    print!("○ Timeout (>500ms)");
-    execute!(io::stdout(), ResetColor)?;
+    /// Cancels every prober and stops accepting results from agents and
+    /// peers, whose threads end with their next result.
+    fn stop(&mut self) {
+        self.probing.clear();
+        self.receiver.close();
+    }
 
-    io::stdout().flush()?;
-    Ok(())
+    fn take_error(&self) -> Option<String> {
+        self.notice
+            .take()
+            .or_else(|| {
+                self.central
+                    .as_ref()
+                    .and_then(Central::take_error)
+                    .or_else(|| self.cluster.as_ref().and_then(Cluster::take_error))
+                    .or_else(|| self.discoverer.as_ref().and_then(Discoverer::take_error))
+            })
+            .or_else(|| {
+                let changes = resolve::take_changes();
+                (!changes.is_empty()).then(|| {
+                    let changes: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+                    changes.join("; ")
+                })
+            })
+            .or_else(|| {
+                let warnings = self.warnings.take();
+                (!warnings.is_empty()).then(|| warnings.join("; "))
+            })
+    }
 }
 
-pub fn get_default_servers() -> Vec<(&'static str, &'static str)> {
-    vec![
-        ("Google DNS", "8.8.8.8"),
-        ("Cloudflare DNS", "1.1.1.1"),
-        ("Google", "google.com"),
-        ("GitHub", "github.com"),
-        ("Stack Overflow", "stackoverflow.com"),
-    ]
+/// When the prober of the `index`th target first probes. Steps of the
+/// golden ratio spread any number of targets evenly over the interval, so
+/// hundreds of them do not all probe, and report, at the same moment.
+fn stagger(index: usize) -> Duration {
+    PROBE_INTERVAL.mul_f64((index as f64 * 0.618_033_988_749_895).fract())
 }
 
-fn main() -> io::Result<()> {
-    let servers = get_default_servers();
+/// Keeps what the graphs span, within the configured memory.
+fn history_store(config: &config::Config) -> HistoryStore {
+    let memory = config.history_memory.unwrap_or(history::DEFAULT_MEMORY);
+    HistoryStore::new(GRAPH_WINDOW, PROBE_INTERVAL, memory)
+}
 
-    smol::block_on(async {
-        terminal::enable_raw_mode()?;
-
-        let (sender, receiver) = channel::unbounded::<ServerStatus>();
-        let mut server_statuses = Vec::new();
-
-        // Initialize server statuses
-        for (name, _host) in &servers {
-            server_statuses.push(ServerStatus {
-                name: name.to_string(),
-                latency: None,
-                last_update: Instant::now(),
-                status: ConnectionStatus::Timeout,
-                history: VecDeque::new(),
-            });
+/// The name of the `selected`th target, if there is one.
+fn selected_name(monitor: &Monitor, selected: usize) -> Option<String> {
+    monitor
+        .histories()
+        .statuses()
+        .get(selected)
+        .map(|status| status.name.to_string())
+}
+
+/// Targets marked on the dashboard, which actions apply to instead of the
+/// selected one.
+#[derive(Default)]
+struct Marks {
+    /// Names of the targets marked one by one.
+    names: HashSet<String>,
+    /// The target a visual range was started on; the range runs from it to
+    /// the selected one.
+    anchor: Option<String>,
+}
+
+impl Marks {
+    /// Names of the targets marked, including those in the visual range.
+    fn marked(&self, monitor: &Monitor, selected: usize) -> HashSet<String> {
+        let mut marked = self.names.clone();
+        let histories = monitor.histories();
+        let statuses = histories.statuses();
+        if let Some(anchor) = &self.anchor
+            && let Some(start) = statuses.iter().position(|s| *s.name == **anchor)
+        {
+            let range = start.min(selected)..=start.max(selected).min(statuses.len() - 1);
+            marked.extend(statuses[range].iter().map(|s| s.name.to_string()));
+        }
+        marked
+    }
+
+    /// Names of the targets an action applies to, in the order of their
+    /// rows: those marked, or the selected one if none are.
+    fn targets(&self, monitor: &Monitor, selected: usize) -> Vec<String> {
+        let marked = self.marked(monitor, selected);
+        if marked.is_empty() {
+            return selected_name(monitor, selected).into_iter().collect();
         }
+        monitor
+            .histories()
+            .statuses()
+            .iter()
+            .map(|s| s.name.to_string())
+            .filter(|name| marked.contains(name))
+            .collect()
+    }
+
+    /// Marks the range of a visual selection one by one and ends it, or
+    /// starts one on the selected target.
+    fn toggle_visual(&mut self, monitor: &Monitor, selected: usize) {
+        if self.anchor.is_some() {
+            self.names = self.marked(monitor, selected);
+            self.anchor = None;
+        } else {
+            self.anchor = selected_name(monitor, selected);
+        }
+    }
 
-        // Start monitoring tasks
-        for (name, host) in servers {
-            let sender = sender.clone();
-            smol::spawn(monitor_server(name.to_string(), host.to_string(), sender)).detach();
+    fn toggle(&mut self, name: String) {
+        if !self.names.remove(&name) {
+            self.names.insert(name);
         }
+    }
+
+    fn clear(&mut self) {
+        self.names.clear();
+        self.anchor = None;
+    }
+
+    /// Forgets the targets no longer shown.
+    fn retain_shown(&mut self, monitor: &Monitor) {
+        let histories = monitor.histories();
+        self.names.retain(|name| histories.get(name).is_some());
+        self.anchor = self
+            .anchor
+            .take()
+            .filter(|name| histories.get(name).is_some());
+    }
+}
+
+/// Names for a notice: listed when there are a few, counted otherwise.
+fn names_text(names: &[String]) -> String {
+    if names.len() <= 3 {
+        names.join(", ")
+    } else {
+        format!("{} targets", names.len())
+    }
+}
+
+/// The figures of a target as `y` copies them, for pasting into tickets:
+/// its names and address, its latency over the graph's window and its last
+/// outage.
+fn target_summary(
+    status: &ServerStatus,
+    label: Option<&str>,
+    address: Option<String>,
+    outage: Option<Outage>,
+) -> String {
+    let summary = stats::summarize(status.history.iter().map(|(_, _, latency)| *latency));
+    let mut lines = vec![match label {
+        Some(label) => format!("{} ({})", label, status.name),
+        None => status.name.to_string(),
+    }];
+    if let Some(address) = address {
+        lines.push(format!("Address: {}", address));
+    }
+    lines.push(format!(
+        "Current: {}  Min: {}  Avg: {}  Max: {}  P95: {}",
+        stats::format_ms(status.latency),
+        stats::format_ms(summary.min),
+        stats::format_ms(summary.avg),
+        stats::format_ms(summary.max),
+        stats::format_ms(summary.p95)
+    ));
+    lines.push(format!(
+        "Loss: {:.1}% of {} probes in the last {} min",
+        summary.loss() * 100.0,
+        summary.samples,
+        GRAPH_HISTORY_MINUTES
+    ));
+    lines.push(match outage {
+        Some(Outage {
+            start,
+            duration: Some(duration),
+        }) => format!(
+            "Last outage: {}, {}",
+            timefmt::long(start),
+            timefmt::duration(duration)
+        ),
+        Some(Outage {
+            start,
+            duration: None,
+        }) => format!("Last outage: ongoing since {}", timefmt::long(start)),
+        None => "Last outage: none".to_string(),
+    });
+    lines.join("\n")
+}
+
+/// What a dashboard prompt asks for.
+#[derive(Clone)]
+enum Asking {
+    Add,
+    /// A label for the target of that name.
+    Rename(String),
+    /// Whether to clear the history of the targets of those names.
+    Clear(Vec<String>),
+    /// Whether to quit, with `[dashboard] confirm_quit`.
+    Quit,
+}
+
+fn quit_prompt() -> Prompt {
+    Prompt::new("Quit latencee? [y/N] ", "")
+}
 
-        // Initial draw
-        draw_ui(&server_statuses)?;
+/// Starts probing the target described by `line`, a host followed by an
+/// optional name and `probe=` and `port=` settings, and adds its row; the
+/// name it is shown under.
+fn add_target(line: &str, inputs: &mut Inputs, monitor: &Monitor) -> Result<String, String> {
+    let mut words = line.split_whitespace();
+    let host = words.next().ok_or("No host given")?;
+    let mut name = Vec::new();
+    let mut kind = None;
+    let mut port = None;
+    for word in words {
+        if let Some(probe) = word.strip_prefix("probe=") {
+            kind = Some(probe.to_string());
+        } else if let Some(number) = word.strip_prefix("port=") {
+            port = Some(
+                number
+                    .parse::<u16>()
+                    .map_err(|_| format!("Invalid port {:?}", number))?,
+            );
+        } else {
+            name.push(word);
+        }
+    }
+    let probe = inputs.probe_for(kind.as_deref())?;
+    let name = if name.is_empty() {
+        host.to_string()
+    } else {
+        name.join(" ")
+    };
+    if inputs.find(&name).is_some() || monitor.histories().get(&name).is_some() {
+        return Err(format!("{} is already monitored", name));
+    }
+    let mut target = Target::new(name.clone(), host);
+    target.port = port;
+    target.probe = kind;
+    inputs.add_with(target, probe);
+    monitor.histories().add(&name);
+    Ok(name)
+}
 
+/// Adds the target described by `line` like [`add_target`] and selects
+/// it.
+fn add_and_select(
+    line: &str,
+    inputs: &mut Inputs,
+    monitor: &Monitor,
+    selected: &mut usize,
+) -> Notice {
+    match add_target(line, inputs, monitor) {
+        Ok(name) => {
+            *selected = monitor
+                .histories()
+                .statuses()
+                .iter()
+                .position(|s| *s.name == *name)
+                .unwrap_or(*selected);
+            Notice::info(format!("Probing {}", name))
+        }
+        Err(e) => Notice::error(e),
+    }
+}
+
+/// Probes every target every `interval` from now on, with histories
+/// resized to cover the same window.
+fn change_interval(inputs: &mut Inputs, monitor: &Monitor, interval: Duration) -> Notice {
+    if inputs.local.is_none() {
+        return Notice::error("Attached to a daemon; its intervals cannot be changed from here");
+    }
+    inputs.set_interval(interval);
+    monitor.histories().set_interval(inputs.fastest_interval());
+    Notice::info(format!("Probing every {}", interval_text(interval)))
+}
+
+/// Silences the alerts of the targets called `names` for `length`, or
+/// extends their silence by it if `extend`, from the furthest of them.
+fn silence_targets(
+    monitor: &mut Monitor,
+    names: &[String],
+    length: Duration,
+    extend: bool,
+) -> Notice {
+    let now = SystemTime::now();
+    let silencer = monitor.silencer_mut();
+    let from = match extend {
+        true => names
+            .iter()
+            .map(|name| silencer.silenced_until(name, now).unwrap_or(now))
+            .max()
+            .unwrap_or(now),
+        false => now,
+    };
+    let until = from + length;
+    for name in names {
+        silencer.silence(name, until);
+    }
+    Notice::info(format!(
+        "{} silenced until {}",
+        names_text(names),
+        timefmt::short(until)
+    ))
+}
+
+/// Shows the target called `name` as `label`, or under its own name again
+/// when that is empty, and saves the labels.
+fn rename_target(name: &str, label: &str, state: &mut DashboardState) -> Notice {
+    let label = label.trim();
+    let text = if label.is_empty() || label == name {
+        state.labels.remove(name);
+        format!("{} shown under its own name", name)
+    } else {
+        state.labels.insert(name.to_string(), label.to_string());
+        format!("{} shown as {}", name, label)
+    };
+    match state.save() {
+        Ok(()) => Notice::info(text),
+        Err(e) => Notice::error(format!("{}, but not saved: {}", text, e)),
+    }
+}
+
+/// Moves the selected target a row down, or up, keeping the selection on
+/// it, and saves the new order; a notice if that did not work out.
+fn move_target(
+    monitor: &mut Monitor,
+    state: &mut DashboardState,
+    selected: &mut usize,
+    down: bool,
+) -> Option<Notice> {
+    let mut histories = monitor.histories();
+    *selected = histories.shift(*selected, down)?;
+    state.order = histories.order().to_vec();
+    state
+        .save()
+        .err()
+        .map(|e| Notice::error(format!("Order not saved: {}", e)))
+}
+
+/// Gathers the targets called `names` in consecutive rows where the first
+/// of them is, in the order of `names`, and saves the new order; the row
+/// of the first of them.
+fn group_targets(
+    monitor: &mut Monitor,
+    state: &mut DashboardState,
+    names: &[String],
+) -> (Option<usize>, Notice) {
+    let mut histories = monitor.histories();
+    let probed = histories.statuses().len() - histories.archived();
+    let mut order: Vec<String> = histories.statuses()[..probed]
+        .iter()
+        .map(|s| s.name.to_string())
+        .collect();
+    let Some(at) = order.iter().position(|name| names.contains(name)) else {
+        return (None, Notice::error("Archived targets cannot be grouped"));
+    };
+    order.retain(|name| !names.contains(name));
+    let grouped: Vec<String> = names
+        .iter()
+        .filter(|name| {
+            histories.statuses()[..probed]
+                .iter()
+                .any(|s| *s.name == ***name)
+        })
+        .cloned()
+        .collect();
+    order.splice(at..at, grouped.iter().cloned());
+    // Names not shown now keep their place at the end.
+    let rest: Vec<String> = histories
+        .order()
+        .iter()
+        .filter(|name| !order.contains(name))
+        .cloned()
+        .collect();
+    order.extend(rest);
+    histories.arrange(order);
+    state.order = histories.order().to_vec();
+    let text = format!("{} grouped", names_text(&grouped));
+    let notice = match state.save() {
+        Ok(()) => Notice::info(text),
+        Err(e) => Notice::error(format!("{}, but the order is not saved: {}", text, e)),
+    };
+    (Some(at), notice)
+}
+
+/// Stops probing the target called `name` and those pinging it from other
+/// machines, and removes their rows, or moves them to the archived ones
+/// at the end if `archive`. An archived target is removed for good.
+fn remove_target(
+    name: &str,
+    archive: bool,
+    inputs: &mut Inputs,
+    monitor: &mut Monitor,
+) -> Result<String, String> {
+    let index = monitor
+        .histories()
+        .statuses()
+        .iter()
+        .position(|s| *s.name == *name);
+    if let Some(index) = index
+        && monitor.histories().is_archived(index)
+    {
+        if archive {
+            return Err(format!("{} is archived already", name));
+        }
+        monitor.forget(name);
+        return Ok(format!("{} removed", name));
+    }
+    let removed = inputs.remove(name);
+    if removed.is_empty() {
+        return Err(match inputs.local {
+            Some(_) => format!("{} is probed elsewhere; remove it there", name),
+            None => "Attached to a daemon; remove targets with its control socket".to_string(),
+        });
+    }
+    for removed in &removed {
+        if archive {
+            monitor.archive(removed);
+        } else {
+            monitor.forget(removed);
+        }
+    }
+    Ok(format!(
+        "{} {}",
+        removed.join(", "),
+        if archive { "archived" } else { "removed" }
+    ))
+}
+
+/// What woke the dashboard up.
+enum Wake {
+    /// A terminal event; `None` once input ended.
+    Input(Option<Event>),
+    /// A result; `None` once results ended.
+    Results(Option<ServerStatus>),
+    Tick,
+}
+
+fn run_dashboard(
+    options: &cli::Options,
+    config: &config::Config,
+    servers: &[Target],
+    mut monitor: Monitor,
+    mut inputs: Inputs,
+) -> io::Result<()> {
+    let mut notice: Option<Notice> = None;
+    // Whether the alert history is shown and which target it is filtered to.
+    let mut show_alerts = false;
+    let mut alert_filter: Option<usize> = None;
+    let mut selected = 0;
+    let mut marks = Marks::default();
+    // Keys pressed so far of those bound to an action, such as the first
+    // 'g' of 'gg'.
+    let mut pending = Vec::new();
+    let mut detached = false;
+    // Where the result of a running speed test arrives.
+    let mut speed_test: Option<channel::Receiver<io::Result<speedtest::Throughput>>> = None;
+    // The prompt being typed into, and what it asks for.
+    let mut prompt: Option<(Prompt, Asking)> = None;
+    let mut palette: Option<Palette> = None;
+    let keys = match &config.speedtest {
+        Some(_) => key_hints(
+            &config.keys,
+            &[DASHBOARD_HINTS, &[(&[Action::SpeedTest], "to test speed")]].concat(),
+        ),
+        None => key_hints(&config.keys, DASHBOARD_HINTS),
+    };
+    let alert_keys = key_hints(&config.keys, ALERT_LOG_HINTS);
+    let snapshot_dir = config
+        .snapshot_dir
+        .clone()
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+
+    let mut session = Session::new();
+    // The dashboard order and labels, for the session summary.
+    let mut shown = (Vec::new(), BTreeMap::new());
+    let stop = term::stop_signal()?;
+    let raw_mode = term::RawMode::enable()?;
+    let result = smol::block_on(async {
+        // Local targets keep their order; those of agents follow as they
+        // report.
+        for target in servers {
+            monitor.histories().add(&target.name);
+        }
+        // As arranged last time.
+        let mut state = DashboardState::load().unwrap_or_else(|e| {
+            notice = Some(Notice::error(format!("Dashboard state ignored: {}", e)));
+            DashboardState::default()
+        });
+        monitor.histories().arrange(state.order.clone());
+        state.theme.apply();
+        // A configured view goes before the one kept with the state.
+        if let Some(view) = config.view.as_deref().and_then(View::named) {
+            state.view = view;
+        }
+
+        // Woken by a key, a resize, new results or the clock, redrawing only
+        // when the screen may have changed, and seldom when nobody looks.
+        let input = term::input_events(Arc::clone(&stop));
+        let mut screen = Screen::new();
+        let mut dirty = true;
+        let mut focused = true;
+        let mut last_input = Instant::now();
+        let mut drawn = Instant::now();
         loop {
-            // Check for keyboard input
-            if event::poll(Duration::from_millis(100))? {
-                if let Event::Key(key_event) = event::read()? {
-                    if key_event.code == KeyCode::Char('q') {
-                        break;
-                    }
+            let idle = !focused || last_input.elapsed() > IDLE_AFTER;
+            if dirty && (!idle || drawn.elapsed() >= IDLE_REDRAW) {
+                let marked = marks.marked(&monitor, selected);
+                let histories = monitor.histories();
+                if show_alerts {
+                    draw_alert_log(
+                        &mut screen,
+                        monitor.alert_log(),
+                        alert_filter.map(|i| &*histories.statuses()[i].name),
+                        &alert_keys,
+                    )?;
+                } else {
+                    draw_ui(
+                        &mut screen,
+                        Rows {
+                            statuses: histories.statuses(),
+                            archived: histories.archived(),
+                            selected,
+                            marked: &marked,
+                            visual: marks.anchor.is_some(),
+                            labels: &state.labels,
+                            paused: &inputs.paused(),
+                            interval: inputs.local.is_some().then_some(inputs.interval),
+                            intervals: &inputs.own_intervals(),
+                        },
+                        state.view,
+                        monitor.silencer(),
+                        &inputs.notes(),
+                        notice.as_ref(),
+                        match (&prompt, &palette) {
+                            (Some((prompt, _)), _) => Header::Prompt(prompt),
+                            (None, Some(palette)) => Header::Palette(palette),
+                            (None, None) => Header::Keys(&keys),
+                        },
+                    )?;
                 }
+                dirty = false;
+                drawn = Instant::now();
             }
 
-            // Update server statuses
-            while let Ok(status) = receiver.try_recv() {
-                if let Some(server) = server_statuses.iter_mut().find(|s| s.name == status.name) {
-                    *server = status;
+            let wake = future::or(
+                async { Wake::Input(input.recv().await.ok()) },
+                future::or(
+                    async {
+                        if detached {
+                            future::pending().await
+                        } else {
+                            Wake::Results(inputs.receiver.recv().await.ok())
+                        }
+                    },
+                    async {
+                        Timer::after(if idle { IDLE_REDRAW } else { TICK }).await;
+                        Wake::Tick
+                    },
+                ),
+            )
+            .await;
+
+            match wake {
+                // Input ends when a termination signal arrives.
+                Wake::Input(None) => break,
+                Wake::Input(Some(Event::Key(key_event))) => {
+                    dirty = true;
+                    last_input = Instant::now();
+                    // Raw mode delivers Ctrl+C as a key instead of SIGINT. It
+                    // quits from prompts and the palette too, and pressed
+                    // again answers the question whether to.
+                    if key_event.code == KeyCode::Char('c')
+                        && key_event.modifiers.contains(KeyModifiers::CONTROL)
+                    {
+                        if !config.confirm_quit || matches!(prompt, Some((_, Asking::Quit))) {
+                            break;
+                        }
+                        prompt = Some((quit_prompt(), Asking::Quit));
+                        palette = None;
+                        pending.clear();
+                        continue;
+                    }
+                    if let Some((editing, asking)) = &mut prompt {
+                        let asking = asking.clone();
+                        match editing.handle(key_event) {
+                            Outcome::Editing => {}
+                            Outcome::Cancel => prompt = None,
+                            Outcome::Submit(line) => {
+                                prompt = None;
+                                match asking {
+                                    Asking::Add => {
+                                        notice = Some(add_and_select(
+                                            &line,
+                                            &mut inputs,
+                                            &monitor,
+                                            &mut selected,
+                                        ));
+                                    }
+                                    Asking::Rename(name) => {
+                                        notice = Some(rename_target(&name, &line, &mut state));
+                                    }
+                                    Asking::Clear(names)
+                                        if line.trim().eq_ignore_ascii_case("y") =>
+                                    {
+                                        let mut histories = monitor.histories();
+                                        for name in &names {
+                                            histories.clear(name);
+                                        }
+                                        notice = Some(Notice::info(format!(
+                                            "History of {} cleared",
+                                            names_text(&names)
+                                        )));
+                                    }
+                                    Asking::Clear(_) => {}
+                                    Asking::Quit if line.trim().eq_ignore_ascii_case("y") => {
+                                        break;
+                                    }
+                                    Asking::Quit => {}
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    // An action chosen in the palette.
+                    let mut chosen = None;
+                    if let Some(open) = &mut palette {
+                        match open.handle(key_event) {
+                            Outcome::Editing => {}
+                            Outcome::Cancel => palette = None,
+                            Outcome::Submit(_) => {
+                                let command = open.command();
+                                palette = None;
+                                match command {
+                                    Ok(Command::Action(action)) => chosen = Some(action),
+                                    Ok(Command::Add(line)) if line.is_empty() => {
+                                        chosen = Some(Action::Add);
+                                    }
+                                    Ok(Command::Add(line)) => {
+                                        notice = Some(add_and_select(
+                                            &line,
+                                            &mut inputs,
+                                            &monitor,
+                                            &mut selected,
+                                        ));
+                                    }
+                                    Ok(Command::Interval(interval)) => {
+                                        notice =
+                                            Some(change_interval(&mut inputs, &monitor, interval));
+                                    }
+                                    Ok(Command::Silence(None)) => chosen = Some(Action::Silence),
+                                    Ok(Command::Silence(Some(length))) => {
+                                        let names = marks.targets(&monitor, selected);
+                                        if !names.is_empty() {
+                                            notice = Some(silence_targets(
+                                                &mut monitor,
+                                                &names,
+                                                length,
+                                                false,
+                                            ));
+                                        }
+                                    }
+                                    Ok(Command::Theme(theme)) => {
+                                        state.theme = theme.unwrap_or(state.theme.next());
+                                        state.theme.apply();
+                                        notice = Some(match state.save() {
+                                            Ok(()) => Notice::info(format!(
+                                                "Theme {}",
+                                                state.theme.name()
+                                            )),
+                                            Err(e) => {
+                                                Notice::error(format!("Theme not saved: {}", e))
+                                            }
+                                        });
+                                    }
+                                    Ok(Command::ExportCsv(path)) => {
+                                        let path = path.unwrap_or_else(|| {
+                                            snapshot_dir.join(format!(
+                                                "latencee-history-{}.csv",
+                                                timefmt::compact(SystemTime::now())
+                                            ))
+                                        });
+                                        let written = snapshot::write_csv(
+                                            &path,
+                                            monitor.histories().statuses(),
+                                        );
+                                        notice = Some(match written {
+                                            Ok(samples) => Notice::info(format!(
+                                                "{} samples written to {}",
+                                                samples,
+                                                path.display()
+                                            )),
+                                            Err(e) => {
+                                                Notice::error(format!("Export failed: {}", e))
+                                            }
+                                        });
+                                    }
+                                    Err(e) => notice = Some(Notice::error(e)),
+                                }
+                            }
+                        }
+                        if chosen.is_none() {
+                            continue;
+                        }
+                    }
+                    let action = match chosen {
+                        Some(action) => action,
+                        None => {
+                            let Some(key) = term::key(&key_event) else {
+                                pending.clear();
+                                continue;
+                            };
+                            pending.push(key);
+                            let mut lookup = config.keys.lookup(&pending);
+                            if lookup == Lookup::Unbound && pending.len() > 1 {
+                                // Not following on the keys before it, the key
+                                // starts anew.
+                                pending = vec![key];
+                                lookup = config.keys.lookup(&pending);
+                            }
+                            match lookup {
+                                Lookup::Pending => continue,
+                                Lookup::Action(action) => {
+                                    pending.clear();
+                                    action
+                                }
+                                Lookup::Unbound => {
+                                    pending.clear();
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    match action {
+                        Action::Quit if config.confirm_quit => {
+                            prompt = Some((quit_prompt(), Asking::Quit));
+                        }
+                        Action::Quit => break,
+                        Action::Add if !show_alerts => {
+                            prompt = Some((
+                                Prompt::new("Add host [name] [probe=KIND] [port=N]: ", ""),
+                                Asking::Add,
+                            ));
+                        }
+                        Action::Rename if !show_alerts => {
+                            let Some(name) = selected_name(&monitor, selected) else {
+                                continue;
+                            };
+                            let label = state.labels.get(&name).unwrap_or(&name);
+                            prompt = Some((
+                                Prompt::new(format!("Show {} as: ", name), label),
+                                Asking::Rename(name),
+                            ));
+                        }
+                        Action::Copy if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            let summaries: Vec<String> = names
+                                .iter()
+                                .filter_map(|name| {
+                                    let status = monitor.histories().get(name)?.clone();
+                                    // The address probes use, if it was
+                                    // looked up already.
+                                    let address = inputs.find(name).map(|probing| {
+                                        let host = &probing.target.host;
+                                        match resolve::cached(host) {
+                                            Some(ip) if ip != *host => {
+                                                format!("{} ({})", host, ip)
+                                            }
+                                            _ => host.clone(),
+                                        }
+                                    });
+                                    Some(target_summary(
+                                        &status,
+                                        state.labels.get(name).map(String::as_str),
+                                        address,
+                                        monitor.last_outage(name),
+                                    ))
+                                })
+                                .collect();
+                            notice = Some(match term::copy(&summaries.join("\n\n")) {
+                                Ok(()) => Notice::info(format!(
+                                    "Copied {} to the clipboard",
+                                    names_text(&names)
+                                )),
+                                Err(e) => Notice::error(format!("Copy failed: {}", e)),
+                            });
+                        }
+                        Action::Clear if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            prompt = Some((
+                                Prompt::new(
+                                    format!("Clear the history of {}? [y/N] ", names_text(&names)),
+                                    "",
+                                ),
+                                Asking::Clear(names),
+                            ));
+                        }
+                        Action::ProbeNow if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            let mut probed = false;
+                            for name in &names {
+                                probed |= inputs.probe_now(name);
+                            }
+                            if !probed {
+                                notice = Some(match inputs.local {
+                                    Some(_) => Notice::error(format!(
+                                        "{} probed elsewhere; not from here",
+                                        names_text(&names)
+                                    )),
+                                    None => Notice::error(
+                                        "Attached to a daemon; its targets cannot be probed from here",
+                                    ),
+                                });
+                            }
+                        }
+                        action @ (Action::Faster | Action::Slower) if !show_alerts => {
+                            if let Some(interval) =
+                                step_interval(inputs.interval, action == Action::Faster)
+                            {
+                                notice = Some(change_interval(&mut inputs, &monitor, interval));
+                            }
+                        }
+                        action @ (Action::TargetFaster | Action::TargetSlower) if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            // Each target steps from its own interval.
+                            let mut changed = Vec::new();
+                            let mut probed_here = false;
+                            for name in names.iter() {
+                                let Some(current) = inputs.interval_of(name) else {
+                                    continue;
+                                };
+                                probed_here = true;
+                                if let Some(interval) =
+                                    step_interval(current, action == Action::TargetFaster)
+                                {
+                                    inputs.set_target_interval(name, interval);
+                                    changed.push((name.clone(), interval));
+                                }
+                            }
+                            if !changed.is_empty() {
+                                monitor.histories().set_interval(inputs.fastest_interval());
+                            }
+                            notice = match changed.as_slice() {
+                                _ if !probed_here => Some(Notice::error(format!(
+                                    "{} not probed here; intervals cannot be changed",
+                                    names_text(&names)
+                                ))),
+                                [] => None,
+                                [(name, interval)] => Some(Notice::info(format!(
+                                    "Probing {} every {}",
+                                    name,
+                                    interval_text(*interval)
+                                ))),
+                                _ => {
+                                    let names: Vec<String> =
+                                        changed.into_iter().map(|(name, _)| name).collect();
+                                    Some(Notice::info(format!(
+                                        "Probing {} {}",
+                                        names_text(&names),
+                                        if action == Action::TargetFaster {
+                                            "more often"
+                                        } else {
+                                            "less often"
+                                        }
+                                    )))
+                                }
+                            };
+                        }
+                        Action::Pause if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            // Resumed if all of them are paused, paused
+                            // otherwise.
+                            let paused = inputs.paused();
+                            let pause = names.iter().any(|name| !paused.contains(name));
+                            let done: Vec<String> = names
+                                .iter()
+                                .filter(|name| inputs.set_paused(name, pause))
+                                .cloned()
+                                .collect();
+                            notice = Some(if !done.is_empty() {
+                                Notice::info(format!(
+                                    "{} {}",
+                                    names_text(&done),
+                                    if pause { "paused" } else { "resumed" }
+                                ))
+                            } else if inputs.local.is_none() {
+                                Notice::error(
+                                    "Attached to a daemon; pause targets with its control socket",
+                                )
+                            } else {
+                                Notice::error(format!(
+                                    "{} probed elsewhere; pause there",
+                                    names_text(&names)
+                                ))
+                            });
+                        }
+                        Action::Palette if !show_alerts => palette = Some(Palette::new()),
+                        Action::View if !show_alerts => {
+                            state.view = state.view.next();
+                            // In `[dashboard] view` of the configuration, or
+                            // with the dashboard state without one.
+                            let saved = match &options.config {
+                                Some(path) => {
+                                    config::set(path, "dashboard", "view", state.view.name())
+                                }
+                                None => state.save(),
+                            };
+                            notice = saved
+                                .err()
+                                .map(|e| Notice::error(format!("View not saved: {}", e)));
+                        }
+                        Action::Alerts => show_alerts = !show_alerts,
+                        Action::Back if show_alerts => show_alerts = false,
+                        Action::Back => marks.clear(),
+                        Action::FilterAlerts if show_alerts => {
+                            // All targets, then each target in turn.
+                            alert_filter = match alert_filter {
+                                None => Some(0),
+                                Some(i) if i + 1 < monitor.histories().statuses().len() => {
+                                    Some(i + 1)
+                                }
+                                Some(_) => None,
+                            };
+                        }
+                        action @ (Action::MoveDown | Action::MoveUp) if !show_alerts => {
+                            notice = move_target(
+                                &mut monitor,
+                                &mut state,
+                                &mut selected,
+                                action == Action::MoveDown,
+                            );
+                        }
+                        Action::SelectUp => selected = selected.saturating_sub(1),
+                        Action::SelectDown => {
+                            let targets = monitor.histories().statuses().len();
+                            selected = (selected + 1).min(targets.saturating_sub(1));
+                        }
+                        Action::SelectFirst => selected = 0,
+                        Action::SelectLast => {
+                            selected = monitor.histories().statuses().len().saturating_sub(1);
+                        }
+                        Action::Visual if !show_alerts => marks.toggle_visual(&monitor, selected),
+                        Action::Mark if !show_alerts => {
+                            if let Some(name) = selected_name(&monitor, selected) {
+                                marks.toggle(name);
+                            }
+                        }
+                        Action::Group if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.len() < 2 {
+                                notice = Some(Notice::info("Mark the targets to group first"));
+                                continue;
+                            }
+                            let (first, grouped) = group_targets(&mut monitor, &mut state, &names);
+                            selected = first.unwrap_or(selected);
+                            notice = Some(grouped);
+                        }
+                        Action::Silence => {
+                            // Each press extends the silence by another step,
+                            // from the furthest of the targets.
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            notice = Some(silence_targets(
+                                &mut monitor,
+                                &names,
+                                config.alerts.silence,
+                                true,
+                            ));
+                        }
+                        Action::Unsilence => {
+                            let names = marks.targets(&monitor, selected);
+                            if names.is_empty() {
+                                continue;
+                            }
+                            for name in &names {
+                                monitor.silencer_mut().unsilence(name);
+                            }
+                            notice = Some(Notice::info(format!(
+                                "{} no longer silenced",
+                                names_text(&names)
+                            )));
+                        }
+                        action @ (Action::Remove | Action::Archive) if !show_alerts => {
+                            let names = marks.targets(&monitor, selected);
+                            let mut done = Vec::new();
+                            let mut failed = None;
+                            for name in &names {
+                                match remove_target(
+                                    name,
+                                    action == Action::Archive,
+                                    &mut inputs,
+                                    &mut monitor,
+                                ) {
+                                    Ok(text) => done.push(text),
+                                    Err(e) => failed = failed.or(Some(e)),
+                                }
+                            }
+                            notice = match failed {
+                                Some(e) => Some(Notice::error(e)),
+                                None if done.is_empty() => None,
+                                None => Some(Notice::info(done.join("; "))),
+                            };
+                            marks.retain_shown(&monitor);
+                            let targets = monitor.histories().statuses().len();
+                            selected = selected.min(targets.saturating_sub(1));
+                            alert_filter = alert_filter.filter(|&i| i < targets);
+                        }
+                        Action::Mute => {
+                            notice = Some(Notice::info(if monitor.toggle_mute() {
+                                "Sound muted"
+                            } else {
+                                "Sound on"
+                            }));
+                        }
+                        Action::SpeedTest => match &config.speedtest {
+                            Some(_) if speed_test.is_some() => {
+                                notice = Some(Notice::info("A speed test is already running"));
+                            }
+                            Some(test) => {
+                                // Takes seconds to minutes, so off the UI thread.
+                                let (sender, receiver) = channel::bounded(1);
+                                let test = test.clone();
+                                notice = Some(Notice::info(format!(
+                                    "Testing speed with {}...",
+                                    test.command()
+                                )));
+                                std::thread::spawn(move || {
+                                    let _ = sender.send_blocking(speedtest::run(&test));
+                                });
+                                speed_test = Some(receiver);
+                            }
+                            None => {
+                                notice = Some(Notice::info(
+                                    "Add a [speedtest] section to the configuration to test speed",
+                                ));
+                            }
+                        },
+                        Action::Snapshot => {
+                            notice = Some(
+                                match snapshot::write_bundle(
+                                    &snapshot_dir,
+                                    monitor.histories().statuses(),
+                                    servers,
+                                    options.config.as_deref(),
+                                ) {
+                                    Ok(path) => Notice::info(format!(
+                                        "Snapshot written to {}",
+                                        path.display()
+                                    )),
+                                    Err(e) => Notice::error(format!("Snapshot failed: {}", e)),
+                                },
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                Wake::Input(Some(Event::FocusLost)) => focused = false,
+                // Back to full rate at once, starting with a fresh frame.
+                Wake::Input(Some(Event::FocusGained)) => {
+                    focused = true;
+                    last_input = Instant::now();
+                    dirty = true;
+                }
+                Wake::Input(Some(_)) => {
+                    last_input = Instant::now();
+                    dirty = true;
+                }
+                Wake::Results(Some(status)) => {
+                    dirty = true;
+                    // Everything already queued goes into the same frame.
+                    let mut next = Some(status);
+                    while let Some(mut status) = next {
+                        monitor.observe(&mut status);
+                        session.record(&status);
+                        next = inputs.receiver.try_recv().ok();
+                    }
+                }
+                Wake::Results(None) => {
+                    // Only happens when attached and the daemon stopped.
+                    notice = Some(Notice::error("Daemon connection lost".to_string()));
+                    detached = true;
+                    dirty = true;
+                }
+                // Ages of stale results count up.
+                Wake::Tick => {
+                    let histories = monitor.histories();
+                    let probed = histories.statuses().len() - histories.archived();
+                    dirty |= !show_alerts
+                        && histories.statuses()[..probed]
+                            .iter()
+                            .any(|s| s.last_update.elapsed() > STALE);
                 }
             }
 
-            // Redraw UI
-            draw_ui(&server_statuses)?;
-            Timer::after(Duration::from_millis(500)).await;
-        }
+            let targets = monitor.histories().statuses().len();
+            inputs.discover(&mut monitor);
+            let now_targets = monitor.histories().statuses().len();
+            if now_targets != targets {
+                selected = selected.min(now_targets.saturating_sub(1));
+                marks.retain_shown(&monitor);
+                alert_filter = alert_filter.filter(|&i| i < now_targets);
+                dirty = true;
+            }
 
-        terminal::disable_raw_mode()?;
-        execute!(
-            io::stdout(),
-            terminal::Clear(ClearType::All),
-            cursor::MoveTo(0, 0)
-        )?;
-        println!("Goodbye!");
+            if let Some(receiver) = &speed_test
+                && let Ok(result) = receiver.try_recv()
+            {
+                notice = Some(match result {
+                    Ok(throughput) => Notice::info(format!("Speed: {}", throughput.describe())),
+                    Err(e) => Notice::error(format!("Speed test failed: {}", e)),
+                });
+                speed_test = None;
+                dirty = true;
+            }
 
+            monitor.tick(SystemTime::now());
+            if let Some(error) = monitor.take_error().or_else(|| inputs.take_error()) {
+                notice = Some(Notice::error(error));
+                dirty = true;
+            }
+        }
+        shown = (
+            monitor
+                .histories()
+                .statuses()
+                .iter()
+                .map(|s| s.name.to_string())
+                .collect(),
+            state.labels,
+        );
         Ok(())
+    });
+
+    drop(raw_mode);
+    inputs.stop();
+    let flushed = monitor.shutdown();
+    if let Some(error) = monitor.take_error() {
+        eprintln!("latencee: {}", error);
+    }
+    let (order, labels) = shown;
+    let order: Vec<&str> = order.iter().map(String::as_str).collect();
+    session.write(&mut io::stdout(), &order, &labels)?;
+    result.and(flushed)?;
+    println!("Goodbye!");
+    Ok(())
+}
+
+/// Probes and feeds the outputs without touching the terminal, until killed.
+/// As a daemon, results are also published to attached dashboards and
+/// requests on the control socket are served; on an agent, results are
+/// streamed to the central instance through `uplink`.
+fn run_headless(
+    mut monitor: Monitor,
+    mut inputs: Inputs,
+    #[cfg(unix)] mut sockets: Option<daemon::Sockets>,
+    mut uplink: Option<Uplink>,
+) -> io::Result<()> {
+    eprintln!("latencee: monitoring without a terminal UI");
+    if let Some(method) = &inputs.method {
+        eprintln!("latencee: probing with {}", method);
+    }
+    let terminate = term::stop_signal()?;
+    // Under systemd: ready once started, then a heartbeat at twice the
+    // rate the watchdog expects, proving the loop below still runs.
+    #[cfg(unix)]
+    let watchdog = systemd::watchdog_interval().map(|interval| interval / 2);
+    #[cfg(unix)]
+    let mut heartbeat = Instant::now();
+    #[cfg(unix)]
+    systemd::notify("READY=1")?;
+
+    smol::block_on(async {
+        loop {
+            if terminate.load(Ordering::Relaxed) {
+                #[cfg(unix)]
+                systemd::notify("STOPPING=1")?;
+                eprintln!("latencee: stopping");
+                inputs.stop();
+                let flushed = monitor.shutdown();
+                if let Some(error) = monitor.take_error() {
+                    eprintln!(
+                        "{} latencee: {}",
+                        timefmt::rfc3339(SystemTime::now()),
+                        error
+                    );
+                }
+                return flushed;
+            }
+            #[cfg(unix)]
+            if let Some(interval) = watchdog
+                && heartbeat.elapsed() >= interval
+            {
+                systemd::notify("WATCHDOG=1")?;
+                heartbeat = Instant::now();
+            }
+            #[cfg(unix)]
+            if let Some(sockets) = &mut sockets {
+                sockets.attach.accept(monitor.histories().statuses());
+                sockets
+                    .control
+                    .poll(|request| control(request, &mut inputs, &mut monitor));
+            }
+            if let Some(uplink) = &mut uplink {
+                uplink.tick(monitor.histories().statuses());
+            }
+            inputs.discover(&mut monitor);
+            while let Ok(mut status) = inputs.receiver.try_recv() {
+                // A result still in flight when its target was removed.
+                if status.source.is_none() && inputs.find(&status.name).is_none() {
+                    continue;
+                }
+                let previous = monitor.histories().get(&status.name).and_then(|s| s.error);
+                monitor.observe(&mut status);
+                if let Some(uplink) = &mut uplink {
+                    uplink.publish(&status);
+                }
+                #[cfg(unix)]
+                if let Some(sockets) = &mut sockets {
+                    sockets.attach.publish(&status);
+                }
+                // Timeouts are routine on a lossy link; anything else means
+                // the probe cannot work at all, so say so once.
+                if let Some(error) = status.error
+                    && error != ProbeError::Timeout
+                    && previous != status.error
+                {
+                    eprintln!(
+                        "{} latencee: {}: {}",
+                        timefmt::rfc3339(status.sampled_at),
+                        status.name,
+                        error
+                    );
+                }
+            }
+            let now = SystemTime::now();
+            monitor.tick(now);
+            let error = monitor
+                .take_error()
+                .or_else(|| inputs.take_error())
+                .or_else(|| uplink.as_mut().and_then(Uplink::take_error));
+            if let Some(error) = error {
+                eprintln!("{} latencee: {}", timefmt::rfc3339(now), error);
+            }
+            Timer::after(Duration::from_millis(500)).await;
+        }
     })
 }
 
+/// Carries out a request from the control socket.
+#[cfg(unix)]
+fn control(
+    request: control::Request,
+    inputs: &mut Inputs,
+    monitor: &mut Monitor,
+) -> Result<Vec<String>, String> {
+    use control::Request;
+    match &request {
+        Request::Add { host, name } => {
+            if inputs.find(name).is_some() || monitor.histories().get(name).is_some() {
+                return Err(format!("target '{}' already exists", name));
+            }
+            inputs.add(Target::new(name, host));
+        }
+        Request::Remove(name) => {
+            let removed = inputs.remove(name);
+            if removed.is_empty() {
+                return Err(format!("no local target '{}'", name));
+            }
+            for name in removed {
+                monitor.forget(&name);
+            }
+        }
+        Request::Pause(name) | Request::Resume(name) => {
+            let Some(probing) = inputs.find(name) else {
+                return Err(format!("no local target '{}'", name));
+            };
+            let pause = matches!(request, Request::Pause(_));
+            probing.paused.store(pause, Ordering::Relaxed);
+        }
+        Request::State => return Ok(state_lines(inputs, &monitor.histories())),
+    }
+    Ok(Vec::new())
+}
+
+/// One JSON object per local target, including those not probed yet, and
+/// per target reported by an agent.
+#[cfg(unix)]
+fn state_lines(inputs: &Inputs, histories: &HistoryStore) -> Vec<String> {
+    let latest_of = |name: &str| histories.get(name);
+    let local = inputs.probing.iter().map(|probing| {
+        let name = probing.target.name.as_str();
+        (
+            name,
+            Some(probing.target.host.as_str()),
+            probing.paused.load(Ordering::Relaxed),
+            latest_of(name),
+        )
+    });
+    let remote = histories
+        .probed()
+        .filter(|s| s.source.is_some())
+        .map(|s| (&*s.name, None, false, Some(s)));
+    local
+        .chain(remote)
+        .map(|(name, host, paused, status)| {
+            Json::object([
+                ("name", Json::from(name)),
+                ("host", Json::from(host)),
+                (
+                    "source",
+                    Json::from(status.and_then(|s| s.source.as_deref())),
+                ),
+                ("paused", Json::from(paused)),
+                ("status", Json::from(status.map(|s| s.status.label()))),
+                (
+                    "rtt_ms",
+                    Json::from(status.and_then(|s| s.latency).map(stats::millis)),
+                ),
+                (
+                    "at",
+                    Json::from(status.map(|s| timefmt::rfc3339(s.sampled_at))),
+                ),
+            ])
+            .to_string()
+        })
+        .collect()
+}
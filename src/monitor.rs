@@ -0,0 +1,369 @@
+//! Everything that happens to a probe result besides drawing it: storage,
+//! reports, outputs, alerts and the HTTP and gRPC APIs. Shared by the
+//! dashboard and headless mode.
+
+use crate::{
+    ServerStatus,
+    alert::{AlertLog, Dispatcher, Engine, Rule, Silencer},
+    config::Config,
+    events::{Event, EventTracker, Outage},
+    grpc,
+    history::HistoryStore,
+    http,
+    pipeline::Pipeline,
+    report::Reporter,
+    rrd::RrdStore,
+    script::{Answer, Script},
+    sinks::{self, Sink, Sinks},
+    wire,
+};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Number of alerts kept for the history pane.
+pub const ALERT_LOG_SIZE: usize = 500;
+
+/// How long outputs may take to deliver their queued events on exit.
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// How often events dropped by outputs and API clients are reported, at
+/// most.
+const DROP_REPORT: Duration = Duration::from_secs(10);
+
+pub struct Monitor {
+    store: Option<RrdStore>,
+    reporter: Option<Reporter>,
+    pipeline: Pipeline,
+    sinks: Sinks,
+    hooks: Sinks,
+    tracker: EventTracker,
+    rules: Engine,
+    dispatcher: Dispatcher,
+    silencer: Silencer,
+    alert_log: AlertLog,
+    muted: Arc<AtomicBool>,
+    histories: Arc<Mutex<HistoryStore>>,
+    http: Option<http::Server>,
+    grpc: Option<grpc::Server>,
+    script: Option<Script>,
+    /// The script's latest answer per target.
+    answers: HashMap<Arc<str>, Answer>,
+    recording: Option<File>,
+    error: Option<String>,
+    /// Since when drops are counted for the next report, and how many of
+    /// each were reported before.
+    drop_period: Option<Instant>,
+    reported: HashMap<&'static str, u64>,
+}
+
+impl Monitor {
+    /// Opens storage and starts every output configured in `config`,
+    /// keeping the history of every target in `histories`.
+    pub fn new(config: &Config, histories: HistoryStore) -> io::Result<Self> {
+        let store = match &config.storage {
+            Some(storage) => Some(RrdStore::open(&storage.path, &storage.archives)?),
+            None => None,
+        };
+        let reporter = config
+            .report
+            .clone()
+            .map(|report| Reporter::new(report, SystemTime::now()));
+
+        let muted = Arc::new(AtomicBool::new(false));
+        let sinks = Sinks::from_config(config, &muted);
+
+        // Hooks drive automation, so they see every alert as it happens,
+        // without cooldown or grouping.
+        let mut hooks = Sinks::default();
+        if config
+            .rules
+            .iter()
+            .any(|r| r.on_fire.is_some() || r.on_resolve.is_some())
+        {
+            hooks.add(Box::new(sinks::hook::HookSink::new(&config.rules)));
+        }
+        let rules = Engine::new(if config.rules.is_empty() {
+            vec![Rule::degraded(config.alerts.level)]
+        } else {
+            config.rules.clone()
+        });
+
+        let histories = Arc::new(Mutex::new(histories));
+        let http = config
+            .http
+            .as_ref()
+            .map(|http| {
+                let storage = config.storage.as_ref().map(|storage| storage.path.clone());
+                http::Server::start(http, Arc::clone(&histories), storage)
+            })
+            .transpose()?;
+
+        Ok(Monitor {
+            store,
+            reporter,
+            pipeline: Pipeline::new(&config.processors),
+            sinks,
+            hooks,
+            tracker: EventTracker::default(),
+            rules,
+            dispatcher: Dispatcher::new(config.alerts.cooldown, config.alerts.group_wait),
+            silencer: Silencer::new(config.maintenance.clone()),
+            alert_log: AlertLog::new(ALERT_LOG_SIZE),
+            muted,
+            histories,
+            http,
+            grpc: config.grpc.as_ref().map(grpc::Server::start).transpose()?,
+            script: config.script.as_ref().map(Script::start).transpose()?,
+            answers: HashMap::new(),
+            recording: None,
+            error: None,
+            drop_period: None,
+            reported: HashMap::new(),
+        })
+    }
+
+    /// Stores, reports and evaluates one probe result, after the configured
+    /// processors and the script's latest answer for the target had the
+    /// chance to rewrite it, and adds it to the history of its target.
+    pub fn observe(&mut self, status: &mut ServerStatus) {
+        self.pipeline.process(status);
+        self.collect_answers();
+        if let Some(script) = &self.script {
+            script.sample(status);
+        }
+        if let Some(answer) = self.answers.get(&status.name) {
+            if let Some(class) = answer.status {
+                status.status = class;
+            }
+            status.metrics.clone_from(&answer.metrics);
+        }
+        if let Some(rrd) = &mut self.store
+            && let Err(e) = rrd.record(status)
+        {
+            // Keep monitoring but stop writing once storage fails.
+            self.error = Some(format!("Storage disabled: {}", e));
+            self.store = None;
+        }
+        if let Some(file) = &mut self.recording
+            && let Err(e) = file.write_all(wire::encode_status(status).as_bytes())
+        {
+            self.error = Some(format!("Recording stopped: {}", e));
+            self.recording = None;
+        }
+        self.histories.lock().unwrap().track(status);
+        if let Some(http) = &self.http {
+            http.update(status);
+        }
+        if let Some(reporter) = &mut self.reporter {
+            reporter.record(&status.name, status.sampled_at, status.latency);
+        }
+        for event in self.tracker.observe(status) {
+            self.notify_script(&event);
+            self.publish(&event);
+            self.sinks.emit(&event);
+        }
+        for alert in self.rules.observe(status) {
+            self.alert_log.push(alert.clone());
+            if self.silencer.allows(&alert) {
+                let event = Event::Alerts(vec![alert.clone()]);
+                self.notify_script(&event);
+                self.publish(&event);
+                self.hooks.emit(&event);
+                self.dispatcher.push(alert);
+            }
+        }
+    }
+
+    /// Feeds events to `sink` too, besides the configured outputs.
+    pub fn add_sink(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.add(sink);
+    }
+
+    /// Appends every result to `path` in the [`crate::wire`] format, to be
+    /// played back with `latencee replay`.
+    pub fn record_to(&mut self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.recording = Some(file);
+        Ok(())
+    }
+
+    /// Sends pending alerts, gives the outputs up to [`SHUTDOWN_GRACE`] to
+    /// deliver what they have queued and flushes stored history to disk,
+    /// before the process exits. Outputs that did not finish in time are
+    /// reported by [`Monitor::take_error`].
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        if let Some(alerts) = self.dispatcher.take_all() {
+            self.sinks.emit(&Event::Alerts(alerts));
+        }
+        let mut busy = self.sinks.shutdown(SHUTDOWN_GRACE);
+        busy.extend(self.hooks.shutdown(SHUTDOWN_GRACE));
+        if !busy.is_empty() {
+            self.error = Some(format!(
+                "Gave up delivering to {} after {}s",
+                busy.join(", "),
+                SHUTDOWN_GRACE.as_secs()
+            ));
+        }
+        self.script = None;
+        match &self.store {
+            Some(store) => store.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// The latest status and history of every target. Hold the guard only
+    /// briefly: the HTTP API waits for it.
+    pub fn histories(&self) -> MutexGuard<'_, HistoryStore> {
+        self.histories.lock().unwrap()
+    }
+
+    /// Drops the history of a target that is no longer monitored.
+    pub fn forget(&mut self, name: &str) {
+        self.histories().remove(name);
+        self.answers.remove(name);
+        self.rules.forget(name);
+    }
+
+    /// Keeps the history of a target that is no longer probed among the
+    /// archived ones; false if there is no such target or it is archived
+    /// already.
+    pub fn archive(&mut self, name: &str) -> bool {
+        self.answers.remove(name);
+        self.rules.forget(name);
+        self.histories().archive(name)
+    }
+
+    /// Streams `event` to the clients of the HTTP and gRPC APIs.
+    fn publish(&self, event: &Event) {
+        if let Some(http) = &self.http {
+            http.publish(event);
+        }
+        if let Some(grpc) = &self.grpc {
+            grpc.publish(event);
+        }
+    }
+
+    fn notify_script(&self, event: &Event) {
+        if let Some(script) = &self.script {
+            script.notify(event);
+        }
+    }
+
+    /// Takes over the answers the script gave meanwhile, and keeps
+    /// monitoring without the script once it fails.
+    fn collect_answers(&mut self) {
+        let Some(script) = &self.script else {
+            return;
+        };
+        let mut failed = None;
+        for answer in script.answers() {
+            match answer {
+                Ok(answer) => {
+                    self.answers.insert(Arc::clone(&answer.target), answer);
+                }
+                Err(e) => failed = Some(e),
+            }
+        }
+        if let Some(e) = failed {
+            self.error = Some(format!("Script disabled: {}", e));
+            self.script = None;
+            self.answers.clear();
+        }
+    }
+
+    /// Sends grouped alerts and due reports; call regularly.
+    pub fn tick(&mut self, now: SystemTime) {
+        self.collect_answers();
+        if let Some(alerts) = self.dispatcher.take_ready() {
+            self.sinks.emit(&Event::Alerts(alerts));
+        }
+
+        if let Some(reporter) = &mut self.reporter
+            && let Err(e) = reporter.tick(now)
+        {
+            self.error = Some(format!("Reports disabled: {}", e));
+            self.reporter = None;
+        }
+    }
+
+    /// The most recent failure of storage, reports or an output, cleared
+    /// once read, or else how many events outputs and HTTP and gRPC clients
+    /// dropped, once per [`DROP_REPORT`] in which they did.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error
+            .take()
+            .or_else(|| self.sinks.take_error())
+            .or_else(|| self.hooks.take_error())
+            .or_else(|| self.take_drops())
+    }
+
+    fn take_drops(&mut self) -> Option<String> {
+        let now = Instant::now();
+        let since = now.duration_since(*self.drop_period.get_or_insert(now));
+        if since < DROP_REPORT {
+            return None;
+        }
+        self.drop_period = Some(now);
+        let mut dropped = self.sinks.dropped();
+        dropped.extend(self.hooks.dropped());
+        if let Some(http) = &self.http {
+            dropped.push(("HTTP clients", http.dropped()));
+        }
+        if let Some(grpc) = &self.grpc {
+            dropped.push(("gRPC clients", grpc.dropped()));
+        }
+        let drops: Vec<String> = dropped
+            .into_iter()
+            .filter_map(|(name, dropped)| {
+                let reported = self.reported.insert(name, dropped).unwrap_or(0);
+                (dropped > reported)
+                    .then(|| format!("{} dropped {} events", name, dropped - reported))
+            })
+            .collect();
+        if drops.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Falling behind: {} in the last {}s",
+            drops.join(", "),
+            since.as_secs()
+        ))
+    }
+
+    /// The processors results pass through, to add ones of your own.
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+
+    /// The outage a target is in, or else the last one it had since the
+    /// start.
+    pub fn last_outage(&self, target: &str) -> Option<Outage> {
+        self.tracker.last_outage(target)
+    }
+
+    pub fn alert_log(&self) -> &AlertLog {
+        &self.alert_log
+    }
+
+    pub fn silencer(&self) -> &Silencer {
+        &self.silencer
+    }
+
+    pub fn silencer_mut(&mut self) -> &mut Silencer {
+        &mut self.silencer
+    }
+
+    /// Mutes or unmutes audible alerts and returns whether they are muted now.
+    pub fn toggle_mute(&self) -> bool {
+        !self.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+}
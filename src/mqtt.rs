@@ -0,0 +1,316 @@
+//! Minimal MQTT 3.1.1 client: connect and publish at QoS 0.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Keep-alive announced to the broker, which drops clients that send
+/// nothing for one and a half times as long.
+const KEEP_ALIVE_SECS: u16 = 60;
+
+/// How long the connection may stay quiet before a PINGREQ goes out, well
+/// within the keep-alive.
+const PING_AFTER: Duration = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+
+/// How long the broker has to answer the handshake or a ping.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct ConnectOptions<'a> {
+    pub address: &'a str,
+    pub client_id: &'a str,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    /// Last will: topic and payload published (retained) by the broker when
+    /// the connection drops unexpectedly.
+    pub will: Option<(&'a str, &'a str)>,
+}
+
+/// Sends the packets of the connection; the pinger thread shares it.
+struct Link {
+    stream: TcpStream,
+    /// When the last packet was sent, `None` once the client is gone.
+    last_sent: Mutex<Option<Instant>>,
+    closed: Condvar,
+}
+
+impl Link {
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        (&self.stream).write_all(packet)?;
+        *last_sent = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Pings the broker whenever the connection has been quiet for
+    /// [`PING_AFTER`], until the client is dropped. A broker that does not
+    /// answer gets its connection shut down, which fails the next publish
+    /// so that the caller reconnects.
+    fn keep_alive(&self) {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        while let Some(sent) = *last_sent {
+            let quiet = sent.elapsed();
+            if quiet < PING_AFTER {
+                last_sent = self
+                    .closed
+                    .wait_timeout(last_sent, PING_AFTER - quiet)
+                    .unwrap()
+                    .0;
+                continue;
+            }
+            drop(last_sent);
+            if self.ping().is_err() {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                return;
+            }
+            last_sent = self.last_sent.lock().unwrap();
+        }
+    }
+
+    /// Sends a PINGREQ and waits for the PINGRESP. Nothing else arrives at
+    /// QoS 0, so the answer is the next packet read.
+    fn ping(&self) -> io::Result<()> {
+        self.send(&[0xc0, 0x00])?;
+        let mut pingresp = [0u8; 2];
+        (&self.stream).read_exact(&mut pingresp)?;
+        if pingresp != [0xd0, 0x00] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "broker did not answer with PINGRESP",
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct Client {
+    link: Arc<Link>,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        *self.link.last_sent.lock().unwrap() = None;
+        self.link.closed.notify_all();
+    }
+}
+
+fn write_remaining_length(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 5);
+    out.push(header);
+    write_remaining_length(&mut out, body.len());
+    out.extend_from_slice(body);
+    out
+}
+
+impl Client {
+    pub fn connect(options: &ConnectOptions) -> io::Result<Client> {
+        let mut stream = TcpStream::connect(options.address)?;
+        stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+        stream.set_write_timeout(Some(ANSWER_TIMEOUT))?;
+
+        let mut flags = 0x02; // clean session
+        let mut body = Vec::new();
+        write_string(&mut body, "MQTT");
+        body.push(4); // protocol level 3.1.1
+        if options.will.is_some() {
+            flags |= 0x04 | 0x20; // will, retained, QoS 0
+        }
+        if options.username.is_some() {
+            flags |= 0x80;
+        }
+        if options.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        write_string(&mut body, options.client_id);
+        if let Some((topic, message)) = options.will {
+            write_string(&mut body, topic);
+            write_string(&mut body, message);
+        }
+        if let Some(username) = options.username {
+            write_string(&mut body, username);
+        }
+        if let Some(password) = options.password {
+            write_string(&mut body, password);
+        }
+        stream.write_all(&packet(0x10, &body))?;
+
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "broker did not answer with CONNACK",
+            ));
+        }
+        match connack[3] {
+            0 => {
+                let link = Arc::new(Link {
+                    stream,
+                    last_sent: Mutex::new(Some(Instant::now())),
+                    closed: Condvar::new(),
+                });
+                let pinger = Arc::clone(&link);
+                thread::spawn(move || pinger.keep_alive());
+                Ok(Client { link })
+            }
+            1 => Err(refused("unacceptable protocol version")),
+            2 => Err(refused("client identifier rejected")),
+            3 => Err(refused("server unavailable")),
+            4 => Err(refused("bad user name or password")),
+            5 => Err(refused("not authorized")),
+            code => Err(refused(&format!("return code {}", code))),
+        }
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+        write_string(&mut body, topic);
+        body.extend_from_slice(payload);
+        let header = 0x30 | u8::from(retain);
+        self.link.send(&packet(header, &body))
+    }
+}
+
+fn refused(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!("MQTT connection refused: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// The remaining length at the start of `bytes`, and how many bytes it
+    /// took, as a broker reads it.
+    fn read_remaining_length(bytes: &[u8]) -> (usize, usize) {
+        let mut len = 0;
+        for (i, byte) in bytes.iter().enumerate().take(4) {
+            len += usize::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return (len, i + 1);
+            }
+        }
+        panic!("remaining length longer than four bytes");
+    }
+
+    #[test]
+    fn remaining_length_takes_a_byte_per_seven_bits() {
+        for (len, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (16_383, &[0xff, 0x7f]),
+            (16_384, &[0x80, 0x80, 0x01]),
+            (2_097_151, &[0xff, 0xff, 0x7f]),
+            (2_097_152, &[0x80, 0x80, 0x80, 0x01]),
+            (268_435_455, &[0xff, 0xff, 0xff, 0x7f]),
+        ] {
+            let mut buf = Vec::new();
+            write_remaining_length(&mut buf, len);
+            assert_eq!(buf, encoded, "{}", len);
+            assert_eq!(read_remaining_length(&buf), (len, encoded.len()));
+        }
+    }
+
+    #[test]
+    fn packets_carry_their_body_after_the_length() {
+        for len in [127, 128, 16_383, 16_384] {
+            let body = vec![0xab; len];
+            let packet = packet(0x30, &body);
+            assert_eq!(packet[0], 0x30);
+            let (decoded, used) = read_remaining_length(&packet[1..]);
+            assert_eq!(decoded, len);
+            assert_eq!(&packet[1 + used..], &body[..]);
+        }
+    }
+
+    #[test]
+    fn connects_and_publishes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = [0u8; 2 + 21];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+            let mut publish = [0u8; 2 + 9];
+            stream.read_exact(&mut publish).unwrap();
+            (connect, publish)
+        });
+        let mut client = Client::connect(&ConnectOptions {
+            address: &address,
+            client_id: "lat",
+            username: Some("u"),
+            password: Some("p"),
+            will: None,
+        })
+        .unwrap();
+        client.publish("a/b/c", b"up", true).unwrap();
+        let (connect, publish) = broker.join().unwrap();
+        assert_eq!(
+            connect,
+            [
+                0x10, 21, // CONNECT
+                0, 4, b'M', b'Q', b'T', b'T', 4,    // protocol 3.1.1
+                0xc2, // user name, password, clean session
+                0, 60, // keep-alive
+                0, 3, b'l', b'a', b't', // client id
+                0, 1, b'u', 0, 1, b'p',
+            ]
+        );
+        assert_eq!(
+            publish,
+            [0x31, 9, 0, 5, b'a', b'/', b'b', b'/', b'c', b'u', b'p']
+        );
+    }
+
+    #[test]
+    fn refusals_name_their_reason() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = [0u8; 2 + 12];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 0x02, 0x00, 0x05]).unwrap();
+        });
+        let error = Client::connect(&ConnectOptions {
+            address: &address,
+            client_id: "",
+            username: None,
+            password: None,
+            will: None,
+        })
+        .err()
+        .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(error.to_string().contains("not authorized"), "{}", error);
+    }
+}
@@ -0,0 +1,265 @@
+//! Probing a target through a particular network on Linux: inside a named
+//! network namespace, as created by `ip netns add`, or bound to a VRF
+//! device. Multi-homed routers can so measure every uplink separately from
+//! one instance.
+//!
+//! Probes of a target in a namespace run on a thread of their own that
+//! enters it, which needs `CAP_SYS_ADMIN`; sockets and `ping` started there
+//! belong to the namespace. Name lookups still use the resolver
+//! configuration of the host. For a VRF, sockets are bound to its device
+//! and `ping` gets `-I <device>`.
+
+use crate::probe::{ProbeError, ProbeResult};
+use std::{
+    cell::RefCell,
+    io,
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Network {
+    /// A namespace by name, in `/run/netns`, or by path, such as
+    /// `/proc/<pid>/ns/net`.
+    Namespace(String),
+    /// A VRF device.
+    Vrf(String),
+}
+
+/// The network of some targets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkRoute {
+    pub targets: Vec<String>,
+    pub network: Network,
+}
+
+/// The network of the target named `name`: that of the first route
+/// listing it.
+pub fn for_target<'a>(routes: &'a [NetworkRoute], name: &str) -> Option<&'a Network> {
+    routes
+        .iter()
+        .find(|r| r.targets.iter().any(|t| t == name))
+        .map(|r| &r.network)
+}
+
+thread_local! {
+    /// The VRF device sockets of this thread are bound to.
+    static DEVICE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The device sockets opened by the current probe must be bound to.
+pub fn bound_device() -> Option<String> {
+    DEVICE.with(|device| device.borrow().clone())
+}
+
+/// Runs the blocking `probe` within `network`, if any.
+pub fn within(
+    network: Option<&Network>,
+    probe: impl FnOnce() -> ProbeResult + Send,
+) -> ProbeResult {
+    match network {
+        None => probe(),
+        Some(Network::Vrf(device)) => {
+            // Binding to a missing device fails with an error of its own,
+            // ENODEV; the target is unreachable all the same.
+            if !Path::new("/sys/class/net").join(device).exists() {
+                return Err(ProbeError::Unreachable);
+            }
+            DEVICE.with(|bound| *bound.borrow_mut() = Some(device.clone()));
+            let result = probe();
+            DEVICE.with(|bound| *bound.borrow_mut() = None);
+            result
+        }
+        Some(Network::Namespace(namespace)) => std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    sys::enter(namespace).map_err(|e| match e.kind() {
+                        io::ErrorKind::NotFound => ProbeError::Unreachable,
+                        _ => ProbeError::PermissionDenied,
+                    })?;
+                    probe()
+                })
+                .join()
+                .unwrap_or(Err(ProbeError::Timeout))
+        }),
+    }
+}
+
+/// Connects to `address` like [`TcpStream::connect_timeout`], over the
+/// [`bound_device`] if any.
+pub fn connect(address: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    match bound_device() {
+        Some(device) => sys::connect_bound(address, timeout, &device),
+        None => TcpStream::connect_timeout(address, timeout),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod sys {
+    use std::{
+        ffi::c_int,
+        fs::File,
+        io::{self, ErrorKind},
+        net::{SocketAddr, TcpStream},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+        path::PathBuf,
+        time::Duration,
+    };
+
+    /// Moves the current thread into `namespace`.
+    pub fn enter(namespace: &str) -> io::Result<()> {
+        let path = if namespace.starts_with('/') {
+            PathBuf::from(namespace)
+        } else {
+            PathBuf::from("/run/netns").join(namespace)
+        };
+        let file = File::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        // SAFETY: the descriptor is valid for the duration of the call.
+        if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Binds the socket `fd` to `device`.
+    pub fn bind_to_device(fd: c_int, device: &str) -> io::Result<()> {
+        // SAFETY: the name is valid for its length, which the kernel reads.
+        let result = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_BINDTODEVICE,
+                device.as_ptr().cast(),
+                device.len() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn connect_bound(
+        address: &SocketAddr,
+        timeout: Duration,
+        device: &str,
+    ) -> io::Result<TcpStream> {
+        let domain = match address {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        // SAFETY: a plain system call; the descriptor is owned right away.
+        let fd = unsafe {
+            libc::socket(
+                domain,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a fresh, valid descriptor nothing else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+        bind_to_device(fd, device)?;
+
+        let (storage, len) = sockaddr(address);
+        // SAFETY: storage holds a socket address of length len.
+        let result = unsafe { libc::connect(fd, (&raw const storage).cast(), len) };
+        if result != 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(e);
+            }
+            let mut poll = libc::pollfd {
+                fd,
+                events: libc::POLLOUT,
+                revents: 0,
+            };
+            let millis = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+            // SAFETY: one valid pollfd.
+            match unsafe { libc::poll(&mut poll, 1, millis) } {
+                0 => return Err(ErrorKind::TimedOut.into()),
+                n if n < 0 => return Err(io::Error::last_os_error()),
+                _ => {}
+            }
+            let mut error: c_int = 0;
+            let mut error_len = size_of::<c_int>() as libc::socklen_t;
+            // SAFETY: error is a c_int of the given length.
+            unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_ERROR,
+                    (&raw mut error).cast(),
+                    &mut error_len,
+                )
+            };
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+        }
+        let stream = TcpStream::from(socket);
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+
+    fn sockaddr(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        // SAFETY: all zeroes is a valid sockaddr_storage.
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let len = match address {
+            SocketAddr::V4(v4) => {
+                let sin = libc::sockaddr_in {
+                    sin_family: libc::AF_INET as libc::sa_family_t,
+                    sin_port: v4.port().to_be(),
+                    sin_addr: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(v4.ip().octets()),
+                    },
+                    sin_zero: [0; 8],
+                };
+                // SAFETY: sockaddr_storage is large enough for any address.
+                unsafe { (&raw mut storage).cast::<libc::sockaddr_in>().write(sin) };
+                size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(v6) => {
+                let sin6 = libc::sockaddr_in6 {
+                    sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                    sin6_port: v6.port().to_be(),
+                    sin6_flowinfo: v6.flowinfo(),
+                    sin6_addr: libc::in6_addr {
+                        s6_addr: v6.ip().octets(),
+                    },
+                    sin6_scope_id: v6.scope_id(),
+                };
+                // SAFETY: as above.
+                unsafe { (&raw mut storage).cast::<libc::sockaddr_in6>().write(sin6) };
+                size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+}
+
+/// Networks are rejected by the configuration elsewhere.
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::{
+        io,
+        net::{SocketAddr, TcpStream},
+        time::Duration,
+    };
+
+    pub fn enter(_namespace: &str) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn connect_bound(
+        _address: &SocketAddr,
+        _timeout: Duration,
+        _device: &str,
+    ) -> io::Result<TcpStream> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,264 @@
+//! The command palette `:` opens: every action of the dashboard by name,
+//! and a few commands taking arguments, fuzzy matched as they are typed so
+//! that nothing needs its key remembered.
+
+use crate::{
+    fit,
+    prompt::{Outcome, Prompt},
+    theme::Theme,
+};
+use crossterm::{
+    event::{KeyCode, KeyEvent},
+    queue,
+    style::{Attribute, Color, ResetColor, SetAttribute, SetForegroundColor},
+};
+use latencee::{
+    config::parse_duration,
+    keys::{ACTIONS, Action},
+};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// What the palette was asked to do.
+pub enum Command {
+    Action(Action),
+    /// Add the target described, as typed into the add prompt; the prompt
+    /// itself if nothing is.
+    Add(String),
+    Interval(Duration),
+    /// Silence the selected targets for that long, or another step of
+    /// `alerts.silence` as their key does.
+    Silence(Option<Duration>),
+    /// Write the history to that file, or to one in the snapshot directory.
+    ExportCsv(Option<PathBuf>),
+    /// Draw the dashboard in that theme, or in the next one.
+    Theme(Option<Theme>),
+}
+
+/// Commands taking arguments: their name, usage and description.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    (
+        "add target",
+        "HOST [NAME] [probe=KIND] [port=N]",
+        "add a target",
+    ),
+    (
+        "set interval",
+        "DURATION",
+        "probe all targets every DURATION, such as 500ms",
+    ),
+    (
+        "silence",
+        "[DURATION]",
+        "silence the alerts of the selected targets",
+    ),
+    (
+        "export csv",
+        "[FILE]",
+        "write the history shown to a CSV file",
+    ),
+    (
+        "switch theme",
+        "[NAME]",
+        "draw the dashboard in the next color theme, or in NAME",
+    ),
+];
+
+/// Actions the palette leaves out: those [`COMMANDS`] cover, and those that
+/// mean nothing there.
+const LEFT_OUT: &[Action] = &[Action::Add, Action::Silence, Action::Palette, Action::Back];
+
+struct Entry {
+    name: String,
+    usage: &'static str,
+    description: &'static str,
+    /// `None` for the commands taking arguments.
+    action: Option<Action>,
+}
+
+pub struct Palette {
+    prompt: Prompt,
+    entries: Vec<Entry>,
+    /// Which of the matches Enter picks.
+    chosen: usize,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        let commands = COMMANDS.iter().map(|(name, usage, description)| Entry {
+            name: name.to_string(),
+            usage,
+            description,
+            action: None,
+        });
+        let actions = ACTIONS
+            .iter()
+            .filter(|(action, _, _)| !LEFT_OUT.contains(action))
+            .map(|(action, name, _)| Entry {
+                name: name.replace('_', " "),
+                usage: "",
+                description: action.description(),
+                action: Some(*action),
+            });
+        Palette {
+            prompt: Prompt::new(":", ""),
+            entries: commands.chain(actions).collect(),
+            chosen: 0,
+        }
+    }
+
+    /// Up and Down choose among the matches and Tab completes the chosen
+    /// one; other keys edit the text.
+    pub fn handle(&mut self, key: KeyEvent) -> Outcome {
+        let matches = self.matches().len();
+        match key.code {
+            KeyCode::Up => self.chosen = self.chosen.saturating_sub(1),
+            KeyCode::Down => self.chosen = (self.chosen + 1).min(matches.saturating_sub(1)),
+            KeyCode::Tab => {
+                if let Some(entry) = self.matches().get(self.chosen) {
+                    let text = match entry.usage {
+                        "" => entry.name.clone(),
+                        _ => format!("{} ", entry.name),
+                    };
+                    self.prompt = Prompt::new(":", &text);
+                    self.chosen = 0;
+                }
+            }
+            _ => {
+                let outcome = self.prompt.handle(key);
+                self.chosen = 0;
+                return outcome;
+            }
+        }
+        Outcome::Editing
+    }
+
+    /// The entry named at the start of the text and its arguments, if the
+    /// text goes on past a name.
+    fn named(&self) -> Option<(&Entry, String)> {
+        let text = self.prompt.text();
+        let text = text.trim_start();
+        self.entries.iter().find_map(|entry| {
+            let rest = text.strip_prefix(&entry.name)?;
+            (rest.is_empty() || rest.starts_with(' ')).then(|| (entry, rest.trim().to_string()))
+        })
+    }
+
+    /// The entries matching the text, best first.
+    fn matches(&self) -> Vec<&Entry> {
+        if let Some((entry, arguments)) = self.named()
+            && !arguments.is_empty()
+        {
+            return vec![entry];
+        }
+        let query = self.prompt.text();
+        let mut scored: Vec<(usize, usize, &Entry)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((score(&query, &entry.name)?, i, entry)))
+            .collect();
+        scored.sort_by_key(|(score, i, _)| (*score, *i));
+        scored.into_iter().map(|(_, _, entry)| entry).collect()
+    }
+
+    /// What Enter asks for: the entry named with its arguments, or else
+    /// the chosen match.
+    pub fn command(&self) -> Result<Command, String> {
+        let (entry, arguments) = match self.named() {
+            Some(named) => named,
+            None => match self.matches().get(self.chosen) {
+                Some(entry) => (*entry, String::new()),
+                None => return Err(format!("No command matches {:?}", self.prompt.text())),
+            },
+        };
+        if let Some(action) = entry.action {
+            if !arguments.is_empty() {
+                return Err(format!("{} takes no arguments", entry.name));
+            }
+            return Ok(Command::Action(action));
+        }
+        let arguments = arguments.as_str();
+        let duration =
+            |text: &str| parse_duration(text).ok_or_else(|| format!("Invalid duration {:?}", text));
+        match entry.name.as_str() {
+            "add target" => Ok(Command::Add(arguments.to_string())),
+            "set interval" if arguments.is_empty() => {
+                Err("set interval needs a duration, such as 1s".to_string())
+            }
+            "set interval" => Ok(Command::Interval(duration(arguments)?)),
+            "silence" if arguments.is_empty() => Ok(Command::Silence(None)),
+            "silence" => Ok(Command::Silence(Some(duration(arguments)?))),
+            "switch theme" if arguments.is_empty() => Ok(Command::Theme(None)),
+            "switch theme" => match Theme::named(arguments) {
+                Some(theme) => Ok(Command::Theme(Some(theme))),
+                None => Err(format!(
+                    "No theme {:?}; there are {}",
+                    arguments,
+                    Theme::names().join(", ")
+                )),
+            },
+            // "export csv"
+            _ => Ok(Command::ExportCsv(
+                (!arguments.is_empty()).then(|| PathBuf::from(arguments)),
+            )),
+        }
+    }
+
+    pub fn draw(&self, line: &mut Vec<u8>, columns: usize) -> io::Result<()> {
+        self.prompt.draw(line, columns)
+    }
+
+    /// The chosen match, with its usage and description, followed by the
+    /// names of the others as far as they fit.
+    pub fn draw_matches(&self, line: &mut Vec<u8>, columns: usize) -> io::Result<()> {
+        let matches = self.matches();
+        let Some(chosen) = matches.get(self.chosen) else {
+            queue!(line, SetForegroundColor(Color::DarkGrey))?;
+            write!(line, "No command matches")?;
+            return queue!(line, ResetColor);
+        };
+        let mut shown = format!("{} {}", chosen.name, chosen.usage);
+        shown = format!("{}: {}", shown.trim_end(), chosen.description);
+        let mut room = columns.saturating_sub(shown.chars().count());
+        queue!(line, SetAttribute(Attribute::Reverse))?;
+        write!(line, "{}", fit(&shown, columns))?;
+        queue!(line, SetAttribute(Attribute::NoReverse))?;
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        for entry in matches.iter().skip(self.chosen + 1) {
+            let width = entry.name.chars().count() + 2;
+            if width > room {
+                break;
+            }
+            write!(line, "  {}", entry.name)?;
+            room -= width;
+        }
+        queue!(line, ResetColor)
+    }
+}
+
+/// How well `query` matches `name`: all its characters, spaces aside,
+/// appear in `name` in order. Lower is better: matches early on, at word
+/// starts and in runs score best. `None` if it does not match.
+fn score(query: &str, name: &str) -> Option<usize> {
+    let name: Vec<char> = name.chars().collect();
+    let mut at = 0;
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_ascii_lowercase();
+        let found = at + name[at..].iter().position(|c| *c == wanted)?;
+        let word_start = found == 0 || name[found - 1] == ' ';
+        score += match previous {
+            Some(previous) if previous + 1 == found => 0,
+            _ if word_start => 1,
+            _ => 2 + found - at,
+        };
+        previous = Some(found);
+        at = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,471 @@
+//! Minimal Apache Parquet writer.
+//!
+//! Writes a single row group with one uncompressed, PLAIN encoded data page
+//! per column. That is all an export needs and keeps us free of the arrow
+//! dependency tree. File metadata is serialized with the Thrift compact
+//! protocol as required by the format specification.
+
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"PAR1";
+
+// parquet.thrift enum values
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REPETITION_REQUIRED: i32 = 0;
+const REPETITION_OPTIONAL: i32 = 1;
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MILLIS: i32 = 9;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_DATA: i32 = 0;
+
+/// Values of one column. Every column of a file must have the same length.
+pub enum ColumnData {
+    /// Milliseconds since the Unix epoch, UTC.
+    TimestampMillis(Vec<i64>),
+    String(Vec<String>),
+    OptionalDouble(Vec<Option<f64>>),
+}
+
+impl ColumnData {
+    fn len(&self) -> usize {
+        match self {
+            ColumnData::TimestampMillis(v) => v.len(),
+            ColumnData::String(v) => v.len(),
+            ColumnData::OptionalDouble(v) => v.len(),
+        }
+    }
+
+    fn physical_type(&self) -> i32 {
+        match self {
+            ColumnData::TimestampMillis(_) => TYPE_INT64,
+            ColumnData::String(_) => TYPE_BYTE_ARRAY,
+            ColumnData::OptionalDouble(_) => TYPE_DOUBLE,
+        }
+    }
+
+    fn repetition(&self) -> i32 {
+        match self {
+            ColumnData::OptionalDouble(_) => REPETITION_OPTIONAL,
+            _ => REPETITION_REQUIRED,
+        }
+    }
+
+    /// Encodes definition levels (if any) followed by the PLAIN values.
+    fn encode_page(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            ColumnData::TimestampMillis(values) => {
+                for v in values {
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            ColumnData::String(values) => {
+                for v in values {
+                    out.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                    out.extend_from_slice(v.as_bytes());
+                }
+            }
+            ColumnData::OptionalDouble(values) => {
+                let levels = encode_levels(values.iter().map(|v| v.is_some()));
+                out.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+                out.extend_from_slice(&levels);
+                for v in values.iter().flatten() {
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+}
+
+pub struct Column {
+    pub name: &'static str,
+    pub data: ColumnData,
+}
+
+/// Definition levels with bit width 1, as RLE runs of the hybrid encoding.
+fn encode_levels(defined: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut run: Option<(bool, u64)> = None;
+    let flush = |value: bool, len: u64, out: &mut Vec<u8>| {
+        write_varint(out, len << 1);
+        out.push(value as u8);
+    };
+    for d in defined {
+        run = match run {
+            Some((value, len)) if value == d => Some((value, len + 1)),
+            Some((value, len)) => {
+                flush(value, len, &mut out);
+                Some((d, 1))
+            }
+            None => Some((d, 1)),
+        };
+    }
+    if let Some((value, len)) = run {
+        flush(value, len, &mut out);
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Thrift compact protocol encoder, just enough for Parquet metadata.
+struct Compact {
+    out: Vec<u8>,
+    last_field: Vec<i16>,
+}
+
+const CT_TRUE: u8 = 1;
+const CT_FALSE: u8 = 2;
+const CT_I32: u8 = 5;
+const CT_I64: u8 = 6;
+const CT_BINARY: u8 = 8;
+const CT_LIST: u8 = 9;
+const CT_STRUCT: u8 = 12;
+
+impl Compact {
+    fn new() -> Self {
+        Compact {
+            out: Vec::new(),
+            last_field: vec![0],
+        }
+    }
+
+    fn field(&mut self, id: i16, ty: u8) {
+        let last = self.last_field.last_mut().expect("inside a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.out.push(((delta as u8) << 4) | ty);
+        } else {
+            self.out.push(ty);
+            write_varint(&mut self.out, zigzag(id as i64));
+        }
+        *last = id;
+    }
+
+    fn begin_struct(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.out.push(0);
+        self.last_field.pop();
+    }
+
+    fn struct_field(&mut self, id: i16) {
+        self.field(id, CT_STRUCT);
+        self.begin_struct();
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.field(id, if value { CT_TRUE } else { CT_FALSE });
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, CT_I32);
+        write_varint(&mut self.out, zigzag(value as i64));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, CT_I64);
+        write_varint(&mut self.out, zigzag(value));
+    }
+
+    fn string(&mut self, id: i16, value: &str) {
+        self.field(id, CT_BINARY);
+        self.binary(value.as_bytes());
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        write_varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value);
+    }
+
+    fn list_header(&mut self, id: i16, elem: u8, len: usize) {
+        self.field(id, CT_LIST);
+        if len < 15 {
+            self.out.push(((len as u8) << 4) | elem);
+        } else {
+            self.out.push(0xf0 | elem);
+            write_varint(&mut self.out, len as u64);
+        }
+    }
+
+    fn i32_list(&mut self, id: i16, values: &[i32]) {
+        self.list_header(id, CT_I32, values.len());
+        for v in values {
+            write_varint(&mut self.out, zigzag(*v as i64));
+        }
+    }
+
+    fn string_list(&mut self, id: i16, values: &[&str]) {
+        self.list_header(id, CT_BINARY, values.len());
+        for v in values {
+            self.binary(v.as_bytes());
+        }
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn page_header(num_values: usize, size: usize) -> Vec<u8> {
+    let mut t = Compact::new();
+    t.i32(1, PAGE_DATA);
+    t.i32(2, size as i32);
+    t.i32(3, size as i32);
+    t.struct_field(5);
+    t.i32(1, num_values as i32);
+    t.i32(2, ENCODING_PLAIN);
+    t.i32(3, ENCODING_RLE);
+    t.i32(4, ENCODING_RLE);
+    t.end_struct();
+    t.out.push(0);
+    t.out
+}
+
+struct ChunkInfo {
+    offset: u64,
+    size: u64,
+}
+
+fn write_schema_element(t: &mut Compact, column: &Column) {
+    t.begin_struct();
+    t.i32(1, column.data.physical_type());
+    t.i32(3, column.data.repetition());
+    t.string(4, column.name);
+    match column.data {
+        ColumnData::TimestampMillis(_) => {
+            t.i32(6, CONVERTED_TIMESTAMP_MILLIS);
+            // LogicalType { TIMESTAMP: { isAdjustedToUTC: true, unit: { MILLIS: {} } } }
+            t.struct_field(10);
+            t.struct_field(8);
+            t.bool(1, true);
+            t.struct_field(2);
+            t.struct_field(1);
+            t.end_struct();
+            t.end_struct();
+            t.end_struct();
+            t.end_struct();
+        }
+        ColumnData::String(_) => {
+            t.i32(6, CONVERTED_UTF8);
+            // LogicalType { STRING: {} }
+            t.struct_field(10);
+            t.struct_field(1);
+            t.end_struct();
+            t.end_struct();
+        }
+        ColumnData::OptionalDouble(_) => {}
+    }
+    t.end_struct();
+}
+
+fn file_metadata(columns: &[Column], chunks: &[ChunkInfo], num_rows: usize) -> Vec<u8> {
+    let mut t = Compact::new();
+    t.i32(1, 1);
+
+    t.list_header(2, CT_STRUCT, columns.len() + 1);
+    t.begin_struct();
+    t.string(4, "schema");
+    t.i32(5, columns.len() as i32);
+    t.end_struct();
+    for column in columns {
+        write_schema_element(&mut t, column);
+    }
+
+    t.i64(3, num_rows as i64);
+
+    t.list_header(4, CT_STRUCT, 1);
+    t.begin_struct();
+    t.list_header(1, CT_STRUCT, columns.len());
+    for (column, chunk) in columns.iter().zip(chunks) {
+        t.begin_struct();
+        t.i64(2, chunk.offset as i64);
+        t.struct_field(3);
+        t.i32(1, column.data.physical_type());
+        t.i32_list(2, &[ENCODING_PLAIN, ENCODING_RLE]);
+        t.string_list(3, &[column.name]);
+        t.i32(4, CODEC_UNCOMPRESSED);
+        t.i64(5, column.data.len() as i64);
+        t.i64(6, chunk.size as i64);
+        t.i64(7, chunk.size as i64);
+        t.i64(9, chunk.offset as i64);
+        t.end_struct();
+        t.end_struct();
+    }
+    t.i64(2, chunks.iter().map(|c| c.size as i64).sum());
+    t.i64(3, num_rows as i64);
+    t.end_struct();
+
+    t.string(6, concat!("latencee version ", env!("CARGO_PKG_VERSION")));
+    t.out.push(0);
+    t.out
+}
+
+/// Writes `columns` as a complete Parquet file.
+pub fn write<W: Write>(out: &mut W, columns: &[Column]) -> io::Result<()> {
+    let num_rows = columns.first().map_or(0, |c| c.data.len());
+    if columns.iter().any(|c| c.data.len() != num_rows) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "all parquet columns must have the same length",
+        ));
+    }
+
+    out.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as u64;
+    let mut chunks = Vec::with_capacity(columns.len());
+
+    for column in columns {
+        let page = column.data.encode_page();
+        let header = page_header(column.data.len(), page.len());
+        out.write_all(&header)?;
+        out.write_all(&page)?;
+        let size = (header.len() + page.len()) as u64;
+        chunks.push(ChunkInfo { offset, size });
+        offset += size;
+    }
+
+    let footer = file_metadata(columns, &chunks, num_rows);
+    out.write_all(&footer)?;
+    out.write_all(&(footer.len() as u32).to_le_bytes())?;
+    out.write_all(MAGIC)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_header_describes_a_plain_data_page() {
+        assert_eq!(
+            page_header(2, 16),
+            [
+                0x15, 0x00, // type: DATA_PAGE
+                0x15, 0x20, // uncompressed_page_size: 16
+                0x15, 0x20, // compressed_page_size: 16
+                0x2c, // data_page_header
+                0x15, 0x04, // num_values: 2
+                0x15, 0x00, // encoding: PLAIN
+                0x15, 0x06, // definition_level_encoding: RLE
+                0x15, 0x06, // repetition_level_encoding: RLE
+                0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_values_follow_their_definition_levels() {
+        let page = ColumnData::OptionalDouble(vec![Some(1.5), None, None]).encode_page();
+        let mut expected = vec![
+            4, 0, 0, 0, // length of the levels
+            0x02, 0x01, // one defined value
+            0x04, 0x00, // two nulls
+        ];
+        expected.extend_from_slice(&1.5f64.to_le_bytes());
+        assert_eq!(page, expected);
+    }
+
+    #[test]
+    fn writes_pages_schema_and_footer() {
+        let columns = [
+            Column {
+                name: "s",
+                data: ColumnData::String(vec!["a".to_string()]),
+            },
+            Column {
+                name: "d",
+                data: ColumnData::OptionalDouble(vec![None]),
+            },
+        ];
+        let mut file = Vec::new();
+        write(&mut file, &columns).unwrap();
+
+        let page = |size: u8| {
+            [
+                0x15, 0x00, 0x15, size, 0x15, size, 0x2c, 0x15, 0x02, 0x15, 0x00, 0x15, 0x06, 0x15,
+                0x06, 0x00, 0x00,
+            ]
+        };
+        let mut expected = b"PAR1".to_vec();
+        // "s" at offset 4: 17 header bytes and a 5 byte page.
+        expected.extend_from_slice(&page(0x0a));
+        expected.extend_from_slice(&[1, 0, 0, 0, b'a']);
+        // "d" at offset 26: 17 header bytes and a 6 byte page of one null.
+        expected.extend_from_slice(&page(0x0c));
+        expected.extend_from_slice(&[2, 0, 0, 0, 0x02, 0x00]);
+
+        let mut footer = vec![
+            0x15, 0x02, // version: 1
+            0x19, 0x3c, // schema: 3 elements
+            0x48, 6, b's', b'c', b'h', b'e', b'm', b'a', // name
+            0x15, 0x04, // num_children: 2
+            0x00, //
+            0x15, 0x0c, // type: BYTE_ARRAY
+            0x25, 0x00, // repetition_type: REQUIRED
+            0x18, 1, b's', // name
+            0x25, 0x00, // converted_type: UTF8
+            0x4c, 0x1c, 0x00, 0x00, // logicalType: STRING
+            0x00, //
+            0x15, 0x0a, // type: DOUBLE
+            0x25, 0x02, // repetition_type: OPTIONAL
+            0x18, 1, b'd', // name
+            0x00, //
+            0x16, 0x02, // num_rows: 1
+            0x19, 0x1c, // row_groups: 1
+            0x19, 0x2c, // columns: 2
+            0x26, 0x08, // file_offset: 4
+            0x1c, // meta_data
+            0x15, 0x0c, // type: BYTE_ARRAY
+            0x19, 0x25, 0x00, 0x06, // encodings: PLAIN, RLE
+            0x19, 0x18, 1, b's', // path_in_schema
+            0x15, 0x00, // codec: UNCOMPRESSED
+            0x16, 0x02, // num_values: 1
+            0x16, 0x2c, // total_uncompressed_size: 22
+            0x16, 0x2c, // total_compressed_size: 22
+            0x26, 0x08, // data_page_offset: 4
+            0x00, 0x00, //
+            0x26, 0x34, // file_offset: 26
+            0x1c, //
+            0x15, 0x0a, // type: DOUBLE
+            0x19, 0x25, 0x00, 0x06, //
+            0x19, 0x18, 1, b'd', //
+            0x15, 0x00, //
+            0x16, 0x02, //
+            0x16, 0x2e, // total_uncompressed_size: 23
+            0x16, 0x2e, //
+            0x26, 0x34, // data_page_offset: 26
+            0x00, 0x00, //
+            0x16, 0x5a, // total_byte_size: 45
+            0x16, 0x02, // num_rows: 1
+            0x00,
+        ];
+        let created_by = concat!("latencee version ", env!("CARGO_PKG_VERSION"));
+        footer.push(0x28);
+        footer.push(created_by.len() as u8);
+        footer.extend_from_slice(created_by.as_bytes());
+        footer.push(0x00);
+
+        expected.extend_from_slice(&footer);
+        expected.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+        expected.extend_from_slice(b"PAR1");
+        assert_eq!(file, expected);
+    }
+}
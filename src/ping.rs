@@ -0,0 +1,343 @@
+//! The `ping` probe: one long-running system `ping` per target, sending an
+//! echo request every interval, instead of a new process per sample.
+//!
+//! Each reply line ping prints is a sample. Lost replies print nothing on
+//! most platforms, so they are told by gaps in the sequence numbers and, if
+//! ping falls silent altogether, by the lack of a reply within the interval
+//! plus [`PING_TIMEOUT`]. A ping that exits, e.g. because the host name
+//! does not resolve, is started again on the next probe, as is one whose
+//! target is now probed at another interval.
+//!
+//! The same ping can run on another machine, started through `ssh` with a
+//! [`Jump`], to measure targets as that machine sees them.
+
+use crate::{
+    Target, network,
+    probe::{
+        PING_TIMEOUT, Probe, ProbeError, ProbeFuture, ProbeResult, classify_ping_failure,
+        parse_rtt, ping_command,
+    },
+    resolve, rt,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, ErrorKind, Read},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Samples kept when the prober falls behind ping; older ones are dropped.
+const BACKLOG: usize = 2;
+
+/// A machine targets are also pinged from, through ssh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    /// Prefix of the names of the targets pinged from there.
+    pub name: String,
+    /// Destination as given to ssh, e.g. `admin@vps.example.com` or a host
+    /// from `~/.ssh/config`.
+    pub host: String,
+    /// Names of the targets pinged from there; all when empty.
+    pub targets: Vec<String>,
+    /// Path or name of the ssh binary.
+    pub ssh: String,
+}
+
+impl Jump {
+    /// A ping of `host` every `interval` on the remote machine, which must
+    /// not ask for a password. The remote ping is assumed to be a Unix one.
+    fn command(&self, host: &str, interval: Duration) -> Command {
+        let mut command = Command::new(&self.ssh);
+        command
+            .args(["-T", "-o", "BatchMode=yes", "-o", "ServerAliveInterval=10"])
+            .arg(&self.host)
+            .arg("--")
+            .arg(format!(
+                "LC_ALL=C ping -n -i {} '{}'",
+                interval.as_secs_f64(),
+                host.replace('\'', "'\\''")
+            ));
+        command
+    }
+}
+
+/// Probes with a persistent system ping per target.
+pub struct Ping {
+    interval: Duration,
+    /// Where ping runs; `None` for here.
+    jump: Option<Jump>,
+    streams: Mutex<HashMap<String, Arc<Stream>>>,
+    /// The intervals of targets probed at another than `interval`, by name.
+    intervals: Mutex<HashMap<String, Duration>>,
+}
+
+impl Ping {
+    /// Pings every `interval`, which should be the probers' interval,
+    /// until told otherwise with [`Probe::set_interval`].
+    pub fn new(interval: Duration) -> Self {
+        Ping {
+            interval,
+            jump: None,
+            streams: Mutex::new(HashMap::new()),
+            intervals: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Pings on the machine `jump` leads to instead of here.
+    pub fn through(jump: Jump, interval: Duration) -> Self {
+        Ping {
+            jump: Some(jump),
+            ..Ping::new(interval)
+        }
+    }
+
+    /// How often `target` is pinged.
+    fn interval(&self, target: &Target) -> Duration {
+        let intervals = self.intervals.lock().unwrap();
+        intervals
+            .get(&target.name)
+            .copied()
+            .unwrap_or(self.interval)
+    }
+
+    /// The stream of `target`, started if needed, or again when its address
+    /// or interval changed. Streams of targets no longer probed are stopped.
+    fn stream(&self, target: &Target) -> Result<Arc<Stream>, ProbeError> {
+        let address = match self.jump {
+            // Resolved by the remote machine, as it sees the name.
+            Some(_) => target.host.clone(),
+            None => resolve::lookup(&target.host)?,
+        };
+        let interval = self.interval(target);
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|name, stream| {
+            name == &target.name || stream.used().elapsed() < stream.interval * 5 + PING_TIMEOUT
+        });
+        if let Some(stream) = streams.get(&target.name)
+            && stream.address == address
+            && stream.interval == interval
+            && stream.is_running()
+        {
+            return Ok(Arc::clone(stream));
+        }
+        streams.remove(&target.name);
+        let command = match &self.jump {
+            Some(jump) => jump.command(&address, interval),
+            None => ping_command(&address, Some(interval)),
+        };
+        let stream = Arc::new(Stream::start(target, address, interval, command)?);
+        streams.insert(target.name.clone(), Arc::clone(&stream));
+        Ok(stream)
+    }
+}
+
+impl Probe for Ping {
+    fn kind(&self) -> &'static str {
+        match self.jump {
+            Some(_) => "ssh",
+            None => "ping",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.jump {
+            Some(_) => "one long-running ping per target on another machine, through ssh",
+            None => "one long-running system ping per target",
+        }
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let stream = self.stream(target);
+        let deadline = self.interval(target) + PING_TIMEOUT;
+        Box::pin(rt::unblock(move || stream?.next(deadline)))
+    }
+
+    fn set_interval(&self, target: &Target, interval: Duration) {
+        let mut intervals = self.intervals.lock().unwrap();
+        if interval == self.interval {
+            intervals.remove(&target.name);
+        } else {
+            intervals.insert(target.name.clone(), interval);
+        }
+    }
+}
+
+/// A running ping and the samples read from it.
+struct Stream {
+    address: String,
+    interval: Duration,
+    child: Mutex<Child>,
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+#[derive(Default)]
+struct State {
+    samples: VecDeque<ProbeResult>,
+    /// Set once ping exited, with why.
+    exited: Option<ProbeError>,
+    /// Losses already reported for lack of a reply, which a later gap in
+    /// the sequence numbers must not count again.
+    timed_out: u64,
+    used: Option<Instant>,
+}
+
+impl Stream {
+    fn start(
+        target: &Target,
+        address: String,
+        interval: Duration,
+        mut command: Command,
+    ) -> Result<Self, ProbeError> {
+        let mut child = None;
+        // Started within the target's network, ping stays in it.
+        network::within(target.network.as_ref(), || {
+            child = Some(
+                command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| match e.kind() {
+                        ErrorKind::NotFound => ProbeError::BinaryMissing,
+                        ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+                        _ => ProbeError::Timeout,
+                    })?,
+            );
+            Ok(Duration::ZERO)
+        })?;
+        let mut child = child.ok_or(ProbeError::Timeout)?;
+
+        let state = Arc::new((Mutex::new(State::default()), Condvar::new()));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let shared = Arc::clone(&state);
+        std::thread::spawn(move || {
+            let (lock, ready) = &*shared;
+            let mut reader = Reader::default();
+            let mut text = String::new();
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                let mut state = lock.lock().unwrap();
+                for result in reader.read(&line, &mut state.timed_out) {
+                    state.samples.push_back(result);
+                }
+                while state.samples.len() > BACKLOG {
+                    state.samples.pop_front();
+                }
+                ready.notify_all();
+                text.push_str(&line);
+                text.push('\n');
+            }
+            let _ = stderr.read_to_string(&mut text);
+            lock.lock().unwrap().exited = Some(classify_ping_failure(&text));
+            ready.notify_all();
+        });
+
+        Ok(Stream {
+            address,
+            interval,
+            child: Mutex::new(child),
+            state,
+        })
+    }
+
+    /// The next sample, waiting up to `deadline` for it.
+    fn next(&self, deadline: Duration) -> ProbeResult {
+        let (lock, ready) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.used = Some(Instant::now());
+        let (mut state, _) = ready
+            .wait_timeout_while(state, deadline, |s| {
+                s.samples.is_empty() && s.exited.is_none()
+            })
+            .unwrap();
+        if let Some(result) = state.samples.pop_front() {
+            return result;
+        }
+        if let Some(error) = state.exited {
+            return Err(error);
+        }
+        state.timed_out += 1;
+        Err(ProbeError::Timeout)
+    }
+
+    fn used(&self) -> Instant {
+        let state = self.state.0.lock().unwrap();
+        state.used.unwrap_or_else(Instant::now)
+    }
+
+    fn is_running(&self) -> bool {
+        self.state.0.lock().unwrap().exited.is_none()
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let child = self.child.get_mut().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Turns ping's output into samples, line by line.
+#[derive(Default)]
+struct Reader {
+    /// The sequence number expected next.
+    next_seq: Option<u64>,
+    /// Whether the header line was read, which Windows' ping starts with.
+    header: bool,
+}
+
+impl Reader {
+    fn read(&mut self, line: &str, timed_out: &mut u64) -> Vec<ProbeResult> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Vec::new();
+        }
+        let lower = line.to_ascii_lowercase();
+        let seq = sequence(&lower);
+        // Windows prints a line per request, but no sequence numbers.
+        let per_request = seq.is_some() || (cfg!(windows) && self.header);
+        self.header = true;
+        let result = if lower.contains("ttl") {
+            match parse_rtt(line) {
+                Some((rtt, _)) => Ok(rtt),
+                None => return Vec::new(),
+            }
+        } else if per_request {
+            Err(classify_ping_failure(line))
+        } else {
+            // The header, and the statistics when ping stops.
+            return Vec::new();
+        };
+
+        let mut results = Vec::new();
+        if let Some(seq) = seq {
+            if let Some(expected) = self.next_seq
+                && seq > expected
+            {
+                let missed = (seq - expected).saturating_sub(*timed_out);
+                results.extend((0..missed).map(|_| Err(ProbeError::Timeout)));
+            }
+            // A late reply leaves the expected number alone.
+            if self.next_seq.is_none_or(|expected| seq >= expected) {
+                self.next_seq = Some(seq + 1);
+            }
+        }
+        *timed_out = 0;
+        results.push(result);
+        results
+    }
+}
+
+/// The sequence number on a per-request line: `icmp_seq=3`, or `seq=3` in
+/// BusyBox.
+fn sequence(line: &str) -> Option<u64> {
+    let at = line.find("seq=")? + "seq=".len();
+    let digits = line[at..]
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .unwrap_or("");
+    digits.parse().ok()
+}
@@ -0,0 +1,159 @@
+//! The processing stage between probes and outputs.
+//!
+//! Every probe result flows probe → processors → outputs. Processors are
+//! configured as `[[processor]]` sections and run in file order; each one
+//! may rewrite the latency or the status of a result before it is stored,
+//! drawn, evaluated by alert rules and sent to the outputs.
+
+use crate::{ConnectionStatus, ServerStatus, classify_latency};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+pub trait Processor: Send {
+    fn name(&self) -> &'static str;
+
+    fn process(&mut self, status: &mut ServerStatus);
+}
+
+/// A configured processor, see [`Pipeline::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    /// Replaces each latency with the mean of the last `samples` replies.
+    Smooth {
+        samples: usize,
+        targets: Vec<String>,
+    },
+    /// Classifies latencies with custom upper bounds for good, fair and
+    /// poor; anything slower counts as a timeout.
+    Classify {
+        good: Duration,
+        fair: Duration,
+        poor: Duration,
+        targets: Vec<String>,
+    },
+}
+
+/// Moving average over the recent replies of each target. Lost probes stay
+/// lost and do not enter the average.
+pub struct Smooth {
+    samples: usize,
+    recent: HashMap<Arc<str>, VecDeque<Duration>>,
+}
+
+impl Smooth {
+    pub fn new(samples: usize) -> Self {
+        Smooth {
+            samples: samples.max(1),
+            recent: HashMap::new(),
+        }
+    }
+}
+
+impl Processor for Smooth {
+    fn name(&self) -> &'static str {
+        "smooth"
+    }
+
+    fn process(&mut self, status: &mut ServerStatus) {
+        let Some(latency) = status.latency else {
+            return;
+        };
+        let recent = self.recent.entry(Arc::clone(&status.name)).or_default();
+        if recent.len() == self.samples {
+            recent.pop_front();
+        }
+        recent.push_back(latency);
+        let mean = recent.iter().sum::<Duration>() / recent.len() as u32;
+        status.latency = Some(mean);
+        status.status = classify_latency(Some(mean));
+    }
+}
+
+/// Classification with thresholds of its own, e.g. tighter ones for a LAN
+/// gateway than for a server abroad.
+pub struct Classify {
+    bounds: [Duration; 3],
+}
+
+impl Classify {
+    pub fn new(good: Duration, fair: Duration, poor: Duration) -> Self {
+        Classify {
+            bounds: [good, fair, poor],
+        }
+    }
+}
+
+impl Processor for Classify {
+    fn name(&self) -> &'static str {
+        "classify"
+    }
+
+    fn process(&mut self, status: &mut ServerStatus) {
+        if let Some(latency) = status.latency {
+            let [good, fair, poor] = self.bounds;
+            status.status = if latency < good {
+                ConnectionStatus::Good
+            } else if latency < fair {
+                ConnectionStatus::Fair
+            } else if latency < poor {
+                ConnectionStatus::Poor
+            } else {
+                ConnectionStatus::Timeout
+            };
+        }
+    }
+}
+
+/// Limits a processor to some targets; empty means all.
+struct Filtered {
+    targets: Vec<String>,
+    processor: Box<dyn Processor>,
+}
+
+/// The configured processors, in order.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Filtered>,
+}
+
+impl Pipeline {
+    pub fn new(stages: &[Stage]) -> Self {
+        let mut pipeline = Pipeline::default();
+        for stage in stages {
+            match stage {
+                Stage::Smooth { samples, targets } => {
+                    pipeline.add(targets.clone(), Box::new(Smooth::new(*samples)));
+                }
+                Stage::Classify {
+                    good,
+                    fair,
+                    poor,
+                    targets,
+                } => {
+                    pipeline.add(
+                        targets.clone(),
+                        Box::new(Classify::new(*good, *fair, *poor)),
+                    );
+                }
+            }
+        }
+        pipeline
+    }
+
+    /// Appends a processor for `targets`, or for all targets when empty.
+    /// Embedders use this to plug in processors of their own.
+    pub fn add(&mut self, targets: Vec<String>, processor: Box<dyn Processor>) {
+        self.stages.push(Filtered { targets, processor });
+    }
+
+    pub fn process(&mut self, status: &mut ServerStatus) {
+        for stage in &mut self.stages {
+            if stage.targets.is_empty() || stage.targets.iter().any(|t| **t == *status.name) {
+                stage.processor.process(status);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Built-in target groups, selected with `--preset`, for comparing the
+//! latency to the regions of cloud providers and game services.
+//!
+//! Presets are maintained as TOML files in `presets/`, compiled into the
+//! binary: a `description` and the `[[target]]`s, each with a `name` and a
+//! `host`, as in the configuration. Every region is represented by the
+//! hostname of a regional service endpoint, which answers from inside that
+//! region. Such endpoints usually drop ICMP, so presets are probed with TCP
+//! connects to port 443 unless the configuration or the target picks a
+//! probe; endpoints behind anycast front ends pick the `https` probe.
+//!
+//! Services that move their servers around, such as Valve's relays and
+//! Discord's voice regions, are presets of `[[discovery]]` sections instead,
+//! which fetch the current list from the service.
+
+use crate::{Target, config, discovery::Discovery};
+use std::sync::LazyLock;
+
+/// A named group of targets.
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: String,
+    targets: Vec<Target>,
+    /// Sources of targets that change, started next to those configured.
+    pub discovery: Vec<Discovery>,
+}
+
+impl Preset {
+    /// The targets, measured by `probe` unless a target picks its own.
+    pub fn targets(&self, probe: &str) -> Vec<Target> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let mut target = target.clone();
+                target.probe.get_or_insert_with(|| probe.to_string());
+                target
+            })
+            .collect()
+    }
+}
+
+/// The probe presets are measured with unless one is configured.
+pub const PROBE: &str = "tcp";
+
+/// The preset files by name, in the order they are listed.
+const FILES: &[(&str, &str)] = &[
+    ("aws-regions", include_str!("../presets/aws-regions.toml")),
+    (
+        "azure-regions",
+        include_str!("../presets/azure-regions.toml"),
+    ),
+    ("cloudflare", include_str!("../presets/cloudflare.toml")),
+    ("gcp-regions", include_str!("../presets/gcp-regions.toml")),
+    ("valorant-eu", include_str!("../presets/valorant-eu.toml")),
+    ("valve", include_str!("../presets/valve.toml")),
+    ("discord", include_str!("../presets/discord.toml")),
+];
+
+pub static PRESETS: LazyLock<Vec<Preset>> = LazyLock::new(|| {
+    FILES
+        .iter()
+        .map(|&(name, text)| {
+            // The files ship with the binary, so a broken one is a bug.
+            let (description, targets, discovery) = config::parse_preset(text)
+                .unwrap_or_else(|e| panic!("presets/{}.toml: {}", name, e));
+            Preset {
+                name,
+                description,
+                targets,
+                discovery,
+            }
+        })
+        .collect()
+});
+
+/// The preset called `name`.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_preset_parses_and_says_what_it_is() {
+        for preset in PRESETS.iter() {
+            assert!(!preset.description.trim().is_empty(), "{}", preset.name);
+            assert!(
+                !preset.targets.is_empty() || !preset.discovery.is_empty(),
+                "{} has no targets",
+                preset.name
+            );
+            for (i, target) in preset.targets.iter().enumerate() {
+                assert!(!target.host.is_empty(), "{}: {}", preset.name, target.name);
+                assert!(
+                    preset.targets[..i].iter().all(|t| t.name != target.name),
+                    "{} names two targets {}",
+                    preset.name,
+                    target.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_file_is_a_preset() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("presets");
+        let mut files: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        let mut listed: Vec<String> = FILES
+            .iter()
+            .map(|(name, _)| format!("{}.toml", name))
+            .collect();
+        listed.sort();
+        assert_eq!(files, listed);
+    }
+
+    #[test]
+    fn targets_get_the_probe_unless_they_pick_one() {
+        for preset in PRESETS.iter() {
+            for (target, own) in preset.targets("icmp").iter().zip(&preset.targets) {
+                let expected = own.probe.as_deref().unwrap_or("icmp");
+                assert_eq!(target.probe.as_deref(), Some(expected), "{}", target.name);
+            }
+        }
+        assert_eq!(find("valve").unwrap().name, "valve");
+        assert!(find("valve.toml").is_none());
+    }
+}
@@ -0,0 +1,815 @@
+//! Probing targets and classifying latencies.
+
+use crate::{
+    history::History,
+    icmp,
+    network::{self, Network},
+    proxy::{self, ProxyRules},
+    resolve,
+    rt::{self, channel, future},
+};
+use std::{
+    fmt,
+    future::Future,
+    io::ErrorKind,
+    net::{Ipv6Addr, ToSocketAddrs},
+    pin::Pin,
+    process::{Command, Output},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A monitored host and the name it is displayed and reported under.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub host: String,
+    /// Network namespace or VRF the target is probed in, see
+    /// [`crate::network`]; `None` for the host's own.
+    pub network: Option<Network>,
+    /// Port the `tcp` probe connects to; `None` for the probe's own.
+    pub port: Option<u16>,
+    /// Kind of probe measuring the target, see [`Registry`]; `None` for
+    /// the one configured for all targets.
+    pub probe: Option<String>,
+    /// What discovery knows about the target, shown next to it, such as
+    /// how a Tailscale peer is reached.
+    pub note: Option<Note>,
+}
+
+/// What discovery tells about a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub text: String,
+    /// Whether the note points at a problem beside the latency, such as a
+    /// relayed path or a stale handshake.
+    pub warning: bool,
+}
+
+impl Note {
+    pub fn info(text: impl Into<String>) -> Self {
+        Note {
+            text: text.into(),
+            warning: false,
+        }
+    }
+
+    pub fn warning(text: impl Into<String>) -> Self {
+        Note {
+            text: text.into(),
+            warning: true,
+        }
+    }
+}
+
+impl Target {
+    pub fn new(name: impl Into<String>, host: impl Into<String>) -> Self {
+        Target {
+            name: name.into(),
+            host: host.into(),
+            network: None,
+            port: None,
+            probe: None,
+            note: None,
+        }
+    }
+}
+
+/// The targets monitored out of the box.
+pub fn default_targets() -> Vec<Target> {
+    vec![
+        Target::new("Google DNS", "8.8.8.8"),
+        Target::new("Cloudflare DNS", "1.1.1.1"),
+        Target::new("Google", "google.com"),
+        Target::new("GitHub", "github.com"),
+        Target::new("Stack Overflow", "stackoverflow.com"),
+    ]
+}
+
+/// Ordered from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionStatus {
+    Good,    // < 50ms
+    Fair,    // 50-150ms
+    Poor,    // 150-500ms
+    Timeout, // > 500ms or failed
+}
+
+impl ConnectionStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionStatus::Good => "good",
+            ConnectionStatus::Fair => "fair",
+            ConnectionStatus::Poor => "poor",
+            ConnectionStatus::Timeout => "timeout",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        [
+            ConnectionStatus::Good,
+            ConnectionStatus::Fair,
+            ConnectionStatus::Poor,
+            ConnectionStatus::Timeout,
+        ]
+        .into_iter()
+        .find(|s| s.label() == label)
+    }
+}
+
+pub fn classify_latency(latency: Option<Duration>) -> ConnectionStatus {
+    match latency {
+        Some(lat) if lat < Duration::from_millis(50) => ConnectionStatus::Good,
+        Some(lat) if lat < Duration::from_millis(150) => ConnectionStatus::Fair,
+        Some(lat) if lat < Duration::from_millis(500) => ConnectionStatus::Poor,
+        _ => ConnectionStatus::Timeout,
+    }
+}
+
+/// Why a probe got no answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProbeError {
+    /// No reply within the timeout.
+    Timeout,
+    /// The host name could not be resolved.
+    Dns,
+    /// The probe may not send, e.g. ping lacks `CAP_NET_RAW`.
+    PermissionDenied,
+    /// No route to the host.
+    Unreachable,
+    /// The tool the probe relies on is not installed.
+    BinaryMissing,
+}
+
+impl ProbeError {
+    /// Short upper-case label for the dashboard.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProbeError::Timeout => "TIMEOUT",
+            ProbeError::Dns => "DNS",
+            ProbeError::PermissionDenied => "NO PERM",
+            ProbeError::Unreachable => "UNREACH",
+            ProbeError::BinaryMissing => "NO TOOL",
+        }
+    }
+
+    /// The error called `name`, see [`ProbeError::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ProbeError::Timeout,
+            ProbeError::Dns,
+            ProbeError::PermissionDenied,
+            ProbeError::Unreachable,
+            ProbeError::BinaryMissing,
+        ]
+        .into_iter()
+        .find(|e| e.name() == name)
+    }
+
+    /// Machine-readable name, as used in the HTTP API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeError::Timeout => "timeout",
+            ProbeError::Dns => "dns",
+            ProbeError::PermissionDenied => "permission_denied",
+            ProbeError::Unreachable => "unreachable",
+            ProbeError::BinaryMissing => "binary_missing",
+        }
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProbeError::Timeout => "no reply within the timeout",
+            ProbeError::Dns => "cannot resolve the host name",
+            ProbeError::PermissionDenied => {
+                "permission denied; ping may need CAP_NET_RAW or setuid root"
+            }
+            ProbeError::Unreachable => "network or host unreachable",
+            ProbeError::BinaryMissing => "the command the probe runs is not installed",
+        })
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+/// Tells failures apart by what ping printed. A plain missing reply is a
+/// timeout.
+pub(crate) fn classify_ping_failure(output: &str) -> ProbeError {
+    let output = output.to_ascii_lowercase();
+    let mentions = |needles: &[&str]| needles.iter().any(|n| output.contains(n));
+    if mentions(&[
+        "unknown host",
+        "name or service not known",
+        "temporary failure in name resolution",
+        "cannot resolve",
+        "could not find host",
+        "no address associated",
+    ]) {
+        ProbeError::Dns
+    } else if mentions(&["operation not permitted", "permission denied"]) {
+        ProbeError::PermissionDenied
+    } else if mentions(&["unreachable", "no route to host", "host is down"]) {
+        ProbeError::Unreachable
+    } else {
+        ProbeError::Timeout
+    }
+}
+
+/// How long the system ping waits for the reply.
+pub(crate) const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One echo request to `host` with [`PING_TIMEOUT`], in the dialect of the
+/// platform's ping: the timeout flag and its unit differ everywhere. With
+/// `every`, echo requests are sent at that interval until ping is killed.
+pub(crate) fn ping_command(host: &str, every: Option<Duration>) -> Command {
+    // The BSDs keep IPv6 in ping6; FreeBSD 13 and OpenBSD link it to ping.
+    let bsd = cfg!(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    ));
+    let ping6 = bsd && host.parse::<Ipv6Addr>().is_ok();
+    let mut command = Command::new(if ping6 {
+        "ping6"
+    } else if cfg!(target_os = "android") {
+        // Termux only puts its own packages on the PATH, but Android ships
+        // an iputils ping.
+        "/system/bin/ping"
+    } else {
+        "ping"
+    });
+    let seconds = PING_TIMEOUT.as_secs().max(1).to_string();
+    if let Some(interval) = every {
+        if cfg!(windows) {
+            // Windows' ping has no interval and sends one a second.
+            command.arg("-t");
+        } else {
+            command.arg("-i").arg(interval.as_secs_f64().to_string());
+        }
+    } else if cfg!(windows) {
+        // -w is in milliseconds.
+        command.args(["-n", "1", "-w"]);
+        command.arg(PING_TIMEOUT.as_millis().to_string());
+    } else if cfg!(any(
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    )) {
+        // -W is in milliseconds in FreeBSD's ping and its descendants.
+        command.args(["-c", "1", "-W"]);
+        command.arg(PING_TIMEOUT.as_millis().to_string());
+    } else if cfg!(target_os = "openbsd") || (cfg!(target_os = "netbsd") && !ping6) {
+        // -w is the time to wait in seconds.
+        command.args(["-c", "1", "-w", &seconds]);
+    } else if cfg!(target_os = "netbsd") {
+        // NetBSD's ping6 calls it -X.
+        command.args(["-c", "1", "-X", &seconds]);
+    } else {
+        // -W is in seconds for iputils and BusyBox.
+        command.args(["-c", "1", "-W", &seconds]);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // Keeps a console window from flashing up when started from a GUI.
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(device) = network::bound_device() {
+        command.args(["-I", &device]);
+    }
+    command.arg(host);
+    command
+}
+
+pub fn ping_host(host: &str) -> ProbeResult {
+    let start = Instant::now();
+
+    let output = ping_command(host, None)
+        .output()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => ProbeError::BinaryMissing,
+            ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Timeout,
+        })?;
+
+    if !replied(&output) {
+        return Err(classify_ping_failure(&ping_text(&output)));
+    }
+    // The wall time includes starting ping, and only serves when its
+    // output cannot be read.
+    let elapsed = start.elapsed();
+    Ok(match parse_rtt(&String::from_utf8_lossy(&output.stdout)) {
+        Some((rtt, true)) => rtt.min(elapsed),
+        Some((rtt, false)) => rtt,
+        None => elapsed,
+    })
+}
+
+/// The round-trip time on ping's reply line, and whether it is only an upper
+/// bound, as in Windows' `time<1ms`.
+///
+/// Every dialect and language puts the TTL on the reply line and the time
+/// right before `ms`: `time=12.3 ms`, `Zeit=14ms`, `temps=14 ms`,
+/// `時間 =14ms`. Only the digits are read, with a decimal point or comma,
+/// so the words around them may be in any language or even garbled by a
+/// console code page.
+pub(crate) fn parse_rtt(output: &str) -> Option<(Duration, bool)> {
+    let line = output
+        .lines()
+        .find(|line| line.to_ascii_lowercase().contains("ttl"))?;
+    let mut rest = line;
+    while let Some(at) = rest.find("ms") {
+        let (before, after) = rest.split_at(at);
+        rest = &after[2..];
+        if rest.starts_with(|c: char| c.is_alphabetic()) {
+            continue;
+        }
+        let before = before.trim_end();
+        let digits = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_ascii_digit() || *c == '.' || *c == ',')
+            .last()
+            .map_or("", |(i, _)| &before[i..]);
+        let Ok(millis) = digits.replace(',', ".").parse::<f64>() else {
+            continue;
+        };
+        let bound = before[..before.len() - digits.len()].ends_with('<');
+        return Some((Duration::from_secs_f64(millis / 1000.0), bound));
+    }
+    None
+}
+
+/// Whether ping got an echo reply. Windows ping also succeeds when a router
+/// answers "destination host unreachable"; only echo replies carry a TTL,
+/// in every language.
+fn replied(output: &Output) -> bool {
+    output.status.success()
+        && (!cfg!(windows) || String::from_utf8_lossy(&output.stdout).contains("TTL="))
+}
+
+/// Everything ping printed, errors first.
+fn ping_text(output: &Output) -> String {
+    let mut text = String::from_utf8_lossy(&output.stderr).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stdout));
+    text
+}
+
+/// How the `icmp` probe reaches targets on this machine, and why.
+pub enum IcmpBackend {
+    /// Echo requests sent by latencee itself, with what.
+    Native(&'static str),
+    /// The system `ping`, as latencee cannot send echo requests itself.
+    SystemPing { why: &'static str },
+    /// Neither works: the `tcp` probe stands in.
+    Unusable { why: &'static str, ping: String },
+}
+
+/// Finds out which [`IcmpBackend`] works, pinging the loopback address
+/// with the system `ping` if needed.
+pub fn icmp_backend() -> IcmpBackend {
+    match icmp::unavailable_reason() {
+        None => IcmpBackend::Native(icmp::backend().unwrap_or("ICMP sockets")),
+        Some(why) => match diagnose_ping() {
+            None => IcmpBackend::SystemPing { why },
+            Some(ping) => IcmpBackend::Unusable { why, ping },
+        },
+    }
+}
+
+impl fmt::Display for IcmpBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcmpBackend::Native(with) => write!(f, "ICMP echo over {}", with),
+            IcmpBackend::SystemPing { why } => {
+                write!(f, "ICMP echo via the system ping, as {}", why)
+            }
+            IcmpBackend::Unusable { why, ping } => write!(f, "{} and {}", why, ping),
+        }
+    }
+}
+
+/// Why the system ping cannot be used for probing, found by pinging the
+/// loopback address; `None` when it works.
+pub fn diagnose_ping() -> Option<String> {
+    let output = match ping_command("127.0.0.1", None).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Some("the ping command is not installed".to_string());
+        }
+        Err(e) => return Some(format!("cannot run ping: {}", e)),
+    };
+    if replied(&output) {
+        return None;
+    }
+    let text = ping_text(&output);
+    let lower = text.to_ascii_lowercase();
+    Some(
+        if [
+            "usage",
+            "invalid option",
+            "illegal option",
+            "unrecognized option",
+        ]
+        .iter()
+        .any(|needle| lower.contains(needle))
+        {
+            "the ping command does not understand the options latencee passes".to_string()
+        } else if classify_ping_failure(&text) == ProbeError::PermissionDenied {
+            "ping may not send; it needs CAP_NET_RAW or setuid root".to_string()
+        } else {
+            let last = text.lines().map(str::trim).rfind(|l| !l.is_empty());
+            format!("ping to 127.0.0.1 failed: {}", last.unwrap_or("no output"))
+        },
+    )
+}
+
+/// The round-trip time of one probe, or why it was lost.
+pub type ProbeResult = Result<Duration, ProbeError>;
+
+/// A probe in flight, see [`Probe::probe`].
+pub type ProbeFuture<'a> = Pin<Box<dyn Future<Output = ProbeResult> + Send + 'a>>;
+
+/// A way of measuring the latency to a target, such as ICMP echo.
+///
+/// Probes are looked up by [`Probe::kind`] in a [`Registry`], which is how
+/// the configuration selects them.
+pub trait Probe: Send + Sync {
+    /// The name the probe is selected by, e.g. `icmp`.
+    fn kind(&self) -> &'static str;
+
+    /// One line describing what the probe measures.
+    fn description(&self) -> &'static str;
+
+    /// Sends one probe to `target` and resolves once it is answered or lost.
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a>;
+
+    /// What the latest answer of `target` told beside the latency, such as
+    /// the players on a game server.
+    fn note(&self, _target: &Target) -> Option<Note> {
+        None
+    }
+
+    /// Tells the probe that `target` is probed every `interval` from now
+    /// on, for probes that keep to a schedule of their own.
+    fn set_interval(&self, _target: &Target, _interval: Duration) {}
+}
+
+/// ICMP echo, over an unprivileged socket where the system allows it and
+/// through the system `ping` command otherwise.
+pub struct Icmp;
+
+impl Probe for Icmp {
+    fn kind(&self) -> &'static str {
+        "icmp"
+    }
+
+    fn description(&self) -> &'static str {
+        "ICMP echo via an unprivileged socket or the system ping command"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let host = target.host.clone();
+        let network = target.network.clone();
+        Box::pin(rt::unblock(move || {
+            network::within(network.as_ref(), || echo(&host))
+        }))
+    }
+}
+
+/// Pings `host` once, see [`Icmp`], at its cached address.
+pub fn echo(host: &str) -> ProbeResult {
+    let address = resolve::lookup(host)?;
+    if icmp::unavailable_reason().is_none() {
+        icmp::echo(&address, PING_TIMEOUT)
+    } else {
+        ping_host(&address)
+    }
+}
+
+/// Port [`Tcp`] connects to unless configured otherwise.
+pub const DEFAULT_TCP_PORT: u16 = 443;
+
+/// Time to establish a TCP connection. A refused connection counts as a
+/// reply, as the host answered it; this also works where ICMP is blocked or
+/// ping unavailable.
+pub struct Tcp {
+    port: u16,
+    proxies: ProxyRules,
+}
+
+impl Tcp {
+    pub fn new(port: u16) -> Self {
+        Tcp {
+            port,
+            proxies: ProxyRules::default(),
+        }
+    }
+
+    /// Connects through proxies, see [`crate::proxy`].
+    pub fn with_proxies(mut self, proxies: ProxyRules) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Probe for Tcp {
+    fn kind(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn description(&self) -> &'static str {
+        "time to open a TCP connection"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let host = target.host.clone();
+        let port = target.port.unwrap_or(self.port);
+        let network = target.network.clone();
+        match self.proxies.for_target(&target.name).cloned() {
+            Some(proxy) => Box::pin(rt::unblock(move || {
+                network::within(network.as_ref(), || {
+                    let start = Instant::now();
+                    proxy::connect(&proxy, &host, port, PING_TIMEOUT)?;
+                    Ok(start.elapsed())
+                })
+            })),
+            None => Box::pin(rt::unblock(move || {
+                network::within(network.as_ref(), || tcp_connect(&host, port))
+            })),
+        }
+    }
+}
+
+fn tcp_connect(host: &str, port: u16) -> ProbeResult {
+    // Resolve first, so that only the handshake is timed.
+    let address = (resolve::lookup(host)?.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|_| ProbeError::Dns)?
+        .next()
+        .ok_or(ProbeError::Dns)?;
+    let start = Instant::now();
+    match network::connect(&address, PING_TIMEOUT) {
+        Ok(_) => Ok(start.elapsed()),
+        Err(e) => match e.kind() {
+            ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset => Ok(start.elapsed()),
+            ErrorKind::PermissionDenied => Err(ProbeError::PermissionDenied),
+            ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable => {
+                Err(ProbeError::Unreachable)
+            }
+            _ => Err(ProbeError::Timeout),
+        },
+    }
+}
+
+/// The probes available by kind.
+#[derive(Clone)]
+pub struct Registry {
+    probes: Vec<Arc<dyn Probe>>,
+}
+
+impl Registry {
+    /// A registry without any probes.
+    pub fn empty() -> Self {
+        Registry { probes: Vec::new() }
+    }
+
+    /// Adds `probe`, replacing one of the same kind.
+    pub fn register(&mut self, probe: Arc<dyn Probe>) {
+        self.probes.retain(|p| p.kind() != probe.kind());
+        self.probes.push(probe);
+    }
+
+    pub fn get(&self, kind: &str) -> Option<Arc<dyn Probe>> {
+        self.probes.iter().find(|p| p.kind() == kind).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Probe>> {
+        self.probes.iter()
+    }
+}
+
+impl Default for Registry {
+    /// The built-in probes.
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry.register(Arc::new(Icmp));
+        registry.register(Arc::new(Tcp::new(DEFAULT_TCP_PORT)));
+        registry
+    }
+}
+
+/// Latest state of a target as seen by its prober.
+#[derive(Clone)]
+pub struct ServerStatus {
+    /// Shared by every result of the target, so that passing results around
+    /// copies no strings.
+    pub name: Arc<str>,
+    /// The agent that measured this target, `None` for local probes.
+    pub source: Option<String>,
+    /// The kind of probe that measured it, `None` when unknown, as for
+    /// samples from agents.
+    pub probe: Option<&'static str>,
+    pub latency: Option<Duration>,
+    /// Why the latest probe failed; `None` after a reply or when unknown,
+    /// as for samples from agents.
+    pub error: Option<ProbeError>,
+    pub last_update: Instant,
+    pub sampled_at: SystemTime,
+    pub status: ConnectionStatus,
+    /// Metrics the script derived from the target's samples, see
+    /// [`crate::script`].
+    pub metrics: Vec<(String, f64)>,
+    /// Results of the last window. Empty in results fresh from a prober or
+    /// a [`crate::wire::Decoder`], which carry only their own sample:
+    /// [`ServerStatus::absorb`] them into the status kept for the target.
+    pub history: History,
+}
+
+impl ServerStatus {
+    /// Placeholder for a target that has not been probed yet.
+    pub fn pending(name: impl Into<Arc<str>>, window: Duration) -> Self {
+        ServerStatus {
+            name: name.into(),
+            source: None,
+            probe: None,
+            latency: None,
+            error: None,
+            last_update: Instant::now(),
+            sampled_at: SystemTime::now(),
+            status: ConnectionStatus::Timeout,
+            metrics: Vec::new(),
+            history: History::new(window),
+        }
+    }
+
+    /// Takes over the latest result of `update`, a result of the same
+    /// target, and adds its sample to the history.
+    pub fn absorb(&mut self, update: &ServerStatus) {
+        self.history
+            .push(update.last_update, update.status, update.latency);
+        self.source.clone_from(&update.source);
+        self.probe = update.probe;
+        self.latency = update.latency;
+        self.error.clone_from(&update.error);
+        self.last_update = update.last_update;
+        self.sampled_at = update.sampled_at;
+        self.status = update.status;
+        self.metrics.clone_from(&update.metrics);
+    }
+}
+
+/// Probes one target. Its results carry only their own sample; whoever
+/// receives them keeps the history, see
+/// [`crate::history::HistoryStore`].
+pub struct Prober {
+    target: Target,
+    /// The target's name, shared by all its results.
+    name: Arc<str>,
+    probe: Arc<dyn Probe>,
+    /// Milliseconds between probes, see [`Prober::interval_setting`].
+    interval: Arc<AtomicU64>,
+    window: Duration,
+    /// How long [`Prober::run`] waits before the first probe.
+    delay: Duration,
+    paused: Arc<AtomicBool>,
+    /// Wakes [`Prober::run`] for a probe out of schedule.
+    wake: (channel::Sender<()>, channel::Receiver<()>),
+}
+
+/// Makes a running [`Prober`] probe at once, see [`Prober::trigger`].
+#[derive(Clone)]
+pub struct Trigger(channel::Sender<()>);
+
+impl Trigger {
+    /// Asks for a probe now; one already asked for and not sent yet
+    /// covers this one too.
+    pub fn fire(&self) {
+        let _ = self.0.try_send(());
+    }
+}
+
+impl Prober {
+    /// Pings `target` every `interval`, for a history of `window`.
+    pub fn new(target: Target, interval: Duration, window: Duration) -> Self {
+        Prober::with_probe(target, Arc::new(Icmp), interval, window)
+    }
+
+    /// Like [`Prober::new`], measuring with `probe`.
+    pub fn with_probe(
+        target: Target,
+        probe: Arc<dyn Probe>,
+        interval: Duration,
+        window: Duration,
+    ) -> Self {
+        Prober {
+            name: target.name.as_str().into(),
+            target,
+            probe,
+            interval: Arc::new(AtomicU64::new(interval.as_millis() as u64)),
+            window,
+            delay: Duration::ZERO,
+            paused: Arc::new(AtomicBool::new(false)),
+            wake: channel::bounded(1),
+        }
+    }
+
+    /// Starts probing after `delay`. Probers of many targets started with
+    /// different delays probe at different points of the interval rather
+    /// than all at once.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// A switch that pauses [`Prober::run`] while set.
+    pub fn pause_switch(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.paused)
+    }
+
+    /// The milliseconds between probes of [`Prober::run`], which may be
+    /// changed while it runs; a change applies from the next probe on.
+    pub fn interval_setting(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.interval)
+    }
+
+    /// A trigger for probes out of schedule, which [`Prober::run`] sends
+    /// even while paused. They do not move the scheduled ones.
+    pub fn trigger(&self) -> Trigger {
+        Trigger(self.wake.0.clone())
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.probe.kind()
+    }
+
+    /// Sends one probe, blocking until it is answered or times out.
+    pub fn probe(&self) -> ServerStatus {
+        let latency = rt::block_on(self.probe.probe(&self.target));
+        self.record(latency)
+    }
+
+    fn record(&self, result: ProbeResult) -> ServerStatus {
+        let latency = result.ok();
+        ServerStatus {
+            name: Arc::clone(&self.name),
+            source: None,
+            probe: Some(self.probe.kind()),
+            latency,
+            error: result.err(),
+            last_update: Instant::now(),
+            sampled_at: SystemTime::now(),
+            status: classify_latency(latency),
+            metrics: Vec::new(),
+            history: History::new(self.window),
+        }
+    }
+
+    /// Probes forever, sending every result until the receiver is gone.
+    /// Probes start every interval on the dot, however long each takes, so
+    /// that staggered probers stay apart.
+    pub async fn run(self, sender: channel::Sender<ServerStatus>) {
+        let mut next = Instant::now() + self.delay;
+        while !sender.is_closed() {
+            let forced = future::or(
+                async {
+                    rt::sleep(next.saturating_duration_since(Instant::now())).await;
+                    false
+                },
+                async { self.wake.1.recv().await.is_ok() },
+            )
+            .await;
+            if !forced {
+                next += Duration::from_millis(self.interval.load(Ordering::Relaxed).max(1));
+            }
+            if forced || !self.paused.load(Ordering::Relaxed) {
+                let latency = self.probe.probe(&self.target).await;
+                if sender.send(self.record(latency)).await.is_err() {
+                    break;
+                }
+            }
+            // After a probe that outlasted the interval, or a wait for the
+            // receiver, skip the starts that were missed.
+            let now = Instant::now();
+            if next < now {
+                next = now;
+            }
+        }
+    }
+}
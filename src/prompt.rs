@@ -0,0 +1,92 @@
+//! A one-line text input the dashboard shows in place of its key hints,
+//! for the few actions that need more than a key.
+
+use crossterm::{
+    event::{KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{Attribute, SetAttribute},
+};
+use std::io::{self, Write};
+
+/// What a key did to the prompt.
+pub enum Outcome {
+    Editing,
+    /// Enter was pressed; the text entered.
+    Submit(String),
+    /// Esc was pressed.
+    Cancel,
+}
+
+pub struct Prompt {
+    label: String,
+    text: Vec<char>,
+    /// Position of the cursor in `text`.
+    cursor: usize,
+}
+
+impl Prompt {
+    pub fn new(label: impl Into<String>, text: &str) -> Self {
+        let text: Vec<char> = text.chars().collect();
+        Prompt {
+            label: label.into(),
+            cursor: text.len(),
+            text,
+        }
+    }
+
+    /// The text entered so far.
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn handle(&mut self, key: KeyEvent) -> Outcome {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Outcome::Submit(self.text.iter().collect()),
+            KeyCode::Esc => return Outcome::Cancel,
+            // Ctrl+U clears the line, as in a shell.
+            KeyCode::Char('u') if control => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char(c) if !control => {
+                self.text.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            _ => {}
+        }
+        Outcome::Editing
+    }
+
+    /// Draws the label and the text with the cursor shown reversed,
+    /// scrolled to keep the cursor within `columns`.
+    pub fn draw(&self, line: &mut Vec<u8>, columns: usize) -> io::Result<()> {
+        write!(line, "{}", self.label)?;
+        let room = columns
+            .saturating_sub(self.label.chars().count() + 1)
+            .max(1);
+        let first = (self.cursor + 1).saturating_sub(room);
+        let end = self.text.len().min(first + room);
+        let before: String = self.text[first..self.cursor].iter().collect();
+        write!(line, "{}", before)?;
+        queue!(line, SetAttribute(Attribute::Reverse))?;
+        write!(line, "{}", self.text.get(self.cursor).unwrap_or(&' '))?;
+        queue!(line, SetAttribute(Attribute::NoReverse))?;
+        if self.cursor < end {
+            let after: String = self.text[self.cursor + 1..end].iter().collect();
+            write!(line, "{}", after)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Tunneling TCP probes through an HTTP CONNECT or SOCKS5 proxy, for
+//! networks that allow no direct connections.
+//!
+//! The round-trip time of a probe through a proxy is the time to open the
+//! tunnel: connecting to the proxy plus the proxy's connection to the
+//! target. The proxy resolves host names itself.
+
+use crate::{network, probe::ProbeError, sinks::email::base64};
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, TcpStream, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// HTTP `CONNECT`.
+    Http,
+    Socks5,
+}
+
+/// A proxy, written as `http://[user:password@]host:port` or
+/// `socks5://[user:password@]host:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub protocol: Protocol,
+    pub address: String,
+    pub credentials: Option<(String, String)>,
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, String> {
+        let (protocol, rest) = if let Some(rest) = url.strip_prefix("http://") {
+            (Protocol::Http, rest)
+        } else if let Some(rest) = url.strip_prefix("socks5://") {
+            (Protocol::Socks5, rest)
+        } else {
+            return Err(format!(
+                "unsupported proxy {:?}, expected http:// or socks5://",
+                url
+            ));
+        };
+        let rest = rest.trim_end_matches('/');
+        let (credentials, address) = match rest.rsplit_once('@') {
+            Some((credentials, address)) => {
+                let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+                (Some((user.to_string(), password.to_string())), address)
+            }
+            None => (None, rest),
+        };
+        if address
+            .rsplit_once(':')
+            .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+        {
+            return Err(format!("proxy {:?} needs a host and port", url));
+        }
+        Ok(Proxy {
+            protocol,
+            address: address.to_string(),
+            credentials,
+        })
+    }
+}
+
+impl fmt::Display for Proxy {
+    /// The URL without the password.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.protocol {
+            Protocol::Http => "http",
+            Protocol::Socks5 => "socks5",
+        };
+        match &self.credentials {
+            Some((user, _)) => write!(f, "{}://{}@{}", scheme, user, self.address),
+            None => write!(f, "{}://{}", scheme, self.address),
+        }
+    }
+}
+
+/// Proxies for some targets, overriding the default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRoute {
+    pub targets: Vec<String>,
+    /// `None` to connect directly.
+    pub proxy: Option<Proxy>,
+}
+
+/// Which proxy, if any, each target's probes go through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyRules {
+    pub default: Option<Proxy>,
+    pub routes: Vec<ProxyRoute>,
+}
+
+impl ProxyRules {
+    /// The proxy for the target named `name`: that of the first route
+    /// listing it, otherwise the default.
+    pub fn for_target(&self, name: &str) -> Option<&Proxy> {
+        match self
+            .routes
+            .iter()
+            .find(|r| r.targets.iter().any(|t| t == name))
+        {
+            Some(route) => route.proxy.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+}
+
+/// Opens a tunnel to `host:port` through `proxy`, all within `timeout`.
+/// A target refusing the connection counts as success, as for direct
+/// probes: it answered.
+pub fn connect(proxy: &Proxy, host: &str, port: u16, timeout: Duration) -> Result<(), ProbeError> {
+    // The proxy's own name must resolve; a failure is ours, not the target's.
+    let address = proxy
+        .address
+        .to_socket_addrs()
+        .map_err(|_| ProbeError::Dns)?
+        .next()
+        .ok_or(ProbeError::Dns)?;
+    let mut stream = network::connect(&address, timeout).map_err(classify)?;
+    stream.set_read_timeout(Some(timeout)).map_err(classify)?;
+    stream.set_write_timeout(Some(timeout)).map_err(classify)?;
+    match proxy.protocol {
+        Protocol::Http => http_connect(&mut stream, proxy, host, port),
+        Protocol::Socks5 => socks5_connect(&mut stream, proxy, host, port),
+    }
+}
+
+fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> Result<(), ProbeError> {
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((user, password)) = &proxy.credentials {
+        let token = base64(format!("{}:{}", user, password).as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).map_err(classify)?;
+
+    // Only the status line matters; the tunnel is closed right after.
+    let mut buffer = [0u8; 512];
+    let mut len = 0;
+    while !buffer[..len].contains(&b'\n') && len < buffer.len() {
+        match stream.read(&mut buffer[len..]).map_err(classify)? {
+            0 => return Err(ProbeError::Unreachable),
+            n => len += n,
+        }
+    }
+    let status = String::from_utf8_lossy(&buffer[..len]);
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some("403" | "407") => Err(ProbeError::PermissionDenied),
+        Some("504") => Err(ProbeError::Timeout),
+        _ => Err(ProbeError::Unreachable),
+    }
+}
+
+fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> Result<(), ProbeError> {
+    const USER_PASSWORD: u8 = 2;
+
+    let method = if proxy.credentials.is_some() {
+        USER_PASSWORD
+    } else {
+        0
+    };
+    stream.write_all(&[5, 1, method]).map_err(classify)?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).map_err(classify)?;
+    if choice != [5, method] {
+        return Err(ProbeError::PermissionDenied);
+    }
+    if let Some((user, password)) = &proxy.credentials {
+        // RFC 1929; both are at most 255 bytes.
+        let mut request = vec![1, user.len() as u8];
+        request.extend_from_slice(user.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).map_err(classify)?;
+        let mut answer = [0u8; 2];
+        stream.read_exact(&mut answer).map_err(classify)?;
+        if answer[1] != 0 {
+            return Err(ProbeError::PermissionDenied);
+        }
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            request.extend_from_slice(&[3, host.len() as u8]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).map_err(classify)?;
+    let mut answer = [0u8; 2];
+    stream.read_exact(&mut answer).map_err(classify)?;
+    // Reply codes of RFC 1928.
+    match answer[1] {
+        0 | 5 => Ok(()),
+        2 => Err(ProbeError::PermissionDenied),
+        6 => Err(ProbeError::Timeout),
+        _ => Err(ProbeError::Unreachable),
+    }
+}
+
+fn classify(e: io::Error) -> ProbeError {
+    match e.kind() {
+        ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+        ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable => ProbeError::Unreachable,
+        // The proxy itself refusing or dropping us is no answer of the target.
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof => {
+            ProbeError::Unreachable
+        }
+        _ => ProbeError::Timeout,
+    }
+}
@@ -0,0 +1,206 @@
+//! Playing back a session recorded with `--record` in the dashboard.
+//!
+//! The recording is kept in memory and the dashboard is redrawn from a
+//! virtual clock: every frame rebuilds each target's history as it was at
+//! the playback position, so seeking backwards works as well as forwards.
+
+use crate::{
+    GRAPH_WINDOW, Header, Notice, Rows, cli::ReplayOptions, draw_ui, screen::Screen, term,
+    views::View,
+};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use latencee::{History, ServerStatus, alert::Silencer, classify_latency, timefmt, wire};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How far the arrow keys seek.
+const SEEK: Duration = Duration::from_secs(60);
+
+/// Width of the position bar in the status line.
+const BAR_WIDTH: usize = 40;
+
+struct Track {
+    name: String,
+    source: Option<String>,
+    /// Sorted by time.
+    samples: Vec<(SystemTime, Option<Duration>)>,
+}
+
+struct Session {
+    tracks: Vec<Track>,
+    start: SystemTime,
+    end: SystemTime,
+}
+
+impl Session {
+    fn load(path: &std::path::Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let mut tracks: Vec<Track> = Vec::new();
+        for sample in text.lines().filter_map(wire::parse) {
+            let index = match tracks.iter().position(|t| t.name == sample.name) {
+                Some(index) => index,
+                None => {
+                    tracks.push(Track {
+                        name: sample.name,
+                        source: sample.source,
+                        samples: Vec::new(),
+                    });
+                    tracks.len() - 1
+                }
+            };
+            tracks[index]
+                .samples
+                .push((sample.sampled_at, sample.latency));
+        }
+        for track in &mut tracks {
+            track.samples.sort_by_key(|(at, _)| *at);
+        }
+        let times = tracks
+            .iter()
+            .flat_map(|t| t.samples.iter().map(|(at, _)| *at));
+        let (Some(start), Some(end)) = (times.clone().min(), times.max()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no recorded samples", path.display()),
+            ));
+        };
+        Ok(Session { tracks, start, end })
+    }
+
+    /// Every target as it looked at `position`, with the history mapped onto
+    /// this process's clock so that the dashboard draws it as recent.
+    fn statuses_at(&self, position: SystemTime) -> Vec<ServerStatus> {
+        let now = Instant::now();
+        let from = position.checked_sub(GRAPH_WINDOW).unwrap_or(position);
+        self.tracks
+            .iter()
+            .map(|track| {
+                let first = track.samples.partition_point(|(at, _)| *at < from);
+                let last = track.samples.partition_point(|(at, _)| *at <= position);
+                let mut history = History::new(GRAPH_WINDOW);
+                let instant = |at: SystemTime| {
+                    let age = position.duration_since(at).unwrap_or_default();
+                    now.checked_sub(age).unwrap_or(now)
+                };
+                for &(at, latency) in &track.samples[first..last] {
+                    // Recordings keep round-trip times only.
+                    history.push(instant(at), classify_latency(latency), latency);
+                }
+                let mut status = ServerStatus::pending(track.name.as_str(), GRAPH_WINDOW);
+                status.source = track.source.clone();
+                if let Some(&(at, latency)) = track.samples[..last].last() {
+                    status.latency = latency;
+                    status.last_update = instant(at);
+                    status.sampled_at = at;
+                    status.status = classify_latency(latency);
+                }
+                status.history = history;
+                status
+            })
+            .collect()
+    }
+}
+
+/// The status line: play state, speed, position and a bar locating it in
+/// the session.
+fn position_line(session: &Session, position: SystemTime, speed: f64, paused: bool) -> String {
+    let total = session
+        .end
+        .duration_since(session.start)
+        .unwrap_or_default();
+    let done = position.duration_since(session.start).unwrap_or_default();
+    let filled = if total.is_zero() {
+        BAR_WIDTH
+    } else {
+        (done.as_secs_f64() / total.as_secs_f64() * BAR_WIDTH as f64) as usize
+    };
+    format!(
+        "{} {}x  {}  [{}{}]  {} / {}",
+        if paused { "⏸" } else { "▶" },
+        speed,
+        timefmt::long(position),
+        "=".repeat(filled.min(BAR_WIDTH)),
+        "-".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+        timefmt::duration(done),
+        timefmt::duration(total),
+    )
+}
+
+pub fn run(options: &ReplayOptions) -> io::Result<()> {
+    let session = Session::load(&options.session)?;
+    let silencer = Silencer::new(Vec::new());
+    let mut speed = options.speed;
+    let mut position = session.start;
+    let mut paused = false;
+    let mut selected: usize = 0;
+    let mut last_frame = Instant::now();
+    let mut screen = Screen::new();
+
+    let _raw_mode = term::RawMode::enable()?;
+    let stop = term::stop_signal()?;
+    while !stop.load(Ordering::Relaxed) {
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+        {
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                KeyCode::Char(' ') => paused = !paused,
+                KeyCode::Char('+') => speed *= 2.0,
+                KeyCode::Char('-') => speed /= 2.0,
+                KeyCode::Left => {
+                    position = position
+                        .checked_sub(SEEK)
+                        .unwrap_or(session.start)
+                        .max(session.start);
+                }
+                KeyCode::Right => position = (position + SEEK).min(session.end),
+                KeyCode::Home => position = session.start,
+                KeyCode::End => position = session.end,
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => {
+                    selected = (selected + 1).min(session.tracks.len().saturating_sub(1));
+                }
+                _ => {}
+            }
+        }
+
+        let elapsed = last_frame.elapsed();
+        last_frame = Instant::now();
+        if !paused {
+            position = (position + elapsed.mul_f64(speed)).min(session.end);
+            if position == session.end {
+                paused = true;
+            }
+        }
+
+        let line = position_line(&session, position, speed, paused);
+        draw_ui(
+            &mut screen,
+            Rows {
+                statuses: &session.statuses_at(position),
+                archived: 0,
+                marked: &HashSet::new(),
+                visual: false,
+                selected,
+                labels: &BTreeMap::new(),
+                paused: &HashSet::new(),
+                interval: None,
+                intervals: &HashMap::new(),
+            },
+            View::Graph,
+            &silencer,
+            &HashMap::new(),
+            Some(&Notice::info(line)),
+            Header::Keys("Press space to pause, ←/→ to seek, +/- to change speed, 'q' to quit"),
+        )?;
+    }
+    Ok(())
+}
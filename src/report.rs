@@ -0,0 +1,336 @@
+//! Periodic Markdown/HTML summary reports.
+//!
+//! Samples are tallied for the current period (aligned to UTC hour or day
+//! boundaries) and written out as one file per format once the period ends.
+//! Every target keeps running aggregates rather than its samples, so a long
+//! period takes no more memory than a short one.
+
+use crate::{
+    classify_latency,
+    config::{ReportConfig, ReportFormat},
+    stats::{self, Running, Summary},
+    timefmt,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Number of columns in the per-target charts.
+const CHART_BUCKETS: usize = 48;
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// What a report shows of one target over a span of time.
+#[derive(Clone, Debug)]
+pub struct Tally {
+    from: SystemTime,
+    to: SystemTime,
+    summary: Running,
+    /// Sum of the answered latencies, the answered probes and all probes
+    /// per chart column.
+    columns: [(Duration, u32, u32); CHART_BUCKETS],
+}
+
+impl Tally {
+    /// An empty tally of the span from `from` to `to`.
+    pub fn new(from: SystemTime, to: SystemTime) -> Self {
+        Tally {
+            from,
+            to,
+            summary: Running::default(),
+            columns: [(Duration::ZERO, 0, 0); CHART_BUCKETS],
+        }
+    }
+
+    /// Adds the result of a probe at `at`.
+    pub fn record(&mut self, at: SystemTime, latency: Option<Duration>) {
+        self.summary.push(latency);
+        let span = self
+            .to
+            .duration_since(self.from)
+            .unwrap_or_default()
+            .as_secs_f64()
+            .max(1.0);
+        let offset = at
+            .duration_since(self.from)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let index = ((offset / span) * CHART_BUCKETS as f64) as usize;
+        let column = &mut self.columns[index.min(CHART_BUCKETS - 1)];
+        column.2 += 1;
+        if let Some(lat) = latency {
+            column.0 += lat;
+            column.1 += 1;
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        self.summary.summary()
+    }
+
+    /// Average latency (`Some(None)` when every probe in the column was
+    /// lost) for each chart column; `None` for columns without samples.
+    fn buckets(&self) -> Vec<Option<Option<Duration>>> {
+        self.columns
+            .iter()
+            .map(|&(sum, answered, total)| match (answered, total) {
+                (_, 0) => None,
+                (0, _) => Some(None),
+                (n, _) => Some(Some(sum / n)),
+            })
+            .collect()
+    }
+}
+
+pub struct Reporter {
+    config: ReportConfig,
+    period_start: SystemTime,
+    targets: HashMap<String, Tally>,
+}
+
+/// Start of the period containing `at`, aligned to multiples of `period`
+/// since the epoch (so hourly reports start on the hour, daily at midnight UTC).
+fn align(at: SystemTime, period: Duration) -> SystemTime {
+    let secs = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let period = period.as_secs().max(1);
+    UNIX_EPOCH + Duration::from_secs(secs - secs % period)
+}
+
+impl Reporter {
+    pub fn new(config: ReportConfig, now: SystemTime) -> Self {
+        Reporter {
+            period_start: align(now, config.period),
+            config,
+            targets: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, target: &str, at: SystemTime, latency: Option<Duration>) {
+        if let Some(tally) = self.targets.get_mut(target) {
+            tally.record(at, latency);
+            return;
+        }
+        let mut tally = Tally::new(self.period_start, self.period_start + self.config.period);
+        tally.record(at, latency);
+        self.targets.insert(target.to_string(), tally);
+    }
+
+    /// Writes the report once the current period is over. Returns the paths
+    /// of the files written, if any.
+    pub fn tick(&mut self, now: SystemTime) -> io::Result<Vec<PathBuf>> {
+        let period_end = self.period_start + self.config.period;
+        if now < period_end {
+            return Ok(Vec::new());
+        }
+
+        let from = self.period_start;
+        self.period_start = align(now, self.config.period);
+        let mut targets: Vec<(String, Tally)> = self.targets.drain().collect();
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+
+        fs::create_dir_all(&self.config.dir)?;
+        let mut written = Vec::new();
+        for format in &self.config.formats {
+            let (body, extension) = match format {
+                ReportFormat::Markdown => (render_markdown(from, period_end, &targets), "md"),
+                ReportFormat::Html => (render_html(from, period_end, &targets), "html"),
+            };
+            let path =
+                self.config
+                    .dir
+                    .join(format!("latencee-{}.{}", timefmt::compact(from), extension));
+            fs::write(&path, body)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+pub fn sparkline(buckets: &[Option<Option<Duration>>]) -> String {
+    let max = buckets
+        .iter()
+        .filter_map(|b| b.flatten())
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64()
+        .max(f64::EPSILON);
+    buckets
+        .iter()
+        .map(|bucket| match bucket {
+            None => ' ',
+            Some(None) => '×',
+            Some(Some(lat)) => {
+                let level = (lat.as_secs_f64() / max * (SPARK_CHARS.len() - 1) as f64).round();
+                SPARK_CHARS[level as usize]
+            }
+        })
+        .collect()
+}
+
+fn loss_pct(summary: &Summary) -> String {
+    format!("{:.1}%", summary.loss() * 100.0)
+}
+
+pub fn render_markdown(from: SystemTime, to: SystemTime, targets: &[(String, Tally)]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Latencee report");
+    let _ = writeln!(
+        out,
+        "\n{} – {} (UTC)\n",
+        timefmt::short(from),
+        timefmt::short(to)
+    );
+    let _ = writeln!(
+        out,
+        "| Target | Status | Samples | Loss | Min | Avg | p95 | Max | Jitter |"
+    );
+    let _ = writeln!(out, "|---|---|---:|---:|---:|---:|---:|---:|---:|");
+    for (name, tally) in targets {
+        let summary = tally.summary();
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+            name.replace('|', "\\|"),
+            classify_latency(summary.avg).label(),
+            summary.samples,
+            loss_pct(&summary),
+            stats::format_ms(summary.min),
+            stats::format_ms(summary.avg),
+            stats::format_ms(summary.p95),
+            stats::format_ms(summary.max),
+            stats::format_ms(summary.jitter),
+        );
+    }
+
+    let _ = writeln!(out, "\n## Latency over time\n");
+    let _ = writeln!(out, "Average latency per column; `×` marks total loss.\n");
+    let _ = writeln!(out, "```");
+    let width = targets
+        .iter()
+        .map(|(n, _)| n.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (name, tally) in targets {
+        let _ = writeln!(
+            out,
+            "{:<width$}  {}",
+            name,
+            sparkline(&tally.buckets()),
+            width = width
+        );
+    }
+    let _ = writeln!(out, "```");
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn status_color(latency: Option<Duration>) -> &'static str {
+    match classify_latency(latency) {
+        crate::ConnectionStatus::Good => "#2e7d32",
+        crate::ConnectionStatus::Fair => "#f9a825",
+        crate::ConnectionStatus::Poor => "#c62828",
+        crate::ConnectionStatus::Timeout => "#6d1b1b",
+    }
+}
+
+fn svg_chart(buckets: &[Option<Option<Duration>>]) -> String {
+    const WIDTH: f64 = 480.0;
+    const HEIGHT: f64 = 60.0;
+    let step = WIDTH / CHART_BUCKETS as f64;
+    let max = buckets
+        .iter()
+        .filter_map(|b| b.flatten())
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64()
+        .max(f64::EPSILON);
+
+    let mut svg = format!(
+        "<svg width=\"{WIDTH}\" height=\"{HEIGHT}\" viewBox=\"0 0 {WIDTH} {HEIGHT}\" xmlns=\"http://www.w3.org/2000/svg\">"
+    );
+    let mut points = String::new();
+    for (i, bucket) in buckets.iter().enumerate() {
+        let x = i as f64 * step + step / 2.0;
+        match bucket {
+            Some(Some(lat)) => {
+                let y = HEIGHT - 4.0 - (lat.as_secs_f64() / max) * (HEIGHT - 8.0);
+                let _ = write!(points, "{:.1},{:.1} ", x, y);
+            }
+            Some(None) => {
+                let _ = write!(
+                    svg,
+                    "<rect x=\"{:.1}\" y=\"0\" width=\"{:.1}\" height=\"{HEIGHT}\" fill=\"#c62828\" opacity=\"0.3\"/>",
+                    x - step / 2.0,
+                    step
+                );
+            }
+            None => {}
+        }
+    }
+    let _ = write!(
+        svg,
+        "<polyline fill=\"none\" stroke=\"#1565c0\" stroke-width=\"1.5\" points=\"{}\"/></svg>",
+        points.trim_end()
+    );
+    svg
+}
+
+pub fn render_html(from: SystemTime, to: SystemTime, targets: &[(String, Tally)]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Latencee report {}</title>",
+        timefmt::short(from)
+    );
+    let _ = writeln!(
+        out,
+        "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+         td,th{{padding:4px 8px;border-bottom:1px solid #ddd;text-align:right}}\
+         td:first-child,th:first-child{{text-align:left}}</style></head><body>"
+    );
+    let _ = writeln!(out, "<h1>Latencee report</h1>");
+    let _ = writeln!(
+        out,
+        "<p>{} – {} (UTC)</p>",
+        timefmt::short(from),
+        timefmt::short(to)
+    );
+    let _ = writeln!(
+        out,
+        "<table><tr><th>Target</th><th>Status</th><th>Samples</th><th>Loss</th>\
+         <th>Min</th><th>Avg</th><th>p95</th><th>Max</th><th>Jitter</th><th>Chart</th></tr>"
+    );
+    for (name, tally) in targets {
+        let summary = tally.summary();
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td style=\"color:{}\">{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(name),
+            status_color(summary.avg),
+            classify_latency(summary.avg).label(),
+            summary.samples,
+            loss_pct(&summary),
+            stats::format_ms(summary.min),
+            stats::format_ms(summary.avg),
+            stats::format_ms(summary.p95),
+            stats::format_ms(summary.max),
+            stats::format_ms(summary.jitter),
+            svg_chart(&tally.buckets()),
+        );
+    }
+    let _ = writeln!(out, "</table></body></html>");
+    out
+}
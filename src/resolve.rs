@@ -0,0 +1,160 @@
+//! Name resolution for probes, cached so that a probe measures the target
+//! and not the resolver.
+//!
+//! A target is resolved in the background when it is added and again every
+//! [`refresh`] interval after that; probes use the cached address meanwhile
+//! and never wait for a lookup, except for the very first one. When a
+//! target's address changes, the change is kept for [`take_changes`].
+//!
+//! Lookups go through the system resolver, which honors `/etc/hosts` and
+//! the like but does not tell how long an answer may be cached. The refresh
+//! interval stands in for the record's TTL; a caching resolver such as
+//! systemd-resolved answers refreshes within the TTL from its own cache.
+
+use crate::probe::ProbeError;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, ToSocketAddrs},
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long addresses are used before they are looked up again, unless
+/// configured.
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(300);
+
+static REFRESH_MS: AtomicU64 = AtomicU64::new(DEFAULT_REFRESH.as_millis() as u64);
+
+static CACHE: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(Mutex::default);
+
+static CHANGES: Mutex<Vec<AddressChange>> = Mutex::new(Vec::new());
+
+struct Entry {
+    address: IpAddr,
+    resolved: Instant,
+    /// Whether a lookup in the background is under way.
+    refreshing: bool,
+}
+
+/// A host that resolved to a different address than before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressChange {
+    pub host: String,
+    pub from: IpAddr,
+    pub to: IpAddr,
+    pub at: SystemTime,
+}
+
+impl fmt::Display for AddressChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} now resolves to {} (was {})",
+            self.host, self.to, self.from
+        )
+    }
+}
+
+/// How long addresses are used before they are looked up again.
+pub fn refresh() -> Duration {
+    Duration::from_millis(REFRESH_MS.load(Ordering::Relaxed))
+}
+
+pub fn set_refresh(every: Duration) {
+    REFRESH_MS.store(every.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// What to send probes for `host` to: its cached address, or `host` itself
+/// if it is an address already. Looks the address up in the background
+/// once it is due, and blocks only when `host` was never resolved.
+pub fn lookup(host: &str) -> Result<String, ProbeError> {
+    if is_literal(host) {
+        return Ok(host.to_string());
+    }
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(entry) = cache.get_mut(host) {
+        if entry.resolved.elapsed() >= refresh() && !entry.refreshing {
+            entry.refreshing = true;
+            spawn_lookup(host);
+        }
+        return Ok(entry.address.to_string());
+    }
+    drop(cache);
+    let address = resolve(host).ok_or(ProbeError::Dns)?;
+    store(host, Some(address));
+    Ok(address.to_string())
+}
+
+/// The address probes for `host` use now, without looking it up; `None`
+/// if it was not resolved yet.
+pub fn cached(host: &str) -> Option<String> {
+    if is_literal(host) {
+        return Some(host.to_string());
+    }
+    let cache = CACHE.lock().unwrap();
+    cache.get(host).map(|entry| entry.address.to_string())
+}
+
+/// Starts looking `host` up in the background, so that its first probe
+/// finds the address ready.
+pub fn prefetch(host: &str) {
+    if !is_literal(host) && !CACHE.lock().unwrap().contains_key(host) {
+        spawn_lookup(host);
+    }
+}
+
+/// Address changes seen since the last call, oldest first.
+pub fn take_changes() -> Vec<AddressChange> {
+    std::mem::take(&mut *CHANGES.lock().unwrap())
+}
+
+/// Addresses, and scoped IPv6 addresses such as `fe80::1%eth0`, which
+/// need no lookup.
+fn is_literal(host: &str) -> bool {
+    host.parse::<IpAddr>().is_ok() || host.contains('%')
+}
+
+fn resolve(host: &str) -> Option<IpAddr> {
+    Some((host, 0).to_socket_addrs().ok()?.next()?.ip())
+}
+
+fn spawn_lookup(host: &str) {
+    let host = host.to_string();
+    thread::spawn(move || {
+        let address = resolve(&host);
+        store(&host, address);
+    });
+}
+
+/// Records the result of a lookup. A failed refresh keeps the previous
+/// address until the next one.
+fn store(host: &str, address: Option<IpAddr>) {
+    let mut cache = CACHE.lock().unwrap();
+    let previous = cache.get(host).map(|entry| entry.address);
+    let Some(address) = address.or(previous) else {
+        return;
+    };
+    if let Some(from) = previous
+        && from != address
+    {
+        CHANGES.lock().unwrap().push(AddressChange {
+            host: host.to_string(),
+            from,
+            to: address,
+            at: SystemTime::now(),
+        });
+    }
+    cache.insert(
+        host.to_string(),
+        Entry {
+            address,
+            resolved: Instant::now(),
+            refreshing: false,
+        },
+    );
+}
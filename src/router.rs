@@ -0,0 +1,348 @@
+//! Pings run by a router or switch, asked through its management API, to
+//! measure targets from the network edge rather than from this machine.
+//!
+//! Two APIs are spoken: the REST API of MikroTik RouterOS 7, through curl,
+//! and the DISMAN-PING-MIB of RFC 4560 over SNMPv2c, through net-snmp's
+//! `snmpset` and `snmpget`. Each sample is one echo request sent by the
+//! router; its round-trip time is the router's measurement, without the way
+//! from here to the router.
+
+use crate::{
+    Target,
+    json::{self, Json},
+    probe::{PING_TIMEOUT, Probe, ProbeError, ProbeFuture, ProbeResult},
+    resolve, rt,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io::{ErrorKind, Write},
+    net::IpAddr,
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A router or switch targets are also pinged from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Router {
+    /// Prefix of the names of the targets pinged from there.
+    pub name: String,
+    pub api: RouterApi,
+    /// Names of the targets pinged from there; all when empty.
+    pub targets: Vec<String>,
+}
+
+/// How the router is asked to ping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouterApi {
+    RouterOs(RouterOs),
+    Snmp(Snmp),
+}
+
+/// The REST API of RouterOS 7, `POST /rest/ping`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouterOs {
+    /// Base URL of the router's web service, e.g. `https://192.168.88.1`.
+    pub url: String,
+    pub user: String,
+    pub password: String,
+    /// Accepts the router's certificate without verifying it, as RouterOS
+    /// creates a self-signed one.
+    pub insecure: bool,
+    /// Path or name of the curl binary.
+    pub curl: String,
+}
+
+/// The DISMAN-PING-MIB, over SNMPv2c.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snmp {
+    /// The agent as net-snmp takes it, e.g. `192.168.1.1` or
+    /// `udp6:[2001:db8::1]:161`.
+    pub agent: String,
+    /// Community with write access to the ping tables.
+    pub community: String,
+    /// Directory of `snmpset` and `snmpget`; searched in `PATH` when not
+    /// set.
+    pub tools: Option<String>,
+}
+
+/// Pings targets on a [`Router`].
+pub struct RouterPing {
+    router: Router,
+}
+
+impl RouterPing {
+    pub fn new(router: Router) -> Self {
+        RouterPing { router }
+    }
+}
+
+impl Probe for RouterPing {
+    fn kind(&self) -> &'static str {
+        match self.router.api {
+            RouterApi::RouterOs(_) => "routeros",
+            RouterApi::Snmp(_) => "snmp",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.router.api {
+            RouterApi::RouterOs(_) => "ping sent by a RouterOS router, through its REST API",
+            RouterApi::Snmp(_) => "ping sent by a router or switch, through DISMAN-PING-MIB",
+        }
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let api = self.router.api.clone();
+        let name = target.name.clone();
+        let host = target.host.clone();
+        Box::pin(rt::unblock(move || match &api {
+            RouterApi::RouterOs(routeros) => routeros.ping(&host),
+            RouterApi::Snmp(snmp) => snmp.ping(&name, &host),
+        }))
+    }
+}
+
+impl RouterOs {
+    /// One echo request, which RouterOS answers once it got the reply or
+    /// gave up after a second. Host names are resolved by the router.
+    fn ping(&self, host: &str) -> ProbeResult {
+        let body = Json::object([
+            ("address", Json::String(host.to_string())),
+            ("count", Json::String("1".to_string())),
+        ]);
+        let mut command = Command::new(&self.curl);
+        command
+            .args(["--silent", "--show-error", "--config", "-"])
+            .args(["--header", "Content-Type: application/json"])
+            .arg("--max-time")
+            .arg((PING_TIMEOUT.as_secs() + 2).to_string())
+            .arg("--data")
+            .arg(body.to_string());
+        if self.insecure {
+            command.arg("--insecure");
+        }
+        command.arg(format!("{}/rest/ping", self.url.trim_end_matches('/')));
+        // On stdin rather than the command line, out of sight of ps.
+        let credentials = format!(
+            "user = \"{}:{}\"\n",
+            curl_escape(&self.user),
+            curl_escape(&self.password)
+        );
+        let output = run(&mut command, Some(&credentials))?;
+        if !output.status.success() {
+            return Err(ProbeError::Timeout);
+        }
+        let answer =
+            json::parse(&String::from_utf8_lossy(&output.stdout)).ok_or(ProbeError::Timeout)?;
+        parse_ping(&answer)
+    }
+}
+
+/// The outcome of a RouterOS ping: a list of replies and a summary, or an
+/// error object.
+fn parse_ping(answer: &Json) -> ProbeResult {
+    if let Some(detail) = answer.get("detail").and_then(Json::as_str) {
+        return Err(if detail.contains("resolve") || detail.contains("dns") {
+            ProbeError::Dns
+        } else {
+            ProbeError::Timeout
+        });
+    }
+    let reply = answer
+        .as_array()
+        .and_then(|replies| replies.last())
+        .ok_or(ProbeError::Timeout)?;
+    let field = |key| reply.get(key).and_then(Json::as_str);
+    if let Some(time) = field("time").or_else(|| field("avg-rtt")) {
+        return parse_duration(time).ok_or(ProbeError::Timeout);
+    }
+    match field("status") {
+        Some(status) if status.contains("unreachable") => Err(ProbeError::Unreachable),
+        _ => Err(ProbeError::Timeout),
+    }
+}
+
+/// A RouterOS duration, e.g. `12ms345us` or `1s2ms`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let value: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += match &rest[..unit] {
+            "h" => Duration::from_secs(value * 3600),
+            "m" => Duration::from_secs(value * 60),
+            "s" => Duration::from_secs(value),
+            "ms" => Duration::from_millis(value),
+            "us" => Duration::from_micros(value),
+            "ns" => Duration::from_nanos(value),
+            _ => return None,
+        };
+        rest = &rest[unit..];
+    }
+    Some(total)
+}
+
+/// `pingCtlEntry` and `pingResultsEntry` of DISMAN-PING-MIB.
+const PING_CTL: &str = "1.3.6.1.2.1.80.1.2.1";
+const PING_RESULTS: &str = "1.3.6.1.2.1.80.1.3.1";
+
+/// `pingCtlOwnerIndex` of the tests created.
+const OWNER: &str = "latencee";
+
+/// How often the results of a running test are read.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Snmp {
+    /// Creates a test sending one echo request to `host`, waits for its
+    /// result and removes it again, so tests do not pile up on the router.
+    /// Host names are resolved here, as the MIB leaves names optional.
+    fn ping(&self, name: &str, host: &str) -> ProbeResult {
+        let address: IpAddr = resolve::lookup(host)?
+            .parse()
+            .map_err(|_| ProbeError::Dns)?;
+        let (kind, octets) = match address {
+            IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (2, ip.octets().to_vec()),
+        };
+        let index = test_index(name);
+        let ctl = |column: u32| format!("{}.{}{}", PING_CTL, column, index);
+        let hex: String = octets.iter().map(|b| format!("{:02X}", b)).collect();
+        let timeout = PING_TIMEOUT.as_secs().clamp(1, 60).to_string();
+
+        // A test left behind by an earlier run would refuse creation.
+        let _ = self.tool("snmpset").args([&ctl(23), "i", "6"]).output();
+        let created = run(
+            self.tool("snmpset").args([
+                // pingCtlTargetAddressType, pingCtlTargetAddress
+                &ctl(3),
+                "i",
+                &kind.to_string(),
+                &ctl(4),
+                "x",
+                &hex,
+                // pingCtlTimeOut, pingCtlProbeCount
+                &ctl(6),
+                "u",
+                &timeout,
+                &ctl(7),
+                "u",
+                "1",
+                // pingCtlAdminStatus enabled, pingCtlRowStatus createAndGo
+                &ctl(8),
+                "i",
+                "1",
+                &ctl(23),
+                "i",
+                "4",
+            ]),
+            None,
+        )?;
+        if !created.status.success() {
+            return Err(ProbeError::Timeout);
+        }
+        let result = self.wait(&index);
+        // pingCtlRowStatus destroy
+        let _ = self.tool("snmpset").args([&ctl(23), "i", "6"]).output();
+        result
+    }
+
+    /// Reads the results of the test at `index` until its probe was
+    /// answered or the test completed without an answer.
+    fn wait(&self, index: &str) -> ProbeResult {
+        let results = |column: u32| format!("{}.{}{}", PING_RESULTS, column, index);
+        let deadline = Instant::now() + PING_TIMEOUT * 3;
+        while Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+            // pingResultsOperStatus, pingResultsAverageRtt,
+            // pingResultsProbeResponses, pingResultsSentProbes
+            let output = run(
+                self.tool("snmpget")
+                    .args([&results(1), &results(6), &results(7), &results(8)]),
+                None,
+            )?;
+            let text = String::from_utf8_lossy(&output.stdout);
+            let values: Vec<u64> = text
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .collect();
+            // Results appear once the router started the test.
+            let [status, rtt, responses, sent] = values[..] else {
+                continue;
+            };
+            if responses > 0 {
+                return Ok(Duration::from_millis(rtt));
+            }
+            // completed, or disabled after its one run
+            if sent > 0 && status != 1 {
+                return Err(ProbeError::Timeout);
+            }
+        }
+        Err(ProbeError::Timeout)
+    }
+
+    /// `snmpset` or `snmpget` addressing the agent, printing bare numeric
+    /// values.
+    fn tool(&self, name: &str) -> Command {
+        let mut command = match &self.tools {
+            Some(dir) => Command::new(std::path::Path::new(dir).join(name)),
+            None => Command::new(name),
+        };
+        command
+            .args(["-v2c", "-c", &self.community, "-r", "0", "-OqveU"])
+            .arg("-t")
+            .arg(PING_TIMEOUT.as_secs().max(1).to_string())
+            .arg(&self.agent)
+            .stdin(Stdio::null());
+        command
+    }
+}
+
+/// The table index of the test pinging the target called `name`: the
+/// owner and a test name, each prefixed with its length. Test names are at
+/// most 32 octets, so they are derived from a hash of the target name.
+fn test_index(name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let test = format!("{:016x}", hasher.finish());
+    [OWNER, test.as_str()]
+        .iter()
+        .map(|part| {
+            let bytes: String = part.bytes().map(|b| format!(".{}", b)).collect();
+            format!(".{}{}", part.len(), bytes)
+        })
+        .collect()
+}
+
+/// Runs `command`, writing `stdin` to it if given.
+fn run(command: &mut Command, stdin: Option<&str>) -> Result<Output, ProbeError> {
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => ProbeError::BinaryMissing,
+            ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Timeout,
+        })?;
+    if let (Some(text), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let _ = pipe.write_all(text.as_bytes());
+    }
+    child.wait_with_output().map_err(|_| ProbeError::Timeout)
+}
+
+/// `text` inside a double-quoted curl config value.
+fn curl_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,687 @@
+//! Round-robin storage for long-term latency history.
+//!
+//! Every target gets one file whose size is fixed when it is created. The
+//! file holds one or more archives; each archive is a ring of `rows` slots of
+//! `step` width and consolidates all samples falling into a slot into their
+//! average, minimum and maximum latency, sample and loss counts and how many
+//! samples ended up with each status. Old slots are simply overwritten, so
+//! disk usage never grows.
+//!
+//! Layout (all integers little endian):
+//!
+//! ```text
+//! magic      8 bytes  "LTNCRRD2"
+//! name      64 bytes  target name, zero padded UTF-8
+//! probe     16 bytes  kind of probe of the latest samples, zero padded
+//! archives   u32      number of archives
+//! reserved   u32
+//! per archive: step (u32 seconds), rows (u32)
+//! per archive: rows * ROW_SIZE bytes of row data
+//! ```
+
+use crate::{ConnectionStatus, ServerStatus, config::ArchiveSpec};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &[u8; 8] = b"LTNCRRD2";
+/// The magic of files from before the probe kind and statuses were kept.
+const OLD_MAGIC: &[u8; 8] = b"LTNCRRD1";
+const NAME_LEN: usize = 64;
+const PROBE_LEN: usize = 16;
+const HEADER_LEN: u64 = 8 + NAME_LEN as u64 + PROBE_LEN as u64 + 8;
+const ARCHIVE_DESC_LEN: u64 = 8;
+const ROW_SIZE: u64 = 56;
+
+/// The statuses counted per row, in the order they are stored.
+const STATUSES: [ConnectionStatus; 4] = [
+    ConnectionStatus::Good,
+    ConnectionStatus::Fair,
+    ConnectionStatus::Poor,
+    ConnectionStatus::Timeout,
+];
+pub const EXTENSION: &str = "rrd";
+
+/// One consolidated slot. Latencies are in milliseconds and are `NaN` when no
+/// probe in the slot got an answer.
+#[derive(Clone, Copy, Debug)]
+pub struct Row {
+    /// Start of the slot in seconds since the Unix epoch; 0 marks an unused row.
+    pub start: u64,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    pub samples: u32,
+    pub lost: u32,
+    /// Samples per final status, as processors and the script left it, in
+    /// the order of [`STATUSES`].
+    pub statuses: [u32; 4],
+}
+
+impl Row {
+    fn empty(start: u64) -> Self {
+        Row {
+            start,
+            avg: f64::NAN,
+            min: f64::NAN,
+            max: f64::NAN,
+            samples: 0,
+            lost: 0,
+            statuses: [0; 4],
+        }
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.start)
+    }
+
+    /// The status most samples of the slot had, the worse one on a tie: what
+    /// the live view showed most of the time.
+    pub fn status(&self) -> ConnectionStatus {
+        let mut most = 0;
+        for (i, &count) in self.statuses.iter().enumerate() {
+            if count >= self.statuses[most] {
+                most = i;
+            }
+        }
+        STATUSES[most]
+    }
+
+    fn add(&mut self, status: ConnectionStatus, latency: Option<Duration>) {
+        self.samples += 1;
+        if let Some(i) = STATUSES.iter().position(|s| *s == status) {
+            self.statuses[i] += 1;
+        }
+        match latency {
+            Some(lat) => {
+                let ms = lat.as_secs_f64() * 1000.0;
+                let answered = (self.samples - self.lost) as f64;
+                if answered == 1.0 {
+                    self.avg = ms;
+                    self.min = ms;
+                    self.max = ms;
+                } else {
+                    self.avg += (ms - self.avg) / answered;
+                    self.min = self.min.min(ms);
+                    self.max = self.max.max(ms);
+                }
+            }
+            None => self.lost += 1,
+        }
+    }
+
+    fn encode(&self) -> [u8; ROW_SIZE as usize] {
+        let mut buf = [0u8; ROW_SIZE as usize];
+        buf[0..8].copy_from_slice(&self.start.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.avg.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.min.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.max.to_le_bytes());
+        buf[32..36].copy_from_slice(&self.samples.to_le_bytes());
+        buf[36..40].copy_from_slice(&self.lost.to_le_bytes());
+        for (i, count) in self.statuses.iter().enumerate() {
+            buf[40 + i * 4..44 + i * 4].copy_from_slice(&count.to_le_bytes());
+        }
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        Row {
+            start: u64_at(0),
+            avg: f64::from_bits(u64_at(8)),
+            min: f64::from_bits(u64_at(16)),
+            max: f64::from_bits(u64_at(24)),
+            samples: u32_at(32),
+            lost: u32_at(36),
+            statuses: [u32_at(40), u32_at(44), u32_at(48), u32_at(52)],
+        }
+    }
+}
+
+fn invalid(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Total file size for the given archive layout.
+pub fn file_size(archives: &[ArchiveSpec]) -> u64 {
+    HEADER_LEN
+        + archives.len() as u64 * ARCHIVE_DESC_LEN
+        + archives
+            .iter()
+            .map(|a| a.rows as u64 * ROW_SIZE)
+            .sum::<u64>()
+}
+
+struct Header {
+    name: String,
+    probe: String,
+    archives: Vec<ArchiveSpec>,
+}
+
+/// A zero padded text field.
+fn text_field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_header(file: &mut File, path: &Path) -> io::Result<Header> {
+    let mut fixed = [0u8; HEADER_LEN as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut fixed)?;
+    if &fixed[0..8] == OLD_MAGIC {
+        return Err(invalid(
+            path,
+            "written by an older latencee; move the file away to start over",
+        ));
+    }
+    if &fixed[0..8] != MAGIC {
+        return Err(invalid(path, "not a latencee round-robin file"));
+    }
+    let name = text_field(&fixed[8..8 + NAME_LEN]);
+    let probe = text_field(&fixed[8 + NAME_LEN..8 + NAME_LEN + PROBE_LEN]);
+    let count_at = 8 + NAME_LEN + PROBE_LEN;
+    let count = u32::from_le_bytes(fixed[count_at..count_at + 4].try_into().unwrap());
+    // The count comes from the file: believe it only as far as the file
+    // has room for its descriptors.
+    let len = file.metadata()?.len();
+    if u64::from(count) > (len - HEADER_LEN) / ARCHIVE_DESC_LEN {
+        return Err(invalid(path, "corrupt archive count"));
+    }
+
+    let mut archives = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut desc = [0u8; ARCHIVE_DESC_LEN as usize];
+        file.read_exact(&mut desc)?;
+        let step = u32::from_le_bytes(desc[0..4].try_into().unwrap());
+        let rows = u32::from_le_bytes(desc[4..8].try_into().unwrap());
+        if step == 0 || rows == 0 {
+            return Err(invalid(path, "corrupt archive descriptor"));
+        }
+        archives.push(ArchiveSpec {
+            step: Duration::from_secs(step as u64),
+            rows,
+        });
+    }
+    if file_size(&archives) > len {
+        return Err(invalid(path, "truncated round-robin file"));
+    }
+    Ok(Header {
+        name,
+        probe,
+        archives,
+    })
+}
+
+/// `text` as it fits into a field of `len` bytes, truncated on a character
+/// boundary.
+fn truncated(text: &str, len: usize) -> &str {
+    let mut end = text.len().min(len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// The name as it fits into the header.
+fn stored_name(name: &str) -> &str {
+    truncated(name, NAME_LEN)
+}
+
+fn write_text(buf: &mut Vec<u8>, text: &str, len: usize) {
+    let text = truncated(text, len);
+    buf.extend_from_slice(text.as_bytes());
+    buf.resize(buf.len() + len - text.len(), 0);
+}
+
+fn write_header(
+    file: &mut File,
+    name: &str,
+    probe: &str,
+    archives: &[ArchiveSpec],
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(HEADER_LEN as usize + archives.len() * 8);
+    buf.extend_from_slice(MAGIC);
+    write_text(&mut buf, name, NAME_LEN);
+    write_text(&mut buf, probe, PROBE_LEN);
+    buf.extend_from_slice(&(archives.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    for archive in archives {
+        buf.extend_from_slice(&(archive.step.as_secs() as u32).to_le_bytes());
+        buf.extend_from_slice(&archive.rows.to_le_bytes());
+    }
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&buf)
+}
+
+/// An open round-robin file for a single target.
+pub struct RrdFile {
+    file: File,
+    /// The kind of probe stored in the header.
+    probe: String,
+    archives: Vec<ArchiveSpec>,
+    /// Byte offset of each archive's row data.
+    offsets: Vec<u64>,
+    /// The slot currently being consolidated, per archive.
+    current: Vec<Row>,
+}
+
+impl RrdFile {
+    /// Opens `path`, creating and preallocating it if it does not exist yet.
+    /// An existing file must have been created with the same archive layout.
+    pub fn open(path: &Path, name: &str, archives: &[ArchiveSpec]) -> io::Result<Self> {
+        let mut probe = String::new();
+        let exists = path.exists();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if exists {
+            let header = read_header(&mut file, path)?;
+            if header.name != stored_name(name) {
+                return Err(invalid(
+                    path,
+                    format!("belongs to target '{}', not '{}'", header.name, name),
+                ));
+            }
+            if header.archives != archives {
+                return Err(invalid(
+                    path,
+                    "archive layout differs from the configuration; move the file away to start over",
+                ));
+            }
+            probe = header.probe;
+        } else {
+            write_header(&mut file, name, "", archives)?;
+            file.set_len(file_size(archives))?;
+        }
+
+        let mut offset = HEADER_LEN + archives.len() as u64 * ARCHIVE_DESC_LEN;
+        let mut offsets = Vec::with_capacity(archives.len());
+        for archive in archives {
+            offsets.push(offset);
+            offset += archive.rows as u64 * ROW_SIZE;
+        }
+
+        let mut rrd = RrdFile {
+            file,
+            probe,
+            archives: archives.to_vec(),
+            offsets,
+            current: Vec::new(),
+        };
+
+        // Resume consolidating the slot we were in when the file was last written.
+        let now = unix_secs(SystemTime::now());
+        for i in 0..rrd.archives.len() {
+            let slot = rrd.slot_start(i, now);
+            let row = rrd.read_row(i, slot)?;
+            rrd.current.push(if row.start == slot {
+                row
+            } else {
+                Row::empty(slot)
+            });
+        }
+
+        Ok(rrd)
+    }
+
+    fn slot_start(&self, archive: usize, secs: u64) -> u64 {
+        let step = self.archives[archive].step.as_secs();
+        secs - secs % step
+    }
+
+    fn row_offset(&self, archive: usize, slot: u64) -> u64 {
+        let spec = &self.archives[archive];
+        let index = (slot / spec.step.as_secs()) % spec.rows as u64;
+        self.offsets[archive] + index * ROW_SIZE
+    }
+
+    fn read_row(&mut self, archive: usize, slot: u64) -> io::Result<Row> {
+        let mut buf = [0u8; ROW_SIZE as usize];
+        self.file
+            .seek(SeekFrom::Start(self.row_offset(archive, slot)))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(Row::decode(&buf))
+    }
+
+    /// Folds one probe result into every archive and writes the affected
+    /// rows, noting the kind of probe in the header when it changed.
+    pub fn record(&mut self, sample: &ServerStatus) -> io::Result<()> {
+        if let Some(probe) = sample.probe
+            && truncated(probe, PROBE_LEN) != self.probe
+        {
+            self.probe = truncated(probe, PROBE_LEN).to_string();
+            self.file.seek(SeekFrom::Start(8 + NAME_LEN as u64))?;
+            let mut field = Vec::with_capacity(PROBE_LEN);
+            write_text(&mut field, probe, PROBE_LEN);
+            self.file.write_all(&field)?;
+        }
+        let secs = unix_secs(sample.sampled_at);
+        for i in 0..self.archives.len() {
+            let slot = self.slot_start(i, secs);
+            if self.current[i].start != slot {
+                self.current[i] = Row::empty(slot);
+            }
+            self.current[i].add(sample.status, sample.latency);
+
+            let offset = self.row_offset(i, slot);
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(&self.current[i].encode())?;
+        }
+        Ok(())
+    }
+
+    /// Waits until everything written has reached the disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// The contents of a round-robin file, as returned by [`read`].
+pub struct Archive {
+    pub spec: ArchiveSpec,
+    /// Rows in chronological order; unused slots are skipped.
+    pub rows: Vec<Row>,
+}
+
+pub struct Contents {
+    pub name: String,
+    /// The kind of probe of the latest samples, empty if none said.
+    pub probe: String,
+    pub archives: Vec<Archive>,
+}
+
+/// Reads every archive of the file at `path`.
+pub fn read(path: &Path) -> io::Result<Contents> {
+    let mut file = File::open(path)?;
+    let header = read_header(&mut file, path)?;
+    let mut archives = Vec::with_capacity(header.archives.len());
+
+    for spec in header.archives {
+        let mut buf = vec![0u8; spec.rows as usize * ROW_SIZE as usize];
+        file.read_exact(&mut buf)?;
+        let mut rows: Vec<Row> = buf
+            .chunks_exact(ROW_SIZE as usize)
+            .map(Row::decode)
+            .filter(|row| row.start != 0)
+            .collect();
+        rows.sort_by_key(|row| row.start);
+
+        // Rows older than one full ring are leftovers from a previous lap.
+        if let Some(last) = rows.last() {
+            let span = spec.step.as_secs() * spec.rows as u64;
+            let oldest = last.start.saturating_sub(span);
+            rows.retain(|row| row.start > oldest);
+        }
+        archives.push(Archive { spec, rows });
+    }
+
+    Ok(Contents {
+        name: header.name,
+        probe: header.probe,
+        archives,
+    })
+}
+
+/// Reads only the target name from the file at `path`.
+pub fn read_name(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    Ok(read_header(&mut file, path)?.name)
+}
+
+/// The file `target` is stored in within a storage directory.
+pub fn path(dir: &Path, target: &str) -> PathBuf {
+    dir.join(format!("{}.{}", file_stem(target), EXTENSION))
+}
+
+/// Lists the round-robin files in a storage directory.
+pub fn list(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Longest readable part of a file name stem.
+const STEM_LEN: usize = 64;
+
+/// Turns a target name into a file name stem: the name made safe for any
+/// file system, readable but ambiguous ("Google DNS" and "google-dns" both
+/// read `google-dns`), followed by a hash of the exact name that tells such
+/// names apart.
+pub fn file_stem(name: &str) -> String {
+    let readable: String = name
+        .chars()
+        .take(STEM_LEN)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}-{:016x}", readable, fnv1a(name.as_bytes()))
+}
+
+/// The 64-bit FNV-1a hash, which unlike the standard library's hashers
+/// stays the same across releases, as file names must.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A directory of round-robin files, one per target, opened on first use.
+pub struct RrdStore {
+    dir: PathBuf,
+    archives: Vec<ArchiveSpec>,
+    files: HashMap<String, RrdFile>,
+}
+
+impl RrdStore {
+    pub fn open(dir: &Path, archives: &[ArchiveSpec]) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(RrdStore {
+            dir: dir.to_path_buf(),
+            archives: archives.to_vec(),
+            files: HashMap::new(),
+        })
+    }
+
+    /// Stores `sample` in the file of its target.
+    pub fn record(&mut self, sample: &ServerStatus) -> io::Result<()> {
+        let target = &*sample.name;
+        if !self.files.contains_key(target) {
+            let file = RrdFile::open(&path(&self.dir, target), target, &self.archives)?;
+            self.files.insert(target.to_string(), file);
+        }
+        self.files
+            .get_mut(target)
+            .expect("file was just opened")
+            .record(sample)
+    }
+
+    /// Flushes every open file to disk, e.g. before shutting down.
+    pub fn sync(&self) -> io::Result<()> {
+        self.files.values().try_for_each(RrdFile::sync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test.
+    fn scratch(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("latencee-rrd-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sample(at: u64, latency: Option<u64>, status: ConnectionStatus) -> ServerStatus {
+        let mut sample = ServerStatus::pending("Google DNS", Duration::from_secs(60));
+        sample.probe = Some("icmp");
+        sample.sampled_at = UNIX_EPOCH + Duration::from_secs(at);
+        sample.latency = latency.map(Duration::from_millis);
+        sample.status = status;
+        sample
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let dir = scratch("header");
+        let archives = [
+            ArchiveSpec {
+                step: Duration::from_secs(10),
+                rows: 6,
+            },
+            ArchiveSpec {
+                step: Duration::from_secs(60),
+                rows: 2,
+            },
+        ];
+        let path = path(&dir, "Google DNS");
+        let mut file = RrdFile::open(&path, "Google DNS", &archives).unwrap();
+        file.record(&sample(1_000, Some(20), ConnectionStatus::Good))
+            .unwrap();
+        drop(file);
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), file_size(&archives));
+        let contents = read(&path).unwrap();
+        assert_eq!(contents.name, "Google DNS");
+        assert_eq!(contents.probe, "icmp");
+        let specs: Vec<ArchiveSpec> = contents.archives.iter().map(|a| a.spec).collect();
+        assert_eq!(specs, archives);
+        assert_eq!(read_name(&path).unwrap(), "Google DNS");
+
+        // Reopening checks the layout against the configuration.
+        RrdFile::open(&path, "Google DNS", &archives).unwrap();
+        assert!(RrdFile::open(&path, "Google DNS", &archives[..1]).is_err());
+        assert!(RrdFile::open(&path, "Cloudflare", &archives).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn consolidates_samples_per_slot() {
+        let dir = scratch("consolidate");
+        let archives = [ArchiveSpec {
+            step: Duration::from_secs(10),
+            rows: 3,
+        }];
+        let path = path(&dir, "Google DNS");
+        let mut file = RrdFile::open(&path, "Google DNS", &archives).unwrap();
+        for sample in [
+            sample(1_000, Some(10), ConnectionStatus::Good),
+            sample(1_003, Some(30), ConnectionStatus::Good),
+            sample(1_006, None, ConnectionStatus::Timeout),
+            sample(1_009, Some(200), ConnectionStatus::Poor),
+            sample(1_010, None, ConnectionStatus::Timeout),
+        ] {
+            file.record(&sample).unwrap();
+        }
+        drop(file);
+
+        let rows = &read(&path).unwrap().archives[0].rows;
+        assert_eq!(rows.len(), 2);
+        let first = rows[0];
+        assert_eq!(first.start, 1_000);
+        assert_eq!((first.samples, first.lost), (4, 1));
+        assert_eq!(first.avg, 80.0);
+        assert_eq!((first.min, first.max), (10.0, 200.0));
+        assert_eq!(first.statuses, [2, 0, 1, 1]);
+        assert_eq!(first.status(), ConnectionStatus::Good);
+        let second = rows[1];
+        assert_eq!(second.start, 1_010);
+        assert_eq!((second.samples, second.lost), (1, 1));
+        assert!(second.avg.is_nan());
+        assert_eq!(second.status(), ConnectionStatus::Timeout);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_rows() {
+        let dir = scratch("ring");
+        let archives = [ArchiveSpec {
+            step: Duration::from_secs(10),
+            rows: 3,
+        }];
+        let path = path(&dir, "Google DNS");
+        let mut file = RrdFile::open(&path, "Google DNS", &archives).unwrap();
+        for slot in 0..5 {
+            file.record(&sample(
+                1_000 + slot * 10,
+                Some(slot),
+                ConnectionStatus::Good,
+            ))
+            .unwrap();
+        }
+        drop(file);
+
+        let starts: Vec<u64> = read(&path).unwrap().archives[0]
+            .rows
+            .iter()
+            .map(|row| row.start)
+            .collect();
+        assert_eq!(starts, [1_020, 1_030, 1_040]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ties_go_to_the_worse_status() {
+        let mut row = Row::empty(0);
+        row.add(ConnectionStatus::Good, Some(Duration::from_millis(10)));
+        row.add(ConnectionStatus::Fair, Some(Duration::from_millis(60)));
+        assert_eq!(row.status(), ConnectionStatus::Fair);
+    }
+
+    #[test]
+    fn names_differing_only_in_punctuation_get_their_own_files() {
+        assert_ne!(file_stem("Google DNS"), file_stem("google-dns"));
+        assert_ne!(file_stem("a"), file_stem("A"));
+        assert!(file_stem("Google DNS").starts_with("google-dns-"));
+        assert_eq!(file_stem("Google DNS"), file_stem("Google DNS"));
+    }
+
+    #[test]
+    fn rejects_counts_the_file_has_no_room_for() {
+        let dir = scratch("count");
+        let path = dir.join("corrupt.rrd");
+        let mut header = MAGIC.to_vec();
+        header.resize(HEADER_LEN as usize, 0);
+        let count_at = 8 + NAME_LEN + PROBE_LEN;
+        header[count_at..count_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &header).unwrap();
+
+        let error = read(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A descriptor promising more rows than the file holds.
+        header[count_at..count_at + 4].copy_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&10u32.to_le_bytes());
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &header).unwrap();
+        let error = read(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
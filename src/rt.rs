@@ -0,0 +1,90 @@
+//! The async runtime behind the library, in one place.
+//!
+//! By default the primitives come from smol. With the `tokio` feature they
+//! come from Tokio instead, so that embedders whose applications already
+//! run on Tokio can spawn probers on their own runtime and share its timer
+//! and blocking pool. Channels are `async-channel` either way, and future
+//! combinators `futures-lite`, which work with any executor.
+
+pub use smol::{channel, future};
+use std::{future::Future, time::Duration};
+
+/// Waits for `duration` without blocking the thread.
+pub async fn sleep(duration: Duration) {
+    #[cfg(not(feature = "tokio"))]
+    smol::Timer::after(duration).await;
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+}
+
+/// Runs blocking code, such as waiting for a child process, on a thread
+/// pool.
+pub async fn unblock<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    #[cfg(not(feature = "tokio"))]
+    return smol::unblock(f).await;
+    #[cfg(feature = "tokio")]
+    return match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    };
+}
+
+/// Runs `future` to completion on the current thread. Must not be called
+/// from async code.
+pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+    #[cfg(not(feature = "tokio"))]
+    return smol::block_on(future);
+    #[cfg(feature = "tokio")]
+    return tokio_runtime::get().block_on(future);
+}
+
+/// A spawned task, cancelled when dropped.
+pub struct Task<T> {
+    #[cfg(not(feature = "tokio"))]
+    _task: smol::Task<T>,
+    #[cfg(feature = "tokio")]
+    handle: tokio::task::JoinHandle<T>,
+}
+
+#[cfg(feature = "tokio")]
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Runs `future` in the background. Under Tokio it joins the runtime of the
+/// caller, if any.
+pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
+    #[cfg(not(feature = "tokio"))]
+    return Task {
+        _task: smol::spawn(future),
+    };
+    #[cfg(feature = "tokio")]
+    return Task {
+        handle: match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle.spawn(future),
+            Err(_) => tokio_runtime::get().spawn(future),
+        },
+    };
+}
+
+/// The runtime used outside of a caller's Tokio runtime: by the binary and
+/// by blocking calls such as [`crate::Prober::probe`].
+#[cfg(feature = "tokio")]
+mod tokio_runtime {
+    use std::sync::OnceLock;
+    use tokio::runtime::{Builder, Runtime};
+
+    pub fn get() -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(|| {
+            Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("latencee-rt")
+                .enable_time()
+                .build()
+                .expect("cannot start the Tokio runtime")
+        })
+    }
+}
@@ -0,0 +1,120 @@
+//! Cron-style schedules: `minute hour day-of-month month day-of-week`, in
+//! UTC. Fields accept `*`, numbers, ranges (`1-5`), lists (`1,15`) and steps
+//! (`*/10`, `0-30/5`). Day of week runs from 0 (Sunday) to 6; 7 is also
+//! Sunday. As in cron, when both day fields are restricted either may match.
+
+use crate::timefmt::DateTime;
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day fields were given as `*`.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Parses one field into a bit set of the allowed values.
+fn field(text: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{}'", part))?,
+            ),
+            None => (part, 1),
+        };
+        let number = |s: &str| {
+            s.parse::<u32>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("'{}' is not in {}-{}", s, min, max))
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                // A single value with a step runs to the end, as in cron.
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if from > to {
+            return Err(format!("empty range '{}'", range));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+        let mut weekdays = field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Schedule {
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+impl Schedule {
+    /// Whether the schedule triggers in the minute containing `at`.
+    pub fn matches(&self, at: SystemTime) -> bool {
+        let t = DateTime::from_system(at);
+        let days = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86400;
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4) % 7;
+        let day = self.days & (1 << t.day) != 0;
+        let weekday = self.weekdays & (1 << weekday) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        };
+        self.minutes & (1 << t.minute) != 0
+            && self.hours & (1 << t.hour) != 0
+            && self.months & (1 << t.month) != 0
+            && day_matches
+    }
+
+    /// The most recent trigger within `within` before or at `at`.
+    pub fn last_start(&self, at: SystemTime, within: Duration) -> Option<SystemTime> {
+        let secs = at.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let minute = UNIX_EPOCH + Duration::from_secs(secs - secs % 60);
+        (0..=within.as_secs() / 60)
+            .map(|back| minute - Duration::from_secs(back * 60))
+            .take_while(|start| at.duration_since(*start).unwrap_or_default() < within)
+            .find(|start| self.matches(*start))
+    }
+}
@@ -0,0 +1,85 @@
+//! Drawing the dashboard a frame at a time, rewriting only the rows that
+//! changed since the last frame instead of clearing the whole screen. Over
+//! SSH on a slow link a full repaint at every update is itself laggy and
+//! flickers.
+
+use crossterm::{
+    cursor, queue,
+    terminal::{self, ClearType},
+};
+use std::io::{self, Write};
+
+/// The rows of the frame being drawn and of the one on screen.
+pub struct Screen {
+    rows: Vec<Vec<u8>>,
+    shown: Vec<Vec<u8>>,
+    /// The terminal size the shown frame was drawn for; the screen is
+    /// cleared when nothing is shown.
+    size: Option<(u16, u16)>,
+    /// Everything a frame sends to the terminal, written at once.
+    output: Vec<u8>,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Screen {
+            rows: Vec::new(),
+            shown: Vec::new(),
+            size: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// Starts a frame and returns the terminal size. After a resize the
+    /// next frame is drawn from scratch.
+    pub fn begin(&mut self) -> io::Result<(u16, u16)> {
+        let size = terminal::size()?;
+        if self.size != Some(size) {
+            // Nothing on screen is where it was drawn any more.
+            self.shown.clear();
+            self.size = Some(size);
+        }
+        // Keep the buffers of the frame before last for this one; rows left
+        // empty are blank.
+        for row in &mut self.rows {
+            row.clear();
+        }
+        Ok(size)
+    }
+
+    /// The contents of `row` in the frame being drawn, styled with queued
+    /// crossterm commands.
+    pub fn row(&mut self, row: u16) -> &mut Vec<u8> {
+        let row = usize::from(row);
+        if self.rows.len() <= row {
+            self.rows.resize_with(row + 1, Vec::new);
+        }
+        &mut self.rows[row]
+    }
+
+    /// Writes the rows that differ from the screen, and blanks those the
+    /// frame no longer has, in a single write.
+    pub fn present(&mut self) -> io::Result<()> {
+        let output = &mut self.output;
+        output.clear();
+        if self.shown.is_empty() {
+            queue!(output, terminal::Clear(ClearType::All))?;
+        }
+        for row in 0..self.rows.len().max(self.shown.len()) {
+            let line = self.rows.get(row).map_or(&[][..], Vec::as_slice);
+            if self.shown.get(row).map(Vec::as_slice) == Some(line) {
+                continue;
+            }
+            queue!(output, cursor::MoveTo(0, row as u16))?;
+            output.extend_from_slice(line);
+            queue!(output, terminal::Clear(ClearType::UntilNewLine))?;
+        }
+        if !output.is_empty() {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(output)?;
+            stdout.flush()?;
+        }
+        std::mem::swap(&mut self.rows, &mut self.shown);
+        Ok(())
+    }
+}
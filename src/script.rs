@@ -0,0 +1,389 @@
+//! User scripts post-processing samples and reacting to events, written in
+//! [Rhai](https://rhai.rs) and run by an engine embedded in latencee. Needs
+//! the `rhai` feature.
+//!
+//! A script defines either or both of these functions:
+//!
+//! ```text
+//! fn sample(target, at, rtt, status)  // rtt in ms, () when lost
+//! fn event(event)                     // a map with kind, target and at
+//! ```
+//!
+//! `sample` returns `()` to keep the built-in classification, a status
+//! label (`good`, `fair`, `poor` or `timeout`) to reclassify the target, or
+//! a map with a `status` and `metrics` it derived, by name. Events are
+//! `status_change` (with `from` and `to`), `outage_start`, `outage_end`
+//! (with `duration` in seconds) and `alert` (with `rule`, `state` and
+//! `value`); their return value is ignored. Timestamps are unix
+//! milliseconds.
+//!
+//! Both functions share `this`, a map kept between calls, for state such as
+//! counters. Scripts cannot touch files, the network or other processes;
+//! each call may take up to the configured timeout, and strings, arrays
+//! and maps are limited in size.
+//!
+//! The script runs off the monitor's path: samples and events queue for a
+//! thread running it, the oldest dropped when it falls behind, and answers
+//! are collected with [`Script::answers`]. An answer therefore applies to
+//! the target's following samples, until the script answers otherwise.
+
+use crate::{ConnectionStatus, ServerStatus, config::ScriptConfig, events::Event, rt::channel};
+use std::{
+    io,
+    sync::{Arc, mpsc},
+    time::{Duration, SystemTime},
+};
+
+/// What the script made of a sample of `target`.
+pub struct Answer {
+    pub target: Arc<str>,
+    /// The status the target's samples get, `None` for the built-in one.
+    pub status: Option<ConnectionStatus>,
+    /// Metrics derived from the target's samples, by name.
+    pub metrics: Vec<(String, f64)>,
+}
+
+/// A call of the script; samples expect an answer.
+#[cfg_attr(not(feature = "rhai"), allow(dead_code))]
+enum Message {
+    Sample {
+        target: Arc<str>,
+        at: SystemTime,
+        latency: Option<Duration>,
+        status: ConnectionStatus,
+    },
+    Event(Event),
+}
+
+pub struct Script {
+    queue: channel::Sender<Message>,
+    answers: mpsc::Receiver<io::Result<Answer>>,
+}
+
+impl Script {
+    /// Compiles the script at `config.path` and starts running it.
+    #[cfg(feature = "rhai")]
+    pub fn start(config: &ScriptConfig) -> io::Result<Self> {
+        let runner = engine::Runner::compile(config)?;
+        let (queue, messages) = channel::bounded(crate::sinks::QUEUE);
+        let (answers, received) = mpsc::channel();
+        std::thread::spawn(move || runner.run(messages, answers));
+        Ok(Script {
+            queue,
+            answers: received,
+        })
+    }
+
+    #[cfg(not(feature = "rhai"))]
+    pub fn start(_config: &ScriptConfig) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "script: latencee was built without the rhai feature",
+        ))
+    }
+
+    /// Queues a call, dropping the oldest queued one if the script fell
+    /// behind.
+    fn queue(&self, message: Message) {
+        let _ = self.queue.force_send(message);
+    }
+
+    /// Passes a sample to the script, to be answered later.
+    pub fn sample(&self, status: &ServerStatus) {
+        self.queue(Message::Sample {
+            target: Arc::clone(&status.name),
+            at: status.sampled_at,
+            latency: status.latency,
+            status: status.status,
+        });
+    }
+
+    /// The answers that arrived since the last call, in order; an error
+    /// means the script failed and answers no more.
+    pub fn answers(&self) -> impl Iterator<Item = io::Result<Answer>> + '_ {
+        self.answers.try_iter()
+    }
+
+    /// Tells the script about an event. Samples are sent by
+    /// [`Script::sample`] instead.
+    pub fn notify(&self, event: &Event) {
+        if !matches!(event, Event::Sample { .. }) {
+            self.queue(Message::Event(event.clone()));
+        }
+    }
+}
+
+#[cfg(feature = "rhai")]
+mod engine {
+    use super::{Answer, Message};
+    use crate::{ConnectionStatus, config::ScriptConfig, events::Event, rt::channel};
+    use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope};
+    use std::{
+        fs, io,
+        sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+            mpsc,
+        },
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    /// The longest string, and the most items of an array or map, a script
+    /// may build.
+    const MAX_SIZE: usize = 1 << 20;
+
+    /// How deep script functions may call each other.
+    const MAX_CALL_LEVELS: usize = 32;
+
+    pub struct Runner {
+        engine: Engine,
+        ast: AST,
+        /// Which of `sample` and `event` the script defines.
+        samples: bool,
+        events: bool,
+        /// When the call running now must end, in milliseconds since
+        /// `started`.
+        deadline: Arc<AtomicU64>,
+        started: Instant,
+        timeout: Duration,
+    }
+
+    impl Runner {
+        pub fn compile(config: &ScriptConfig) -> io::Result<Self> {
+            let source = fs::read_to_string(&config.path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("script: cannot read {}: {}", config.path.display(), e),
+                )
+            })?;
+            let started = Instant::now();
+            let deadline = Arc::new(AtomicU64::new(u64::MAX));
+            let mut engine = Engine::new();
+            engine
+                .set_max_string_size(MAX_SIZE)
+                .set_max_array_size(MAX_SIZE)
+                .set_max_map_size(MAX_SIZE)
+                .set_max_call_levels(MAX_CALL_LEVELS)
+                // Nowhere to print to while the dashboard draws.
+                .on_print(|_| {})
+                .on_debug(|_, _, _| {});
+            let limit = Arc::clone(&deadline);
+            engine.on_progress(move |_| {
+                let elapsed = started.elapsed().as_millis() as u64;
+                (elapsed > limit.load(Ordering::Relaxed)).then_some(Dynamic::UNIT)
+            });
+            let ast = engine.compile(&source).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("script: {}: {}", config.path.display(), e),
+                )
+            })?;
+            let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+            let (samples, events) = (defines("sample"), defines("event"));
+            Ok(Runner {
+                engine,
+                ast,
+                samples,
+                events,
+                deadline,
+                started,
+                timeout: config.timeout,
+            })
+        }
+
+        /// Runs the script on every message until it fails or the monitor
+        /// drops the queue.
+        pub fn run(
+            self,
+            messages: channel::Receiver<Message>,
+            answers: mpsc::Sender<io::Result<Answer>>,
+        ) {
+            let mut this = Dynamic::from_map(Map::new());
+            while let Ok(message) = messages.recv_blocking() {
+                let answer = match message {
+                    Message::Sample {
+                        target,
+                        at,
+                        latency,
+                        status,
+                    } if self.samples => {
+                        let rtt = latency.map_or(Dynamic::UNIT, |l| {
+                            Dynamic::from_float(l.as_secs_f64() * 1000.0)
+                        });
+                        let args = (
+                            target.to_string(),
+                            millis(at),
+                            rtt,
+                            status.label().to_string(),
+                        );
+                        self.call(&mut this, "sample", args)
+                            .and_then(|answer| read_answer(target, answer))
+                            .map(Some)
+                    }
+                    Message::Event(event) if self.events => maps(&event)
+                        .into_iter()
+                        .try_for_each(|event| {
+                            self.call(&mut this, "event", (Dynamic::from_map(event),))
+                                .map(drop)
+                        })
+                        .map(|()| None),
+                    _ => Ok(None),
+                };
+                match answer {
+                    Ok(None) => {}
+                    Ok(Some(answer)) => {
+                        if answers.send(Ok(answer)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = answers.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        }
+
+        /// Calls the script function `name`, for up to the timeout.
+        fn call(
+            &self,
+            this: &mut Dynamic,
+            name: &str,
+            args: impl rhai::FuncArgs,
+        ) -> io::Result<Dynamic> {
+            let deadline = self.started.elapsed() + self.timeout;
+            self.deadline
+                .store(deadline.as_millis() as u64, Ordering::Relaxed);
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                name,
+                args,
+            );
+            result.map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("script's {} took longer than {:?}", name, self.timeout),
+                ),
+                e => io::Error::other(format!("script's {}: {}", name, e)),
+            })
+        }
+    }
+
+    /// The answer `sample` gave for a sample of `target`.
+    fn read_answer(target: Arc<str>, answer: Dynamic) -> io::Result<Answer> {
+        let invalid = |what: String| io::Error::new(io::ErrorKind::InvalidData, what);
+        let (status, metrics) = if answer.is_map() {
+            let map = answer.cast::<Map>();
+            (
+                map.get("status").cloned().unwrap_or(Dynamic::UNIT),
+                map.get("metrics").cloned().unwrap_or(Dynamic::UNIT),
+            )
+        } else {
+            (answer, Dynamic::UNIT)
+        };
+        let status = if status.is_unit() {
+            None
+        } else {
+            let label = status.into_string().map_err(|kind| {
+                invalid(format!("script's sample answered a {} as status", kind))
+            })?;
+            Some(ConnectionStatus::from_label(&label).ok_or_else(|| {
+                invalid(format!(
+                    "script's sample answered unknown status {:?}",
+                    label
+                ))
+            })?)
+        };
+        let metrics = if metrics.is_unit() {
+            Vec::new()
+        } else {
+            let metrics = metrics.try_cast::<Map>().ok_or_else(|| {
+                invalid("script's sample answered metrics that are not a map".to_string())
+            })?;
+            metrics
+                .into_iter()
+                .map(|(name, value)| {
+                    value
+                        .as_float()
+                        .ok()
+                        .or_else(|| value.as_int().ok().map(|value| value as f64))
+                        .filter(|value| value.is_finite())
+                        .map(|value| (name.to_string(), value))
+                        .ok_or_else(|| {
+                            invalid(format!("script's sample answered bad metric {:?}", name))
+                        })
+                })
+                .collect::<io::Result<_>>()?
+        };
+        Ok(Answer {
+            target,
+            status,
+            metrics,
+        })
+    }
+
+    /// The maps `event` is passed to the script as: one per alert of a
+    /// group, one otherwise.
+    fn maps(event: &Event) -> Vec<Map> {
+        let map = |kind: &str, target: &str, at: SystemTime, fields: Vec<(&str, Dynamic)>| {
+            let mut map = Map::new();
+            map.insert("kind".into(), kind.into());
+            map.insert("target".into(), target.into());
+            map.insert("at".into(), millis(at).into());
+            for (name, value) in fields {
+                map.insert(name.into(), value);
+            }
+            map
+        };
+        match event {
+            Event::Sample { .. } => Vec::new(),
+            Event::StatusChange {
+                target,
+                at,
+                from,
+                to,
+                ..
+            } => vec![map(
+                "status_change",
+                target,
+                *at,
+                vec![("from", from.label().into()), ("to", to.label().into())],
+            )],
+            Event::OutageStart { target, at } => vec![map("outage_start", target, *at, vec![])],
+            Event::OutageEnd {
+                target,
+                at,
+                duration,
+            } => vec![map(
+                "outage_end",
+                target,
+                *at,
+                vec![("duration", (duration.as_secs() as i64).into())],
+            )],
+            Event::Alerts(alerts) => alerts
+                .iter()
+                .map(|alert| {
+                    map(
+                        "alert",
+                        &alert.target,
+                        alert.at,
+                        vec![
+                            ("rule", alert.rule.as_str().into()),
+                            ("state", alert.state.label().into()),
+                            ("value", alert.value.as_str().into()),
+                        ],
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn millis(at: SystemTime) -> i64 {
+        at.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    }
+}
@@ -0,0 +1,88 @@
+//! Monitoring from boot on Windows.
+//!
+//! `service install` registers a Task Scheduler task that starts at boot
+//! under the SYSTEM account and runs latencee headless, so history, reports
+//! and alerts keep flowing without anyone logged in. The task runs a small
+//! wrapper script that appends all output to a log. Like the rest of
+//! latencee, this leans on a system tool, `schtasks`, rather than binding
+//! the service control manager API.
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const TASK: &str = "latencee";
+
+/// `%ProgramData%\latencee`, holding the wrapper script and the log.
+fn dir() -> PathBuf {
+    env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("latencee")
+}
+
+fn wrapper_path() -> PathBuf {
+    dir().join("latencee-service.cmd")
+}
+
+pub fn log_path() -> PathBuf {
+    dir().join("latencee.log")
+}
+
+fn schtasks(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("schtasks")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::other(format!("schtasks: {}", stderr.trim())))
+}
+
+pub fn install(config: Option<&Path>) -> io::Result<()> {
+    fs::create_dir_all(dir())?;
+    let mut command = format!("\"{}\" --headless", env::current_exe()?.display());
+    if let Some(config) = config {
+        command += &format!(" --config \"{}\"", fs::canonicalize(config)?.display());
+    }
+    fs::write(
+        wrapper_path(),
+        format!(
+            "@echo off\r\n{} >> \"{}\" 2>&1\r\n",
+            command,
+            log_path().display()
+        ),
+    )?;
+
+    let run = format!("\"{}\"", wrapper_path().display());
+    schtasks(&[
+        "/Create", "/TN", TASK, "/TR", &run, "/SC", "ONSTART", "/RU", "SYSTEM", "/RL", "HIGHEST",
+        "/F",
+    ])?;
+    schtasks(&["/Run", "/TN", TASK])?;
+    println!(
+        "latencee installed and started, logging to {}",
+        log_path().display()
+    );
+    Ok(())
+}
+
+pub fn uninstall() -> io::Result<()> {
+    // Not running is fine; the task is deleted either way.
+    let _ = schtasks(&["/End", "/TN", TASK]);
+    schtasks(&["/Delete", "/TN", TASK, "/F"])?;
+    match fs::remove_file(wrapper_path()) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    println!(
+        "latencee uninstalled; the log is kept at {}",
+        log_path().display()
+    );
+    Ok(())
+}
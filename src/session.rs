@@ -0,0 +1,107 @@
+//! What a dashboard session saw, printed once the terminal is restored on
+//! exit: every probe since the start, not only the graph's window.
+
+use latencee::{ConnectionStatus, ServerStatus, stats, timefmt};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct Tally {
+    samples: usize,
+    lost: usize,
+    total: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    /// Times the target went down.
+    outages: usize,
+    down: bool,
+}
+
+pub struct Session {
+    started: Instant,
+    tallies: HashMap<Arc<str>, Tally>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            started: Instant::now(),
+            tallies: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, status: &ServerStatus) {
+        let tally = self.tallies.entry(Arc::clone(&status.name)).or_default();
+        tally.samples += 1;
+        match status.latency {
+            Some(latency) => {
+                tally.total += latency;
+                tally.min = Some(tally.min.map_or(latency, |min| min.min(latency)));
+                tally.max = Some(tally.max.map_or(latency, |max| max.max(latency)));
+            }
+            None => tally.lost += 1,
+        }
+        let down = status.status == ConnectionStatus::Timeout;
+        if down && !tally.down {
+            tally.outages += 1;
+        }
+        tally.down = down;
+    }
+
+    /// The length of the session and a line per target probed, those of
+    /// `order` first and in that order, under their names in `labels`.
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        order: &[&str],
+        labels: &BTreeMap<String, String>,
+    ) -> io::Result<()> {
+        let mut names: Vec<&str> = order
+            .iter()
+            .copied()
+            .filter(|name| self.tallies.contains_key(*name))
+            .collect();
+        // Targets removed meanwhile follow.
+        let mut gone: Vec<&str> = self
+            .tallies
+            .keys()
+            .map(|name| &**name)
+            .filter(|name| !order.contains(name))
+            .collect();
+        gone.sort_unstable();
+        names.extend(gone);
+
+        writeln!(
+            out,
+            "Session of {}, {} targets probed",
+            timefmt::duration(self.started.elapsed()),
+            names.len()
+        )?;
+        for name in names {
+            let tally = &self.tallies[name];
+            let shown = labels.get(name).map_or(name, String::as_str);
+            let answered = tally.samples - tally.lost;
+            let avg = (answered > 0).then(|| tally.total / answered as u32);
+            write!(
+                out,
+                "  {:<20} {:>6} probes {:>6.1}% lost  min {:>6}  avg {:>6}  max {:>6}",
+                shown,
+                tally.samples,
+                tally.lost as f64 * 100.0 / tally.samples as f64,
+                stats::format_ms(tally.min),
+                stats::format_ms(avg),
+                stats::format_ms(tally.max)
+            )?;
+            match tally.outages {
+                0 => writeln!(out)?,
+                1 => writeln!(out, "  1 outage")?,
+                n => writeln!(out, "  {} outages", n)?,
+            }
+        }
+        Ok(())
+    }
+}
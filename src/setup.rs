@@ -0,0 +1,110 @@
+//! `latencee setup-caps`: explaining how targets are pinged and, when asked
+//! to as root, letting latencee send echo requests itself without running
+//! as root. Nothing on the system changes without `--sysctl` or `--setcap`.
+
+use crate::cli::SetupOptions;
+use latencee::probe::{self, IcmpBackend};
+use std::io;
+
+/// The group range allowing every group to open unprivileged ICMP sockets.
+#[cfg(target_os = "linux")]
+const OPEN_RANGE: &str = "0 2147483647";
+
+/// The groups allowed to open unprivileged ICMP sockets, now.
+#[cfg(target_os = "linux")]
+const GROUP_RANGE: &str = "/proc/sys/net/ipv4/ping_group_range";
+
+/// Keeps [`OPEN_RANGE`] across reboots.
+#[cfg(target_os = "linux")]
+const SYSCTL_FILE: &str = "/etc/sysctl.d/60-latencee.conf";
+
+pub fn run(options: &SetupOptions) -> io::Result<()> {
+    let backend = probe::icmp_backend();
+    match backend {
+        IcmpBackend::Unusable { .. } => {
+            println!("Cannot ping: {}; probing with TCP connects.", backend)
+        }
+        _ => println!("Probing with {}.", backend),
+    }
+
+    apply(options)
+}
+
+#[cfg(target_os = "linux")]
+fn apply(options: &SetupOptions) -> io::Result<()> {
+    use latencee::icmp;
+    use std::{env, fs, process::Command};
+
+    // Raw sockets work for root alone; unprivileged sockets need nothing.
+    if icmp::unprivileged() {
+        if options.sysctl || options.setcap {
+            println!("Unprivileged ICMP sockets already work; nothing changed.");
+        }
+        return Ok(());
+    }
+    if !options.sysctl && !options.setcap {
+        println!(
+            "Run `sudo latencee setup-caps --sysctl` to allow unprivileged ICMP sockets for \
+             every group, or `sudo latencee setup-caps --setcap` to grant this binary \
+             CAP_NET_RAW."
+        );
+        return Ok(());
+    }
+    // SAFETY: no preconditions.
+    if unsafe { libc::geteuid() } != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "changing this needs root; run it with sudo",
+        ));
+    }
+
+    if options.setcap {
+        let exe = env::current_exe()?;
+        let status = Command::new("setcap")
+            .arg("cap_net_raw+ep")
+            .arg(&exe)
+            .status()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot run setcap: {}", e)))?;
+        if !status.success() {
+            return Err(io::Error::other(format!("setcap failed with {}", status)));
+        }
+        println!(
+            "Granted CAP_NET_RAW to {}: it now opens raw ICMP sockets for any user.",
+            exe.display()
+        );
+        println!("Copying or upgrading the binary drops the capability; run this again then.");
+        println!("To undo: sudo setcap -r {}", exe.display());
+    } else {
+        let previous = fs::read_to_string(GROUP_RANGE)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", GROUP_RANGE, e)))?;
+        let previous = previous.split_whitespace().collect::<Vec<_>>().join(" ");
+        let undo = format!(
+            "sudo sysctl -w net.ipv4.ping_group_range=\"{}\" && sudo rm {}",
+            previous, SYSCTL_FILE
+        );
+        fs::write(
+            SYSCTL_FILE,
+            format!(
+                "# Written by latencee setup-caps: every group may send ICMP echo.\n\
+                 # The range was {}; to undo: {}\n\
+                 net.ipv4.ping_group_range = {}\n",
+                previous, undo, OPEN_RANGE
+            ),
+        )
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", SYSCTL_FILE, e)))?;
+        fs::write(GROUP_RANGE, OPEN_RANGE)?;
+        println!(
+            "Set net.ipv4.ping_group_range from {} to {} and kept it in {}: every user may \
+             now open unprivileged ICMP sockets.",
+            previous, OPEN_RANGE, SYSCTL_FILE
+        );
+        println!("To undo: {}", undo);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply(_: &SetupOptions) -> io::Result<()> {
+    println!("There is nothing to set up on this platform.");
+    Ok(())
+}
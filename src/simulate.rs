@@ -0,0 +1,136 @@
+//! A probe that makes latencies up, for demos and for exercising the
+//! dashboard and alert rules without touching the network.
+//!
+//! Each target follows a [`Pattern`] over its sample count, so a run is the
+//! same every time:
+//!
+//! ```text
+//! steady 20ms                    always 20ms
+//! spike 20ms 300ms every 10      20ms, every 10th sample 300ms
+//! drift 20ms +5ms                20ms, 25ms, 30ms, ...
+//! outage 20ms at 30 for 10       20ms, samples 30 to 39 lost
+//! ```
+
+use crate::{
+    Target,
+    config::parse_duration,
+    probe::{Probe, ProbeError, ProbeFuture},
+};
+use std::{collections::HashMap, str::FromStr, sync::Mutex, time::Duration};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Steady(Duration),
+    Spike {
+        base: Duration,
+        peak: Duration,
+        every: u64,
+    },
+    Drift {
+        base: Duration,
+        step: Duration,
+    },
+    Outage {
+        base: Duration,
+        at: u64,
+        length: u64,
+    },
+}
+
+impl Pattern {
+    /// The latency of sample `n`, counting from zero; `None` when lost.
+    pub fn latency(&self, n: u64) -> Option<Duration> {
+        match *self {
+            Pattern::Steady(base) => Some(base),
+            Pattern::Spike { base, peak, every } => Some(if (n + 1).is_multiple_of(every) {
+                peak
+            } else {
+                base
+            }),
+            Pattern::Drift { base, step } => {
+                Some(base + step.saturating_mul(n.min(u32::MAX as u64) as u32))
+            }
+            Pattern::Outage { base, at, length } => {
+                (!(at..at.saturating_add(length)).contains(&n)).then_some(base)
+            }
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let latency = |word: &str| {
+            parse_duration(word.trim_start_matches('+'))
+                .ok_or_else(|| format!("invalid latency '{}'", word))
+        };
+        let count = |word: &str| {
+            word.parse::<u64>()
+                .map_err(|_| format!("invalid sample count '{}'", word))
+        };
+        match words.as_slice() {
+            ["steady", base] => Ok(Pattern::Steady(latency(base)?)),
+            ["spike", base, peak, "every", every] => Ok(Pattern::Spike {
+                base: latency(base)?,
+                peak: latency(peak)?,
+                every: count(every)?.max(1),
+            }),
+            ["drift", base, step] => Ok(Pattern::Drift {
+                base: latency(base)?,
+                step: latency(step)?,
+            }),
+            ["outage", base, "at", at, "for", length] => Ok(Pattern::Outage {
+                base: latency(base)?,
+                at: count(at)?,
+                length: count(length)?,
+            }),
+            _ => Err(format!(
+                "invalid pattern '{}', expected steady, spike, drift or outage",
+                text
+            )),
+        }
+    }
+}
+
+/// Follows the pattern configured for each target; others answer steadily
+/// in 20ms.
+pub struct Simulate {
+    patterns: HashMap<String, Pattern>,
+    /// Samples taken so far, per target.
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Simulate {
+    pub fn new(patterns: impl IntoIterator<Item = (String, Pattern)>) -> Self {
+        Simulate {
+            patterns: patterns.into_iter().collect(),
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Probe for Simulate {
+    fn kind(&self) -> &'static str {
+        "simulate"
+    }
+
+    fn description(&self) -> &'static str {
+        "made-up latencies following a pattern per target"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let n = {
+            let mut counts = self.counts.lock().unwrap();
+            let count = counts.entry(target.name.clone()).or_default();
+            *count += 1;
+            *count - 1
+        };
+        let result = match self.patterns.get(&target.name) {
+            Some(pattern) => pattern.latency(n).ok_or(ProbeError::Timeout),
+            None => Ok(Duration::from_millis(20)),
+        };
+        Box::pin(async move { result })
+    }
+}
@@ -0,0 +1,119 @@
+//! Desktop notifications for alerts, via the platform's notification tool:
+//! `notify-send` on Linux/BSD, `osascript` on macOS and a PowerShell toast on
+//! Windows.
+
+use super::Sink;
+use crate::{
+    alert::{self, Alert, AlertState},
+    events::Event,
+};
+use std::{collections::HashSet, io, process::Command};
+
+pub struct DesktopSink {
+    /// Targets that opted in to desktop notifications.
+    targets: HashSet<String>,
+}
+
+impl DesktopSink {
+    pub fn new(targets: HashSet<String>) -> Self {
+        DesktopSink { targets }
+    }
+}
+
+fn body(alerts: &[Alert]) -> String {
+    match alerts {
+        [alert] => match &alert.worst {
+            Some(worst) => format!(
+                "{} ({})\n{}",
+                alert.condition,
+                alert.value,
+                worst.describe()
+            ),
+            None => format!("{} ({})", alert.condition, alert.value),
+        },
+        _ => alerts
+            .iter()
+            .map(Alert::summary)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn command(title: &str, body: &str, _urgent: bool) -> Command {
+    let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification \"{}\" with title \"latencee\" subtitle \"{}\"",
+        quote(body),
+        quote(title)
+    ));
+    command
+}
+
+#[cfg(windows)]
+fn command(title: &str, body: &str, _urgent: bool) -> Command {
+    let quote = |s: &str| {
+        s.replace('\'', "''")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let script = format!(
+        "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+         $xml = New-Object Windows.Data.Xml.Dom.XmlDocument; \
+         $xml.LoadXml('<toast><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text></binding></visual></toast>'); \
+         [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('latencee').Show([Windows.UI.Notifications.ToastNotification]::new($xml))",
+        quote(title),
+        quote(body)
+    );
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+    command
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn command(title: &str, body: &str, urgent: bool) -> Command {
+    let mut command = Command::new("notify-send");
+    command
+        .arg("--app-name=latencee")
+        .arg(format!(
+            "--urgency={}",
+            if urgent { "critical" } else { "normal" }
+        ))
+        .arg(title)
+        .arg(body);
+    command
+}
+
+impl Sink for DesktopSink {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        let alerts: Vec<Alert> = alerts
+            .iter()
+            .filter(|a| self.targets.contains(&a.target))
+            .cloned()
+            .collect();
+        if alerts.is_empty() {
+            return Ok(());
+        }
+
+        let urgent = alerts.iter().any(|a| a.state == AlertState::Firing);
+        let status = command(&alert::group_summary(&alerts), &body(&alerts), urgent)
+            .output()?
+            .status;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "notification command exited with {}",
+                status
+            )))
+        }
+    }
+}
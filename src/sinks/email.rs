@@ -0,0 +1,211 @@
+//! Email alerts over SMTP.
+//!
+//! Mail is submitted with `curl`, which speaks SMTP with STARTTLS or
+//! implicit TLS and handles authentication. Each alert group becomes one
+//! mail; in digest mode alerts are collected and sent as a single message
+//! once the digest period is over.
+
+use super::{Sink, run_with_input, select};
+use crate::{
+    alert::{self, Alert, AlertState},
+    config::{EmailConfig, SmtpTls},
+    events::Event,
+    host, stats, timefmt,
+};
+use std::{
+    io,
+    process::Command,
+    time::{Instant, SystemTime},
+};
+
+pub struct EmailSink {
+    config: EmailConfig,
+    host: String,
+    /// Alerts waiting for the digest and when the first of them arrived.
+    pending: Vec<Alert>,
+    pending_since: Option<Instant>,
+    sent: u64,
+}
+
+impl EmailSink {
+    pub fn new(config: EmailConfig) -> Self {
+        EmailSink {
+            config,
+            host: host::hostname(),
+            pending: Vec::new(),
+            pending_since: None,
+            sent: 0,
+        }
+    }
+
+    fn send(&mut self, subject: &str, body: &str) -> io::Result<()> {
+        let now = SystemTime::now();
+        self.sent += 1;
+        let mut message = String::new();
+        let mut header = |name: &str, value: &str| {
+            message.push_str(name);
+            message.push_str(": ");
+            message.push_str(value);
+            message.push_str("\r\n");
+        };
+        header("From", &self.config.from);
+        header("To", &self.config.to.join(", "));
+        header("Subject", &encode_header(subject));
+        header("Date", &timefmt::rfc2822(now));
+        header(
+            "Message-ID",
+            &format!(
+                "<latencee.{}.{}.{}@{}>",
+                timefmt::compact(now),
+                std::process::id(),
+                self.sent,
+                self.host
+            ),
+        );
+        header("MIME-Version", "1.0");
+        header("Content-Type", "text/plain; charset=utf-8");
+        header("Content-Transfer-Encoding", "8bit");
+        message.push_str("\r\n");
+        for line in body.lines() {
+            // Dot-stuffing is done by curl; only normalize line endings.
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+
+        let scheme = match self.config.tls {
+            SmtpTls::Implicit => "smtps",
+            SmtpTls::StartTls | SmtpTls::None => "smtp",
+        };
+        let mut command = Command::new(&self.config.curl);
+        command
+            .args(["--silent", "--show-error"])
+            .arg("--url")
+            .arg(format!("{}://{}", scheme, self.config.server))
+            .arg("--mail-from")
+            .arg(address(&self.config.from));
+        if self.config.tls == SmtpTls::StartTls {
+            command.arg("--ssl-reqd");
+        }
+        for to in &self.config.to {
+            command.arg("--mail-rcpt").arg(address(to));
+        }
+        if let Some(username) = &self.config.username {
+            command.arg("--user").arg(format!(
+                "{}:{}",
+                username,
+                self.config.password.as_deref().unwrap_or("")
+            ));
+        }
+        command.args(["--upload-file", "-"]);
+        run_with_input(&mut command, message.as_bytes())
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.config.server, e)))
+    }
+
+    fn send_alerts(&mut self, alerts: &[Alert]) -> io::Result<()> {
+        let subject = format!("[latencee] {}", alert::group_summary(alerts));
+        let mut body = String::new();
+        if alerts.len() > 1 {
+            for alert in alerts {
+                body.push_str(&format!(
+                    "{}  {}\n",
+                    timefmt::short(alert.at),
+                    alert.summary()
+                ));
+            }
+            body.push('\n');
+        }
+        for alert in alerts {
+            body.push_str(&describe(alert));
+            body.push('\n');
+        }
+        body.push_str(&format!("-- \nlatencee on {}\n", self.host));
+        self.send(&subject, &body)
+    }
+}
+
+fn describe(alert: &Alert) -> String {
+    let mut text = format!(
+        "{}\n  Time:      {}\n  Condition: {} ({})\n  Status:    {}\n  Latency:   {}\n",
+        alert.summary(),
+        timefmt::rfc3339(alert.at),
+        alert.condition,
+        alert.value,
+        alert.status.label(),
+        stats::format_ms(alert.latency)
+    );
+    if let Some(duration) = alert.duration {
+        text.push_str(&format!("  Lasted:    {}\n", timefmt::duration(duration)));
+    }
+    if let Some(worst) = &alert.worst {
+        text.push_str(&format!("  Worst:     {}\n", worst.describe()));
+    }
+    text
+}
+
+/// The bare address of `Name <addr>`.
+fn address(mailbox: &str) -> String {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => mailbox[start..=end].to_string(),
+        _ => format!("<{}>", mailbox.trim()),
+    }
+}
+
+/// RFC 2047 encoded word for non-ASCII header values.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    format!("=?UTF-8?B?{}?=", base64(value.as_bytes()))
+}
+
+/// Standard base64, padded.
+pub fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl Sink for EmailSink {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        if let Event::Alerts(alerts) = event {
+            let mut alerts = select(alerts, &self.config.targets, &self.config.rules);
+            alerts.retain(|a| a.state == AlertState::Firing || self.config.recovery);
+            if !alerts.is_empty() {
+                if self.config.digest.is_none() {
+                    return self.send_alerts(&alerts);
+                }
+                self.pending.extend(alerts);
+                self.pending_since.get_or_insert_with(Instant::now);
+            }
+        }
+
+        // Samples arrive every few seconds, which is plenty to time digests.
+        if let (Some(digest), Some(since)) = (self.config.digest, self.pending_since)
+            && since.elapsed() >= digest
+        {
+            let alerts = std::mem::take(&mut self.pending);
+            self.pending_since = None;
+            return self.send_alerts(&alerts);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Pings a Healthchecks.io check, or any service with the same ping API,
+//! as a dead man's switch: the check goes down when the pings stop because
+//! the monitoring host died, lost its network or hung.
+//!
+//! Every `interval` the URL is pinged while no watched target is in an
+//! outage, and `<url>/fail` while one is, with the outages in the body for
+//! the check's log. A change between the two is pinged right away. Requests
+//! are made with `curl`, like the webhooks.
+
+use super::{Sink, run_with_input};
+use crate::{config::HealthchecksConfig, events::Event, timefmt};
+use std::{
+    collections::BTreeMap,
+    io,
+    process::Command,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+pub struct HealthchecksSink {
+    config: HealthchecksConfig,
+    /// Watched targets in an outage, and since when.
+    down: BTreeMap<Arc<str>, SystemTime>,
+    /// When the last ping was made and whether it reported a failure.
+    pinged: Option<(Instant, bool)>,
+}
+
+impl HealthchecksSink {
+    pub fn new(config: HealthchecksConfig) -> Self {
+        HealthchecksSink {
+            config,
+            down: BTreeMap::new(),
+            pinged: None,
+        }
+    }
+
+    fn watches(&self, target: &str) -> bool {
+        self.config.targets.is_empty() || self.config.targets.iter().any(|t| t == target)
+    }
+
+    /// What the check's log shows for this ping.
+    fn body(&self) -> String {
+        if self.down.is_empty() {
+            return "All targets answer.\n".to_string();
+        }
+        self.down
+            .iter()
+            .map(|(target, since)| format!("{} down since {}\n", target, timefmt::rfc3339(*since)))
+            .collect()
+    }
+
+    fn ping(&self, failing: bool) -> io::Result<()> {
+        let url = if failing {
+            format!("{}/fail", self.config.url.trim_end_matches('/'))
+        } else {
+            self.config.url.clone()
+        };
+        let mut command = Command::new(&self.config.curl);
+        command
+            .args(["--silent", "--show-error", "--fail"])
+            .arg("--max-time")
+            .arg(self.config.timeout.as_secs_f64().to_string())
+            .args(["-H", "Content-Type: text/plain"])
+            .args(["--data-binary", "@-"])
+            .arg(&url);
+        run_with_input(&mut command, self.body().as_bytes())
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e)))
+    }
+}
+
+impl Sink for HealthchecksSink {
+    fn name(&self) -> &'static str {
+        "healthchecks"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::OutageStart { target, at } if self.watches(target) => {
+                self.down.insert(Arc::clone(target), *at);
+            }
+            Event::OutageEnd { target, .. } => {
+                self.down.remove(target);
+            }
+            Event::Sample { .. } => {}
+            _ => return Ok(()),
+        }
+        let failing = !self.down.is_empty();
+        let due = match self.pinged {
+            Some((at, failed)) => failed != failing || at.elapsed() >= self.config.interval,
+            None => true,
+        };
+        if !due {
+            return Ok(());
+        }
+        // Failed pings are retried at the next interval, not sooner.
+        self.pinged = Some((Instant::now(), failing));
+        self.ping(failing)
+    }
+}
@@ -0,0 +1,103 @@
+//! Runs the `on_fire` / `on_resolve` commands of alert rules.
+//!
+//! The alert is described in environment variables: `RULE`, `TARGET`,
+//! `STATE`, `STATUS`, `RTT` (milliseconds, empty after a lost probe),
+//! `VALUE`, `CONDITION` and, when resolving, `DURATION` in seconds plus the
+//! worst values seen while firing: `WORST`, `MAX_RTT` and `LOST`.
+
+use super::{Sink, shell};
+use crate::{
+    alert::{AlertState, Rule},
+    events::Event,
+    stats,
+};
+use std::{collections::HashMap, io};
+
+pub struct HookSink {
+    /// `(on_fire, on_resolve)` by rule name.
+    commands: HashMap<String, (Option<String>, Option<String>)>,
+}
+
+impl HookSink {
+    pub fn new(rules: &[Rule]) -> Self {
+        HookSink {
+            commands: rules
+                .iter()
+                .map(|r| (r.name.clone(), (r.on_fire.clone(), r.on_resolve.clone())))
+                .collect(),
+        }
+    }
+}
+
+impl Sink for HookSink {
+    fn name(&self) -> &'static str {
+        "hook"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        for alert in alerts {
+            let Some((on_fire, on_resolve)) = self.commands.get(&alert.rule) else {
+                continue;
+            };
+            let command = match alert.state {
+                AlertState::Firing => on_fire,
+                AlertState::Resolved => on_resolve,
+            };
+            let Some(command) = command else {
+                continue;
+            };
+
+            let status = shell(command)
+                .env("RULE", &alert.rule)
+                .env("TARGET", &alert.target)
+                .env("STATE", alert.state.label())
+                .env("STATUS", alert.status.label())
+                .env(
+                    "RTT",
+                    alert
+                        .latency
+                        .map_or(String::new(), |l| stats::millis(l).to_string()),
+                )
+                .env("VALUE", &alert.value)
+                .env("CONDITION", &alert.condition)
+                .env(
+                    "DURATION",
+                    alert
+                        .duration
+                        .map_or(String::new(), |d| d.as_secs().to_string()),
+                )
+                .env(
+                    "WORST",
+                    alert.worst.as_ref().map_or("", |w| w.value.as_str()),
+                )
+                .env(
+                    "MAX_RTT",
+                    alert
+                        .worst
+                        .as_ref()
+                        .and_then(|w| w.max_latency)
+                        .map_or(String::new(), |l| stats::millis(l).to_string()),
+                )
+                .env(
+                    "LOST",
+                    alert
+                        .worst
+                        .as_ref()
+                        .map_or(String::new(), |w| w.lost.to_string()),
+                )
+                .status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "{} hook of rule {} exited with {}",
+                    alert.state.label(),
+                    alert.rule,
+                    status
+                )));
+            }
+        }
+        Ok(())
+    }
+}
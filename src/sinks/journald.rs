@@ -0,0 +1,151 @@
+//! Structured logging to the systemd journal using its native protocol.
+//!
+//! Each entry is a datagram of `FIELD=value` lines sent to journald's socket,
+//! so `journalctl -t latencee -o json` shows `TARGET`, `RTT_MS`, `STATUS` and
+//! friends as separate fields.
+
+use super::Sink;
+use crate::{ConnectionStatus, config::JournaldConfig, events::Event};
+use std::{io, os::unix::net::UnixDatagram};
+
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+pub struct JournaldSink {
+    config: JournaldConfig,
+    socket: Option<UnixDatagram>,
+}
+
+fn priority(status: ConnectionStatus) -> u8 {
+    match status {
+        ConnectionStatus::Good => 6,
+        ConnectionStatus::Fair => 5,
+        ConnectionStatus::Poor => 4,
+        ConnectionStatus::Timeout => 3,
+    }
+}
+
+/// Appends one field. Values containing newlines use the length-prefixed
+/// binary form of the protocol.
+fn push_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+fn rtt_ms(latency: Option<std::time::Duration>) -> Option<String> {
+    latency.map(|lat| format!("{:.3}", lat.as_secs_f64() * 1000.0))
+}
+
+impl JournaldSink {
+    pub fn new(config: JournaldConfig) -> Self {
+        JournaldSink {
+            config,
+            socket: None,
+        }
+    }
+
+    fn entry(&self, event: &Event) -> Option<Vec<u8>> {
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        let (priority, message) = match event {
+            Event::Alerts(_) => return None,
+            Event::Sample {
+                target,
+                latency,
+                status,
+                ..
+            } => {
+                if !self.config.samples {
+                    return None;
+                }
+                fields.push(("EVENT", "sample".to_string()));
+                fields.push(("STATUS", status.label().to_string()));
+                let text = match latency {
+                    Some(lat) => format!("{}: {}ms", target, lat.as_millis()),
+                    None => format!("{}: timeout", target),
+                };
+                (7, text)
+            }
+            Event::StatusChange {
+                target, from, to, ..
+            } => {
+                fields.push(("EVENT", "status_change".to_string()));
+                fields.push(("STATUS", to.label().to_string()));
+                fields.push(("PREVIOUS_STATUS", from.label().to_string()));
+                (
+                    priority(*to),
+                    format!("{} changed from {} to {}", target, from.label(), to.label()),
+                )
+            }
+            Event::OutageStart { target, .. } => {
+                fields.push(("EVENT", "outage_start".to_string()));
+                fields.push(("STATUS", ConnectionStatus::Timeout.label().to_string()));
+                (3, format!("{} is unreachable", target))
+            }
+            Event::OutageEnd {
+                target, duration, ..
+            } => {
+                fields.push(("EVENT", "outage_end".to_string()));
+                fields.push(("OUTAGE_DURATION_S", duration.as_secs().to_string()));
+                (
+                    5,
+                    format!(
+                        "{} is reachable again after {}s",
+                        target,
+                        duration.as_secs()
+                    ),
+                )
+            }
+        };
+
+        let latency = match event {
+            Event::Sample { latency, .. } | Event::StatusChange { latency, .. } => *latency,
+            _ => None,
+        };
+
+        let mut buf = Vec::with_capacity(256);
+        push_field(&mut buf, "MESSAGE", &message);
+        push_field(&mut buf, "PRIORITY", &priority.to_string());
+        push_field(&mut buf, "SYSLOG_IDENTIFIER", &self.config.identifier);
+        push_field(&mut buf, "TARGET", event.target());
+        if let Some(rtt) = rtt_ms(latency) {
+            push_field(&mut buf, "RTT_MS", &rtt);
+        }
+        for (key, value) in &fields {
+            push_field(&mut buf, key, value);
+        }
+        Some(buf)
+    }
+}
+
+impl Sink for JournaldSink {
+    fn name(&self) -> &'static str {
+        "journald"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Some(entry) = self.entry(event) else {
+            return Ok(());
+        };
+        if self.socket.is_none() {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(SOCKET)?;
+            self.socket = Some(socket);
+        }
+        let result = self
+            .socket
+            .as_ref()
+            .expect("connected above")
+            .send(&entry)
+            .map(drop);
+        if result.is_err() {
+            self.socket = None;
+        }
+        result
+    }
+}
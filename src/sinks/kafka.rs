@@ -0,0 +1,132 @@
+//! Kafka producer sink.
+//!
+//! Like probing, this leans on a widely packaged system tool instead of a
+//! protocol implementation: one long-running `kcat -P` child receives a
+//! `key<TAB>value` line per sample on stdin. kcat takes care of partitioning
+//! (Java-compatible murmur2, so all samples of a target land on the same
+//! partition), batching, TLS and SASL.
+
+use super::Sink;
+use crate::{config::KafkaConfig, events::Event, host, json::Json, stats, timefmt};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+pub struct KafkaSink {
+    config: KafkaConfig,
+    host: String,
+    producer: Option<(Child, ChildStdin)>,
+    /// Last line kcat wrote to stderr, used to explain failures.
+    last_stderr: Arc<Mutex<Option<String>>>,
+}
+
+impl KafkaSink {
+    pub fn new(config: KafkaConfig) -> Self {
+        KafkaSink {
+            config,
+            host: host::hostname(),
+            producer: None,
+            last_stderr: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn spawn(&self) -> io::Result<(Child, ChildStdin)> {
+        let mut command = Command::new(&self.config.kcat);
+        command
+            .arg("-P")
+            .arg("-b")
+            .arg(self.config.brokers.join(","))
+            .arg("-t")
+            .arg(&self.config.topic)
+            .arg("-K")
+            .arg("\t")
+            .arg("-X")
+            .arg("partitioner=murmur2_random");
+        for (key, value) in &self.config.properties {
+            command.arg("-X").arg(format!("{}={}", key, value));
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot run {}: {}", self.config.kcat, e))
+            })?;
+
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let last_stderr = Arc::clone(&self.last_stderr);
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                *last_stderr.lock().unwrap() = Some(line);
+            }
+        });
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        Ok((child, stdin))
+    }
+
+    fn record(&self, event: &Event) -> Option<String> {
+        let Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } = event
+        else {
+            return None;
+        };
+        let value = Json::object([
+            ("host", Json::from(self.host.as_str())),
+            ("target", Json::from(&**target)),
+            ("at", Json::from(timefmt::rfc3339(*at))),
+            ("rtt_ms", Json::from(latency.map(stats::millis))),
+            ("status", Json::from(status.label())),
+        ]);
+        // The key ends at the first tab and the record at the newline.
+        let key = target.replace(['\t', '\n'], " ");
+        Some(format!("{}\t{}\n", key, value))
+    }
+
+    fn failure(&mut self, error: io::Error) -> io::Error {
+        if let Some((mut child, _)) = self.producer.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        match self.last_stderr.lock().unwrap().take() {
+            Some(reason) => io::Error::new(error.kind(), format!("kcat: {}", reason)),
+            None => error,
+        }
+    }
+}
+
+impl Sink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Some(line) = self.record(event) else {
+            return Ok(());
+        };
+
+        if let Some((child, _)) = &mut self.producer
+            && let Ok(Some(status)) = child.try_wait()
+        {
+            let error = io::Error::other(format!("kcat exited with {}", status));
+            return Err(self.failure(error));
+        }
+        if self.producer.is_none() {
+            self.producer = Some(self.spawn()?);
+        }
+
+        let (_, stdin) = self.producer.as_mut().expect("spawned above");
+        match stdin.write_all(line.as_bytes()).and_then(|_| stdin.flush()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(self.failure(e)),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Outputs that receive [`Event`]s.
+//!
+//! Every sink runs on its own thread so that slow I/O never delays probing
+//! or rendering; the UI loop only pushes events into a channel. The channel
+//! holds up to [`QUEUE`] events: a sink that falls further behind loses its
+//! oldest events, which is counted and reported, rather than holding up
+//! probing or taking ever more memory.
+
+pub mod desktop;
+pub mod email;
+pub mod healthchecks;
+pub mod hook;
+#[cfg(unix)]
+pub mod journald;
+pub mod kafka;
+pub mod mqtt;
+pub mod netdata;
+pub mod opsgenie;
+pub mod pagerduty;
+pub mod pushgateway;
+pub mod socket;
+pub mod sound;
+pub mod syslog;
+pub mod telegram;
+pub mod uptime_kuma;
+pub mod wasm;
+pub mod webhook;
+pub mod zabbix;
+
+use crate::rt::channel;
+use crate::{alert::Alert, config::Config, events::Event};
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Events waiting for a sink.
+pub const QUEUE: usize = 1024;
+
+pub trait Sink: Send {
+    fn name(&self) -> &'static str;
+
+    fn handle(&mut self, event: &Event) -> io::Result<()>;
+}
+
+/// A command running `command` through the platform's shell.
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Runs `command` with `input` on stdin, failing with the last line it wrote
+/// to stderr if it exits unsuccessfully. What it writes to stdout, such as
+/// the answer curl receives, is discarded.
+fn run_with_input(command: &mut Command, input: &[u8]) -> io::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", program, e)))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input)?;
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().last().map(str::trim) {
+        Some(line) if !line.is_empty() => Err(io::Error::other(line.to_string())),
+        _ => Err(io::Error::other(format!("{} {}", program, output.status))),
+    }
+}
+
+/// The alerts of a group matching a sink's `targets` and `rules` filters;
+/// empty filters match everything.
+fn select(alerts: &[Alert], targets: &[String], rules: &[String]) -> Vec<Alert> {
+    alerts
+        .iter()
+        .filter(|a| {
+            (targets.is_empty() || targets.contains(&a.target))
+                && (rules.is_empty() || rules.contains(&a.rule))
+        })
+        .cloned()
+        .collect()
+}
+
+/// POSTs a JSON `body` to `url` with curl.
+fn post_json(
+    curl: &str,
+    url: &str,
+    headers: &[String],
+    timeout: Duration,
+    body: &str,
+) -> io::Result<()> {
+    let mut command = Command::new(curl);
+    command
+        .args(["--silent", "--show-error", "--fail"])
+        .arg("--max-time")
+        .arg(timeout.as_secs_f64().to_string())
+        .args(["-H", "Content-Type: application/json"]);
+    for header in headers {
+        command.arg("-H").arg(header);
+    }
+    command.args(["--data-binary", "@-"]).arg(url);
+    run_with_input(&mut command, body.as_bytes())
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e)))
+}
+
+/// Calls `attempt` until it succeeds, at most `retries` more times, backing
+/// off 1s, 2s, 4s, ... up to a minute in between.
+fn retry(retries: u32, mut attempt: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    let mut tries = 0;
+    loop {
+        match attempt() {
+            Ok(()) => return Ok(()),
+            Err(e) if tries >= retries => return Err(e),
+            Err(_) => {
+                thread::sleep(Duration::from_secs(1 << tries.min(6)));
+                tries += 1;
+            }
+        }
+    }
+}
+
+struct Worker {
+    name: &'static str,
+    sender: channel::Sender<Event>,
+    thread: thread::JoinHandle<()>,
+    /// Events dropped because the queue was full.
+    dropped: AtomicU64,
+}
+
+/// The set of running sinks.
+#[derive(Default)]
+pub struct Sinks {
+    workers: Vec<Worker>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Sinks {
+    /// Starts every output configured in `config`. Audible alerts stay quiet
+    /// while `muted` is set.
+    pub fn from_config(config: &Config, muted: &Arc<AtomicBool>) -> Self {
+        let mut sinks = Sinks::default();
+        if let Some(syslog) = &config.syslog {
+            sinks.add(Box::new(syslog::SyslogSink::new(syslog.clone())));
+        }
+        if let Some(kafka) = &config.kafka {
+            sinks.add(Box::new(kafka::KafkaSink::new(kafka.clone())));
+        }
+        if let Some(socket) = &config.socket {
+            sinks.add(Box::new(socket::SocketSink::new(socket.clone())));
+        }
+        if let Some(pushgateway) = &config.pushgateway {
+            sinks.add(Box::new(pushgateway::PushgatewaySink::new(
+                pushgateway.clone(),
+            )));
+        }
+        if let Some(zabbix) = &config.zabbix {
+            sinks.add(Box::new(zabbix::ZabbixSink::new(zabbix.clone())));
+        }
+        if let Some(healthchecks) = &config.healthchecks {
+            sinks.add(Box::new(healthchecks::HealthchecksSink::new(
+                healthchecks.clone(),
+            )));
+        }
+        if let Some(uptime_kuma) = &config.uptime_kuma {
+            sinks.add(Box::new(uptime_kuma::UptimeKumaSink::new(
+                uptime_kuma.clone(),
+            )));
+        }
+        if let Some(mqtt) = &config.mqtt {
+            sinks.add(Box::new(mqtt::MqttSink::new(mqtt.clone())));
+        }
+        #[cfg(unix)]
+        if let Some(journald) = &config.journald {
+            sinks.add(Box::new(journald::JournaldSink::new(journald.clone())));
+        }
+        for webhook in &config.webhooks {
+            sinks.add(Box::new(webhook::WebhookSink::new(webhook.clone())));
+        }
+        if let Some(pagerduty) = &config.pagerduty {
+            sinks.add(Box::new(pagerduty::PagerDutySink::new(pagerduty.clone())));
+        }
+        if let Some(opsgenie) = &config.opsgenie {
+            sinks.add(Box::new(opsgenie::OpsgenieSink::new(opsgenie.clone())));
+        }
+        if let Some(telegram) = &config.telegram {
+            sinks.add(Box::new(telegram::TelegramSink::new(telegram.clone())));
+        }
+        for email in &config.emails {
+            sinks.add(Box::new(email::EmailSink::new(email.clone())));
+        }
+        for module in &config.wasm_sinks {
+            sinks.add(Box::new(wasm::WasmSink::new(module.clone())));
+        }
+        if let Some(desktop) = &config.desktop {
+            sinks.add(Box::new(desktop::DesktopSink::new(
+                desktop.targets.iter().cloned().collect(),
+            )));
+        }
+        if let Some(sound) = &config.sound {
+            sinks.add(Box::new(sound::SoundSink::new(
+                sound.targets.iter().cloned().collect(),
+                sound.command.clone(),
+                Arc::clone(muted),
+            )));
+        }
+        sinks
+    }
+
+    pub fn add(&mut self, mut sink: Box<dyn Sink>) {
+        let (sender, receiver) = channel::bounded::<Event>(QUEUE);
+        let last_error = Arc::clone(&self.last_error);
+        let name = sink.name();
+        // Ends once the sender is gone and every queued event is handled.
+        let thread = thread::spawn(move || {
+            while let Ok(event) = receiver.recv_blocking() {
+                if let Err(e) = sink.handle(&event) {
+                    *last_error.lock().unwrap() = Some(format!("{}: {}", sink.name(), e));
+                }
+            }
+        });
+        self.workers.push(Worker {
+            name,
+            sender,
+            thread,
+            dropped: AtomicU64::new(0),
+        });
+    }
+
+    /// Queues `event` for every sink. A sink whose queue is full loses its
+    /// oldest event instead.
+    pub fn emit(&self, event: &Event) {
+        for worker in &self.workers {
+            if let Ok(Some(_)) = worker.sender.force_send(event.clone()) {
+                worker.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Events each sink dropped so far because it fell behind.
+    pub fn dropped(&self) -> Vec<(&'static str, u64)> {
+        self.workers
+            .iter()
+            .map(|worker| (worker.name, worker.dropped.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Stops accepting events and waits up to `grace` for the sinks to
+    /// deliver those already queued. Returns the names of sinks that are
+    /// still busy, whose remaining events are lost.
+    pub fn shutdown(&mut self, grace: Duration) -> Vec<&'static str> {
+        let deadline = Instant::now() + grace;
+        let threads: Vec<_> = self
+            .workers
+            .drain(..)
+            .map(|worker| (worker.name, worker.thread))
+            .collect();
+        let mut busy = Vec::new();
+        for (name, thread) in threads {
+            while !thread.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if thread.is_finished() {
+                let _ = thread.join();
+            } else {
+                busy.push(name);
+            }
+        }
+        busy
+    }
+
+    /// The most recent sink failure, cleared once read.
+    pub fn take_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().take()
+    }
+}
@@ -0,0 +1,198 @@
+//! Publishes per-target latency and status to an MQTT broker.
+//!
+//! Topics, below the configured prefix:
+//!
+//! - `<prefix>/status`: `online`/`offline` (retained, offline via last will)
+//! - `<prefix>/<target>/rtt`: latency in milliseconds, only for answered probes
+//! - `<prefix>/<target>/status`: `good`, `fair`, `poor` or `timeout` (retained)
+//! - `<prefix>/<target>/state`: JSON with status, latency and timestamp (retained)
+//!
+//! With Home Assistant discovery enabled, entity configs are published under
+//! the discovery prefix the first time a target is seen after connecting: a
+//! latency and a status sensor and a reachability binary sensor per target.
+//! Their ids and device are scoped by the topic prefix, so that instances
+//! publishing below different prefixes to one broker stay apart.
+
+use super::Sink;
+use crate::{
+    config::MqttConfig,
+    events::Event,
+    json::Json,
+    mqtt::{Client, ConnectOptions},
+    stats, timefmt,
+};
+use std::{collections::HashSet, io};
+
+pub struct MqttSink {
+    config: MqttConfig,
+    client: Option<Client>,
+    /// Targets whose discovery configs were published on this connection.
+    announced: HashSet<String>,
+}
+
+/// Turns a target name into a topic level / Home Assistant object id.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl MqttSink {
+    pub fn new(config: MqttConfig) -> Self {
+        MqttSink {
+            config,
+            client: None,
+            announced: HashSet::new(),
+        }
+    }
+
+    fn availability_topic(&self) -> String {
+        format!("{}/status", self.config.topic_prefix)
+    }
+
+    fn client(&mut self) -> io::Result<&mut Client> {
+        if self.client.is_none() {
+            let availability = self.availability_topic();
+            let mut client = Client::connect(&ConnectOptions {
+                address: &self.config.broker,
+                client_id: &self.config.client_id,
+                username: self.config.username.as_deref(),
+                password: self.config.password.as_deref(),
+                will: Some((&availability, "offline")),
+            })?;
+            client.publish(&availability, b"online", true)?;
+            self.announced.clear();
+            self.client = Some(client);
+        }
+        Ok(self.client.as_mut().expect("connected above"))
+    }
+
+    fn announce(&mut self, target: &str) -> io::Result<()> {
+        let Some(discovery_prefix) = self.config.discovery_prefix.clone() else {
+            return Ok(());
+        };
+        if self.announced.contains(target) {
+            return Ok(());
+        }
+
+        let scope = slug(&self.config.topic_prefix);
+        let id = format!("{}_{}", scope, slug(target));
+        let base = format!("{}/{}", self.config.topic_prefix, slug(target));
+        let availability = self.availability_topic();
+        let device = Json::object([
+            ("identifiers", Json::from(vec![scope.as_str()])),
+            ("name", Json::from(self.config.topic_prefix.as_str())),
+        ]);
+        let entities = [
+            (
+                "sensor",
+                "rtt",
+                Json::object([
+                    ("name", Json::from(format!("{} latency", target))),
+                    ("unique_id", Json::from(format!("{}_rtt", id))),
+                    ("state_topic", Json::from(format!("{}/rtt", base))),
+                    ("unit_of_measurement", Json::from("ms")),
+                    ("device_class", Json::from("duration")),
+                    ("state_class", Json::from("measurement")),
+                    ("icon", Json::from("mdi:timer-outline")),
+                    ("availability_topic", Json::from(availability.clone())),
+                    ("device", device.clone()),
+                ]),
+            ),
+            (
+                "binary_sensor",
+                "reachable",
+                Json::object([
+                    ("name", Json::from(format!("{} reachable", target))),
+                    ("unique_id", Json::from(format!("{}_reachable", id))),
+                    ("state_topic", Json::from(format!("{}/status", base))),
+                    (
+                        "value_template",
+                        Json::from("{{ 'OFF' if value == 'timeout' else 'ON' }}"),
+                    ),
+                    ("device_class", Json::from("connectivity")),
+                    ("availability_topic", Json::from(availability.clone())),
+                    ("device", device.clone()),
+                ]),
+            ),
+            (
+                "sensor",
+                "status",
+                Json::object([
+                    ("name", Json::from(format!("{} status", target))),
+                    ("unique_id", Json::from(format!("{}_status", id))),
+                    ("state_topic", Json::from(format!("{}/status", base))),
+                    ("icon", Json::from("mdi:lan-connect")),
+                    ("availability_topic", Json::from(availability)),
+                    ("device", device),
+                ]),
+            ),
+        ];
+
+        let client = self.client()?;
+        for (component, kind, payload) in entities {
+            let topic = format!("{}/{}/{}_{}/config", discovery_prefix, component, id, kind);
+            client.publish(&topic, payload.to_string().as_bytes(), true)?;
+        }
+        self.announced.insert(target.to_string());
+        Ok(())
+    }
+
+    fn publish_sample(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } = event
+        else {
+            return Ok(());
+        };
+
+        self.announce(target)?;
+        let base = format!("{}/{}", self.config.topic_prefix, slug(target));
+        let retain = self.config.retain;
+        let rtt_ms = latency.map(stats::millis);
+        let state = Json::object([
+            ("status", Json::from(status.label())),
+            ("rtt_ms", Json::from(rtt_ms)),
+            ("at", Json::from(timefmt::rfc3339(*at))),
+        ]);
+
+        let client = self.client()?;
+        if let Some(ms) = rtt_ms {
+            client.publish(
+                &format!("{}/rtt", base),
+                format!("{:.1}", ms).as_bytes(),
+                retain,
+            )?;
+        }
+        client.publish(&format!("{}/status", base), status.label().as_bytes(), true)?;
+        client.publish(
+            &format!("{}/state", base),
+            state.to_string().as_bytes(),
+            true,
+        )
+    }
+}
+
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let result = self.publish_sample(event);
+        if result.is_err() {
+            // Reconnect (and re-announce) on the next sample.
+            self.client = None;
+        }
+        result
+    }
+}
@@ -0,0 +1,180 @@
+//! Speaks Netdata's external plugin protocol on stdout, so that Netdata
+//! draws a round-trip time and a loss chart per target.
+//!
+//! A target's charts are defined with `CHART` and `DIMENSION` lines when its
+//! first result arrives. Every `update_every` the results since the last
+//! update are sent between `BEGIN` and `END`: the lowest, average and
+//! highest round-trip time in microseconds, shown as milliseconds, and the
+//! share of probes lost.
+
+use super::Sink;
+use crate::events::Event;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Chart priority, after Netdata's own network charts.
+const PRIORITY: u32 = 70_000;
+
+/// Results of one target since the last update.
+#[derive(Default)]
+struct Collected {
+    id: String,
+    probes: u64,
+    lost: u64,
+    rtts: Vec<Duration>,
+}
+
+pub struct NetdataSink {
+    update_every: Duration,
+    targets: BTreeMap<Arc<str>, Collected>,
+    updated: Option<Instant>,
+}
+
+impl NetdataSink {
+    pub fn new(update_every: Duration) -> Self {
+        NetdataSink {
+            update_every,
+            targets: BTreeMap::new(),
+            updated: None,
+        }
+    }
+
+    /// `CHART` and `DIMENSION` lines for a new target.
+    fn define(&self, name: &str, id: &str) -> String {
+        let title = name.replace(['\'', '"'], "");
+        let every = self.update_every.as_secs().max(1);
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "CHART latencee.{id}_rtt '' '{title} round-trip time' 'milliseconds' '{title}' \
+             latencee.rtt line {PRIORITY} {every} '' latencee rtt"
+        );
+        for dimension in ["min", "avg", "max"] {
+            let _ = writeln!(out, "DIMENSION {dimension} '' absolute 1 1000");
+        }
+        let _ = writeln!(
+            out,
+            "CHART latencee.{id}_loss '' '{title} packet loss' 'percentage' '{title}' \
+             latencee.loss area {} {every} '' latencee loss",
+            PRIORITY + 1
+        );
+        let _ = writeln!(out, "DIMENSION lost '' absolute 1 1000");
+        out
+    }
+
+    /// `BEGIN`, `SET` and `END` lines with what was collected, which is then
+    /// reset. Targets without results are left out, which Netdata shows as
+    /// a gap.
+    fn update(&mut self, since: Option<Duration>) -> String {
+        // After the first update, Netdata wants the time since the previous
+        // one to place the values.
+        let elapsed = since.map_or(String::new(), |d| format!(" {}", d.as_micros()));
+        let mut out = String::new();
+        for collected in self.targets.values_mut() {
+            if collected.probes == 0 {
+                continue;
+            }
+            let id = &collected.id;
+            if !collected.rtts.is_empty() {
+                let micros = |d: &Duration| d.as_micros();
+                let total: u128 = collected.rtts.iter().map(micros).sum();
+                let _ = writeln!(out, "BEGIN latencee.{id}_rtt{elapsed}");
+                let _ = writeln!(
+                    out,
+                    "SET min = {}",
+                    collected.rtts.iter().map(micros).min().unwrap_or(0)
+                );
+                let _ = writeln!(out, "SET avg = {}", total / collected.rtts.len() as u128);
+                let _ = writeln!(
+                    out,
+                    "SET max = {}",
+                    collected.rtts.iter().map(micros).max().unwrap_or(0)
+                );
+                let _ = writeln!(out, "END");
+            }
+            let _ = writeln!(out, "BEGIN latencee.{id}_loss{elapsed}");
+            let _ = writeln!(
+                out,
+                "SET lost = {}",
+                collected.lost * 100_000 / collected.probes
+            );
+            let _ = writeln!(out, "END");
+            collected.probes = 0;
+            collected.lost = 0;
+            collected.rtts.clear();
+        }
+        out
+    }
+}
+
+/// A chart ID for a target name: lowercase letters, digits and underscores,
+/// with a suffix for names that are taken already.
+fn chart_id<'a>(name: &str, taken: impl Iterator<Item = &'a str> + Clone) -> String {
+    let base: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut id = base.clone();
+    let mut n = 1;
+    while taken.clone().any(|other| other == id) {
+        n += 1;
+        id = format!("{}_{}", base, n);
+    }
+    id
+}
+
+impl Sink for NetdataSink {
+    fn name(&self) -> &'static str {
+        "netdata"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Sample {
+            target, latency, ..
+        } = event
+        else {
+            return Ok(());
+        };
+        let mut out = String::new();
+        if !self.targets.contains_key(target) {
+            let id = chart_id(target, self.targets.values().map(|c| c.id.as_str()));
+            out.push_str(&self.define(target, &id));
+            self.targets.insert(
+                Arc::clone(target),
+                Collected {
+                    id,
+                    ..Collected::default()
+                },
+            );
+        }
+        let collected = self.targets.get_mut(target).expect("inserted above");
+        collected.probes += 1;
+        match latency {
+            Some(latency) => collected.rtts.push(*latency),
+            None => collected.lost += 1,
+        }
+
+        let since = self.updated.map(|updated| updated.elapsed());
+        if since.is_none_or(|since| since >= self.update_every) {
+            self.updated = Some(Instant::now());
+            out.push_str(&self.update(since));
+        }
+        if out.is_empty() {
+            return Ok(());
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+}
@@ -0,0 +1,140 @@
+//! Opsgenie alerts through the Alert API.
+//!
+//! Alerts are created with an alias per rule/target pair, which Opsgenie
+//! uses to deduplicate them and by which they are closed on resolution.
+
+use super::{Sink, post_json, retry, select};
+use crate::{
+    alert::{Alert, AlertState},
+    config::OpsgenieConfig,
+    events::Event,
+    host,
+    json::Json,
+    stats, timefmt,
+};
+use std::{io, time::Duration};
+
+pub struct OpsgenieSink {
+    config: OpsgenieConfig,
+    host: String,
+    headers: Vec<String>,
+}
+
+impl OpsgenieSink {
+    pub fn new(config: OpsgenieConfig) -> Self {
+        let headers = vec![format!("Authorization: GenieKey {}", config.api_key)];
+        OpsgenieSink {
+            config,
+            host: host::hostname(),
+            headers,
+        }
+    }
+
+    fn alias(&self, alert: &Alert) -> String {
+        format!("latencee/{}/{}/{}", self.host, alert.rule, alert.target)
+    }
+
+    /// The URL and body of the API request for `alert`.
+    fn request(&self, alert: &Alert) -> (String, Json) {
+        let base = format!("{}/v2/alerts", self.config.api_url);
+        match alert.state {
+            AlertState::Firing => (
+                base,
+                Json::object([
+                    ("message", alert.summary().into()),
+                    ("alias", self.alias(alert).into()),
+                    (
+                        "description",
+                        format!(
+                            "{} ({}) since {}",
+                            alert.condition,
+                            alert.value,
+                            timefmt::rfc3339(alert.at)
+                        )
+                        .into(),
+                    ),
+                    ("entity", alert.target.as_str().into()),
+                    ("source", self.host.as_str().into()),
+                    ("priority", self.config.priority.as_str().into()),
+                    (
+                        "tags",
+                        vec!["latencee".to_string(), alert.rule.clone()].into(),
+                    ),
+                    (
+                        "details",
+                        Json::object([
+                            ("rule", alert.rule.as_str().into()),
+                            ("value", alert.value.as_str().into()),
+                            ("status", alert.status.label().into()),
+                            (
+                                "rtt_ms",
+                                alert
+                                    .latency
+                                    .map_or("-".to_string(), |l| stats::millis(l).to_string())
+                                    .into(),
+                            ),
+                        ]),
+                    ),
+                ]),
+            ),
+            AlertState::Resolved => (
+                format!(
+                    "{}/{}/close?identifierType=alias",
+                    base,
+                    percent_encode(&self.alias(alert))
+                ),
+                Json::object([
+                    ("source", self.host.as_str().into()),
+                    (
+                        "note",
+                        match &alert.worst {
+                            Some(worst) => format!("{}: {}", alert.summary(), worst.describe()),
+                            None => alert.summary(),
+                        }
+                        .into(),
+                    ),
+                ]),
+            ),
+        }
+    }
+}
+
+/// Percent-encodes everything but unreserved URL characters.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+impl Sink for OpsgenieSink {
+    fn name(&self) -> &'static str {
+        "opsgenie"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        for alert in select(alerts, &self.config.targets, &self.config.rules) {
+            let (url, body) = self.request(&alert);
+            let body = body.to_string();
+            retry(self.config.retries, || {
+                post_json(
+                    &self.config.curl,
+                    &url,
+                    &self.headers,
+                    Duration::from_secs(10),
+                    &body,
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
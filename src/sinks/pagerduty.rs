@@ -0,0 +1,95 @@
+//! PagerDuty incidents through the Events API v2.
+//!
+//! Every rule/target pair maps to one dedup key, so a firing alert triggers
+//! an incident and its resolution resolves the same incident.
+
+use super::{Sink, post_json, retry, select};
+use crate::{
+    alert::{Alert, AlertState},
+    config::PagerDutyConfig,
+    events::Event,
+    host,
+    json::Json,
+    stats, timefmt,
+};
+use std::{io, time::Duration};
+
+const ENQUEUE_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+pub struct PagerDutySink {
+    config: PagerDutyConfig,
+    host: String,
+}
+
+impl PagerDutySink {
+    pub fn new(config: PagerDutyConfig) -> Self {
+        PagerDutySink {
+            config,
+            host: host::hostname(),
+        }
+    }
+
+    fn event(&self, alert: &Alert) -> Json {
+        let dedup_key = format!("latencee/{}/{}/{}", self.host, alert.rule, alert.target);
+        let mut fields = vec![
+            ("routing_key", self.config.routing_key.as_str().into()),
+            ("dedup_key", dedup_key.into()),
+        ];
+        match alert.state {
+            AlertState::Firing => {
+                fields.push(("event_action", "trigger".into()));
+                fields.push((
+                    "payload",
+                    Json::object([
+                        (
+                            "summary",
+                            format!("{} ({})", alert.summary(), alert.value).into(),
+                        ),
+                        ("source", self.host.as_str().into()),
+                        ("severity", self.config.severity.as_str().into()),
+                        ("timestamp", timefmt::rfc3339(alert.at).into()),
+                        ("component", alert.target.as_str().into()),
+                        ("class", alert.rule.as_str().into()),
+                        ("group", "latencee".into()),
+                        (
+                            "custom_details",
+                            Json::object([
+                                ("condition", alert.condition.as_str().into()),
+                                ("value", alert.value.as_str().into()),
+                                ("status", alert.status.label().into()),
+                                ("rtt_ms", alert.latency.map(stats::millis).into()),
+                            ]),
+                        ),
+                    ]),
+                ));
+            }
+            AlertState::Resolved => fields.push(("event_action", "resolve".into())),
+        }
+        Json::object(fields)
+    }
+}
+
+impl Sink for PagerDutySink {
+    fn name(&self) -> &'static str {
+        "pagerduty"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        for alert in select(alerts, &self.config.targets, &self.config.rules) {
+            let body = self.event(&alert).to_string();
+            retry(self.config.retries, || {
+                post_json(
+                    &self.config.curl,
+                    ENQUEUE_URL,
+                    &[],
+                    Duration::from_secs(10),
+                    &body,
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
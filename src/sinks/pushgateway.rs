@@ -0,0 +1,220 @@
+//! Pushes per-target metrics to a Prometheus Pushgateway, for hosts that
+//! Prometheus cannot scrape, such as those behind NAT.
+//!
+//! Every `interval` the latest values of all targets are PUT to
+//! `<url>/metrics/job/<job>/instance/<instance>` in the Prometheus text
+//! format, replacing what this instance pushed before. Requests are made
+//! with `curl`, like the webhooks.
+
+use super::{Sink, email::base64, run_with_input};
+use crate::{ConnectionStatus, config::PushgatewayConfig, events::Event};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// What is known about one target.
+#[derive(Default)]
+struct Metrics {
+    /// The round-trip time of the latest answered probe.
+    rtt: Option<Duration>,
+    /// Whether the latest probe got an answer.
+    up: bool,
+    status: Option<ConnectionStatus>,
+    probes: u64,
+    lost: u64,
+    /// When the latest probe was made.
+    at: Option<SystemTime>,
+}
+
+pub struct PushgatewaySink {
+    config: PushgatewayConfig,
+    targets: BTreeMap<Arc<str>, Metrics>,
+    pushed: Option<Instant>,
+}
+
+impl PushgatewaySink {
+    pub fn new(config: PushgatewayConfig) -> Self {
+        PushgatewaySink {
+            config,
+            targets: BTreeMap::new(),
+            pushed: None,
+        }
+    }
+
+    /// The URL of this instance's group of metrics.
+    fn url(&self) -> String {
+        let mut url = format!("{}/metrics", self.config.url.trim_end_matches('/'));
+        let labels = [
+            ("job", self.config.job.as_str()),
+            ("instance", self.config.instance.as_str()),
+        ];
+        let extra = self
+            .config
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()));
+        for (name, value) in labels.into_iter().chain(extra) {
+            url.push('/');
+            url.push_str(&path_label(name, value));
+        }
+        url
+    }
+
+    /// The text exposition of every target's metrics.
+    fn body(&self) -> String {
+        let mut out = String::new();
+        let mut family =
+            |name: &str, kind: &str, help: &str, value: &dyn Fn(&Metrics) -> Option<f64>| {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                for (target, metrics) in &self.targets {
+                    if let Some(value) = value(metrics) {
+                        let _ =
+                            writeln!(out, "{}{{target=\"{}\"}} {}", name, escape(target), value);
+                    }
+                }
+            };
+        family(
+            "latencee_rtt_seconds",
+            "gauge",
+            "Round-trip time of the latest answered probe.",
+            &|m| m.rtt.map(|rtt| rtt.as_secs_f64()),
+        );
+        family(
+            "latencee_up",
+            "gauge",
+            "Whether the latest probe got an answer.",
+            &|m| Some(f64::from(u8::from(m.up))),
+        );
+        family(
+            "latencee_probes_total",
+            "counter",
+            "Probes made since latencee started.",
+            &|m| Some(m.probes as f64),
+        );
+        family(
+            "latencee_probes_lost_total",
+            "counter",
+            "Probes without an answer since latencee started.",
+            &|m| Some(m.lost as f64),
+        );
+        family(
+            "latencee_last_probe_timestamp_seconds",
+            "gauge",
+            "When the latest probe was made.",
+            &|m| {
+                m.at.map(|at| {
+                    at.duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64()
+                })
+            },
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP latencee_status Classification of the latest probe, 1 for the current one."
+        );
+        let _ = writeln!(out, "# TYPE latencee_status gauge");
+        for (target, metrics) in &self.targets {
+            let Some(current) = metrics.status else {
+                continue;
+            };
+            for status in [
+                ConnectionStatus::Good,
+                ConnectionStatus::Fair,
+                ConnectionStatus::Poor,
+                ConnectionStatus::Timeout,
+            ] {
+                let _ = writeln!(
+                    out,
+                    "latencee_status{{target=\"{}\",status=\"{}\"}} {}",
+                    escape(target),
+                    status.label(),
+                    u8::from(status == current)
+                );
+            }
+        }
+        out
+    }
+
+    fn push(&self) -> io::Result<()> {
+        let url = self.url();
+        let mut command = Command::new(&self.config.curl);
+        command
+            .args(["--silent", "--show-error", "--fail", "--request", "PUT"])
+            .arg("--max-time")
+            .arg(self.config.timeout.as_secs_f64().to_string())
+            .args(["-H", "Content-Type: text/plain; version=0.0.4"])
+            .args(["--data-binary", "@-"])
+            .arg(&url);
+        run_with_input(&mut command, self.body().as_bytes())
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e)))
+    }
+}
+
+/// A grouping label as a URL path segment pair. Values that are not plain
+/// URL characters are sent in the Pushgateway's base64 form.
+fn path_label(name: &str, value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'));
+    if plain {
+        format!("{}/{}", name, value)
+    } else {
+        let encoded = base64(value.as_bytes()).replace('+', "-").replace('/', "_");
+        format!("{}@base64/{}", name, encoded)
+    }
+}
+
+/// Escapes a label value for the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Sink for PushgatewaySink {
+    fn name(&self) -> &'static str {
+        "pushgateway"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } = event
+        else {
+            return Ok(());
+        };
+        let metrics = self.targets.entry(Arc::clone(target)).or_default();
+        metrics.probes += 1;
+        metrics.up = latency.is_some();
+        if latency.is_some() {
+            metrics.rtt = *latency;
+        } else {
+            metrics.lost += 1;
+        }
+        metrics.status = Some(*status);
+        metrics.at = Some(*at);
+
+        if self
+            .pushed
+            .is_some_and(|pushed| pushed.elapsed() < self.config.interval)
+        {
+            return Ok(());
+        }
+        // Failed pushes are retried at the next interval, not sooner.
+        self.pushed = Some(Instant::now());
+        self.push()
+    }
+}
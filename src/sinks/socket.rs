@@ -0,0 +1,145 @@
+//! Newline-delimited JSON events over a TCP or Unix stream socket, as read
+//! by vector's `socket` source and fluentd's `tcp` input.
+//!
+//! Every event is one line, the same objects `/watch` streams, with the
+//! `hostname` of this machine added. A lost connection is reopened on the
+//! next event, backing off up to a minute while the other end stays away;
+//! events queue meanwhile, and the oldest are dropped once the queue is
+//! full.
+
+use super::Sink;
+use crate::{
+    config::{SocketAddress, SocketConfig},
+    events::Event,
+    host,
+    json::Json,
+};
+use std::{
+    io::{self, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// Longest wait between connection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+pub struct SocketSink {
+    config: SocketConfig,
+    hostname: String,
+    connection: Option<Box<dyn Write + Send>>,
+    /// When to try connecting again, and the wait after that.
+    retry: Option<(Instant, Duration)>,
+}
+
+impl SocketSink {
+    pub fn new(config: SocketConfig) -> Self {
+        SocketSink {
+            config,
+            hostname: host::hostname(),
+            connection: None,
+            retry: None,
+        }
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.config.address {
+            SocketAddress::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            SocketAddress::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(not(unix))]
+            SocketAddress::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets are only available on Unix",
+            )),
+        }
+    }
+
+    /// Waits out the backoff, then connects, backing off further if that
+    /// fails.
+    fn reconnect(&mut self) -> io::Result<()> {
+        if let Some((at, _)) = self.retry {
+            thread::sleep(at.saturating_duration_since(Instant::now()));
+        }
+        match self.connect() {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.retry = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.back_off();
+                Err(e)
+            }
+        }
+    }
+
+    fn back_off(&mut self) {
+        let wait = self.retry.map_or(Duration::from_secs(1), |(_, wait)| {
+            (wait * 2).min(MAX_BACKOFF)
+        });
+        self.retry = Some((Instant::now() + wait, wait));
+    }
+
+    /// The lines of an event, empty if its type is not sent.
+    fn lines(&self, event: &Event) -> String {
+        event
+            .json()
+            .into_iter()
+            .filter_map(|json| {
+                let Json::Object(mut fields) = json else {
+                    return None;
+                };
+                let kind = fields
+                    .iter()
+                    .find(|(key, _)| key == "type")
+                    .and_then(|(_, value)| value.as_str())?;
+                if !self.config.events.is_empty() && !self.config.events.iter().any(|e| e == kind) {
+                    return None;
+                }
+                fields.insert(
+                    1,
+                    ("hostname".to_string(), Json::from(self.hostname.as_str())),
+                );
+                Some(format!("{}\n", Json::Object(fields)))
+            })
+            .collect()
+    }
+}
+
+impl Sink for SocketSink {
+    fn name(&self) -> &'static str {
+        "socket"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let lines = self.lines(event);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        if self.connection.is_none() {
+            self.reconnect()?;
+        }
+        let connection = self.connection.as_mut().expect("connected above");
+        let result = connection
+            .write_all(lines.as_bytes())
+            .and_then(|_| connection.flush());
+        if result.is_err() {
+            // Reconnect on the next event.
+            self.connection = None;
+            self.back_off();
+        }
+        result
+    }
+}
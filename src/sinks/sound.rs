@@ -0,0 +1,68 @@
+//! Audible alerts: the terminal bell or a configurable sound command.
+
+use super::{Sink, shell};
+use crate::{alert::AlertState, events::Event};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+pub struct SoundSink {
+    /// Targets that make a sound when their alert fires.
+    targets: HashSet<String>,
+    /// Shell command playing the sound; the terminal bell when unset.
+    command: Option<String>,
+    /// Toggled from the UI.
+    muted: Arc<AtomicBool>,
+}
+
+impl SoundSink {
+    pub fn new(targets: HashSet<String>, command: Option<String>, muted: Arc<AtomicBool>) -> Self {
+        SoundSink {
+            targets,
+            command,
+            muted,
+        }
+    }
+}
+
+impl Sink for SoundSink {
+    fn name(&self) -> &'static str {
+        "sound"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        let firing = alerts
+            .iter()
+            .any(|a| a.state == AlertState::Firing && self.targets.contains(&a.target));
+        if !firing || self.muted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        match &self.command {
+            None => {
+                let mut stdout = io::stdout();
+                stdout.write_all(b"\x07")?;
+                stdout.flush()
+            }
+            Some(command) => {
+                let status = shell(command).status()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!(
+                        "sound command exited with {}",
+                        status
+                    )))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! RFC 5424 syslog output for status changes and outages.
+
+use super::Sink;
+use crate::{
+    ConnectionStatus,
+    config::{SyslogConfig, SyslogTransport},
+    events::Event,
+    host, timefmt,
+};
+use std::{
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+/// Structured data ID; 32473 is the private enterprise number reserved for
+/// documentation, which is what RFC 5424 suggests for unregistered use.
+const SD_ID: &str = "latencee@32473";
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+pub struct SyslogSink {
+    config: SyslogConfig,
+    hostname: String,
+    connection: Option<Connection>,
+}
+
+impl SyslogSink {
+    pub fn new(config: SyslogConfig) -> Self {
+        SyslogSink {
+            config,
+            hostname: host::hostname(),
+            connection: None,
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        match &self.config.transport {
+            SyslogTransport::Udp(addr) => {
+                let address = addr.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("{}: no address", addr))
+                })?;
+                // From the family of the collector, so that IPv6 ones work.
+                let socket = UdpSocket::bind(match address {
+                    SocketAddr::V4(_) => "0.0.0.0:0",
+                    SocketAddr::V6(_) => "[::]:0",
+                })?;
+                socket.connect(address)?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTransport::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "local syslog sockets are only available on Unix",
+            )),
+        }
+    }
+
+    fn send(&mut self, message: &str) -> io::Result<()> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+        let result = match self.connection.as_mut().expect("connected above") {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).map(drop),
+            // RFC 6587 octet counting framing.
+            Connection::Tcp(stream) => {
+                write!(stream, "{} {}", message.len(), message).and_then(|_| stream.flush())
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(message.as_bytes()).map(drop),
+        };
+        if result.is_err() {
+            // Reconnect on the next event.
+            self.connection = None;
+        }
+        result
+    }
+
+    fn severity_for(&self, status: ConnectionStatus) -> u8 {
+        let map = &self.config.severity;
+        match status {
+            ConnectionStatus::Good => map.good,
+            ConnectionStatus::Fair => map.fair,
+            ConnectionStatus::Poor => map.poor,
+            ConnectionStatus::Timeout => map.timeout,
+        }
+    }
+
+    /// Builds the complete RFC 5424 message for an event, or `None` for
+    /// events that are not logged.
+    fn format(&self, event: &Event) -> Option<String> {
+        let (severity, msgid, params, text) = match event {
+            Event::Sample { .. } | Event::Alerts(_) => return None,
+            Event::StatusChange {
+                target,
+                from,
+                to,
+                latency,
+                ..
+            } => {
+                let mut params = vec![
+                    ("target", target.to_string()),
+                    ("from", from.label().to_string()),
+                    ("to", to.label().to_string()),
+                ];
+                if let Some(lat) = latency {
+                    params.push(("rtt_ms", format!("{:.1}", lat.as_secs_f64() * 1000.0)));
+                }
+                (
+                    self.severity_for(*to),
+                    "STATUS",
+                    params,
+                    format!("{} changed from {} to {}", target, from.label(), to.label()),
+                )
+            }
+            Event::OutageStart { target, .. } => (
+                self.config.severity.outage_start,
+                "OUTAGE_START",
+                vec![("target", target.to_string())],
+                format!("{} is unreachable", target),
+            ),
+            Event::OutageEnd {
+                target, duration, ..
+            } => (
+                self.config.severity.outage_end,
+                "OUTAGE_END",
+                vec![
+                    ("target", target.to_string()),
+                    ("duration_s", duration.as_secs().to_string()),
+                ],
+                format!(
+                    "{} is reachable again after {}s",
+                    target,
+                    duration.as_secs()
+                ),
+            ),
+        };
+
+        let pri = self.config.facility as u16 * 8 + severity as u16;
+        let data: String = params
+            .iter()
+            .map(|(key, value)| format!(" {}=\"{}\"", key, escape_param(value)))
+            .collect();
+        Some(format!(
+            "<{}>1 {} {} {} {} {} [{}{}] {}",
+            pri,
+            timefmt::rfc3339(event.at()),
+            self.hostname,
+            self.config.app_name,
+            std::process::id(),
+            msgid,
+            SD_ID,
+            data,
+            text
+        ))
+    }
+}
+
+/// Escapes a structured data parameter value as required by RFC 5424.
+fn escape_param(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Sink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        match self.format(event) {
+            Some(message) => self.send(&message),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a severity name (`err`, `warning`, ...) into its numeric code.
+pub fn severity_code(name: &str) -> Option<u8> {
+    Some(match name {
+        "emerg" | "emergency" => 0,
+        "alert" => 1,
+        "crit" | "critical" => 2,
+        "err" | "error" => 3,
+        "warning" | "warn" => 4,
+        "notice" => 5,
+        "info" | "informational" => 6,
+        "debug" => 7,
+        _ => return None,
+    })
+}
+
+/// Parses a facility name (`daemon`, `local0`, ...) into its numeric code.
+pub fn facility_code(name: &str) -> Option<u8> {
+    Some(match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        _ => {
+            let n: u8 = name.strip_prefix("local")?.parse().ok()?;
+            if n > 7 {
+                return None;
+            }
+            16 + n
+        }
+    })
+}
@@ -0,0 +1,97 @@
+//! Telegram messages sent by a bot through the Bot API.
+
+use super::{Sink, post_json, retry, select};
+use crate::{
+    alert::{self, Alert, AlertState},
+    config::TelegramConfig,
+    events::Event,
+    json::Json,
+    report, stats, timefmt,
+};
+use std::{io, time::Duration};
+
+pub struct TelegramSink {
+    config: TelegramConfig,
+}
+
+impl TelegramSink {
+    pub fn new(config: TelegramConfig) -> Self {
+        TelegramSink { config }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The message in Telegram's HTML subset: a line per alert with the recent
+/// latency as a sparkline below it.
+fn message(alerts: &[Alert]) -> String {
+    let mut text = format!("<b>{}</b>\n", escape(&alert::group_summary(alerts)));
+    for alert in alerts {
+        let icon = match alert.state {
+            AlertState::Firing => "🔴",
+            AlertState::Resolved => "🟢",
+        };
+        text.push_str(&format!(
+            "\n{} <b>{}</b> {}\n{} ({}), last {}",
+            icon,
+            escape(&alert.target),
+            escape(&alert.rule),
+            escape(&alert.condition),
+            escape(&alert.value),
+            stats::format_ms(alert.latency)
+        ));
+        if let Some(duration) = alert.duration {
+            text.push_str(&format!(", lasted {}", timefmt::duration(duration)));
+        }
+        if let Some(worst) = &alert.worst {
+            text.push_str(&format!("\n{}", escape(&worst.describe())));
+        }
+        let buckets: Vec<_> = alert.recent.iter().map(|&l| Some(l)).collect();
+        let recent = stats::summarize(alert.recent.iter().copied());
+        text.push_str(&format!(
+            "\n<pre>{}</pre>avg {} · max {} · loss {:.0}%\n",
+            report::sparkline(&buckets),
+            stats::format_ms(recent.avg),
+            stats::format_ms(recent.max),
+            recent.loss() * 100.0
+        ));
+    }
+    text
+}
+
+impl Sink for TelegramSink {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        let alerts = select(alerts, &self.config.targets, &self.config.rules);
+        if alerts.is_empty() {
+            return Ok(());
+        }
+
+        let body = Json::object([
+            ("chat_id", self.config.chat_id.as_str().into()),
+            ("text", message(&alerts).into()),
+            ("parse_mode", "HTML".into()),
+            ("disable_web_page_preview", true.into()),
+        ])
+        .to_string();
+        let url = format!(
+            "https://api.telegram.org/bot{}/sendMessage",
+            self.config.token
+        );
+        retry(self.config.retries, || {
+            post_json(&self.config.curl, &url, &[], Duration::from_secs(10), &body)
+        })
+        // The token is part of the URL; keep it out of error messages.
+        .map_err(|e| io::Error::new(e.kind(), e.to_string().replace(&self.config.token, "…")))
+    }
+}
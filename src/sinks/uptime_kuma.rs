@@ -0,0 +1,91 @@
+//! Pushes the status and round-trip time of targets to Uptime Kuma push
+//! monitors, so an existing Kuma dashboard shows what latencee measures.
+//!
+//! Every target with a push URL is pushed every `interval`, and right away
+//! when it goes from up to down or back: `status=up` or `down` by its
+//! classification, `ping` with the round-trip time in milliseconds and
+//! `msg` with the classification. Requests are made with `curl`, like the
+//! webhooks.
+
+use super::{Sink, run_with_input};
+use crate::{ConnectionStatus, config::UptimeKumaConfig, events::Event, stats};
+use std::{
+    collections::HashMap,
+    io,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub struct UptimeKumaSink {
+    config: UptimeKumaConfig,
+    /// When each target was last pushed and whether it was up then.
+    pushed: HashMap<Arc<str>, (Instant, bool)>,
+}
+
+impl UptimeKumaSink {
+    pub fn new(config: UptimeKumaConfig) -> Self {
+        UptimeKumaSink {
+            config,
+            pushed: HashMap::new(),
+        }
+    }
+
+    fn push(
+        &self,
+        url: &str,
+        up: bool,
+        status: ConnectionStatus,
+        latency: Option<Duration>,
+    ) -> io::Result<()> {
+        let mut command = Command::new(&self.config.curl);
+        command
+            .args(["--silent", "--show-error", "--fail", "--get"])
+            .arg("--max-time")
+            .arg(self.config.timeout.as_secs_f64().to_string())
+            .arg("--data-urlencode")
+            .arg(format!("status={}", if up { "up" } else { "down" }))
+            .arg("--data-urlencode")
+            .arg(format!("msg={}", status.label()));
+        if let Some(latency) = latency {
+            command
+                .arg("--data-urlencode")
+                .arg(format!("ping={}", stats::millis(latency)));
+        }
+        command.arg(url);
+        run_with_input(&mut command, &[])
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e)))
+    }
+}
+
+impl Sink for UptimeKumaSink {
+    fn name(&self) -> &'static str {
+        "uptime-kuma"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Sample {
+            target,
+            latency,
+            status,
+            ..
+        } = event
+        else {
+            return Ok(());
+        };
+        let Some((_, url)) = self.config.targets.iter().find(|(t, _)| **t == **target) else {
+            return Ok(());
+        };
+        let up = *status < self.config.down;
+        let due = match self.pushed.get(target) {
+            Some((at, was_up)) => *was_up != up || at.elapsed() >= self.config.interval,
+            None => true,
+        };
+        if !due {
+            return Ok(());
+        }
+        // Failed pushes are retried at the next interval, not sooner.
+        self.pushed.insert(Arc::clone(target), (Instant::now(), up));
+        self.push(url, up, *status, *latency)
+    }
+}
@@ -0,0 +1,171 @@
+//! Events piped into a WebAssembly module, see [`crate::wasm`]. Each event
+//! is one tab-separated line with unix-millisecond timestamps:
+//!
+//! ```text
+//! sample        <target> <at> <rtt µs or -> <status>
+//! status_change <target> <at> <from> <to>
+//! outage_start  <target> <at>
+//! outage_end    <target> <at> <duration s>
+//! alert         <target> <at> <rule> <firing|resolved> <value>
+//! ```
+
+use super::Sink;
+use crate::{ConnectionStatus, config::WasmConfig, events::Event, wasm};
+use std::{
+    io::{self, Write},
+    process::{Child, ChildStdin, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long the module may take to exit once its input ends.
+const EXIT_GRACE: Duration = Duration::from_secs(2);
+
+pub struct WasmSink {
+    config: WasmConfig,
+    running: Option<(Child, ChildStdin)>,
+}
+
+impl WasmSink {
+    pub fn new(config: WasmConfig) -> Self {
+        WasmSink {
+            config,
+            running: None,
+        }
+    }
+
+    fn start(&self) -> io::Result<(Child, ChildStdin)> {
+        let mut child = wasm::command(&self.config)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot run {}: {}", self.config.runtime[0], e),
+                )
+            })?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        Ok((child, stdin))
+    }
+}
+
+impl Sink for WasmSink {
+    fn name(&self) -> &'static str {
+        "wasm"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        if self.running.is_none() {
+            self.running = Some(self.start()?);
+        }
+        let (_, stdin) = self.running.as_mut().expect("started above");
+        let written = encode(event)
+            .iter()
+            .try_for_each(|line| writeln!(stdin, "{}", line))
+            .and_then(|()| stdin.flush());
+        if let Err(e) = written {
+            // The module exited; start it again with the next event.
+            if let Some((mut child, _)) = self.running.take() {
+                let _ = child.kill();
+                let status = child.wait()?;
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{} exited with {}", self.config.module.display(), status),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WasmSink {
+    fn drop(&mut self) {
+        if let Some((mut child, stdin)) = self.running.take() {
+            // End of input tells the module to finish; give it a moment to
+            // handle what is still in the pipe.
+            drop(stdin);
+            let deadline = Instant::now() + EXIT_GRACE;
+            while matches!(child.try_wait(), Ok(None)) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// The lines describing an event, without line endings: one per alert of a
+/// group, one otherwise.
+fn encode(event: &Event) -> Vec<String> {
+    match event {
+        Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } => vec![sample_line(target, *at, *latency, *status)],
+        Event::StatusChange {
+            target,
+            at,
+            from,
+            to,
+            ..
+        } => vec![join(&[
+            "status_change",
+            target,
+            &millis(*at),
+            from.label(),
+            to.label(),
+        ])],
+        Event::OutageStart { target, at } => vec![join(&["outage_start", target, &millis(*at)])],
+        Event::OutageEnd {
+            target,
+            at,
+            duration,
+        } => vec![join(&[
+            "outage_end",
+            target,
+            &millis(*at),
+            &duration.as_secs().to_string(),
+        ])],
+        Event::Alerts(alerts) => alerts
+            .iter()
+            .map(|alert| {
+                join(&[
+                    "alert",
+                    &alert.target,
+                    &millis(alert.at),
+                    &alert.rule,
+                    alert.state.label(),
+                    &alert.value,
+                ])
+            })
+            .collect(),
+    }
+}
+
+fn sample_line(
+    target: &str,
+    at: SystemTime,
+    latency: Option<Duration>,
+    status: ConnectionStatus,
+) -> String {
+    let rtt = latency.map_or("-".to_string(), |l| l.as_micros().to_string());
+    join(&["sample", target, &millis(at), &rtt, status.label()])
+}
+
+fn join(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|f| f.replace(['\t', '\n'], " "))
+        .collect();
+    fields.join("\t")
+}
+
+fn millis(at: SystemTime) -> String {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string()
+}
@@ -0,0 +1,248 @@
+//! Webhook sink: POSTs every alert group to a URL.
+//!
+//! Requests are made with `curl`, which brings TLS, proxies and redirects
+//! along for free. The body is a JSON document describing the alerts, or a
+//! user supplied template in which `{{field}}` placeholders are replaced by
+//! the JSON encoding of that field. Slack and Discord get a message in
+//! their own webhook format instead.
+
+use super::{Sink, post_json, retry, select};
+use crate::{
+    alert::{self, Alert, AlertState},
+    config::{WebhookConfig, WebhookFormat},
+    events::Event,
+    host,
+    json::Json,
+    report, stats, timefmt,
+};
+use std::io;
+
+pub struct WebhookSink {
+    config: WebhookConfig,
+    host: String,
+}
+
+impl WebhookSink {
+    pub fn new(config: WebhookConfig) -> Self {
+        WebhookSink {
+            config,
+            host: host::hostname(),
+        }
+    }
+
+    fn alert_json(alert: &Alert) -> Json {
+        Json::object([
+            ("target", alert.target.as_str().into()),
+            ("state", alert.state.label().into()),
+            ("rule", alert.rule.as_str().into()),
+            ("condition", alert.condition.as_str().into()),
+            ("value", alert.value.as_str().into()),
+            ("status", alert.status.label().into()),
+            ("rtt_ms", alert.latency.map(stats::millis).into()),
+            ("duration_s", alert.duration.map(|d| d.as_secs()).into()),
+            (
+                "worst",
+                alert.worst.as_ref().map_or(Json::Null, |w| {
+                    Json::object([
+                        ("value", w.value.as_str().into()),
+                        ("max_rtt_ms", w.max_latency.map(stats::millis).into()),
+                        ("lost", w.lost.into()),
+                        ("samples", w.samples.into()),
+                    ])
+                }),
+            ),
+            ("at", timefmt::rfc3339(alert.at).into()),
+            ("summary", alert.summary().into()),
+        ])
+    }
+
+    fn fields(&self, alerts: &[Alert]) -> Vec<(&'static str, Json)> {
+        vec![
+            ("host", self.host.as_str().into()),
+            ("summary", alert::group_summary(alerts).into()),
+            ("count", alerts.len().into()),
+            (
+                "alerts",
+                Json::Array(alerts.iter().map(Self::alert_json).collect()),
+            ),
+        ]
+    }
+
+    fn body(&self, alerts: &[Alert]) -> String {
+        match self.config.format {
+            WebhookFormat::Json => {
+                let fields = self.fields(alerts);
+                match &self.config.template {
+                    Some(template) => render(template, &fields),
+                    None => Json::object(fields).to_string(),
+                }
+            }
+            WebhookFormat::Slack => self.slack(alerts).to_string(),
+            WebhookFormat::Discord => self.discord(alerts).to_string(),
+        }
+    }
+
+    /// Title/value pairs shown in chat messages.
+    fn chat_fields(&self, alert: &Alert) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Target", alert.target.clone()),
+            ("Rule", alert.rule.clone()),
+            (
+                "Condition",
+                format!("{} ({})", alert.condition, alert.value),
+            ),
+            ("Latency", stats::format_ms(alert.latency)),
+        ];
+        if let Some(duration) = alert.duration {
+            fields.push(("Duration", timefmt::duration(duration)));
+        }
+        if let Some(worst) = &alert.worst {
+            fields.push(("While firing", worst.describe()));
+        }
+        let recent = stats::summarize(alert.recent.iter().copied());
+        if recent.samples > 0 {
+            fields.push((
+                "Recent",
+                format!(
+                    "avg {} · loss {:.0}%",
+                    stats::format_ms(recent.avg),
+                    recent.loss() * 100.0
+                ),
+            ));
+        }
+        fields
+    }
+
+    /// Recent latency as a sparkline in a code block, for a fixed-width font.
+    fn graph(alert: &Alert) -> String {
+        let buckets: Vec<_> = alert.recent.iter().map(|&l| Some(l)).collect();
+        format!("```{}```", report::sparkline(&buckets))
+    }
+
+    fn footer(&self) -> String {
+        format!("latencee on {}", self.host)
+    }
+
+    fn slack(&self, alerts: &[Alert]) -> Json {
+        let attachment = |alert: &Alert| {
+            let fields = self.chat_fields(alert).into_iter().map(|(title, value)| {
+                Json::object([
+                    ("title", title.into()),
+                    ("value", value.into()),
+                    ("short", true.into()),
+                ])
+            });
+            let ts = alert
+                .at
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            Json::object([
+                ("color", color(alert).into()),
+                ("title", alert.summary().into()),
+                ("fallback", alert.summary().into()),
+                ("text", Self::graph(alert).into()),
+                ("fields", Json::Array(fields.collect())),
+                ("footer", self.footer().into()),
+                ("ts", ts.into()),
+            ])
+        };
+        Json::object([
+            ("text", alert::group_summary(alerts).into()),
+            (
+                "attachments",
+                Json::Array(alerts.iter().map(attachment).collect()),
+            ),
+        ])
+    }
+
+    fn discord(&self, alerts: &[Alert]) -> Json {
+        let embed = |alert: &Alert| {
+            let fields = self.chat_fields(alert).into_iter().map(|(name, value)| {
+                Json::object([
+                    ("name", name.into()),
+                    ("value", value.into()),
+                    ("inline", true.into()),
+                ])
+            });
+            let rgb = u64::from_str_radix(&color(alert)[1..], 16).unwrap_or_default();
+            Json::object([
+                ("title", alert.summary().into()),
+                ("color", rgb.into()),
+                ("description", Self::graph(alert).into()),
+                ("fields", Json::Array(fields.collect())),
+                ("footer", Json::object([("text", self.footer().into())])),
+                ("timestamp", timefmt::rfc3339(alert.at).into()),
+            ])
+        };
+        Json::object([
+            ("username", "latencee".into()),
+            ("content", alert::group_summary(alerts).into()),
+            (
+                "embeds",
+                Json::Array(alerts.iter().take(DISCORD_MAX_EMBEDS).map(embed).collect()),
+            ),
+        ])
+    }
+}
+
+/// Discord rejects messages with more embeds; the content line still names
+/// every alert.
+const DISCORD_MAX_EMBEDS: usize = 10;
+
+fn color(alert: &Alert) -> &'static str {
+    match alert.state {
+        AlertState::Firing => "#d62728",
+        AlertState::Resolved => "#2ca02c",
+    }
+}
+
+/// Replaces `{{name}}` placeholders; unknown placeholders are left as-is.
+fn render(template: &str, fields: &[(&str, Json)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = after[..end].trim();
+        match fields.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Alerts(alerts) = event else {
+            return Ok(());
+        };
+        let alerts = select(alerts, &self.config.targets, &self.config.rules);
+        if alerts.is_empty() {
+            return Ok(());
+        }
+
+        let body = self.body(&alerts);
+        let config = &self.config;
+        retry(config.retries, || {
+            post_json(
+                &config.curl,
+                &config.url,
+                &config.headers,
+                config.timeout,
+                &body,
+            )
+        })
+    }
+}
@@ -0,0 +1,232 @@
+//! Sends per-target values to a Zabbix server or proxy with the trapper
+//! protocol that `zabbix_sender` speaks, so results land in "Zabbix
+//! trapper" items without a Zabbix agent.
+//!
+//! Values are collected and sent in one batch every `interval`. Each
+//! message is `ZBXD\x01`, the length of the JSON payload as a little endian
+//! u64 and the payload; the server answers in the same framing.
+
+use super::Sink;
+use crate::{
+    config::{ZabbixConfig, ZabbixKeys},
+    events::Event,
+    json::{self, Json},
+    stats,
+};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+
+/// Values kept while the server cannot be reached; the oldest go first.
+const BACKLOG: usize = 10_000;
+
+/// Refuses answers larger than this rather than reading them.
+const MAX_RESPONSE: u64 = 1 << 20;
+
+struct Value {
+    host: String,
+    key: String,
+    value: String,
+    at: SystemTime,
+}
+
+pub struct ZabbixSink {
+    config: ZabbixConfig,
+    pending: Vec<Value>,
+    sent: Option<Instant>,
+}
+
+impl ZabbixSink {
+    pub fn new(config: ZabbixConfig) -> Self {
+        ZabbixSink {
+            config,
+            pending: Vec::new(),
+            sent: None,
+        }
+    }
+
+    /// Queues a value for the item `key` picks, unless it is switched off.
+    fn queue(&mut self, target: &str, key: fn(&ZabbixKeys) -> &str, value: String, at: SystemTime) {
+        let key = key(&self.config.keys);
+        if key.is_empty() {
+            return;
+        }
+        let value = Value {
+            host: expand(&self.config.host, target),
+            key: expand(key, target),
+            value,
+            at,
+        };
+        if self.pending.len() == BACKLOG {
+            self.pending.remove(0);
+        }
+        self.pending.push(value);
+    }
+
+    fn payload(&self) -> Json {
+        let (now, now_ns) = clock(SystemTime::now());
+        let data = self
+            .pending
+            .iter()
+            .map(|value| {
+                let (clock, ns) = clock(value.at);
+                Json::object([
+                    ("host", Json::from(value.host.as_str())),
+                    ("key", Json::from(value.key.as_str())),
+                    ("value", Json::from(value.value.as_str())),
+                    ("clock", Json::from(clock)),
+                    ("ns", Json::from(ns)),
+                ])
+            })
+            .collect();
+        Json::object([
+            ("request", Json::from("sender data")),
+            ("data", Json::Array(data)),
+            ("clock", Json::from(now)),
+            ("ns", Json::from(now_ns)),
+        ])
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let address = self
+            .config
+            .server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other(format!("{}: no address", self.config.server)))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.config.timeout)?;
+        stream.set_read_timeout(Some(self.config.timeout))?;
+        stream.set_write_timeout(Some(self.config.timeout))?;
+
+        let payload = self.payload().to_string();
+        let mut message = Vec::with_capacity(HEADER.len() + 8 + payload.len());
+        message.extend_from_slice(HEADER);
+        message.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        stream.write_all(&message)?;
+
+        let response = read_response(&mut stream)?;
+        let total = self.pending.len();
+        self.pending.clear();
+        check_response(&response, total)
+    }
+}
+
+/// Replaces `{target}` by the target name as a key parameter, quoted when
+/// it has to be, and `{name}` by the plain name.
+fn expand(template: &str, target: &str) -> String {
+    template
+        .replace("{target}", &key_parameter(target))
+        .replace("{name}", target)
+}
+
+/// Quotes an item key parameter if it contains characters with a meaning
+/// in keys.
+fn key_parameter(value: &str) -> String {
+    if value.contains([',', ']', '[', '"', ' ']) {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Seconds and nanoseconds since the Unix epoch.
+fn clock(at: SystemTime) -> (u64, u64) {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs(), u64::from(since.subsec_nanos()))
+}
+
+fn read_response(stream: &mut TcpStream) -> io::Result<String> {
+    let mut header = [0u8; 13];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != b"ZBXD" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a Zabbix answer",
+        ));
+    }
+    let length = u64::from_le_bytes(header[5..13].try_into().unwrap());
+    if length > MAX_RESPONSE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "oversized Zabbix answer",
+        ));
+    }
+    let mut body = vec![0; length as usize];
+    stream.read_exact(&mut body)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Turns a refusal, or values the server did not take, into an error. The
+/// answer looks like `{"response":"success","info":"processed: 3; failed:
+/// 1; total: 4; seconds spent: 0.000055"}`.
+fn check_response(response: &str, total: usize) -> io::Result<()> {
+    let parsed = json::parse(response);
+    let field = |key| {
+        parsed
+            .as_ref()
+            .and_then(|json| json.get(key))
+            .and_then(Json::as_str)
+    };
+    if field("response") != Some("success") {
+        return Err(io::Error::other(format!(
+            "server refused the values: {}",
+            field("info").unwrap_or(response)
+        )));
+    }
+    let failed = field("info")
+        .and_then(|info| {
+            info.split(';')
+                .find_map(|part| part.trim().strip_prefix("failed:"))
+        })
+        .and_then(|n| n.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "{} of {} values failed; check that the trapper items exist and allow this host",
+            failed, total
+        )));
+    }
+    Ok(())
+}
+
+impl Sink for ZabbixSink {
+    fn name(&self) -> &'static str {
+        "zabbix"
+    }
+
+    fn handle(&mut self, event: &Event) -> io::Result<()> {
+        let Event::Sample {
+            target,
+            at,
+            latency,
+            status,
+        } = event
+        else {
+            return Ok(());
+        };
+        if let Some(latency) = latency {
+            let ms = stats::millis(*latency).to_string();
+            self.queue(target, |keys| &keys.rtt, ms, *at);
+        }
+        let up = u8::from(latency.is_some()).to_string();
+        self.queue(target, |keys| &keys.up, up, *at);
+        self.queue(target, |keys| &keys.status, status.label().to_string(), *at);
+
+        if self
+            .sent
+            .is_some_and(|sent| sent.elapsed() < self.config.interval)
+            || self.pending.is_empty()
+        {
+            return Ok(());
+        }
+        // Values that could not be sent are kept for the next interval.
+        self.sent = Some(Instant::now());
+        self.send()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.config.server, e)))
+    }
+}
@@ -0,0 +1,166 @@
+//! Incident snapshot bundles.
+//!
+//! A snapshot is a timestamped directory with everything worth attaching to
+//! a ticket: the in-memory history as JSON, the configuration in use, some
+//! facts about the environment and a rendered Markdown report. The same
+//! history can also be written as CSV on its own.
+
+use crate::{
+    ServerStatus, Target, host,
+    json::Json,
+    report::{self, Tally},
+    stats, timefmt,
+};
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+/// Converts a monotonic timestamp into wall-clock time.
+fn wall_time(at: Instant, now: Instant, wall_now: SystemTime) -> SystemTime {
+    wall_now - now.saturating_duration_since(at)
+}
+
+fn history_json(servers: &[ServerStatus], targets: &[Target]) -> Json {
+    let now = Instant::now();
+    let wall_now = SystemTime::now();
+    Json::Array(
+        servers
+            .iter()
+            .map(|server| {
+                let host = targets
+                    .iter()
+                    .find(|target| *target.name == *server.name)
+                    .map(|target| target.host.as_str());
+                let samples = server
+                    .history
+                    .iter()
+                    .map(|(at, status, latency)| {
+                        Json::object([
+                            (
+                                "at",
+                                Json::from(timefmt::rfc3339(wall_time(*at, now, wall_now))),
+                            ),
+                            ("status", Json::from(status.label())),
+                            ("rtt_ms", Json::from(latency.map(stats::millis))),
+                        ])
+                    })
+                    .collect();
+                Json::object([
+                    ("name", Json::from(&*server.name)),
+                    ("host", Json::from(host)),
+                    ("status", Json::from(server.status.label())),
+                    ("rtt_ms", Json::from(server.latency.map(stats::millis))),
+                    ("history", Json::Array(samples)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// A CSV field, quoted when it holds a separator or a quote.
+fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Writes the in-memory history of `servers` to `path` as CSV, one sample
+/// per line with its time, target, status and round-trip time; the number
+/// of samples written.
+pub fn write_csv(path: &Path, servers: &[ServerStatus]) -> io::Result<usize> {
+    let now = Instant::now();
+    let wall_now = SystemTime::now();
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    writeln!(out, "time,target,status,rtt_ms")?;
+    let mut written = 0;
+    for server in servers {
+        for (at, status, latency) in server.history.iter() {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                timefmt::rfc3339(wall_time(*at, now, wall_now)),
+                csv_field(&server.name),
+                status.label(),
+                latency.map_or(String::new(), |l| stats::millis(l).to_string())
+            )?;
+            written += 1;
+        }
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+fn environment_json(config: Option<&Path>) -> Json {
+    Json::object([
+        ("latencee_version", Json::from(env!("CARGO_PKG_VERSION"))),
+        ("hostname", Json::from(host::hostname())),
+        ("os", Json::from(std::env::consts::OS)),
+        ("arch", Json::from(std::env::consts::ARCH)),
+        ("pid", Json::from(std::process::id() as u64)),
+        ("taken_at", Json::from(timefmt::rfc3339(SystemTime::now()))),
+        (
+            "config_path",
+            Json::from(config.map(|p| p.display().to_string())),
+        ),
+    ])
+}
+
+/// Writes a snapshot bundle below `dir` and returns the bundle's path.
+pub fn write_bundle(
+    dir: &Path,
+    servers: &[ServerStatus],
+    targets: &[Target],
+    config: Option<&Path>,
+) -> io::Result<PathBuf> {
+    let taken_at = SystemTime::now();
+    let bundle = dir.join(format!("latencee-snapshot-{}", timefmt::compact(taken_at)));
+    fs::create_dir_all(&bundle)?;
+
+    fs::write(
+        bundle.join("history.json"),
+        history_json(servers, targets).to_string(),
+    )?;
+    fs::write(
+        bundle.join("environment.json"),
+        environment_json(config).to_string(),
+    )?;
+    match config {
+        Some(path) => {
+            fs::copy(path, bundle.join("config.toml"))?;
+        }
+        None => fs::write(
+            bundle.join("config.toml"),
+            "# no configuration file, defaults in use\n",
+        )?,
+    }
+
+    let now = Instant::now();
+    let from = servers
+        .iter()
+        .filter_map(|server| server.history.iter().next())
+        .map(|(at, _, _)| wall_time(*at, now, taken_at))
+        .min()
+        .unwrap_or(taken_at);
+    let tallies: Vec<(String, Tally)> = servers
+        .iter()
+        .map(|server| {
+            let mut tally = Tally::new(from, taken_at);
+            for (at, _, latency) in server.history.iter() {
+                tally.record(wall_time(*at, now, taken_at), *latency);
+            }
+            (server.name.to_string(), tally)
+        })
+        .collect();
+    fs::write(
+        bundle.join("report.md"),
+        report::render_markdown(from, taken_at, &tallies),
+    )?;
+
+    Ok(bundle)
+}
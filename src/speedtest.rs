@@ -0,0 +1,235 @@
+//! On-demand throughput tests, to tell a saturated line from a slow one.
+//!
+//! A test runs an external client and reads its JSON report: `iperf3`
+//! against a configured server, uploading and then downloading, or the
+//! Speedtest CLI of Ookla or the Python `speedtest-cli` against the closest
+//! Speedtest server. Results are appended to a log, one JSON object per
+//! line, by default in the storage directory next to the latency history.
+
+use crate::{
+    config::{SpeedtestConfig, SpeedtestTool},
+    json::{self, Json},
+    timefmt,
+};
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often a running client is checked for having finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a test measured.
+#[derive(Clone, Debug)]
+pub struct Throughput {
+    pub at: SystemTime,
+    /// Bits per second from the server.
+    pub download: f64,
+    /// Bits per second to the server.
+    pub upload: f64,
+    /// The round-trip time the client measured, if it reports one.
+    pub latency: Option<Duration>,
+    /// Which server was tested against.
+    pub server: String,
+}
+
+impl Throughput {
+    /// `↓ 94.1 Mbit/s ↑ 38.0 Mbit/s, 12ms to Example ISP (Berlin)`
+    pub fn describe(&self) -> String {
+        format!(
+            "↓ {:.1} Mbit/s ↑ {:.1} Mbit/s{} to {}",
+            self.download / 1e6,
+            self.upload / 1e6,
+            self.latency
+                .map_or(String::new(), |l| format!(", {}ms", l.as_millis())),
+            self.server
+        )
+    }
+
+    /// The log line of this result.
+    pub fn to_json(&self, tool: SpeedtestTool) -> Json {
+        Json::object([
+            ("time", Json::from(timefmt::rfc3339(self.at))),
+            ("tool", Json::from(tool.name())),
+            ("server", Json::from(self.server.as_str())),
+            ("download_bps", Json::from(self.download.round())),
+            ("upload_bps", Json::from(self.upload.round())),
+            (
+                "latency_ms",
+                Json::from(self.latency.map(|l| l.as_secs_f64() * 1000.0)),
+            ),
+        ])
+    }
+}
+
+/// Runs a test as configured, blocking until it finishes, and logs the
+/// result.
+pub fn run(config: &SpeedtestConfig) -> io::Result<Throughput> {
+    let result = match config.tool {
+        SpeedtestTool::Iperf3 => iperf3(config),
+        SpeedtestTool::Ookla | SpeedtestTool::SpeedtestCli => speedtest(config),
+    }?;
+    if let Some(log) = &config.log {
+        append(log, &result.to_json(config.tool))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", log.display(), e)))?;
+    }
+    Ok(result)
+}
+
+fn iperf3(config: &SpeedtestConfig) -> io::Result<Throughput> {
+    let server = config.server.as_deref().unwrap_or_default();
+    let measure = |reverse: bool| -> io::Result<f64> {
+        let mut command = Command::new(config.command());
+        command
+            .args(["--json", "--client", server])
+            .arg("--port")
+            .arg(config.port.to_string())
+            .arg("--time")
+            .arg(config.duration.as_secs().max(1).to_string());
+        if reverse {
+            command.arg("--reverse");
+        }
+        let report = output(&mut command, config.timeout)?;
+        if let Some(error) = report.get("error").and_then(Json::as_str) {
+            return Err(io::Error::other(format!("iperf3: {}", error)));
+        }
+        report
+            .get("end")
+            .and_then(|end| end.get("sum_received"))
+            .and_then(|sum| sum.get("bits_per_second"))
+            .and_then(Json::as_f64)
+            .ok_or_else(|| invalid("iperf3 report without a received total"))
+    };
+    let upload = measure(false)?;
+    let download = measure(true)?;
+    Ok(Throughput {
+        at: SystemTime::now(),
+        download,
+        upload,
+        latency: None,
+        server: format!("{}:{}", server, config.port),
+    })
+}
+
+fn speedtest(config: &SpeedtestConfig) -> io::Result<Throughput> {
+    let mut command = Command::new(config.command());
+    let ookla = config.tool == SpeedtestTool::Ookla;
+    if ookla {
+        command.args(["--format=json", "--accept-license", "--accept-gdpr"]);
+        if let Some(server) = &config.server {
+            command.arg(format!("--server-id={}", server));
+        }
+    } else {
+        command.arg("--json");
+        if let Some(server) = &config.server {
+            command.args(["--server", server]);
+        }
+    }
+    let report = output(&mut command, config.timeout)?;
+    let number = |path: &[&str]| {
+        path.iter()
+            .try_fold(&report, |json, key| json.get(key))
+            .and_then(Json::as_f64)
+    };
+    let server = report.get("server");
+    let field = |key| server.and_then(|s| s.get(key)).and_then(Json::as_str);
+    let server = match (
+        field(if ookla { "name" } else { "sponsor" }),
+        field(if ookla { "location" } else { "name" }),
+    ) {
+        (Some(name), Some(location)) => format!("{} ({})", name, location),
+        (name, location) => name.or(location).unwrap_or("unknown server").to_string(),
+    };
+    // Ookla reports bytes per second, speedtest-cli bits.
+    let (download, upload, latency) = if ookla {
+        (
+            number(&["download", "bandwidth"]).map(|b| b * 8.0),
+            number(&["upload", "bandwidth"]).map(|b| b * 8.0),
+            number(&["ping", "latency"]),
+        )
+    } else {
+        (
+            number(&["download"]),
+            number(&["upload"]),
+            number(&["ping"]),
+        )
+    };
+    let (Some(download), Some(upload)) = (download, upload) else {
+        return Err(invalid("speedtest report without download and upload"));
+    };
+    Ok(Throughput {
+        at: SystemTime::now(),
+        download,
+        upload,
+        latency: latency
+            .filter(|ms| ms.is_finite() && *ms >= 0.0)
+            .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+        server,
+    })
+}
+
+/// Runs `command` and parses what it prints as JSON, killing it after
+/// `timeout`.
+fn output(command: &mut Command, timeout: Duration) -> io::Result<Json> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", program, e)))?;
+    // Read while waiting, so a long report cannot fill the pipe and stall
+    // the client.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).map(|_| text)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait()? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} did not finish within {}s", program, timeout.as_secs()),
+                ));
+            }
+        }
+    };
+    let text = reader
+        .join()
+        .map_err(|_| io::Error::other("reading the report failed"))??;
+    // Some clients log JSON lines of their own before the report.
+    let report = json::parse(text.trim())
+        .or_else(|| text.lines().rev().find_map(|line| json::parse(line.trim())));
+    if let Some(report) = report {
+        return Ok(report);
+    }
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    Err(io::Error::other(format!(
+        "{} {}: {}",
+        program,
+        status,
+        stderr.lines().last().unwrap_or("no report")
+    )))
+}
+
+fn append(path: &Path, line: &Json) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,133 @@
+//! What the dashboard remembers between runs, such as the order and the
+//! labels of its targets, the theme last used and, without a configuration
+//! file, the view. Kept as JSON in `$XDG_STATE_HOME/latencee/dashboard.json`,
+//! `~/.local/state` when that is not set, and in `%LOCALAPPDATA%` on
+//! Windows.
+
+use crate::{theme::Theme, views::View};
+use latencee::json::{self, Json};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+#[derive(Debug, Default)]
+pub struct DashboardState {
+    /// Target names in the order their rows were arranged.
+    pub order: Vec<String>,
+    /// Names shown instead of those of targets, by target name.
+    pub labels: BTreeMap<String, String>,
+    pub view: View,
+    pub theme: Theme,
+}
+
+/// Where the state is kept; `None` without a home directory.
+pub fn path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)?
+    } else {
+        match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+        }
+    };
+    Some(dir.join("latencee").join("dashboard.json"))
+}
+
+impl DashboardState {
+    /// The state saved last, empty if there is none.
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = path() else {
+            return Ok(DashboardState::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(DashboardState::default()),
+            Err(e) => return Err(e),
+        };
+        let invalid = || {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: not a dashboard state", path.display()),
+            )
+        };
+        let json = json::parse(&text).ok_or_else(invalid)?;
+        let order = match json.get("order") {
+            Some(order) => order
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|name| name.as_str().map(str::to_string).ok_or_else(invalid))
+                .collect::<io::Result<_>>()?,
+            None => Vec::new(),
+        };
+        let labels = match json.get("labels") {
+            Some(Json::Object(fields)) => fields
+                .iter()
+                .map(|(name, label)| {
+                    let label = label.as_str().ok_or_else(invalid)?;
+                    Ok((name.clone(), label.to_string()))
+                })
+                .collect::<io::Result<_>>()?,
+            Some(_) => return Err(invalid()),
+            None => BTreeMap::new(),
+        };
+        // A view this version does not have falls back to the graphs.
+        let view = json
+            .get("view")
+            .and_then(Json::as_str)
+            .and_then(View::named)
+            .unwrap_or_default();
+        let theme = json
+            .get("theme")
+            .and_then(Json::as_str)
+            .and_then(Theme::named)
+            .unwrap_or_default();
+        Ok(DashboardState {
+            order,
+            labels,
+            view,
+            theme,
+        })
+    }
+
+    /// Replaces the saved state, through a temporary file so that a crash
+    /// does not leave half of it.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = path() else {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                "no home directory to keep the dashboard state in",
+            ));
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = Json::object([
+            (
+                "order",
+                Json::Array(
+                    self.order
+                        .iter()
+                        .map(|name| Json::from(name.as_str()))
+                        .collect(),
+                ),
+            ),
+            (
+                "labels",
+                Json::object(
+                    self.labels
+                        .iter()
+                        .map(|(name, label)| (name.as_str(), Json::from(label.as_str()))),
+                ),
+            ),
+            ("view", Json::from(self.view.name())),
+            ("theme", Json::from(self.theme.name())),
+        ]);
+        let partial = path.with_extension("json.tmp");
+        fs::write(&partial, format!("{}\n", json))?;
+        fs::rename(&partial, &path)
+    }
+}
@@ -0,0 +1,190 @@
+//! Latency statistics over a set of samples.
+
+use std::time::Duration;
+
+/// Summary of a series of probe results. Latency figures only consider
+/// answered probes and are `None` when every probe was lost.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub samples: usize,
+    pub lost: usize,
+    pub min: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub max: Option<Duration>,
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    /// Mean absolute difference between consecutive answered probes.
+    pub jitter: Option<Duration>,
+}
+
+impl Summary {
+    /// Fraction of lost probes in `0.0..=1.0`.
+    pub fn loss(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.lost as f64 / self.samples as f64
+        }
+    }
+}
+
+pub fn summarize(latencies: impl IntoIterator<Item = Option<Duration>>) -> Summary {
+    let mut summary = Summary::default();
+    let mut answered = Vec::new();
+    let mut jitter_total = Duration::ZERO;
+    let mut previous: Option<Duration> = None;
+
+    for latency in latencies {
+        summary.samples += 1;
+        match latency {
+            Some(lat) => {
+                if let Some(prev) = previous {
+                    jitter_total += prev.abs_diff(lat);
+                }
+                previous = Some(lat);
+                answered.push(lat);
+            }
+            None => summary.lost += 1,
+        }
+    }
+
+    if answered.is_empty() {
+        return summary;
+    }
+
+    if answered.len() > 1 {
+        summary.jitter = Some(jitter_total / (answered.len() - 1) as u32);
+    }
+    summary.avg = Some(answered.iter().sum::<Duration>() / answered.len() as u32);
+    answered.sort_unstable();
+    summary.min = answered.first().copied();
+    summary.max = answered.last().copied();
+    summary.p50 = Some(percentile(&answered, 50.0));
+    summary.p95 = Some(percentile(&answered, 95.0));
+    summary
+}
+
+/// Growth from one [`Sketch`] bucket to the next: estimated percentiles are
+/// within 2% of the exact ones.
+const SKETCH_GROWTH: f64 = 1.02;
+
+/// Buckets of a [`Sketch`]: from 1µs to beyond a minute.
+const SKETCH_BUCKETS: usize = 920;
+
+/// Counts of latencies in buckets of exponentially growing width, from
+/// which percentiles are estimated in constant memory however many samples
+/// go in.
+#[derive(Clone, Debug)]
+pub struct Sketch {
+    counts: Vec<u32>,
+    total: u64,
+}
+
+impl Default for Sketch {
+    fn default() -> Self {
+        Sketch {
+            counts: vec![0; SKETCH_BUCKETS],
+            total: 0,
+        }
+    }
+}
+
+impl Sketch {
+    pub fn add(&mut self, latency: Duration) {
+        let micros = latency.as_secs_f64() * 1e6;
+        let index = if micros <= 1.0 {
+            0
+        } else {
+            (micros.ln() / SKETCH_GROWTH.ln()).ceil() as usize
+        };
+        self.counts[index.min(SKETCH_BUCKETS - 1)] += 1;
+        self.total += 1;
+    }
+
+    /// Nearest-rank percentile, as the upper bound of its bucket; `None`
+    /// without samples.
+    pub fn percentile(&self, pct: f64) -> Option<Duration> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((pct / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        let index = self.counts.iter().position(|&count| {
+            seen += u64::from(count);
+            seen >= rank
+        })?;
+        Some(Duration::from_secs_f64(
+            SKETCH_GROWTH.powi(index as i32) / 1e6,
+        ))
+    }
+}
+
+/// A [`Summary`] kept up to date sample by sample in constant memory, for
+/// series too long to keep. Percentiles come from a [`Sketch`].
+#[derive(Clone, Debug, Default)]
+pub struct Running {
+    samples: usize,
+    lost: usize,
+    total: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    previous: Option<Duration>,
+    jitter_total: Duration,
+    sketch: Sketch,
+}
+
+impl Running {
+    pub fn push(&mut self, latency: Option<Duration>) {
+        self.samples += 1;
+        let Some(lat) = latency else {
+            self.lost += 1;
+            return;
+        };
+        if let Some(prev) = self.previous {
+            self.jitter_total += prev.abs_diff(lat);
+        }
+        self.previous = Some(lat);
+        self.total += lat;
+        self.min = Some(self.min.map_or(lat, |min| min.min(lat)));
+        self.max = Some(self.max.map_or(lat, |max| max.max(lat)));
+        self.sketch.add(lat);
+    }
+
+    pub fn summary(&self) -> Summary {
+        let answered = (self.samples - self.lost) as u32;
+        // Estimates stay within the exact extremes.
+        let percentile = |pct| {
+            let estimate = self.sketch.percentile(pct)?;
+            Some(estimate.clamp(self.min?, self.max?))
+        };
+        Summary {
+            samples: self.samples,
+            lost: self.lost,
+            min: self.min,
+            avg: (answered > 0).then(|| self.total / answered),
+            max: self.max,
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            jitter: (answered > 1).then(|| self.jitter_total / (answered - 1)),
+        }
+    }
+}
+
+/// Nearest-rank percentile of an already sorted, non-empty slice.
+pub fn percentile(sorted: &[Duration], pct: f64) -> Duration {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Latency in milliseconds, rounded to microseconds for serialization.
+pub fn millis(latency: Duration) -> f64 {
+    (latency.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+/// Formats an optional latency as whole milliseconds, or `-`.
+pub fn format_ms(latency: Option<Duration>) -> String {
+    match latency {
+        Some(lat) => format!("{:.0}ms", lat.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
@@ -0,0 +1,45 @@
+//! Readiness and watchdog notifications for systemd services of
+//! `Type=notify`, see sd_notify(3).
+//!
+//! Outside of such a service `$NOTIFY_SOCKET` is unset and every
+//! notification is a no-op.
+
+use std::{env, io, os::unix::net::UnixDatagram, process, time::Duration};
+
+/// Sends `state`, e.g. `READY=1`, to the service manager.
+pub fn notify(state: &str) -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    let bytes = path.as_encoded_bytes();
+    if let Some(name) = bytes.strip_prefix(b"@") {
+        // An abstract socket, only available on Linux.
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::other("abstract NOTIFY_SOCKET not supported"));
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+/// How often the service manager expects `WATCHDOG=1`, if it watches this
+/// process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(process::id())
+    {
+        return None;
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec))
+}
@@ -0,0 +1,145 @@
+//! Leaving the terminal and the process in a clean state, however the
+//! program ends: quitting, a termination signal, an error or a panic.
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    terminal::{self, ClearType},
+};
+use latencee::{
+    keys::{self, Key},
+    sinks::email::base64,
+};
+use smol::channel;
+use std::{
+    io::{self, Write},
+    panic,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+/// Raw mode for the lifetime of the guard. Dropping it, also when unwinding
+/// from an error, restores the terminal.
+pub struct RawMode(());
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        // A panic message printed in raw mode would be garbled and leave the
+        // shell unusable, so restore the terminal before it is printed.
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = terminal::disable_raw_mode();
+            let _ = execute!(io::stdout(), event::DisableFocusChange, cursor::Show);
+            default(info);
+        }));
+        terminal::enable_raw_mode()?;
+        // Terminals that support it report when they lose and regain focus.
+        execute!(io::stdout(), event::EnableFocusChange)?;
+        Ok(RawMode(()))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            event::DisableFocusChange,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            cursor::Show
+        );
+    }
+}
+
+/// A flag raised by SIGTERM or SIGINT, so that loops can stop and flush
+/// instead of being killed midway. SIGHUP is left alone for `nohup`.
+#[cfg(unix)]
+pub fn stop_signal() -> io::Result<Arc<AtomicBool>> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+/// Never raised: without Unix signals, Ctrl+C ends the process directly.
+#[cfg(not(unix))]
+pub fn stop_signal() -> io::Result<Arc<AtomicBool>> {
+    Ok(Arc::new(AtomicBool::new(false)))
+}
+
+/// How often [`input_events`] checks for the stop flag.
+const STOP_CHECK: Duration = Duration::from_millis(200);
+
+/// Terminal events, read on a thread of their own so that the dashboard can
+/// wait for them and for results at once. The channel closes once `stop` is
+/// raised.
+pub fn input_events(stop: Arc<AtomicBool>) -> channel::Receiver<Event> {
+    let (sender, receiver) = channel::bounded(16);
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            let event = match event::poll(STOP_CHECK) {
+                Ok(false) => continue,
+                Ok(true) => event::read(),
+                Err(e) => Err(e),
+            };
+            let Ok(event) = event else { break };
+            if sender.send_blocking(event).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// A key pressed, as [`keys::Keymap`] knows it; `None` for keys it has no
+/// name for, such as media keys.
+pub fn key(event: &KeyEvent) -> Option<Key> {
+    use keys::{KeyCode as Code, Named};
+    let code = match event.code {
+        KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+            Code::Char(c.to_ascii_lowercase())
+        }
+        KeyCode::Char(c) => Code::Char(c),
+        KeyCode::Up => Code::Named(Named::Up),
+        KeyCode::Down => Code::Named(Named::Down),
+        KeyCode::Left => Code::Named(Named::Left),
+        KeyCode::Right => Code::Named(Named::Right),
+        KeyCode::Enter => Code::Named(Named::Enter),
+        KeyCode::Esc => Code::Named(Named::Esc),
+        KeyCode::Tab => Code::Named(Named::Tab),
+        KeyCode::BackTab => Code::Named(Named::BackTab),
+        KeyCode::Home => Code::Named(Named::Home),
+        KeyCode::End => Code::Named(Named::End),
+        KeyCode::PageUp => Code::Named(Named::PageUp),
+        KeyCode::PageDown => Code::Named(Named::PageDown),
+        KeyCode::Insert => Code::Named(Named::Insert),
+        KeyCode::Delete => Code::Named(Named::Delete),
+        KeyCode::Backspace => Code::Named(Named::Backspace),
+        KeyCode::F(n) => Code::Named(Named::F(n)),
+        _ => return None,
+    };
+    Some(Key {
+        code,
+        ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        alt: event.modifiers.contains(KeyModifiers::ALT),
+        // Part of the character already.
+        shift: matches!(code, Code::Named(_)) && event.modifiers.contains(KeyModifiers::SHIFT),
+    })
+}
+
+/// Puts `text` on the system clipboard through the terminal, with the
+/// OSC 52 sequence most terminals and tmux (`set-clipboard on`) honor.
+/// Terminals without it ignore the sequence, so success is not known.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
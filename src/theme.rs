@@ -0,0 +1,91 @@
+//! Color themes of the dashboard: which color each status is drawn in.
+//! Switched with `switch theme` in the palette and kept with the dashboard
+//! state. Statuses keep their symbols in every theme, so they tell apart
+//! without colors too.
+
+use crossterm::style::Color;
+use latencee::ConnectionStatus;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// Green to red.
+    #[default]
+    Classic,
+    /// Blue, yellow and magenta, which stay apart for red-green color
+    /// blindness.
+    Colorblind,
+    /// The terminal's own color, dimmed for timeouts.
+    Mono,
+}
+
+/// Every theme, in the order `switch theme` cycles through them, with its
+/// name in the palette and the dashboard state.
+const THEMES: &[(Theme, &str)] = &[
+    (Theme::Classic, "classic"),
+    (Theme::Colorblind, "colorblind"),
+    (Theme::Mono, "mono"),
+];
+
+/// Index in [`THEMES`] of the theme in use. The dashboard draws from one
+/// thread, but statuses pick their color far from where it is drawn.
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+impl Theme {
+    pub fn named(name: &str) -> Option<Theme> {
+        THEMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(theme, _)| *theme)
+    }
+
+    pub fn name(self) -> &'static str {
+        THEMES
+            .iter()
+            .find(|(theme, _)| *theme == self)
+            .map_or("", |(_, name)| name)
+    }
+
+    /// The names of every theme, for errors.
+    pub fn names() -> Vec<&'static str> {
+        THEMES.iter().map(|(_, name)| *name).collect()
+    }
+
+    /// The theme after this one, back to the first after the last.
+    pub fn next(self) -> Theme {
+        THEMES[(self.index() + 1) % THEMES.len()].0
+    }
+
+    fn index(self) -> usize {
+        THEMES
+            .iter()
+            .position(|(theme, _)| *theme == self)
+            .unwrap_or(0)
+    }
+
+    /// The theme the dashboard is drawn in.
+    pub fn current() -> Theme {
+        THEMES[CURRENT.load(Ordering::Relaxed)].0
+    }
+
+    /// Draws the dashboard in this theme from now on.
+    pub fn apply(self) {
+        CURRENT.store(self.index(), Ordering::Relaxed);
+    }
+
+    /// The color of `status`.
+    pub fn color(self, status: ConnectionStatus) -> Color {
+        match (self, status) {
+            (Theme::Classic, ConnectionStatus::Good) => Color::Green,
+            (Theme::Classic, ConnectionStatus::Fair) => Color::Yellow,
+            (Theme::Classic, ConnectionStatus::Poor) => Color::Red,
+            (Theme::Classic, ConnectionStatus::Timeout) => Color::DarkRed,
+            (Theme::Colorblind, ConnectionStatus::Good) => Color::Blue,
+            (Theme::Colorblind, ConnectionStatus::Fair) => Color::Yellow,
+            (Theme::Colorblind, ConnectionStatus::Poor) => Color::Magenta,
+            (Theme::Colorblind, ConnectionStatus::Timeout) => Color::DarkMagenta,
+            (Theme::Mono, ConnectionStatus::Timeout) => Color::DarkGrey,
+            (Theme::Mono, _) => Color::Reset,
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! The layouts of the dashboard besides the graphs: a dense table of each
+//! target's figures and a summary of all of them. `Tab` cycles through
+//! them; the last one used is kept in `[dashboard] view` of the
+//! configuration, or with the dashboard state without one.
+
+use crate::{
+    Appearance, COMPACT_COLUMNS, GRAPH_HISTORY_MINUTES, Rows, fit, interval_text, screen::Screen,
+};
+use crossterm::{
+    queue,
+    style::{Attribute, Color, ResetColor, SetAttribute, SetForegroundColor},
+};
+use latencee::{
+    ConnectionStatus, ProbeError, ServerStatus, alert,
+    stats::{self, Summary},
+    timefmt,
+};
+use std::{
+    cmp::Reverse,
+    io::{self, Write},
+    time::SystemTime,
+};
+
+/// How many targets the summary lists as slowest and as losing most.
+const WORST: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum View {
+    /// A graph of the last minutes per target.
+    #[default]
+    Graph,
+    Table,
+    Summary,
+}
+
+/// Every view, in the order `Tab` cycles through them, with its name in
+/// the dashboard state.
+const VIEWS: &[(View, &str)] = &[
+    (View::Graph, "graph"),
+    (View::Table, "table"),
+    (View::Summary, "summary"),
+];
+
+impl View {
+    pub fn named(name: &str) -> Option<View> {
+        VIEWS
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(view, _)| *view)
+    }
+
+    pub fn name(self) -> &'static str {
+        VIEWS
+            .iter()
+            .find(|(view, _)| *view == self)
+            .map_or("", |(_, name)| name)
+    }
+
+    /// The view after this one, back to the first after the last.
+    pub fn next(self) -> View {
+        let index = VIEWS
+            .iter()
+            .position(|(view, _)| *view == self)
+            .unwrap_or(0);
+        VIEWS[(index + 1) % VIEWS.len()].0
+    }
+}
+
+/// The name a target is shown under.
+fn shown_name<'a>(rows: &'a Rows, status: &'a ServerStatus) -> &'a str {
+    rows.labels
+        .get(&*status.name)
+        .map_or(&*status.name, String::as_str)
+}
+
+fn summarize(status: &ServerStatus) -> Summary {
+    stats::summarize(status.history.iter().map(|(_, _, latency)| *latency))
+}
+
+/// One row per target with its latest result and its figures over the
+/// graph's window; the row below them.
+pub fn draw_table(
+    screen: &mut Screen,
+    rows: &Rows,
+    silencer: &alert::Silencer,
+    columns: usize,
+    height: u16,
+) -> io::Result<u16> {
+    let statuses = rows.statuses;
+    let compact = columns < COMPACT_COLUMNS;
+    let name_width = if compact { 12 } else { 20 };
+    let figures: &[&str] = if compact {
+        &["Now", "Avg", "P95", "Loss"]
+    } else {
+        &["Now", "Min", "Avg", "Max", "P95", "Jitter", "Loss"]
+    };
+
+    // The title, keys, column names and notice take five rows.
+    let fitting = usize::from(height).saturating_sub(5).max(1);
+    let first = (rows.selected + 1).saturating_sub(fitting);
+    let shown = statuses.len().saturating_sub(first).min(fitting);
+
+    let line = screen.row(2);
+    queue!(line, SetForegroundColor(Color::DarkGrey))?;
+    write!(line, "  {:<name_width$}", "Target")?;
+    for figure in figures {
+        write!(line, "{:>8}", figure)?;
+    }
+    if shown < statuses.len() {
+        write!(
+            line,
+            "  {}-{} of {}",
+            first + 1,
+            first + shown,
+            statuses.len()
+        )?;
+    }
+    queue!(line, ResetColor)?;
+
+    for (slot, status) in statuses.iter().skip(first).take(shown).enumerate() {
+        let i = first + slot;
+        let line = screen.row((slot + 3) as u16);
+        let is_archived = i >= statuses.len() - rows.archived;
+        let summary = summarize(status);
+
+        let color = if is_archived {
+            Color::DarkGrey
+        } else {
+            status.status.color()
+        };
+        queue!(line, SetForegroundColor(color))?;
+        write!(line, "{} ", status.status.symbol())?;
+        queue!(line, ResetColor)?;
+        if i == rows.selected {
+            queue!(line, SetAttribute(Attribute::Reverse))?;
+        }
+        if rows.marked.contains(&*status.name) {
+            queue!(line, SetAttribute(Attribute::Underlined))?;
+        }
+        write!(
+            line,
+            "{:<name_width$}",
+            fit(shown_name(rows, status), name_width - 1)
+        )?;
+        queue!(
+            line,
+            SetAttribute(Attribute::NoReverse),
+            SetAttribute(Attribute::NoUnderline),
+        )?;
+
+        match status.latency {
+            Some(latency) => {
+                queue!(line, SetForegroundColor(status.status.color()))?;
+                write!(line, "{:>8}", stats::format_ms(Some(latency)))?;
+            }
+            None => {
+                let label = status.error.unwrap_or(ProbeError::Timeout).label();
+                queue!(line, SetForegroundColor(Color::DarkRed))?;
+                write!(line, "{:>8}", fit(label, 7))?;
+            }
+        }
+        queue!(line, ResetColor)?;
+        let latencies = if compact {
+            vec![summary.avg, summary.p95]
+        } else {
+            vec![
+                summary.min,
+                summary.avg,
+                summary.max,
+                summary.p95,
+                summary.jitter,
+            ]
+        };
+        for latency in latencies {
+            write!(line, "{:>8}", stats::format_ms(latency))?;
+        }
+        if summary.lost > 0 {
+            queue!(line, SetForegroundColor(Color::DarkRed))?;
+        }
+        write!(line, "{:>7.1}%", summary.loss() * 100.0)?;
+        queue!(line, ResetColor)?;
+
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        if is_archived {
+            write!(line, "  archived")?;
+        } else if rows.paused.contains(&*status.name) {
+            write!(line, "  paused")?;
+        }
+        if let Some(own) = rows.intervals.get(&*status.name) {
+            write!(line, "  every {}", interval_text(*own))?;
+        }
+        if let Some(until) = silencer.silenced_until(&status.name, SystemTime::now()) {
+            write!(line, "  silenced until {}", timefmt::short(until))?;
+        }
+        queue!(line, ResetColor)?;
+    }
+    Ok((shown + 4) as u16)
+}
+
+/// How all targets are doing at once: how many are in each state, the
+/// figures of all their probes together, the slowest targets, those
+/// losing most and those down; the row below.
+pub fn draw_summary(screen: &mut Screen, rows: &Rows, columns: usize) -> io::Result<u16> {
+    let probed = &rows.statuses[..rows.statuses.len() - rows.archived];
+
+    let line = screen.row(3);
+    write!(line, "{} targets:", probed.len())?;
+    for (status, label) in [
+        (ConnectionStatus::Good, "good"),
+        (ConnectionStatus::Fair, "fair"),
+        (ConnectionStatus::Poor, "poor"),
+        (ConnectionStatus::Timeout, "down"),
+    ] {
+        let count = probed.iter().filter(|s| s.status == status).count();
+        queue!(line, SetForegroundColor(status.color()))?;
+        write!(line, "  {} {} {}", status.symbol(), count, label)?;
+    }
+    queue!(line, SetForegroundColor(Color::DarkGrey))?;
+    if !rows.paused.is_empty() {
+        write!(line, "  {} paused", rows.paused.len())?;
+    }
+    if rows.archived > 0 {
+        write!(line, "  {} archived", rows.archived)?;
+    }
+    queue!(line, ResetColor)?;
+
+    let all = stats::summarize(
+        probed
+            .iter()
+            .flat_map(|s| s.history.iter().map(|(_, _, latency)| *latency)),
+    );
+    write!(
+        screen.row(4),
+        "All probes: avg {}  p95 {}  loss {:.1}% of {} in the last {} min",
+        stats::format_ms(all.avg),
+        stats::format_ms(all.p95),
+        all.loss() * 100.0,
+        all.samples,
+        GRAPH_HISTORY_MINUTES
+    )?;
+
+    let summaries: Vec<(&ServerStatus, Summary)> =
+        probed.iter().map(|s| (s, summarize(s))).collect();
+    let mut row = 6;
+
+    let mut slowest: Vec<_> = summaries.iter().filter(|(_, s)| s.p95.is_some()).collect();
+    slowest.sort_by_key(|(_, summary)| Reverse(summary.p95));
+    write!(screen.row(row), "Slowest by p95:")?;
+    row += 1;
+    for (status, summary) in slowest.iter().take(WORST) {
+        write!(
+            screen.row(row),
+            "  {:<20}{:>8} p95{:>8} avg",
+            fit(shown_name(rows, status), 19),
+            stats::format_ms(summary.p95),
+            stats::format_ms(summary.avg)
+        )?;
+        row += 1;
+    }
+    if slowest.is_empty() {
+        write_none(screen.row(row))?;
+        row += 1;
+    }
+
+    let mut losing: Vec<_> = summaries.iter().filter(|(_, s)| s.lost > 0).collect();
+    losing.sort_by(|(_, a), (_, b)| b.loss().total_cmp(&a.loss()));
+    row += 1;
+    write!(screen.row(row), "Most loss:")?;
+    row += 1;
+    for (status, summary) in losing.iter().take(WORST) {
+        let line = screen.row(row);
+        write!(line, "  {:<20}", fit(shown_name(rows, status), 19))?;
+        queue!(line, SetForegroundColor(Color::DarkRed))?;
+        write!(line, "{:>7.1}%", summary.loss() * 100.0)?;
+        queue!(line, ResetColor)?;
+        write!(line, " ({} of {})", summary.lost, summary.samples)?;
+        row += 1;
+    }
+    if losing.is_empty() {
+        write_none(screen.row(row))?;
+        row += 1;
+    }
+
+    // Paused targets are not down, only no longer asked.
+    let down: Vec<&str> = probed
+        .iter()
+        .filter(|s| s.status == ConnectionStatus::Timeout && !rows.paused.contains(&*s.name))
+        .map(|s| shown_name(rows, s))
+        .collect();
+    row += 1;
+    let line = screen.row(row);
+    write!(line, "Down: ")?;
+    if down.is_empty() {
+        queue!(line, SetForegroundColor(Color::DarkGrey))?;
+        write!(line, "none")?;
+    } else {
+        queue!(line, SetForegroundColor(Color::DarkRed))?;
+        write!(line, "{}", fit(&down.join(", "), columns.saturating_sub(6)))?;
+    }
+    queue!(line, ResetColor)?;
+    Ok(row + 2)
+}
+
+fn write_none(line: &mut Vec<u8>) -> io::Result<()> {
+    queue!(line, SetForegroundColor(Color::DarkGrey))?;
+    write!(line, "  none")?;
+    queue!(line, ResetColor)
+}
@@ -0,0 +1,107 @@
+//! WebAssembly plugins for probes and sinks.
+//!
+//! Modules are WASI programs run by an external runtime (`wasmtime` by
+//! default), so third parties can extend latencee without recompiling it and
+//! without native code in its process. A module only gets what the runtime
+//! command grants: probes get sockets and name lookups, sinks nothing but
+//! stdin and stdout.
+//!
+//! A probe module is run once per probe with the host as its argument. It
+//! prints the round-trip time in microseconds, or `error <reason>` with a
+//! reason of `timeout`, `dns`, `permission_denied` or `unreachable`. Any
+//! other output, a failure or no answer within the timeout counts as a
+//! timeout.
+//!
+//! A sink module runs for as long as latencee does and reads events from
+//! stdin, one line per event as described in [`crate::sinks::wasm`]. It
+//! does not answer.
+
+use crate::{
+    Target,
+    config::WasmConfig,
+    probe::{Probe, ProbeError, ProbeFuture, ProbeResult},
+    rt,
+};
+use std::{
+    io::{ErrorKind, Read},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a running probe module is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The runtime command for `config`, ready for arguments to the module.
+pub(crate) fn command(config: &WasmConfig) -> Command {
+    let mut command = Command::new(&config.runtime[0]);
+    command.args(&config.runtime[1..]).arg(&config.module);
+    command
+}
+
+pub struct WasmProbe {
+    config: WasmConfig,
+}
+
+impl WasmProbe {
+    pub fn new(config: WasmConfig) -> Self {
+        WasmProbe { config }
+    }
+}
+
+impl Probe for WasmProbe {
+    fn kind(&self) -> &'static str {
+        "wasm"
+    }
+
+    fn description(&self) -> &'static str {
+        "a WebAssembly module run by a WASI runtime"
+    }
+
+    fn probe<'a>(&'a self, target: &'a Target) -> ProbeFuture<'a> {
+        let config = self.config.clone();
+        let host = target.host.clone();
+        Box::pin(rt::unblock(move || run_probe(&config, &host)))
+    }
+}
+
+fn run_probe(config: &WasmConfig, host: &str) -> ProbeResult {
+    let mut child = command(config)
+        .arg(host)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => ProbeError::BinaryMissing,
+            ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Timeout,
+        })?;
+    let deadline = Instant::now() + config.timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ProbeError::Timeout);
+            }
+        }
+    }
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut output);
+    }
+    parse_answer(output.lines().next().unwrap_or(""))
+}
+
+fn parse_answer(line: &str) -> ProbeResult {
+    let line = line.trim();
+    if let Some(reason) = line.strip_prefix("error") {
+        return Err(ProbeError::from_name(reason.trim()).unwrap_or(ProbeError::Timeout));
+    }
+    line.parse()
+        .map(Duration::from_micros)
+        .map_err(|_| ProbeError::Timeout)
+}
@@ -0,0 +1,241 @@
+//! Line format for streaming probe results between latencee processes, used
+//! by attached dashboards and remote agents:
+//!
+//! ```text
+//! <target>\t<unix milliseconds>\t<round-trip microseconds, or ->[\t<source>]
+//! ```
+//!
+//! The source names the agent that measured the sample and is omitted for
+//! local probes. Every line ends in `\n`, and peers' lines are read with
+//! [`lines`], which bounds their length.
+
+use crate::{History, ServerStatus, classify_latency};
+use std::{
+    io::{self, BufRead, Read},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub fn encode(
+    name: &str,
+    at: SystemTime,
+    latency: Option<Duration>,
+    source: Option<&str>,
+) -> String {
+    let ms = at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let mut line = match latency {
+        Some(latency) => format!("{}\t{}\t{}", name, ms, latency.as_micros()),
+        None => format!("{}\t{}\t-", name, ms),
+    };
+    if let Some(source) = source {
+        line.push('\t');
+        line.push_str(source);
+    }
+    line.push('\n');
+    line
+}
+
+/// Encodes the current result of `status`.
+pub fn encode_status(status: &ServerStatus) -> String {
+    encode(
+        &status.name,
+        status.sampled_at,
+        status.latency,
+        status.source.as_deref(),
+    )
+}
+
+/// Encodes every sample in the history of `status`, oldest first.
+pub fn encode_history(status: &ServerStatus) -> String {
+    status
+        .history
+        .iter()
+        .map(|(at, _, latency)| {
+            let age = status.last_update.saturating_duration_since(*at);
+            encode(
+                &status.name,
+                status.sampled_at - age,
+                *latency,
+                status.source.as_deref(),
+            )
+        })
+        .collect()
+}
+
+/// The longest line accepted from a peer; longer ones are skipped.
+pub const MAX_LINE: usize = 4096;
+
+/// The complete lines read from `reader`, without their line ends. Lines
+/// longer than [`MAX_LINE`] or not UTF-8 are skipped, and so is a last line
+/// cut short by the peer going away.
+pub fn lines(mut reader: impl BufRead) -> impl Iterator<Item = io::Result<String>> {
+    let mut line = Vec::new();
+    std::iter::from_fn(move || {
+        loop {
+            line.clear();
+            let limit = MAX_LINE as u64 + 1;
+            match (&mut reader).take(limit).read_until(b'\n', &mut line) {
+                Err(e) => return Some(Err(e)),
+                Ok(0) => return None,
+                Ok(_) if line.last() != Some(&b'\n') => {
+                    if line.len() <= MAX_LINE {
+                        return None;
+                    }
+                    if let Err(e) = reader.skip_until(b'\n') {
+                        return Some(Err(e));
+                    }
+                }
+                Ok(_) => {
+                    line.pop();
+                    if let Ok(line) = std::str::from_utf8(&line) {
+                        return Some(Ok(line.to_string()));
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// One decoded line.
+pub struct Sample {
+    pub name: String,
+    pub sampled_at: SystemTime,
+    pub latency: Option<Duration>,
+    pub source: Option<String>,
+}
+
+/// Parses one line; malformed lines yield `None`.
+pub fn parse(line: &str) -> Option<Sample> {
+    let mut fields = line.trim_end().split('\t');
+    let name = fields.next()?.to_string();
+    let ms = fields.next()?.parse().ok()?;
+    let latency = match fields.next()? {
+        "-" => None,
+        us => Some(Duration::from_micros(us.parse().ok()?)),
+    };
+    Some(Sample {
+        name,
+        sampled_at: UNIX_EPOCH + Duration::from_millis(ms),
+        latency,
+        source: fields.next().map(str::to_string),
+    })
+}
+
+/// Turns received lines back into statuses on this process's clock. Each
+/// carries only its own sample, see [`ServerStatus::absorb`].
+pub struct Decoder {
+    window: Duration,
+    /// Names seen so far, shared by all results of their target.
+    names: Vec<Arc<str>>,
+}
+
+impl Decoder {
+    pub fn new(window: Duration) -> Self {
+        Decoder {
+            window,
+            names: Vec::new(),
+        }
+    }
+
+    /// Decodes one line; malformed lines yield `None`.
+    pub fn decode(&mut self, line: &str) -> Option<ServerStatus> {
+        let Sample {
+            name,
+            sampled_at,
+            latency,
+            source,
+        } = parse(line)?;
+        let age = SystemTime::now()
+            .duration_since(sampled_at)
+            .unwrap_or_default();
+        let at = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+        let name = match self.names.iter().find(|known| ***known == *name) {
+            Some(known) => Arc::clone(known),
+            None => {
+                let name: Arc<str> = name.into();
+                self.names.push(Arc::clone(&name));
+                name
+            }
+        };
+        Some(ServerStatus {
+            name,
+            source,
+            probe: None,
+            latency,
+            error: None,
+            last_update: at,
+            sampled_at,
+            status: classify_latency(latency),
+            metrics: Vec::new(),
+            history: History::new(self.window),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &[u8]) -> Vec<String> {
+        lines(input).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let latency = Some(Duration::from_micros(12_345));
+        for (latency, source) in [(latency, None), (None, Some("branch-1"))] {
+            let line = encode("example.com", at, latency, source);
+            assert!(line.ends_with('\n'));
+            let sample = parse(&line).unwrap();
+            assert_eq!(sample.name, "example.com");
+            assert_eq!(sample.sampled_at, at);
+            assert_eq!(sample.latency, latency);
+            assert_eq!(sample.source.as_deref(), source);
+        }
+        assert_eq!(encode("a", at, None, Some("b")), "a\t1700000000123\t-\tb\n");
+    }
+
+    #[test]
+    fn refuses_malformed_lines() {
+        for line in ["", "a", "a\t1", "a\tx\t-", "a\t1\tx", "a\t1\t-1"] {
+            assert!(parse(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn reads_complete_lines() {
+        assert_eq!(read(b"a\t1\t2\nb\t3\t-\r\n"), ["a\t1\t2", "b\t3\t-\r"]);
+        assert_eq!(read(b""), Vec::<String>::new());
+        assert_eq!(read(b"\n"), [""]);
+    }
+
+    #[test]
+    fn drops_a_last_line_cut_short() {
+        // The peer went away in the middle of "a\t1\t234".
+        assert_eq!(read(b"a\t1\t2\na\t1\t23"), ["a\t1\t2"]);
+    }
+
+    #[test]
+    fn skips_oversized_and_garbled_lines() {
+        let long = "x".repeat(MAX_LINE);
+        let longer = "x".repeat(MAX_LINE + 1);
+        let input = format!("{}\n{}\na\t1\t2\n{}", long, longer, longer);
+        assert_eq!(read(input.as_bytes()), [long.as_str(), "a\t1\t2"]);
+        assert_eq!(read(b"\xff\tb\nc\t1\t2\n"), ["c\t1\t2"]);
+    }
+
+    #[test]
+    fn decodes_names_once() {
+        let mut decoder = Decoder::new(Duration::from_secs(60));
+        let first = decoder.decode("a\t1\t2000\n").unwrap();
+        let second = decoder.decode("a\t2\t-\n").unwrap();
+        assert!(Arc::ptr_eq(&first.name, &second.name));
+        assert_eq!(first.latency, Some(Duration::from_millis(2)));
+        assert!(second.latency.is_none());
+        assert!(decoder.decode("a\t2").is_none());
+    }
+}